@@ -0,0 +1,123 @@
+//! Counting retired instructions of the current thread with
+//! `perf_event_open(2)`.
+
+#[cfg(target_os = "linux")]
+pub use self::linux::Instructions;
+
+#[cfg(not(target_os = "linux"))]
+pub use self::unsupported::Instructions;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use libc;
+    use std::mem;
+
+    const PERF_TYPE_HARDWARE: u32 = 0;
+    const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+    const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+    const PERF_EVENT_IOC_DISABLE: libc::c_ulong = 0x2401;
+    const PERF_EVENT_IOC_RESET: libc::c_ulong = 0x2403;
+
+    // Flag bits of `perf_event_attr`
+    const DISABLED: u64 = 1 << 0;
+    const EXCLUDE_KERNEL: u64 = 1 << 5;
+    const EXCLUDE_HV: u64 = 1 << 6;
+
+    /// `struct perf_event_attr`, `PERF_ATTR_SIZE_VER5` layout.
+    #[repr(C)]
+    struct PerfEventAttr {
+        type_: u32,
+        size: u32,
+        config: u64,
+        sample_period: u64,
+        sample_type: u64,
+        read_format: u64,
+        flags: u64,
+        wakeup_events: u32,
+        bp_type: u32,
+        config1: u64,
+        config2: u64,
+        branch_sample_type: u64,
+        sample_regs_user: u64,
+        sample_stack_user: u32,
+        clockid: i32,
+        sample_regs_intr: u64,
+        aux_watermark: u32,
+        sample_max_stack: u16,
+        reserved: u16,
+    }
+
+    pub struct Instructions {
+        fd: libc::c_int,
+    }
+
+    impl Instructions {
+        /// `None` if the counter can't be opened, e.g. because of
+        /// `perf_event_paranoid` or a missing PMU.
+        pub fn new() -> Option<Instructions> {
+            unsafe {
+                let mut attr: PerfEventAttr = mem::zeroed();
+                attr.type_ = PERF_TYPE_HARDWARE;
+                attr.size = mem::size_of::<PerfEventAttr>() as u32;
+                attr.config = PERF_COUNT_HW_INSTRUCTIONS;
+                attr.flags = DISABLED | EXCLUDE_KERNEL | EXCLUDE_HV;
+                let fd = libc::syscall(
+                    libc::SYS_perf_event_open,
+                    &attr as *const PerfEventAttr,
+                    0,  // this thread
+                    -1, // any CPU
+                    -1, // no group
+                    0,
+                );
+                if fd < 0 {
+                    None
+                } else {
+                    Some(Instructions { fd: fd as libc::c_int })
+                }
+            }
+        }
+
+        pub fn start(&mut self) {
+            unsafe {
+                libc::ioctl(self.fd, PERF_EVENT_IOC_RESET, 0);
+                libc::ioctl(self.fd, PERF_EVENT_IOC_ENABLE, 0);
+            }
+        }
+
+        /// Instructions retired since `start`.
+        pub fn stop(&mut self) -> u64 {
+            let mut count = 0u64;
+            unsafe {
+                libc::ioctl(self.fd, PERF_EVENT_IOC_DISABLE, 0);
+                let n = libc::read(self.fd, &mut count as *mut u64 as *mut libc::c_void, 8);
+                assert_eq!(n, 8, "failed to read instruction counter");
+            }
+            count
+        }
+    }
+
+    impl Drop for Instructions {
+        fn drop(&mut self) {
+            unsafe {
+                libc::close(self.fd);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod unsupported {
+    pub struct Instructions;
+
+    impl Instructions {
+        pub fn new() -> Option<Instructions> {
+            None
+        }
+
+        pub fn start(&mut self) {}
+
+        pub fn stop(&mut self) -> u64 {
+            0
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Harness for runtime benchmarks: programs that measure how fast code
+//! compiled by rustc runs, rather than how fast rustc compiles it.
+//!
+//! A runtime benchmark crate is a binary whose `main` is generated by
+//! `run_benchmark_group!`:
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate benchlib;
+//!
+//! run_benchmark_group!(|group| {
+//!     group.register("sort-10k", || {
+//!         let mut v = (0..10_000u32).rev().collect::<Vec<_>>();
+//!         v.sort();
+//!         v
+//!     });
+//! });
+//! ```
+//!
+//! The binary accepts `--iterations N`, `--warmup N` and an optional filter
+//! on benchmark names, and prints a `Results` as JSON on stdout for the
+//! collector to ingest.
+
+extern crate libc;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+mod counters;
+
+use std::env;
+use std::io::stdout;
+use std::time::Instant;
+
+use counters::Instructions;
+
+/// Everything a benchmark binary reports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Results {
+    pub benchmarks: Vec<BenchmarkResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    pub name: String,
+    /// One entry per measured iteration
+    pub iterations: Vec<Iteration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Iteration {
+    pub wall_time_ns: u64,
+    /// `None` if hardware counters are unavailable, e.g. outside of Linux or
+    /// in a VM without PMU access.
+    pub instructions: Option<u64>,
+}
+
+/// Options parsed from the benchmark binary's command line.
+pub struct Options {
+    pub iterations: u32,
+    pub warmup: u32,
+    pub filter: Option<String>,
+}
+
+impl Options {
+    pub fn from_args() -> Options {
+        let mut options = Options {
+            iterations: 5,
+            warmup: 1,
+            filter: None,
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match &*arg {
+                "--iterations" => options.iterations = parse_count(&arg, args.next()),
+                "--warmup" => options.warmup = parse_count(&arg, args.next()),
+                _ => options.filter = Some(arg),
+            }
+        }
+        options
+    }
+}
+
+fn parse_count(flag: &str, value: Option<String>) -> u32 {
+    match value.and_then(|v| v.parse().ok()) {
+        Some(n) => n,
+        None => panic!("{} expects a number", flag),
+    }
+}
+
+pub struct BenchmarkGroup {
+    benchmarks: Vec<(&'static str, Box<Fn()>)>,
+}
+
+impl BenchmarkGroup {
+    pub fn new() -> BenchmarkGroup {
+        BenchmarkGroup {
+            benchmarks: Vec::new(),
+        }
+    }
+
+    /// Register `f` as the benchmark `name`. The value returned by `f` is
+    /// passed through `black_box` so that the computation isn't optimized
+    /// away.
+    pub fn register<F, R>(&mut self, name: &'static str, f: F)
+    where
+        F: Fn() -> R + 'static,
+    {
+        self.benchmarks.push((
+            name,
+            Box::new(move || {
+                black_box(f());
+            }),
+        ));
+    }
+
+    /// Run all registered benchmarks matching `options.filter`.
+    pub fn run(&self, options: &Options) -> Results {
+        let mut counter = Instructions::new();
+        let mut results = Vec::new();
+        for &(name, ref f) in &self.benchmarks {
+            if let Some(ref filter) = options.filter {
+                if !name.contains(&**filter) {
+                    continue;
+                }
+            }
+            for _ in 0..options.warmup {
+                f();
+            }
+            let mut iterations = Vec::new();
+            for _ in 0..options.iterations {
+                if let Some(ref mut counter) = counter {
+                    counter.start();
+                }
+                let start = Instant::now();
+                f();
+                let elapsed = start.elapsed();
+                let instructions = counter.as_mut().map(|counter| counter.stop());
+                iterations.push(Iteration {
+                    wall_time_ns: elapsed.as_secs() * 1_000_000_000
+                        + elapsed.subsec_nanos() as u64,
+                    instructions: instructions,
+                });
+            }
+            results.push(BenchmarkResult {
+                name: name.to_string(),
+                iterations: iterations,
+            });
+        }
+        Results {
+            benchmarks: results,
+        }
+    }
+
+    /// Run the benchmarks selected on the command line and print the results
+    /// as JSON.
+    pub fn run_and_print(&self) {
+        let results = self.run(&Options::from_args());
+        serde_json::to_writer(&mut stdout(), &results).expect("failed to write results");
+        println!("");
+    }
+}
+
+/// Hide `value` from the optimizer.
+pub fn black_box<T>(value: T) -> T {
+    unsafe {
+        let ret = ::std::ptr::read_volatile(&value);
+        ::std::mem::forget(value);
+        ret
+    }
+}
+
+/// Define `main` for a runtime benchmark binary. The closure receives a
+/// `&mut BenchmarkGroup` to register benchmarks with.
+#[macro_export]
+macro_rules! run_benchmark_group {
+    (|$group:ident| $body:block) => {
+        fn main() {
+            let mut group = $crate::BenchmarkGroup::new();
+            {
+                let $group = &mut group;
+                $body
+            }
+            group.run_and_print();
+        }
+    };
+}
@@ -0,0 +1,231 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A client for the HTTP API of the rustc-perf site, for tools such as
+//! bisection scripts and dashboards.
+//!
+//! The requests and responses are the site's own types, re-exported from
+//! the `site-api` crate, so a change to the API is a compile error here
+//! rather than a parse error at runtime.
+//!
+//! A site served under a path needs a trailing slash to keep it, e.g.
+//! `https://example.com/rustc-perf/`.
+//!
+//! ```no_run
+//! use rustc_perf_client::{days, Client, List};
+//!
+//! let client = Client::new("https://perf.rust-lang.org").unwrap();
+//! let comparison = client.compare(&days::Request {
+//!     commit_a: String::from("6ac6b8fc1de9a0e16c2e35ba1e7dfd8277e10e28"),
+//!     commit_b: String::from("d0f4ac8e9ef3c36b4c8c8bc0a87ec2e8a9f64e1a"),
+//!     crates: List::All,
+//!     stat: String::from("instructions:u"),
+//!     extra_stats: Vec::new(),
+//!     profile: Default::default(),
+//!     backend: Default::default(),
+//!     flags: None,
+//!     flags_b: None,
+//!     target: String::from("x86_64-unknown-linux-gnu"),
+//!     machine: None,
+//! }).unwrap();
+//! println!("{}", comparison.summary.verdict);
+//! ```
+
+extern crate reqwest;
+extern crate serde;
+extern crate serde_json;
+extern crate site_api;
+extern crate url;
+#[macro_use]
+extern crate error_chain;
+
+use reqwest::header::{Authorization, Bearer};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::io::Read;
+use url::Url;
+
+pub use site_api::{bootstrap, collectors, data, days, experiment, info, queue, self_profile,
+                   stats, timings, triage, Backend, Date, End, List, OptionalDate, Profile,
+                   Start};
+
+pub mod errors {
+    error_chain! {
+        foreign_links {
+            Io(::std::io::Error);
+            Json(::serde_json::Error);
+            Reqwest(::reqwest::Error);
+            Url(::url::ParseError);
+        }
+    }
+}
+
+use errors::*;
+
+/// A connection to one site, e.g. `https://perf.rust-lang.org`.
+pub struct Client {
+    site: Url,
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Client {
+    pub fn new(site: &str) -> Result<Client> {
+        Ok(Client {
+            site: Url::parse(site)?,
+            token: None,
+            client: reqwest::Client::new()?,
+        })
+    }
+
+    /// Authenticate requests with `token`, as needed for the endpoints that
+    /// change the site's state.
+    pub fn with_token(mut self, token: &str) -> Client {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// The crates, statistics, targets etc. the site has results for.
+    pub fn info(&self) -> Result<info::Response> {
+        self.get("/perf/info", &[])
+    }
+
+    /// The values of a statistic over a range of commits, as graphed by the
+    /// site's front page.
+    pub fn data(&self, request: &data::Request) -> Result<data::Response> {
+        self.post("/perf/data", request)
+    }
+
+    /// The results of two commits, as shown by the compare page.
+    pub fn compare(&self, request: &days::Request) -> Result<days::Response> {
+        self.post("/perf/get", request)
+    }
+
+    /// Summary statistics of each crate over a range of commits.
+    pub fn stats(&self, request: &stats::Request) -> Result<stats::Response> {
+        self.post("/perf/stats", request)
+    }
+
+    /// The per-query timings of a benchmark for two commits.
+    pub fn self_profile(&self, request: &self_profile::Request) -> Result<self_profile::Response> {
+        self.post("/perf/self_profile", request)
+    }
+
+    /// When each crate of a benchmark's build was compiled with two commits.
+    pub fn timings(&self, request: &timings::Request) -> Result<timings::Response> {
+        self.post("/perf/timings", request)
+    }
+
+    pub fn bootstrap(&self, request: &bootstrap::Request) -> Result<bootstrap::Response> {
+        self.post("/perf/bootstrap", request)
+    }
+
+    pub fn collectors(&self) -> Result<collectors::Response> {
+        self.get("/perf/collectors", &[])
+    }
+
+    pub fn queue(&self) -> Result<queue::Response> {
+        self.get("/perf/queue", &[])
+    }
+
+    /// The Markdown triage report.
+    pub fn triage(&self, request: &triage::Request) -> Result<String> {
+        let mut query = vec![
+            ("start", date_param(&request.start_date)),
+            ("end", date_param(&request.end_date)),
+            ("stat", request.stat.clone()),
+            ("profile", request.profile.to_string()),
+            ("backend", request.backend.name().to_string()),
+            ("target", request.target.clone()),
+        ];
+        if let Some(ref flags) = request.flags {
+            query.push(("flags", flags.clone()));
+        }
+        let mut response = self.send(self.client.get(self.url("/perf/triage", &query)?))?;
+        let mut report = String::new();
+        response.read_to_string(&mut report)?;
+        Ok(report)
+    }
+
+    /// Queue an experiment; needs a token of the `member` role.
+    pub fn experiment(&self, request: &experiment::Request) -> Result<experiment::Response> {
+        self.post("/perf/experiments", request)
+    }
+
+    fn get<R: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<R> {
+        let url = self.url(path, query)?;
+        Ok(self.send(self.client.get(url))?.json()?)
+    }
+
+    fn post<B: Serialize, R: DeserializeOwned>(&self, path: &str, body: &B) -> Result<R> {
+        let url = self.url(path, &[])?;
+        Ok(self.send(self.client.post(url).json(body))?.json()?)
+    }
+
+    /// `path` is relative to the site, so that a site served under a path
+    /// keeps it.
+    fn url(&self, path: &str, query: &[(&str, String)]) -> Result<Url> {
+        let mut url = self.site.join(path.trim_left_matches('/'))?;
+        if !query.is_empty() {
+            url.query_pairs_mut()
+                .extend_pairs(query.iter().map(|&(key, ref value)| (key, value)));
+        }
+        Ok(url)
+    }
+
+    fn send(&self, mut request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        if let Some(ref token) = self.token {
+            request = request.header(Authorization(Bearer {
+                token: token.clone(),
+            }));
+        }
+        let response = request.send()?;
+        if !response.status().is_success() {
+            bail!("{} returned {}", response.url(), response.status());
+        }
+        Ok(response)
+    }
+}
+
+/// As the site parses dates in query strings; an unparsable date stands
+/// for the default.
+fn date_param<B: site_api::Bound>(date: &OptionalDate<B>) -> String {
+    match *date {
+        OptionalDate::Date(date, _) => date.to_string(),
+        OptionalDate::CouldNotParse(ref value) => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_keeps_base_path() {
+        let client = Client::new("https://example.com/rustc-perf/").unwrap();
+        assert_eq!(
+            client.url("/perf/info", &[]).unwrap().as_str(),
+            "https://example.com/rustc-perf/perf/info"
+        );
+        let client = Client::new("https://perf.rust-lang.org").unwrap();
+        let query = [("stat", String::from("instructions:u"))];
+        assert_eq!(
+            client.url("/perf/triage", &query).unwrap().as_str(),
+            "https://perf.rust-lang.org/perf/triage?stat=instructions%3Au"
+        );
+    }
+
+    #[test]
+    fn date_params() {
+        let date = OptionalDate::<Start>::new(Date::ymd_hms(2017, 10, 1, 0, 0, 0));
+        assert_eq!(date_param(&date), "2017-10-01T00:00:00+00:00");
+        let unparsed = OptionalDate::<End>::CouldNotParse(String::from("yesterday"));
+        assert_eq!(date_param(&unparsed), "yesterday");
+    }
+}
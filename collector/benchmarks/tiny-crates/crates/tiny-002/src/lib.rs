@@ -0,0 +1,24 @@
+// Generated by generate.py; change that instead.
+
+pub struct Item {
+    pub id: u32,
+    pub name: &'static str,
+}
+
+pub trait Describe {
+    fn describe(&self) -> String;
+}
+
+impl Describe for Item {
+    fn describe(&self) -> String {
+        format!("{}: {}", self.id, self.name)
+    }
+}
+
+pub fn item() -> Item {
+    Item { id: 2, name: "tiny_002" }
+}
+
+pub fn describe() -> String {
+    item().describe()
+}
@@ -0,0 +1,98 @@
+#! /usr/bin/python
+#
+# Generates the tiny-crates benchmark: a workspace of N tiny library crates in
+# crates/ and a root crate depending on all of them, with the Cargo.lock Cargo
+# would write. Checking it measures the fixed costs the compiler has per crate,
+# such as setting up the session and reading and writing metadata.
+#
+# Usage: ./generate.py [N], in this directory. N defaults to 50. The existing
+# crates are removed first.
+
+from __future__ import print_function
+
+import os
+import shutil
+import sys
+
+DEFAULT_CRATES = 50
+
+HEADER = '// Generated by generate.py; change that instead.\n'
+
+MANIFEST = '''[package]
+name = "{name}"
+version = "0.1.0"
+
+[dependencies]
+'''
+
+TINY_LIB = HEADER + '''
+pub struct Item {{
+    pub id: u32,
+    pub name: &'static str,
+}}
+
+pub trait Describe {{
+    fn describe(&self) -> String;
+}}
+
+impl Describe for Item {{
+    fn describe(&self) -> String {{
+        format!("{{}}: {{}}", self.id, self.name)
+    }}
+}}
+
+pub fn item() -> Item {{
+    Item {{ id: {id}, name: "{crate}" }}
+}}
+
+pub fn describe() -> String {{
+    item().describe()
+}}
+'''
+
+
+def write(path, contents):
+    with open(path, 'w') as f:
+        f.write(contents)
+
+
+def main():
+    crates = int(sys.argv[1]) if len(sys.argv) > 1 else DEFAULT_CRATES
+    root = os.path.dirname(os.path.abspath(__file__))
+    crates_dir = os.path.join(root, 'crates')
+    if os.path.exists(crates_dir):
+        shutil.rmtree(crates_dir)
+
+    names = ['tiny-%03d' % i for i in range(crates)]
+    for i, name in enumerate(names):
+        src = os.path.join(crates_dir, name, 'src')
+        os.makedirs(src)
+        write(os.path.join(crates_dir, name, 'Cargo.toml'), MANIFEST.format(name=name))
+        write(os.path.join(src, 'lib.rs'),
+              TINY_LIB.format(id=i, crate=name.replace('-', '_')))
+
+    manifest = MANIFEST.format(name='tiny-crates')
+    manifest += ''.join('%s = { path = "crates/%s" }\n' % (name, name) for name in names)
+    manifest += '\n[workspace]\n'
+    write(os.path.join(root, 'Cargo.toml'), manifest)
+
+    lib = HEADER + '\n'
+    lib += ''.join('extern crate %s;\n' % name.replace('-', '_') for name in names)
+    lib += '\npub fn describe_all() -> Vec<String> {\n    vec![\n'
+    lib += ''.join('        %s::describe(),\n' % name.replace('-', '_') for name in names)
+    lib += '    ]\n}\n'
+    if not os.path.exists(os.path.join(root, 'src')):
+        os.makedirs(os.path.join(root, 'src'))
+    write(os.path.join(root, 'src', 'lib.rs'), lib)
+
+    lock = '[root]\nname = "tiny-crates"\nversion = "0.1.0"\ndependencies = [\n'
+    lock += ''.join(' "%s 0.1.0",\n' % name for name in names)
+    lock += ']\n'
+    lock += ''.join('\n[[package]]\nname = "%s"\nversion = "0.1.0"\n' % name for name in names)
+    write(os.path.join(root, 'Cargo.lock'), lock)
+
+    print('generated %d crates' % crates)
+
+
+if __name__ == '__main__':
+    main()
@@ -0,0 +1,107 @@
+// Generated by generate.py; change that instead.
+
+extern crate tiny_000;
+extern crate tiny_001;
+extern crate tiny_002;
+extern crate tiny_003;
+extern crate tiny_004;
+extern crate tiny_005;
+extern crate tiny_006;
+extern crate tiny_007;
+extern crate tiny_008;
+extern crate tiny_009;
+extern crate tiny_010;
+extern crate tiny_011;
+extern crate tiny_012;
+extern crate tiny_013;
+extern crate tiny_014;
+extern crate tiny_015;
+extern crate tiny_016;
+extern crate tiny_017;
+extern crate tiny_018;
+extern crate tiny_019;
+extern crate tiny_020;
+extern crate tiny_021;
+extern crate tiny_022;
+extern crate tiny_023;
+extern crate tiny_024;
+extern crate tiny_025;
+extern crate tiny_026;
+extern crate tiny_027;
+extern crate tiny_028;
+extern crate tiny_029;
+extern crate tiny_030;
+extern crate tiny_031;
+extern crate tiny_032;
+extern crate tiny_033;
+extern crate tiny_034;
+extern crate tiny_035;
+extern crate tiny_036;
+extern crate tiny_037;
+extern crate tiny_038;
+extern crate tiny_039;
+extern crate tiny_040;
+extern crate tiny_041;
+extern crate tiny_042;
+extern crate tiny_043;
+extern crate tiny_044;
+extern crate tiny_045;
+extern crate tiny_046;
+extern crate tiny_047;
+extern crate tiny_048;
+extern crate tiny_049;
+
+pub fn describe_all() -> Vec<String> {
+    vec![
+        tiny_000::describe(),
+        tiny_001::describe(),
+        tiny_002::describe(),
+        tiny_003::describe(),
+        tiny_004::describe(),
+        tiny_005::describe(),
+        tiny_006::describe(),
+        tiny_007::describe(),
+        tiny_008::describe(),
+        tiny_009::describe(),
+        tiny_010::describe(),
+        tiny_011::describe(),
+        tiny_012::describe(),
+        tiny_013::describe(),
+        tiny_014::describe(),
+        tiny_015::describe(),
+        tiny_016::describe(),
+        tiny_017::describe(),
+        tiny_018::describe(),
+        tiny_019::describe(),
+        tiny_020::describe(),
+        tiny_021::describe(),
+        tiny_022::describe(),
+        tiny_023::describe(),
+        tiny_024::describe(),
+        tiny_025::describe(),
+        tiny_026::describe(),
+        tiny_027::describe(),
+        tiny_028::describe(),
+        tiny_029::describe(),
+        tiny_030::describe(),
+        tiny_031::describe(),
+        tiny_032::describe(),
+        tiny_033::describe(),
+        tiny_034::describe(),
+        tiny_035::describe(),
+        tiny_036::describe(),
+        tiny_037::describe(),
+        tiny_038::describe(),
+        tiny_039::describe(),
+        tiny_040::describe(),
+        tiny_041::describe(),
+        tiny_042::describe(),
+        tiny_043::describe(),
+        tiny_044::describe(),
+        tiny_045::describe(),
+        tiny_046::describe(),
+        tiny_047::describe(),
+        tiny_048::describe(),
+        tiny_049::describe(),
+    ]
+}
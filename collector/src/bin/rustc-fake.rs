@@ -1,9 +1,20 @@
 use std::env;
-use std::process::Command;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 fn main() {
     let mut args = env::args_os().skip(1).collect::<Vec<_>>();
 
+    // A measured rustc runs us as its linker to tell their memory apart.
+    if let Some(linker) = env::var_os("RUSTC_FAKE_LINKER") {
+        link(&linker, &args);
+        return;
+    }
+
     let rustc = env::var_os("RUSTC_REAL").unwrap();
     let mut cmd = Command::new(&rustc);
 
@@ -12,28 +23,649 @@ fn main() {
     if let Some(pos) = time_passes {
         args.remove(pos);
     }
+    // Options a benchmark passes through RUSTFLAGS, to measure every crate
+    // of its workspace, also reach Cargo's probes of the compiler, which
+    // aren't measured.
+    let probe = args.iter()
+        .any(|arg| arg.to_str().map_or(false, |arg| arg.starts_with("--print")));
+    let time_passes = if probe { None } else { time_passes };
+
+    // Unlike the other tools, this one covers every crate of the build, not
+    // just the benchmark's own.
+    if env::var("PERF_TOOL").ok().map_or(false, |tool| tool == "timings") {
+        record_timing(&rustc, &args, time_passes.is_some());
+        return;
+    }
+
+    if let (Some(_), Ok(tool)) = (time_passes, env::var("PERF_TOOL")) {
+        run_with_profiler(&tool, &rustc, &args);
+        return;
+    }
 
     if env::var_os("USE_PERF").is_some() && time_passes.is_some() {
+        let events = env::var("PERF_EVENTS")
+            .unwrap_or_else(|_| String::from("instructions:u,cycles:u,task-clock,cpu-clock,faults"));
         cmd = Command::new("perf");
         cmd.arg("stat")
             .arg("-x;")
             .arg("-e")
-            .arg("instructions:u,cycles:u,task-clock,cpu-clock,faults")
+            .arg(&events)
             .arg("--log-fd")
             .arg("1")
             .arg(&rustc);
     }
+    let split_rss = env::var_os("RUSTC_FAKE_SPLIT_RSS").is_some();
+    let split_instructions = env::var_os("RUSTC_FAKE_SPLIT_INSTRUCTIONS").is_some();
+    let link_log = if time_passes.is_some() && (split_rss || split_instructions) {
+        wrap_linker(&mut cmd, &args)
+    } else {
+        None
+    };
     cmd.args(&args);
 
     if time_passes.is_some() {
         raise_priority();
-        assert!(cmd.status().expect("failed to spawn").success());
-        print_memory();
+        let _lock = measurement_lock();
+        let start = Instant::now();
+        let mut child = cmd.spawn().expect("failed to spawn");
+        assert!(child.wait().expect("failed to wait").success());
+        print_wall_time(start.elapsed());
+        print_memory(&child);
+        if let Some(ref link_log) = link_log {
+            if split_rss {
+                print_split_memory(link_log);
+            }
+            if split_instructions {
+                print_linker_instructions(link_log);
+            }
+        }
+        print_artifact_sizes(&args);
+        print_incr_cache_size(&args);
+    } else if split_instructions && !probe {
+        run_dependency(&rustc, &args);
     } else {
         exec(&mut cmd);
     }
 }
 
+/// Run the rustc of a crate other than the measured one, e.g. of a
+/// dependency or a build script, counting its instructions as
+/// `instructions:deps`. Unlike the measured rustc, it isn't serialized with
+/// the others; instruction counts don't depend on what else runs. Build
+/// scripts are wrapped so that their runs are counted too.
+fn run_dependency(rustc: &OsStr, args: &[OsString]) {
+    let out_dir = args.iter()
+        .position(|arg| arg == "--out-dir")
+        .and_then(|idx| args.get(idx + 1));
+    let crate_name = args.iter()
+        .position(|arg| arg == "--crate-name")
+        .and_then(|idx| args.get(idx + 1))
+        .map(|crate_name| crate_name.to_string_lossy());
+    let output = match (out_dir, crate_name.as_ref()) {
+        (Some(out_dir), Some(crate_name)) => {
+            Path::new(out_dir).join(format!("{}{}", crate_name, extra_filename(args)))
+        }
+        // Not a compilation Cargo asked for, e.g. `rustc -vV`.
+        _ => exec(Command::new(rustc).args(args)),
+    };
+    let log = output.with_extension("instructions");
+    let status = Command::new("perf")
+        .arg("stat")
+        .arg("-x;")
+        .arg("-e")
+        .arg("instructions:u")
+        .arg("-o")
+        .arg(&log)
+        .arg(rustc)
+        .args(args)
+        .status()
+        .expect("failed to spawn");
+    if let Some(instructions) = read_instructions(&log) {
+        println!("{};;instructions:deps;3;100.00", instructions);
+    }
+    let _ = fs::remove_file(&log);
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    if crate_name.map_or(false, |crate_name| crate_name.starts_with("build_script_")) {
+        wrap_build_script(&output);
+    }
+}
+
+/// The sum of the `instructions:u` counts `perf stat -x;` wrote to `log`.
+fn read_instructions(log: &Path) -> Option<u64> {
+    let mut contents = String::new();
+    File::open(log)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .ok()?;
+    let counts = contents
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split(';');
+            let count = parts.next()?.parse::<u64>().ok()?;
+            match parts.nth(1) {
+                Some("instructions:u") => Some(count),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    if counts.is_empty() {
+        None
+    } else {
+        Some(counts.iter().sum())
+    }
+}
+
+/// Run rustc under the profiler requested by the collector. Profilers
+/// write their output into `PERF_TOOL_OUT_DIR`.
+fn run_with_profiler(tool: &str, rustc: &OsStr, args: &[OsString]) {
+    let out_dir = PathBuf::from(env::var_os("PERF_TOOL_OUT_DIR").unwrap());
+    let mut cmd = Command::new(rustc);
+    cmd.args(args);
+
+    match tool {
+        "self-profile" => {
+            cmd.arg(&format!("-Zself-profile={}", out_dir.display()));
+            // Cargo reads the diagnostics from stderr, so they are passed on
+            // once counted.
+            let output = cmd.output().expect("failed to spawn");
+            std::io::stderr().write_all(&output.stderr).unwrap();
+            assert!(output.status.success());
+            print_warning_count(&String::from_utf8_lossy(&output.stderr));
+            return;
+        }
+        "eprintln" => {
+            let filter = env::var_os("PERF_TOOL_RUSTC_LOG").unwrap();
+            let log = File::create(out_dir.join("eprintln")).expect("failed to create log file");
+            cmd.env("RUSTC_LOG", filter).stderr(log);
+        }
+        "llvm-lines" => {
+            // Unoptimized IR, as the optimizer would hide how much code
+            // monomorphization produced. Anonymous globals are named so that
+            // the IR can be emitted without optimizing.
+            cmd.arg("--emit=llvm-ir")
+                .arg("-Cno-prepopulate-passes")
+                .arg("-Cpasses=name-anon-globals");
+            assert!(cmd.status().expect("failed to spawn").success());
+            // With several codegen units, there may be a `.ll` file for each.
+            for path in crate_outputs(args) {
+                if path.extension().map_or(false, |ext| ext == "ll") {
+                    let dest = out_dir.join(path.file_name().unwrap());
+                    fs::copy(&path, &dest).expect("failed to copy LLVM IR");
+                }
+            }
+            return;
+        }
+        "massif" => {
+            cmd = Command::new("valgrind");
+            cmd.arg("--tool=massif")
+                .arg(format!("--massif-out-file={}", out_dir.join("massif.out").display()))
+                .arg(rustc)
+                .args(args);
+        }
+        "heaptrack" => {
+            // heaptrack adds the extension of the compression it uses.
+            cmd = Command::new("heaptrack");
+            cmd.arg("-o")
+                .arg(out_dir.join("heaptrack"))
+                .arg(rustc)
+                .args(args);
+        }
+        "perf-record" => {
+            // With call graphs, from frame pointers where rustc has them.
+            cmd = Command::new("perf");
+            cmd.arg("record")
+                .arg("-g")
+                .arg("-o")
+                .arg(out_dir.join("perf.data"))
+                .arg(rustc)
+                .args(args);
+        }
+        "cgu-sizes" => {
+            // Keeps the object file of each codegen unit.
+            cmd.arg("-Csave-temps");
+            assert!(cmd.status().expect("failed to spawn").success());
+            print_cgu_sizes(args);
+            return;
+        }
+        "llvm-ir" => {
+            // Optimized as usual for the profile, next to the usual outputs.
+            cmd.arg("--emit=llvm-ir");
+            assert!(cmd.status().expect("failed to spawn").success());
+            // Without Cargo's hash in the name, so that the files of two
+            // toolchains can be compared by name.
+            let extra = extra_filename(args);
+            for path in crate_outputs(args) {
+                if path.extension().map_or(false, |ext| ext == "ll") {
+                    let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+                    let file_name = if extra.is_empty() {
+                        file_name
+                    } else {
+                        file_name.replacen(extra, "", 1)
+                    };
+                    fs::copy(&path, out_dir.join(file_name)).expect("failed to copy LLVM IR");
+                }
+            }
+            return;
+        }
+        "llvm-passes" => {
+            cmd.arg("-Ztime-llvm-passes");
+            let output = cmd.output().expect("failed to spawn");
+            // LLVM prints its report to stderr, among the diagnostics Cargo
+            // reads, so that is passed on.
+            std::io::stderr().write_all(&output.stderr).unwrap();
+            assert!(output.status.success());
+            let mut report = String::from_utf8_lossy(&output.stdout).into_owned();
+            report.push_str(&String::from_utf8_lossy(&output.stderr));
+            record_llvm_passes(&report, &out_dir);
+            return;
+        }
+        "time-passes" => {
+            cmd.arg("-Ztime-passes");
+            let output = cmd.output().expect("failed to spawn");
+            assert!(output.status.success());
+            print_sections(&String::from_utf8_lossy(&output.stdout));
+            return;
+        }
+        _ => panic!("unknown profiler `{}`", tool),
+    }
+
+    assert!(cmd.status().expect("failed to spawn").success());
+}
+
+/// Run rustc and append when it started and finished compiling the crate to
+/// `timings` in `PERF_TOOL_OUT_DIR`, as a line like
+/// `regex;regex;1;1520000000.123;1520000003.456`: the package and crate names,
+/// whether it is the benchmark's own crate, and the start and end time.
+fn record_timing(rustc: &OsStr, args: &[OsString], leaf: bool) {
+    let start = SystemTime::now();
+    let status = Command::new(rustc)
+        .args(args)
+        .status()
+        .expect("failed to spawn");
+    let end = SystemTime::now();
+
+    // Cargo also runs rustc to query it, as crate `___`.
+    let crate_name = args.iter()
+        .position(|arg| arg == "--crate-name")
+        .and_then(|idx| args.get(idx + 1))
+        .map(|name| name.to_string_lossy().into_owned());
+    match crate_name {
+        Some(ref crate_name) if crate_name != "___" => {
+            let package = env::var("CARGO_PKG_NAME").unwrap_or_else(|_| crate_name.clone());
+            let line = format!(
+                "{};{};{};{};{}\n",
+                package,
+                crate_name,
+                leaf as u8,
+                unix_secs(start),
+                unix_secs(end)
+            );
+            // A single write, so that the lines of concurrent rustcs don't
+            // mix.
+            let out_dir = PathBuf::from(env::var_os("PERF_TOOL_OUT_DIR").unwrap());
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(out_dir.join("timings"))
+                .and_then(|mut file| file.write_all(line.as_bytes()))
+                .expect("failed to record timing");
+        }
+        _ => {}
+    }
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+fn unix_secs(time: SystemTime) -> f64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap();
+    since_epoch.as_secs() as f64 + since_epoch.subsec_nanos() as f64 / 1e9
+}
+
+/// Sum the top-level `-Ztime-passes` timings into frontend, backend and
+/// linker time, printed in the same format as `perf stat -x;`. Lines look
+/// like `time: 0.123; rss: 45MB\tparsing`; nested passes are indented and
+/// already included in their parent's time.
+fn print_sections(time_passes: &str) {
+    let (mut frontend, mut backend, mut link) = (0.0, 0.0, 0.0);
+    for line in time_passes.lines() {
+        if !line.starts_with("time:") {
+            continue;
+        }
+        let secs = line["time:".len()..]
+            .trim_left()
+            .split(|c: char| c == ';' || c.is_whitespace())
+            .next()
+            .and_then(|secs| secs.parse::<f64>().ok());
+        let secs = match secs {
+            Some(secs) => secs,
+            None => continue,
+        };
+        let pass = line.rsplit('\t').next().unwrap();
+        if pass.contains("link") {
+            link += secs;
+        } else if pass.contains("translation") || pass.contains("codegen")
+            || pass.contains("LLVM") || pass.contains("LTO")
+        {
+            backend += secs;
+        } else {
+            frontend += secs;
+        }
+    }
+    println!("{};;frontend-time;3;100.00", frontend);
+    println!("{};;backend-time;3;100.00", backend);
+    println!("{};;link-time;3;100.00", link);
+}
+
+/// Sum the wall time of each pass in the LLVM timing reports of
+/// `-Ztime-llvm-passes` and append it to `llvm-passes` in `out_dir`, as
+/// lines like `0.0123;X86 DAG->DAG Instruction Selection`. Lines of the
+/// reports look like
+/// `   0.0090 ( 21.4%)   0.0000 (  0.0%)   0.0090 ( 21.0%)   0.0092 ( 21.2%)  Name`,
+/// with the wall time in the last column; with several codegen units, there
+/// is a report for each.
+fn record_llvm_passes(report: &str, out_dir: &Path) {
+    let mut passes: Vec<(String, f64)> = Vec::new();
+    for line in report.lines() {
+        let end = match line.rfind("%)") {
+            Some(end) => end,
+            None => continue,
+        };
+        let name = line[end + "%)".len()..].trim();
+        let column = line[..end].rsplit("%)").next().unwrap();
+        let secs = column
+            .split('(')
+            .next()
+            .and_then(|secs| secs.trim().parse::<f64>().ok());
+        let secs = match secs {
+            Some(secs) if !name.is_empty() && name != "Total" => secs,
+            _ => continue,
+        };
+        match passes.iter().position(|pass| pass.0 == name) {
+            Some(idx) => passes[idx].1 += secs,
+            None => passes.push((name.to_string(), secs)),
+        }
+    }
+    let lines = passes
+        .iter()
+        .map(|&(ref name, secs)| format!("{};{}\n", secs, name))
+        .collect::<String>();
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(out_dir.join("llvm-passes"))
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .expect("failed to record LLVM pass timings");
+}
+
+/// Print how long rustc ran, in seconds, in the same format as `perf stat
+/// -x;`. Under `perf stat`, this includes perf's own startup.
+fn print_wall_time(elapsed: Duration) {
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+    println!("{};;wall-time;3;100.00", secs);
+}
+
+/// Print the number of warnings in the diagnostics rustc wrote to `stderr`,
+/// in the same format as `perf stat -x;`. Cargo has rustc write them as one
+/// JSON object per line, but a plain rustc renders them for humans. Either
+/// way, the closing `N warnings emitted` isn't a warning of its own.
+fn print_warning_count(stderr: &str) {
+    let count = stderr
+        .lines()
+        .filter(|line| {
+            let warning = if line.starts_with('{') {
+                // The first `level` is that of the diagnostic, not of one of
+                // its children.
+                line.find("\"level\":\"")
+                    .map_or(false, |idx| line[idx + 9..].starts_with("warning\""))
+            } else {
+                line.starts_with("warning: ") || line.starts_with("warning[")
+            };
+            warning && !line.contains("warnings emitted") && !line.contains("warning emitted")
+        })
+        .count();
+    println!("{};;warning-count;3;100.00", count);
+}
+
+/// Print the number of codegen units of the crate compiled with `args` and
+/// the smallest, median and largest size of their object files in bytes, in
+/// the same format as `perf stat -x;`.
+fn print_cgu_sizes(args: &[OsString]) {
+    let mut sizes = crate_outputs(args)
+        .into_iter()
+        .filter(|path| is_cgu_object(&path.file_name().unwrap().to_string_lossy()))
+        .map(|path| fs::metadata(&path).expect("failed to stat object file").len())
+        .collect::<Vec<_>>();
+    if sizes.is_empty() {
+        return;
+    }
+    sizes.sort();
+    println!("{};;cgu-count;3;100.00", sizes.len());
+    println!("{};;cgu-size-min;3;100.00", sizes[0]);
+    println!("{};;cgu-size-median;3;100.00", sizes[sizes.len() / 2]);
+    println!("{};;cgu-size-max;3;100.00", sizes[sizes.len() - 1]);
+}
+
+/// Whether `file_name` is the object file of a codegen unit, named like
+/// `foo-1234.foo.a1b2c3-cgu.0.rcgu.o`. Those of the allocator shim and the
+/// metadata aren't codegen units of the crate's code.
+fn is_cgu_object(file_name: &str) -> bool {
+    if !file_name.ends_with(".rcgu.o") {
+        return false;
+    }
+    let mut parts = file_name[..file_name.len() - ".rcgu.o".len()].rsplitn(2, '.');
+    let index = parts.next().unwrap_or("");
+    let rest = parts.next().unwrap_or("");
+    rest.ends_with("-cgu") && !index.is_empty() && index.chars().all(|c| c.is_digit(10))
+}
+
+/// Print the total size of the files rustc wrote for the crate, and of the
+/// code (`.text*`) and debuginfo (`.debug_*`) sections in them, in bytes, in
+/// the same format as `perf stat -x;`. Section sizes are only known for
+/// 64-bit little-endian ELF objects, possibly inside rlib archives.
+fn print_artifact_sizes(args: &[OsString]) {
+    let (mut total, mut text, mut debuginfo) = (0, 0, 0);
+    let mut sections_known = false;
+    for path in crate_outputs(args) {
+        if path.to_string_lossy().ends_with(".d") {
+            continue;
+        }
+        let mut contents = Vec::new();
+        File::open(&path)
+            .and_then(|mut file| file.read_to_end(&mut contents))
+            .expect("failed to read artifact");
+        total += contents.len() as u64;
+        if let Some(sizes) = section_sizes(&contents) {
+            sections_known = true;
+            text += sizes.text;
+            debuginfo += sizes.debuginfo;
+        }
+    }
+    println!("{};;artifact-size;3;100.00", total);
+    if sections_known {
+        println!("{};;text-size;3;100.00", text);
+        println!("{};;debuginfo-size;3;100.00", debuginfo);
+    }
+}
+
+/// Print the size in bytes of the incremental compilation cache of the crate
+/// compiled with `args`, in the same format as `perf stat -x;`, if it was
+/// compiled incrementally. Cargo puts the caches of all crates into one
+/// directory, `-C incremental`; rustc keeps each crate's in a directory
+/// named `<crate name>-<hash>`, with a session directory for each
+/// compilation. Older sessions may not be deleted yet, so only the newest
+/// finished one is counted.
+fn print_incr_cache_size(args: &[OsString]) {
+    let incr_dir = args.iter()
+        .filter_map(|arg| arg.to_str())
+        .map(|arg| arg.trim_left_matches("-C"))
+        .find(|arg| arg.starts_with("incremental="))
+        .map(|arg| &arg["incremental=".len()..]);
+    let crate_name = args.iter()
+        .position(|arg| arg == "--crate-name")
+        .and_then(|idx| args.get(idx + 1))
+        .and_then(|arg| arg.to_str());
+    let (incr_dir, crate_name) = match (incr_dir, crate_name) {
+        (Some(incr_dir), Some(crate_name)) => (incr_dir, crate_name),
+        _ => return,
+    };
+    let prefix = format!("{}-", crate_name);
+    let mut newest = None;
+    for crate_dir in entries(Path::new(incr_dir)) {
+        let name = crate_dir.file_name().unwrap().to_string_lossy().into_owned();
+        if !name.starts_with(&prefix) || !crate_dir.is_dir() {
+            continue;
+        }
+        for session in entries(&crate_dir) {
+            let name = session.file_name().unwrap().to_string_lossy().into_owned();
+            if !session.is_dir() || name.ends_with("-working") {
+                continue;
+            }
+            let modified = fs::metadata(&session).and_then(|m| m.modified()).ok();
+            if newest.as_ref().map_or(true, |&(ref time, _)| modified > *time) {
+                newest = Some((modified, session));
+            }
+        }
+    }
+    if let Some((_, session)) = newest {
+        println!("{};;incr-cache-size;3;100.00", dir_size(&session));
+    }
+}
+
+/// The paths in `dir`; none if it can't be read.
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|entry| entry.ok()).map(|e| e.path()).collect())
+        .unwrap_or_default()
+}
+
+/// The total size of the files in `dir` and its subdirectories, in bytes.
+fn dir_size(dir: &Path) -> u64 {
+    entries(dir)
+        .iter()
+        .map(|path| match fs::symlink_metadata(path) {
+            Ok(ref metadata) if metadata.is_dir() => dir_size(path),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// The files rustc wrote for the crate it compiled with `args`, found by
+/// the `--out-dir`, `--crate-name` and Cargo's `-C extra-filename=-<hash>`.
+fn crate_outputs(args: &[OsString]) -> Vec<PathBuf> {
+    let arg = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|idx| args.get(idx + 1))
+            .and_then(|arg| arg.to_str())
+    };
+    let (out_dir, crate_name) = match (arg("--out-dir"), arg("--crate-name")) {
+        (Some(out_dir), Some(crate_name)) => (out_dir, crate_name),
+        _ => return Vec::new(),
+    };
+    let stem = format!("{}{}", crate_name, extra_filename(args));
+
+    let mut outputs = Vec::new();
+    for entry in fs::read_dir(out_dir).expect("failed to list output directory") {
+        let path = entry.expect("failed to list output directory").path();
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if file_name == stem || file_name.starts_with(&format!("{}.", stem))
+            || file_name.starts_with(&format!("lib{}.", stem))
+        {
+            outputs.push(path);
+        }
+    }
+    outputs
+}
+
+/// The `-<hash>` Cargo appends to the names of the crate's files, which
+/// differs between toolchains.
+fn extra_filename(args: &[OsString]) -> &str {
+    args.iter()
+        .filter_map(|arg| arg.to_str())
+        .find(|arg| arg.starts_with("extra-filename="))
+        .map(|arg| &arg["extra-filename=".len()..])
+        .unwrap_or("")
+}
+
+#[derive(Default)]
+struct SectionSizes {
+    text: u64,
+    debuginfo: u64,
+}
+
+/// Section sizes of an ELF object, or summed over the ELF objects in an `ar`
+/// archive such as an rlib. `None` for other formats.
+fn section_sizes(data: &[u8]) -> Option<SectionSizes> {
+    if !data.starts_with(b"!<arch>\n") {
+        return elf_section_sizes(data);
+    }
+    // Each member has a 60 byte header with its decimal size at offset 48;
+    // members are 2-byte aligned.
+    let mut sizes = SectionSizes::default();
+    let mut found = false;
+    let mut pos = 8;
+    while pos + 60 <= data.len() {
+        let size = std::str::from_utf8(&data[pos + 48..pos + 58])
+            .ok()
+            .and_then(|size| size.trim().parse::<usize>().ok())?;
+        let member = data.get(pos + 60..pos + 60 + size)?;
+        if let Some(member) = elf_section_sizes(member) {
+            found = true;
+            sizes.text += member.text;
+            sizes.debuginfo += member.debuginfo;
+        }
+        pos += 60 + size + (size & 1);
+    }
+    if found {
+        Some(sizes)
+    } else {
+        None
+    }
+}
+
+fn elf_section_sizes(data: &[u8]) -> Option<SectionSizes> {
+    // 64-bit, little-endian
+    if !data.starts_with(b"\x7fELF") || data.get(4) != Some(&2) || data.get(5) != Some(&1) {
+        return None;
+    }
+    let shoff = read_u64(data, 0x28)? as usize;
+    let shentsize = read_u16(data, 0x3a)? as usize;
+    let shnum = read_u16(data, 0x3c)? as usize;
+    let shstrndx = read_u16(data, 0x3e)? as usize;
+    let strtab = read_u64(data, shoff + shstrndx * shentsize + 0x18)? as usize;
+
+    let mut sizes = SectionSizes::default();
+    for idx in 0..shnum {
+        let header = shoff + idx * shentsize;
+        let name_start = strtab + read_u32(data, header)? as usize;
+        let name_len = data.get(name_start..)?.iter().position(|&b| b == 0)?;
+        let name = &data[name_start..name_start + name_len];
+        let size = read_u64(data, header + 0x20)?;
+        if name.starts_with(b".text") {
+            sizes.text += size;
+        } else if name.starts_with(b".debug_") || name.starts_with(b".zdebug_") {
+            sizes.debuginfo += size;
+        }
+    }
+    Some(sizes)
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    let bytes = data.get(pos..pos + 2)?;
+    Some(bytes[0] as u16 | (bytes[1] as u16) << 8)
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(read_u16(data, pos)? as u32 | (read_u16(data, pos + 2)? as u32) << 16)
+}
+
+fn read_u64(data: &[u8], pos: usize) -> Option<u64> {
+    Some(read_u32(data, pos)? as u64 | (read_u32(data, pos + 4)? as u64) << 32)
+}
+
 #[cfg(unix)]
 fn exec(cmd: &mut Command) -> ! {
     use std::os::unix::prelude::*;
@@ -56,23 +688,398 @@ fn raise_priority() {
     }
 }
 
+/// When the collector runs several benchmarks at once, measurements are
+/// serialized through this lock so they get exclusive use of the hardware
+/// counters. The lock is released when the returned file is dropped.
+#[cfg(unix)]
+fn measurement_lock() -> Option<File> {
+    extern crate libc;
+
+    use std::os::unix::io::AsRawFd;
+
+    let path = env::var_os("RUSTC_FAKE_LOCK")?;
+    let file = File::create(path).expect("failed to create lock file");
+    unsafe {
+        assert_eq!(libc::flock(file.as_raw_fd(), libc::LOCK_EX), 0);
+    }
+    Some(file)
+}
+
 #[cfg(unix)]
-fn print_memory() {
+fn print_memory(_child: &Child) {
+    if let Some(rss) = children_max_rss() {
+        println!("{};;max-rss;3;100.00", rss);
+    }
+}
+
+/// The peak resident set size in kilobytes of the largest process among our
+/// finished descendants.
+#[cfg(unix)]
+fn children_max_rss() -> Option<i64> {
     extern crate libc;
 
     use std::mem;
 
     unsafe {
-        let mut usage = mem::zeroed();
-        let r = libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage);
-        if r == 0 {
-            println!("{};;max-rss;3;100.00", usage.ru_maxrss);
+        let mut usage: libc::rusage = mem::zeroed();
+        if libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) == 0 {
+            Some(usage.ru_maxrss as i64)
+        } else {
+            None
+        }
+    }
+}
+
+/// Make rustc run the linker through us, so that the linker's peak memory
+/// can be told apart from rustc's. Returns the file the linker invocations
+/// are logged to. Crates that configure a linker of their own are left
+/// alone, as rustc picks the linker flavor by the linker's name.
+#[cfg(unix)]
+fn wrap_linker(cmd: &mut Command, args: &[OsString]) -> Option<PathBuf> {
+    extern crate libc;
+
+    let configures_linker = args.iter().enumerate().any(|(idx, arg)| {
+        let next = args.get(idx + 1).map(|next| next.to_string_lossy());
+        arg.to_string_lossy().starts_with("-Clinker=")
+            || (arg == "-C" && next.map_or(false, |next| next.starts_with("linker=")))
+    });
+    if configures_linker {
+        return None;
+    }
+    let log = env::temp_dir().join(format!("rustc-fake-link-{}", unsafe { libc::getpid() }));
+    let _ = fs::remove_file(&log);
+    let _ = fs::remove_file(instructions_log(&log));
+    let fake = env::current_exe().expect("failed to find rustc-fake");
+    cmd.arg(format!("-Clinker={}", fake.display()))
+        .env("RUSTC_FAKE_LINKER", "cc")
+        .env("RUSTC_FAKE_LINK_LOG", &log);
+    Some(log)
+}
+
+/// Run the real linker for rustc, and log rustc's peak memory so far and the
+/// linker's, as a line like `512000;256000`.
+#[cfg(unix)]
+fn link(linker: &OsStr, args: &[OsString]) {
+    extern crate libc;
+
+    let log = env::var_os("RUSTC_FAKE_LINK_LOG").unwrap();
+    let mut cmd = Command::new(linker);
+    if env::var_os("RUSTC_FAKE_SPLIT_INSTRUCTIONS").is_some() {
+        // Appended to, as rustc may link more than once.
+        cmd = Command::new("perf");
+        cmd.arg("stat")
+            .arg("-x;")
+            .arg("-e")
+            .arg("instructions:u")
+            .arg("--append")
+            .arg("-o")
+            .arg(instructions_log(Path::new(&log)))
+            .arg(linker);
+    }
+    let status = cmd.args(args).status().expect("failed to spawn linker");
+    // rustc waits for the linker, so its peak is the one before linking.
+    let status_file = format!("/proc/{}/status", unsafe { libc::getppid() });
+    let mut proc_status = String::new();
+    let _ = File::open(status_file).and_then(|mut file| file.read_to_string(&mut proc_status));
+    let rustc_rss = proc_status
+        .lines()
+        .find(|line| line.starts_with("VmHWM:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<i64>().ok());
+    if let (Some(rustc_rss), Some(linker_rss)) = (rustc_rss, children_max_rss()) {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log)
+            .and_then(|mut file| writeln!(file, "{};{}", rustc_rss, linker_rss))
+            .expect("failed to log linker memory");
+    }
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+/// Replace the build script Cargo just had compiled to `binary` with a
+/// script running it under `perf stat`, which Cargo runs in its place. The
+/// runs are counted into `RUSTC_FAKE_BUILD_SCRIPT_LOG` if it is set, so
+/// that the collector can report them as `instructions:build-script`.
+#[cfg(unix)]
+fn wrap_build_script(binary: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut real = binary.as_os_str().to_os_string();
+    real.push(".real");
+    let real = PathBuf::from(real);
+    fs::rename(binary, &real).expect("failed to move build script");
+    let quoted = format!("'{}'", real.display().to_string().replace('\'', "'\\''"));
+    let script = format!(
+        "#!/bin/sh\n\
+         if [ -n \"$RUSTC_FAKE_BUILD_SCRIPT_LOG\" ]; then\n\
+         exec perf stat -x';' -e instructions:u --append \
+         -o \"$RUSTC_FAKE_BUILD_SCRIPT_LOG\" {real} \"$@\"\n\
+         fi\n\
+         exec {real} \"$@\"\n",
+        real = quoted
+    );
+    File::create(binary)
+        .and_then(|mut file| file.write_all(script.as_bytes()))
+        .and_then(|_| fs::set_permissions(binary, fs::Permissions::from_mode(0o755)))
+        .expect("failed to wrap build script");
+}
+
+/// Split `max-rss` into `rustc-max-rss` and `linker-max-rss`, according to
+/// what the linker invocations logged to `log`. The build's peak is rustc's
+/// unless a linker used as much; rustc's is then the most it had used when it
+/// started linking.
+#[cfg(unix)]
+fn print_split_memory(log: &Path) {
+    let total = match children_max_rss() {
+        Some(total) => total,
+        None => return,
+    };
+    let mut links = String::new();
+    let _ = File::open(log).and_then(|mut file| file.read_to_string(&mut links));
+    let _ = fs::remove_file(log);
+    let mut rustc_at_link: i64 = 0;
+    let mut linker: Option<i64> = None;
+    for line in links.lines() {
+        let mut parts = line.split(';').map(|part| part.parse::<i64>());
+        if let (Some(Ok(rustc_rss)), Some(Ok(linker_rss))) = (parts.next(), parts.next()) {
+            rustc_at_link = rustc_at_link.max(rustc_rss);
+            linker = Some(linker.unwrap_or(0).max(linker_rss));
         }
     }
+    match linker {
+        Some(linker) => {
+            let rustc = if total > linker { total } else { rustc_at_link };
+            println!("{};;rustc-max-rss;3;100.00", rustc);
+            println!("{};;linker-max-rss;3;100.00", linker);
+        }
+        // Nothing was linked, e.g. for a library.
+        None => println!("{};;rustc-max-rss;3;100.00", total),
+    }
+}
+
+/// Where the linkers of the rustc with the link log `log` count their
+/// instructions.
+fn instructions_log(log: &Path) -> PathBuf {
+    let mut name = log.as_os_str().to_os_string();
+    name.push("-instructions");
+    PathBuf::from(name)
+}
+
+/// The instructions of the linkers the measured rustc ran, as
+/// `instructions:linker`; 0 if it linked nothing.
+fn print_linker_instructions(link_log: &Path) {
+    let log = instructions_log(link_log);
+    println!("{};;instructions:linker;3;100.00", read_instructions(&log).unwrap_or(0));
+    let _ = fs::remove_file(&log);
+}
+
+#[cfg(windows)]
+fn exec(cmd: &mut Command) -> ! {
+    let status = cmd.status().expect("failed to spawn");
+    std::process::exit(status.code().unwrap_or(1));
 }
 
 #[cfg(windows)]
 fn raise_priority() {}
 
 #[cfg(windows)]
-fn print_memory() {}
+fn measurement_lock() -> Option<File> {
+    None
+}
+
+#[cfg(windows)]
+fn wrap_linker(_cmd: &mut Command, _args: &[OsString]) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(windows)]
+fn link(_linker: &OsStr, _args: &[OsString]) {
+    unreachable!("the linker is only wrapped on Unix");
+}
+
+#[cfg(windows)]
+fn print_split_memory(_log: &Path) {}
+
+#[cfg(windows)]
+fn wrap_build_script(_binary: &Path) {}
+
+#[cfg(windows)]
+fn print_memory(child: &Child) {
+    use std::mem;
+    use std::os::raw::c_void;
+    use std::os::windows::io::AsRawHandle;
+
+    // PROCESS_MEMORY_COUNTERS from psapi.h
+    #[repr(C)]
+    struct ProcessMemoryCounters {
+        cb: u32,
+        page_fault_count: u32,
+        peak_working_set_size: usize,
+        working_set_size: usize,
+        quota_peak_paged_pool_usage: usize,
+        quota_paged_pool_usage: usize,
+        quota_peak_non_paged_pool_usage: usize,
+        quota_non_paged_pool_usage: usize,
+        pagefile_usage: usize,
+        peak_pagefile_usage: usize,
+    }
+
+    // Exported by kernel32 since Windows 7, which avoids linking psapi.
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn K32GetProcessMemoryInfo(
+            process: *mut c_void,
+            counters: *mut ProcessMemoryCounters,
+            cb: u32,
+        ) -> i32;
+    }
+
+    unsafe {
+        let mut counters: ProcessMemoryCounters = mem::zeroed();
+        let cb = mem::size_of::<ProcessMemoryCounters>() as u32;
+        counters.cb = cb;
+        let r = K32GetProcessMemoryInfo(child.as_raw_handle() as *mut c_void, &mut counters, cb);
+        if r != 0 {
+            // Report kilobytes, like `ru_maxrss` does on Linux.
+            println!("{};;max-rss;3;100.00", counters.peak_working_set_size / 1024);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cgu_objects() {
+        // As written by rustc with `-C save-temps`
+        assert!(is_cgu_object("foo-0a1b2c3d.foo.7a3a86edb360415a-cgu.0.rcgu.o"));
+        assert!(is_cgu_object("foo-0a1b2c3d.foo.7a3a86edb360415a-cgu.12.rcgu.o"));
+        assert!(is_cgu_object("foo-0a1b2c3d.foo.7a3a86edb360415a-cgu.03.rcgu.o"));
+        assert!(!is_cgu_object("foo-0a1b2c3d.foo.7a3a86edb360415a-cgu.0.rcgu.bc"));
+        assert!(!is_cgu_object("foo-0a1b2c3d.foo.7a3a86edb360415a-cgu.0.rcgu.no-opt.bc"));
+        // The allocator shim and, with older toolchains, the metadata
+        assert!(!is_cgu_object("foo-0a1b2c3d.7nphpu2difn1mlv7wydee8duo.rcgu.o"));
+        assert!(!is_cgu_object("foo-0a1b2c3d.foo.7a3a86ed-cgu.metadata.rcgu.o"));
+        assert!(!is_cgu_object("foo.crate.allocator.rcgu.o"));
+        assert!(!is_cgu_object("libfoo-0a1b2c3d.rlib"));
+    }
+
+    /// Sections of an object rustc wrote with `-g`, with their sizes as
+    /// `readelf -S` reports them.
+    const SECTIONS: &[(&str, u64)] = &[
+        (".text", 0),
+        (".text._ZN3foo3sum17hc34bbd880bbdf1f7E", 0x31),
+        (".rela.text._ZN3foo3sum17hc34bbd880bbdf1f7E", 0x48),
+        (
+            ".text._ZN3foo3sum28_$u7b$$u7b$closure$u7d$$u7d$17hb4580fb01bf78301E",
+            0x28,
+        ),
+        (".rodata.str1.1", 0xfd),
+        (".debug_abbrev", 0x258),
+        (".debug_info", 0x1763),
+        (".rela.debug_info", 0x2ef8),
+        (".debug_str", 0x2217),
+        (".comment", 0x2d),
+        (".debug_line", 0x8b3),
+        (".rela.debug_line", 0x288),
+    ];
+
+    #[test]
+    fn elf_section_sizes() {
+        let sizes = section_sizes(&elf(SECTIONS)).unwrap();
+        assert_eq!(sizes.text, 0x31 + 0x28);
+        assert_eq!(sizes.debuginfo, 0x258 + 0x1763 + 0x2217 + 0x8b3);
+
+        assert!(section_sizes(b"fn main() {}").is_none());
+        // 32-bit
+        let mut elf32 = elf(SECTIONS);
+        elf32[4] = 1;
+        assert!(section_sizes(&elf32).is_none());
+        // Truncated within the section headers
+        let object = elf(SECTIONS);
+        assert!(section_sizes(&object[..object.len() - 40]).is_none());
+    }
+
+    #[test]
+    fn rlib_section_sizes() {
+        let object = elf(SECTIONS);
+        let rmeta = &b"rust\0\0\0"[..];
+        let rlib = ar(&[("lib.rmeta/", rmeta), ("/0", &object), ("/48", &object)]);
+        let sizes = section_sizes(&rlib).unwrap();
+        assert_eq!(sizes.text, 2 * (0x31 + 0x28));
+        assert_eq!(sizes.debuginfo, 2 * (0x258 + 0x1763 + 0x2217 + 0x8b3));
+
+        assert!(section_sizes(&ar(&[("lib.rmeta/", rmeta)])).is_none());
+    }
+
+    /// A 64-bit little-endian ELF object with `sections` and the section
+    /// names, whose contents are left out.
+    fn elf(sections: &[(&str, u64)]) -> Vec<u8> {
+        let mut names = vec![0];
+        let mut name_offsets = Vec::new();
+        for &(name, _) in sections {
+            name_offsets.push(names.len() as u64);
+            names.extend(name.as_bytes());
+            names.push(0);
+        }
+        let shstrtab_name = names.len() as u64;
+        names.extend(b".shstrtab\0");
+
+        let mut data = vec![0; 64];
+        data[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        let strtab = data.len() as u64;
+        data.extend(&names);
+        let shnum = sections.len() + 2;
+        let shoff = data.len() as u64;
+        put(&mut data, 0x28, shoff, 8);
+        put(&mut data, 0x3a, 64, 2);
+        put(&mut data, 0x3c, shnum as u64, 2);
+        put(&mut data, 0x3e, shnum as u64 - 1, 2);
+
+        fn section(data: &mut Vec<u8>, name: u64, offset: u64, size: u64) {
+            let header = data.len();
+            data.extend(&[0; 64][..]);
+            put(data, header, name, 4);
+            put(data, header + 0x18, offset, 8);
+            put(data, header + 0x20, size, 8);
+        }
+        section(&mut data, 0, 0, 0);
+        for (&(_, size), &name) in sections.iter().zip(&name_offsets) {
+            section(&mut data, name, 0, size);
+        }
+        section(&mut data, shstrtab_name, strtab, names.len() as u64);
+        data
+    }
+
+    fn put(data: &mut [u8], pos: usize, value: u64, len: usize) {
+        for i in 0..len {
+            data[pos + i] = (value >> (8 * i)) as u8;
+        }
+    }
+
+    /// An `ar` archive of `members`, as rlibs are.
+    fn ar(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = b"!<arch>\n".to_vec();
+        for &(name, contents) in members {
+            let header = format!(
+                "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                name,
+                0,
+                0,
+                0,
+                644,
+                contents.len()
+            );
+            data.extend(header.as_bytes());
+            data.extend(contents);
+            if contents.len() % 2 == 1 {
+                data.push(b'\n');
+            }
+        }
+        data
+    }
+}
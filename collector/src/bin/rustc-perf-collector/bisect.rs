@@ -0,0 +1,157 @@
+//! Binary search for the first commit regressing a single benchmark.
+
+use rust_sysroot::git::Commit as GitCommit;
+
+use config::Config;
+use errors::*;
+use execute::Benchmark;
+use toolchain;
+
+/// What counts as a regression while bisecting.
+pub struct Criterion<'a> {
+    pub stat: &'a str,
+    /// Minimum increase over the good commit, in percent.
+    pub threshold: f64,
+}
+
+/// Bisects the commits between `good` and `bad` (both inclusive, `good`
+/// before `bad`), benchmarking only `benchmark`. Returns the first commit
+/// whose `stat` exceeds the value measured for `good` by more than the
+/// threshold.
+pub fn bisect<'a>(
+    commits: &'a [GitCommit],
+    good: &str,
+    bad: &str,
+    benchmark: &Benchmark,
+    criterion: &Criterion,
+    config: &Config,
+    preserve_sysroot: bool,
+) -> Result<&'a GitCommit> {
+    let position = |sha: &str| -> Result<usize> {
+        match commits.iter().position(|c| c.sha == sha) {
+            Some(idx) => Ok(idx),
+            None => bail!("commit {} has no published artifacts", sha),
+        }
+    };
+    let lo = position(good)?;
+    let hi = position(bad)?;
+    if lo >= hi {
+        bail!("good commit {} is not older than bad commit {}", good, bad);
+    }
+
+    let baseline = measure(&commits[lo], benchmark, criterion.stat, config, preserve_sysroot)?;
+    let limit = baseline * (1.0 + criterion.threshold / 100.0);
+    let regressed = |commit: &GitCommit| -> Result<bool> {
+        let value = measure(commit, benchmark, criterion.stat, config, preserve_sysroot)?;
+        println!(
+            "{} ({}): {} = {} ({:+.2}%)",
+            commit.sha,
+            commit.date,
+            criterion.stat,
+            value,
+            100.0 * (value - baseline) / baseline
+        );
+        Ok(value > limit)
+    };
+    if !regressed(&commits[hi])? {
+        bail!(
+            "bad commit {} does not regress {} by more than {}%",
+            bad,
+            criterion.stat,
+            criterion.threshold
+        );
+    }
+
+    Ok(&commits[first_regressed(commits, lo, hi, regressed)?])
+}
+
+/// The index of the first of `commits[lo..hi]` that regressed, given that
+/// `commits[lo]` didn't and `commits[hi]` did. Only the commits in between
+/// are tested.
+fn first_regressed<F>(
+    commits: &[GitCommit],
+    mut lo: usize,
+    mut hi: usize,
+    mut regressed: F,
+) -> Result<usize>
+where
+    F: FnMut(&GitCommit) -> Result<bool>,
+{
+    // Invariant: `lo` is good and `hi` regressed.
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if regressed(&commits[mid])? {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    Ok(hi)
+}
+
+/// Benchmarks `commit` and returns `stat` summed over all patches of
+/// `benchmark` and all configured backends.
+fn measure(
+    commit: &GitCommit,
+    benchmark: &Benchmark,
+    stat: &str,
+    config: &Config,
+    preserve_sysroot: bool,
+) -> Result<f64> {
+    let sysroot = toolchain::install(commit, config, preserve_sysroot)?;
+    let patches = benchmark.run_all(&sysroot, config, None, None)?;
+    let mut total = 0.0;
+    for patch in &patches {
+        match patch.run().get_stat(stat) {
+            Some(value) => total += value,
+            None => bail!("{} did not record {} for {}", patch.name, stat, commit.sha),
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    fn commits(n: usize) -> Vec<GitCommit> {
+        (0..n)
+            .map(|i| GitCommit {
+                sha: format!("{:03}", i),
+                date: Utc.ymd(2017, 10, 1).and_hms(i as u32, 0, 0),
+                summary: String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_regressed_halves_the_range() {
+        let commits = commits(20);
+        for first in 1..20 {
+            let mut tested = Vec::new();
+            let found = first_regressed(&commits, 0, 19, |commit| {
+                tested.push(commit.sha.clone());
+                Ok(commit.sha >= format!("{:03}", first))
+            }).unwrap();
+            assert_eq!(found, first);
+            assert!(tested.len() <= 5, "tested {:?}", tested);
+            assert!(!tested.contains(&commits[0].sha) && !tested.contains(&commits[19].sha));
+        }
+    }
+
+    #[test]
+    fn first_regressed_of_adjacent_commits() {
+        let commits = commits(2);
+        let found = first_regressed(&commits, 0, 1, |_| panic!("nothing to test")).unwrap();
+        assert_eq!(found, 1);
+    }
+
+    #[test]
+    fn first_regressed_stops_at_errors() {
+        let commits = commits(10);
+        let result = first_regressed(&commits, 0, 9, |_| bail!("failed to build"));
+        assert!(result.is_err());
+    }
+}
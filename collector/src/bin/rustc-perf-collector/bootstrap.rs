@@ -0,0 +1,51 @@
+//! Extract step timings from the `metrics.json` written by rustc's bootstrap.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde_json::{self, Value};
+
+use errors::{Result, ResultExt};
+
+/// Returns the time spent in each kind of bootstrap step, summed over all
+/// invocations of `x.py` recorded in `path`. Time spent in child steps is
+/// attributed to the child.
+pub fn step_timings(path: &Path) -> Result<BTreeMap<String, f64>> {
+    let mut contents = String::new();
+    File::open(path)
+        .chain_err(|| format!("failed to open {}", path.display()))?
+        .read_to_string(&mut contents)?;
+    let metrics: Value = serde_json::from_str(&contents)?;
+
+    let invocations = match metrics.get("invocations").and_then(|i| i.as_array()) {
+        Some(invocations) => invocations,
+        None => bail!("{} contains no bootstrap invocations", path.display()),
+    };
+
+    let mut steps = BTreeMap::new();
+    for invocation in invocations {
+        collect_steps(invocation, &mut steps);
+    }
+    Ok(steps)
+}
+
+fn collect_steps(node: &Value, steps: &mut BTreeMap<String, f64>) {
+    let children = match node.get("children").and_then(|c| c.as_array()) {
+        Some(children) => children,
+        None => return,
+    };
+    for child in children {
+        if child.get("kind").and_then(|k| k.as_str()) == Some("rustbuild_step") {
+            let name = child.get("type").and_then(|t| t.as_str());
+            let duration = child
+                .get("duration_excluding_children_sec")
+                .and_then(|d| d.as_f64());
+            if let (Some(name), Some(duration)) = (name, duration) {
+                *steps.entry(name.to_string()).or_insert(0.0) += duration;
+            }
+        }
+        collect_steps(child, steps);
+    }
+}
@@ -0,0 +1,234 @@
+//! Fitting a local run into a time budget, for quick iteration on a change
+//! to the compiler: `bench_local --time-budget 20min` only runs as much of
+//! the suite as its builds are expected to take.
+//!
+//! How long each build takes is estimated from the durations this machine
+//! recorded for it, or else from the newest results in the output
+//! repository, like `--plan` does. Benchmarks are chosen primary first, and
+//! within a category the quickest first, so that as many of them as possible
+//! fit. Each gets its full build first; the budget left is then spent on
+//! further scenarios, round-robin. Incremental scenarios build on those
+//! before them, so only a benchmark's first scenarios can be run. What was
+//! left out is reported on stderr.
+
+use std::io::{stderr, Write};
+
+use collector::{Category, CommitData};
+
+use config::Config;
+use durations::{self, Durations};
+use errors::*;
+use execute::Benchmark;
+use plan;
+
+/// The seconds in a budget like `20min`, `1h30m`, `90s` or `45`, which is
+/// minutes.
+pub fn parse(budget: &str) -> Result<f64> {
+    let budget = budget.trim();
+    if let Ok(minutes) = budget.parse::<f64>() {
+        return Ok(minutes * 60.0);
+    }
+    let mut seconds = 0.0;
+    let mut rest = budget;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_digit(10) && c != '.').unwrap_or(rest.len());
+        let unit_len = rest[digits..]
+            .find(|c: char| c.is_digit(10))
+            .unwrap_or(rest.len() - digits);
+        let value = rest[..digits]
+            .parse::<f64>()
+            .chain_err(|| format!("invalid time budget `{}`", budget))?;
+        seconds += value * match &rest[digits..digits + unit_len] {
+            "h" => 3600.0,
+            "m" | "min" => 60.0,
+            "s" => 1.0,
+            unit => bail!("unknown unit `{}` in time budget `{}`", unit, budget),
+        };
+        rest = &rest[digits + unit_len..];
+    }
+    if seconds <= 0.0 {
+        bail!("the time budget `{}` is empty", budget);
+    }
+    Ok(seconds)
+}
+
+/// The benchmarks and, by limiting their patches, scenarios that fit into
+/// `budget` seconds with `jobs` concurrent jobs.
+pub fn select(
+    benchmarks: &[Benchmark],
+    config: &Config,
+    durations: &Durations,
+    history: Option<&CommitData>,
+    budget: f64,
+    jobs: usize,
+) -> Result<Vec<Benchmark>> {
+    // The number of scenarios of each benchmark, and the estimated seconds of
+    // those up to the first one without an estimate
+    let mut costs = Vec::new();
+    for benchmark in benchmarks {
+        let patches = benchmark.source_patches()?;
+        let mut scenarios = Vec::new();
+        for (idx, patch) in patches.iter().enumerate() {
+            match cost(benchmark, patch, idx == 0, config, durations, history) {
+                Some(seconds) => scenarios.push(seconds),
+                None => break,
+            }
+        }
+        costs.push((patches.len(), scenarios));
+    }
+
+    let mut order = (0..benchmarks.len())
+        .filter(|&idx| !costs[idx].1.is_empty())
+        .collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        let category = |idx: usize| benchmarks[idx].config.category;
+        category(a)
+            .cmp(&category(b))
+            .then_with(|| costs[a].1[0].partial_cmp(&costs[b].1[0]).unwrap())
+    });
+
+    let scenarios = costs.iter().map(|c| c.1.clone()).collect::<Vec<_>>();
+    let (chosen, spent) = allocate(&scenarios, &order, budget * jobs as f64);
+
+    let mut out = stderr();
+    writeln!(
+        out,
+        "time budget: {:.0}s, estimated to take {:.0}s",
+        budget,
+        spent / jobs as f64
+    )?;
+    let mut selected = Vec::new();
+    for (idx, benchmark) in benchmarks.iter().enumerate() {
+        let (patches, ref scenarios) = costs[idx];
+        if chosen[idx] == 0 {
+            let why = if scenarios.is_empty() {
+                "no earlier timings"
+            } else {
+                "over budget"
+            };
+            writeln!(out, "skipped {}: {}", benchmark.name, why)?;
+            continue;
+        }
+        if chosen[idx] < patches {
+            writeln!(
+                out,
+                "{}: only the first {} of {} scenarios",
+                benchmark.name,
+                chosen[idx],
+                patches
+            )?;
+        }
+        let mut benchmark = benchmark.clone();
+        benchmark.scenarios = Some(chosen[idx]);
+        selected.push(benchmark);
+    }
+    if selected.is_empty() {
+        bail!("no benchmark fits into the time budget");
+    }
+    if !selected.iter().any(|b| b.config.category == Category::Primary)
+        && benchmarks.iter().any(|b| b.config.category == Category::Primary)
+    {
+        warn!("no primary benchmark fits into the time budget");
+    }
+    Ok(selected)
+}
+
+/// How many of their scenarios, costing `scenarios[idx]` seconds each, the
+/// benchmarks in `order` get out of `total` seconds, and the seconds spent.
+/// The full builds come first, then one more scenario of each benchmark per
+/// round, for as long as any fits.
+fn allocate(scenarios: &[Vec<f64>], order: &[usize], total: f64) -> (Vec<usize>, f64) {
+    let mut spent = 0.0;
+    let mut chosen = vec![0; scenarios.len()];
+    let mut round = 0;
+    loop {
+        let mut added = false;
+        for &idx in order {
+            let scenarios = &scenarios[idx];
+            if chosen[idx] != round || round == scenarios.len() {
+                continue;
+            }
+            if spent + scenarios[round] <= total {
+                spent += scenarios[round];
+                chosen[idx] += 1;
+                added = true;
+            }
+        }
+        if !added {
+            break;
+        }
+        round += 1;
+    }
+    (chosen, spent)
+}
+
+/// Estimated seconds of all builds of the patch `patch` of `benchmark`: the
+/// measured builds and the extra builds of every configured variant.
+fn cost(
+    benchmark: &Benchmark,
+    patch: &str,
+    full: bool,
+    config: &Config,
+    durations: &Durations,
+    history: Option<&CommitData>,
+) -> Option<f64> {
+    let name = benchmark.name.clone() + patch;
+    let mut builds = config.iterations() + config.extra_builds().len();
+    if full && config.warm_full {
+        builds += config.iterations();
+    }
+    let mut seconds = 0.0;
+    for &profile in &config.profiles {
+        for &backend in &config.backends {
+            for &std in &config.std {
+                for flags in config.flag_set_names(profile) {
+                    for custom in config.custom_profile_names() {
+                        let key = durations::build_key(&name, profile, backend, std, flags, custom);
+                        let build = durations.typical(&key).or_else(|| {
+                            plan::last_seconds(
+                                history,
+                                &benchmark.name,
+                                &name,
+                                profile,
+                                backend,
+                                std,
+                                flags,
+                                custom,
+                            )
+                        })?;
+                        seconds += build * builds as f64;
+                    }
+                }
+            }
+        }
+    }
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_units() {
+        assert_eq!(parse("20min").unwrap(), 1200.0);
+        assert_eq!(parse("1h30m").unwrap(), 5400.0);
+        assert_eq!(parse(" 90s ").unwrap(), 90.0);
+        assert_eq!(parse("45").unwrap(), 2700.0);
+        assert!(parse("10d").is_err());
+        assert!(parse("0s").is_err());
+    }
+
+    #[test]
+    fn allocate_full_builds_first_then_round_robin() {
+        let scenarios = vec![vec![10.0, 5.0, 5.0], vec![20.0, 5.0], vec![50.0], vec![]];
+        // Full builds of the first two, then one more scenario of each
+        assert_eq!(allocate(&scenarios, &[0, 1, 2], 32.0), (vec![1, 1, 0, 0], 30.0));
+        assert_eq!(allocate(&scenarios, &[0, 1, 2], 37.0), (vec![2, 1, 0, 0], 35.0));
+        assert_eq!(allocate(&scenarios, &[0, 1, 2], 40.0), (vec![2, 2, 0, 0], 40.0));
+        assert_eq!(allocate(&scenarios, &[0, 1, 2], 45.0), (vec![3, 2, 0, 0], 45.0));
+        // What does not fit is passed over for what does
+        assert_eq!(allocate(&scenarios, &[2, 0, 1], 65.0), (vec![2, 0, 1, 0], 65.0));
+        assert_eq!(allocate(&scenarios, &[0, 1, 2], 5.0), (vec![0, 0, 0, 0], 0.0));
+    }
+}
@@ -0,0 +1,188 @@
+//! Build caches of `profile_local` runs, kept across runs.
+//!
+//! Each benchmark is normally built in a fresh copy, so every profiling run
+//! rebuilds all of its dependencies from scratch. With a cache directory,
+//! the `target` directory of a benchmark (including Cargo's incremental
+//! state) is saved after profiling and restored before the next run with the
+//! same toolchain. Copying the benchmark refreshes the mtimes of its
+//! sources, so the crates being profiled are still rebuilt; only the
+//! dependencies are reused.
+//!
+//! Entries are keyed by benchmark and toolchain. When the cache grows beyond
+//! its size limit, the least recently used entries are evicted.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::Utc;
+use rust_sysroot::sysroot::Sysroot;
+
+use errors::*;
+use manifest::Fnv;
+
+/// Records when an entry was last restored or stored, in seconds since the
+/// epoch.
+const LAST_USED: &str = "last-used";
+
+pub struct Cache {
+    dir: PathBuf,
+    /// In bytes
+    max_size: u64,
+    /// Identifies the toolchain; see `toolchain_key`
+    toolchain: String,
+}
+
+impl Cache {
+    pub fn open(dir: &Path, max_size: u64, sysroot: &Sysroot) -> Result<Cache> {
+        fs::create_dir_all(dir)
+            .chain_err(|| format!("can't create cache directory {}", dir.display()))?;
+        Ok(Cache {
+            dir: dir.canonicalize()?,
+            max_size: max_size,
+            toolchain: toolchain_key(sysroot)?,
+        })
+    }
+
+    fn entry(&self, benchmark: &str) -> PathBuf {
+        self.dir.join(format!("{}-{}", benchmark, self.toolchain))
+    }
+
+    /// Copy the cached `target` directory of `benchmark` into `build_dir`.
+    /// Returns whether there was one.
+    pub fn restore(&self, benchmark: &str, build_dir: &Path) -> Result<bool> {
+        let entry = self.entry(benchmark);
+        if !entry.join("target").is_dir() {
+            return Ok(false);
+        }
+        info!("restoring build cache {}", entry.display());
+        copy_dir(&entry.join("target"), &build_dir.join("target"))?;
+        touch(&entry)?;
+        Ok(true)
+    }
+
+    /// Replace the cache entry of `benchmark` with the `target` directory of
+    /// `build_dir`, then evict old entries if the cache is too large.
+    pub fn store(&self, benchmark: &str, build_dir: &Path) -> Result<()> {
+        let target = build_dir.join("target");
+        if !target.is_dir() {
+            return Ok(());
+        }
+        let entry = self.entry(benchmark);
+        // Copy next to the entry first, so that an interrupted copy doesn't
+        // leave a partial entry behind.
+        let tmp = entry.with_extension("tmp");
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        fs::create_dir_all(&tmp)?;
+        copy_dir(&target, &tmp.join("target"))?;
+        touch(&tmp)?;
+        if entry.exists() {
+            fs::remove_dir_all(&entry)?;
+        }
+        fs::rename(&tmp, &entry)
+            .chain_err(|| format!("failed to write build cache {}", entry.display()))?;
+        info!("stored build cache {}", entry.display());
+        self.evict(&entry)
+    }
+
+    /// Remove the least recently used entries until the cache fits in
+    /// `max_size`. `keep` is never evicted, even if it alone is too large.
+    fn evict(&self, keep: &Path) -> Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let size = dir_size(&path)?;
+            total += size;
+            entries.push((last_used(&path), size, path));
+        }
+        entries.sort();
+        for (_, size, path) in entries {
+            if total <= self.max_size {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            info!("evicting build cache {}", path.display());
+            fs::remove_dir_all(&path)?;
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+/// Hash of `rustc -vV`, which names the commit and host of the toolchain,
+/// and of the paths of `rustc` and `cargo`, since locally built toolchains
+/// all report the same version.
+fn toolchain_key(sysroot: &Sysroot) -> Result<String> {
+    let output = Command::new(&sysroot.rustc)
+        .arg("-vV")
+        .output()
+        .chain_err(|| format!("could not run {}", sysroot.rustc.display()))?;
+    if !output.status.success() {
+        bail!("`{} -vV` failed", sysroot.rustc.display());
+    }
+    let mut hasher = Fnv::new();
+    hasher.write(&output.stdout);
+    hasher.write(sysroot.rustc.to_string_lossy().as_bytes());
+    hasher.write(sysroot.cargo.to_string_lossy().as_bytes());
+    Ok(format!("{:016x}", hasher.0))
+}
+
+fn touch(entry: &Path) -> Result<()> {
+    write!(File::create(entry.join(LAST_USED))?, "{}", Utc::now().timestamp())?;
+    Ok(())
+}
+
+/// Entries without a valid timestamp sort first, and are evicted first.
+fn last_used(entry: &Path) -> i64 {
+    let mut contents = String::new();
+    File::open(entry.join(LAST_USED))
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .ok()
+        .and_then(|_| contents.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    // `-p` keeps the mtimes, which Cargo's freshness checks compare.
+    let output = Command::new("cp")
+        .arg("-r")
+        .arg("-p")
+        .arg("-T")
+        .arg("--")
+        .arg(from)
+        .arg(to)
+        .output()
+        .chain_err(|| "could not spawn cp")?;
+    if !output.status.success() {
+        bail!(
+            "copying {} to {} failed: {}",
+            from.display(),
+            to.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+fn dir_size(dir: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
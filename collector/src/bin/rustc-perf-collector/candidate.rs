@@ -0,0 +1,351 @@
+//! Evaluating a crates.io crate as a candidate for the benchmark suite.
+//!
+//! The crate is downloaded, given the makefile of a typical benchmark and
+//! benchmarked with the two newest toolchains that have results in the
+//! output repository. The report shows how long the candidate takes to
+//! build, how noisy its wall time is, and which benchmarks of the suite
+//! respond to the change between the two toolchains like it does: a
+//! candidate that closely follows an existing benchmark adds little to the
+//! suite.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+
+use collector::{CommitData, Patch};
+use reqwest;
+use rust_sysroot::git::Commit as GitCommit;
+use serde_json::Value;
+
+use config::Config;
+use errors::*;
+use execute::{Benchmark, BenchmarkConfig};
+use outrepo::Repo;
+use toolchain;
+
+/// The scenarios of a typical incremental benchmark.
+const MAKEFILE: &str = "\
+.PHONY: all all@010-baseline all@020-incr-from-scratch all@030-incr-no-change patches
+
+INCREMENTAL_FLAGS=-Z incremental=incr
+
+all@010-baseline:
+\t$(CARGO) rustc $(CARGO_OPTS) -- $(CARGO_RUSTC_OPTS)
+
+all@020-incr-from-scratch:
+\tfind . -name \"*.rs\" | xargs touch
+\tRUSTFLAGS=\"${INCREMENTAL_FLAGS}\" $(CARGO) rustc $(CARGO_OPTS) -- \\
+\t  $(CARGO_RUSTC_OPTS) -Z incremental-info
+
+all@030-incr-no-change:
+\tfind . -name \"*.rs\" | xargs touch
+\tRUSTFLAGS=\"${INCREMENTAL_FLAGS}\" $(CARGO) rustc $(CARGO_OPTS) -- \\
+\t  $(CARGO_RUSTC_OPTS) -Z incremental-info
+
+patches:
+\t@echo \"@010-baseline @020-incr-from-scratch @030-incr-no-change\"
+";
+
+/// Number of existing benchmarks to list as the most similar.
+const SIMILAR: usize = 5;
+
+/// Download version `version` of the crate `name`, or its newest version,
+/// into `dir` and set it up as a benchmark.
+pub fn download(name: &str, version: Option<&str>, dir: &Path) -> Result<Benchmark> {
+    let version = match version {
+        Some(version) => version.to_string(),
+        None => {
+            let url = format!("https://crates.io/api/v1/crates/{}", name);
+            let mut response = reqwest::get(&url)?;
+            if !response.status().is_success() {
+                bail!("no crate {} on crates.io: {} returned {}", name, url, response.status());
+            }
+            let krate: Value = response.json()?;
+            match krate["crate"]["max_version"].as_str() {
+                Some(version) => version.to_string(),
+                None => bail!("{} returned no version of {}", url, name),
+            }
+        }
+    };
+
+    let url = format!("https://crates.io/api/v1/crates/{}/{}/download", name, version);
+    info!("downloading {}", url);
+    let mut response = reqwest::get(&url)?;
+    if !response.status().is_success() {
+        bail!("no version {} of {}: {} returned {}", version, name, url, response.status());
+    }
+    let tarball = dir.join("crate.tar.gz");
+    io::copy(&mut response, &mut File::create(&tarball)?)?;
+
+    // The tarball contains `<name>-<version>/`.
+    let path = dir.join(format!("{}-{}", name, version));
+    fs::create_dir(&path)?;
+    let status = Command::new("tar")
+        .arg("-xzf")
+        .arg(&tarball)
+        .arg("-C")
+        .arg(&path)
+        .arg("--strip-components=1")
+        .status()
+        .chain_err(|| "could not spawn tar")?;
+    if !status.success() {
+        bail!("failed to extract {}", url);
+    }
+    File::create(path.join("makefile"))?.write_all(MAKEFILE.as_bytes())?;
+
+    // Published library crates don't come with a lock file. Both toolchains
+    // have to build the same dependencies.
+    let status = Command::new("cargo")
+        .current_dir(&path)
+        .arg("generate-lockfile")
+        .status()
+        .chain_err(|| "could not spawn cargo")?;
+    if !status.success() {
+        bail!("failed to resolve the dependencies of {} {}", name, version);
+    }
+
+    let mut config = BenchmarkConfig::default();
+    config.description = Some(format!("{} {} from crates.io", name, version));
+    // `cargo rustc` only takes rustc arguments for a single target.
+    config.cargo_args = vec![String::from("--lib")];
+    Ok(Benchmark {
+        name: format!("candidate-{}", name),
+        path: path,
+        config: config,
+        scenarios: None,
+    })
+}
+
+/// Benchmark `candidate` with the two newest toolchains with results in
+/// `out_repo` and print the report.
+pub fn analyze(
+    candidate: &Benchmark,
+    commits: &[GitCommit],
+    out_repo: &Repo,
+    config: &Config,
+    preserve_sysroot: bool,
+) -> Result<()> {
+    let mut config = config.clone();
+    // The variance is that of the wall time.
+    config.metrics.insert(String::from("wall-time"));
+
+    let measured = out_repo
+        .times_files()?
+        .into_iter()
+        .filter(|file| file.triple == config.target)
+        .map(|file| file.sha)
+        .collect::<HashSet<_>>();
+    let toolchains = commits
+        .iter()
+        .rev()
+        .filter(|commit| measured.contains(&commit.sha))
+        .take(2)
+        .collect::<Vec<_>>();
+    if toolchains.len() < 2 {
+        bail!("the output repository has results for fewer than two toolchains");
+    }
+    let (a, b) = (toolchains[1], toolchains[0]);
+
+    let mut runs = Vec::new();
+    for commit in &[a, b] {
+        println!("benchmarking {} with {} ({})", candidate.name, commit.sha, commit.date);
+        let sysroot = toolchain::install(commit, &config, preserve_sysroot)?;
+        runs.push(candidate.run_all(&sysroot, &config, None, None)?);
+    }
+    let (runs_a, runs_b) = (&runs[0], &runs[1]);
+
+    println!();
+    println!("toolchain a: {} ({})", a.sha, a.date);
+    println!("toolchain b: {} ({})", b.sha, b.date);
+    println!();
+    println!(
+        "{:<40} {:>16} {:>16} {:>9} {:>10} {:>10} {:>9}",
+        "scenario",
+        "instructions a",
+        "instructions b",
+        "change",
+        "wall a",
+        "wall b",
+        "variance"
+    );
+    for patch_a in runs_a {
+        let patch_b = match find_patch(runs_b, patch_a) {
+            Some(patch) => patch,
+            None => continue,
+        };
+        let run_a = patch_a.run();
+        let run_b = patch_b.run();
+        let instructions_a = run_a.get_stat("instructions:u");
+        let instructions_b = run_b.get_stat("instructions:u");
+        println!(
+            "{:<40} {:>16} {:>16} {:>9} {:>10} {:>10} {:>9}",
+            format!("{} {} {} {}", patch_a.name, patch_a.profile, patch_a.backend, patch_a.std),
+            format_value(instructions_a, 0),
+            format_value(instructions_b, 0),
+            match (instructions_a, instructions_b) {
+                (Some(x), Some(y)) => format!("{:+.2}%", change(x, y)),
+                _ => String::from("-"),
+            },
+            format_value(run_a.get_stat("wall-time"), 3),
+            format_value(run_b.get_stat("wall-time"), 3),
+            match variation(patch_a).or_else(|| variation(patch_b)) {
+                Some(cv) => format!("{:.2}%", cv),
+                None => String::from("-"),
+            }
+        );
+    }
+    println!();
+    println!("variance: coefficient of variation of the wall time over all iterations");
+
+    let changes_a = out_repo.load_commit_data(a, &config.target)?;
+    let changes_b = out_repo.load_commit_data(b, &config.target)?;
+    let similar = similar_benchmarks(runs_a, runs_b, &changes_a, &changes_b);
+    println!();
+    if similar.is_empty() {
+        println!("no existing benchmark has enough results for both toolchains to compare to");
+        return Ok(());
+    }
+    println!("existing benchmarks responding most like it to the change from a to b:");
+    for &(ref name, correlation) in similar.iter().take(SIMILAR) {
+        println!("  {:<40} {:+.3}", name, correlation);
+    }
+    Ok(())
+}
+
+/// The result for the same build as `patch` in `patches`.
+fn find_patch<'a>(patches: &'a [Patch], patch: &Patch) -> Option<&'a Patch> {
+    patches.iter().find(|p| {
+        p.name == patch.name && p.profile == patch.profile && p.backend == patch.backend
+            && p.std == patch.std && p.flags == patch.flags
+            && p.custom_profile == patch.custom_profile
+    })
+}
+
+fn format_value(value: Option<f64>, precision: usize) -> String {
+    match value {
+        Some(value) => format!("{:.*}", precision, value),
+        None => String::from("-"),
+    }
+}
+
+fn change(a: f64, b: f64) -> f64 {
+    100.0 * (b - a) / a
+}
+
+/// The coefficient of variation of the wall time samples of `patch`, in
+/// percent.
+fn variation(patch: &Patch) -> Option<f64> {
+    let samples = &patch.run().stats.iter().find(|s| s.name == "wall-time")?.samples;
+    if samples.len() < 2 {
+        return None;
+    }
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    Some(100.0 * variance.sqrt() / mean)
+}
+
+/// Which of the standard scenarios the patch `name` is, if any. Benchmarks
+/// without incremental patches only have the full build.
+fn scenario(name: &str) -> Option<&'static str> {
+    let patch = match name.find('@') {
+        Some(idx) => &name[idx + 1..],
+        None => return Some("full"),
+    };
+    if patch.ends_with("baseline") {
+        Some("full")
+    } else if patch.ends_with("incr-from-scratch") {
+        Some("incr-full")
+    } else if patch.ends_with("incr-no-change") {
+        Some("incr-unchanged")
+    } else {
+        None
+    }
+}
+
+/// The change between two toolchains of every statistic of every standard
+/// scenario, keyed by the scenario, build and statistic.
+fn changes(a: &[Patch], b: &[Patch]) -> BTreeMap<String, f64> {
+    let mut changes = BTreeMap::new();
+    for patch_a in a {
+        let scenario = match scenario(&patch_a.name) {
+            Some(scenario) => scenario,
+            None => continue,
+        };
+        let patch_b = match find_patch(b, patch_a) {
+            Some(patch) => patch,
+            None => continue,
+        };
+        for stat in &patch_a.run().stats {
+            if let Some(value) = patch_b.run().get_stat(&stat.name) {
+                if stat.cnt > 0.0 {
+                    let key = format!(
+                        "{} {} {} {} {:?} {:?} {}",
+                        scenario,
+                        patch_a.profile,
+                        patch_a.backend,
+                        patch_a.std,
+                        patch_a.flags,
+                        patch_a.custom_profile,
+                        stat.name
+                    );
+                    changes.insert(key, change(stat.cnt, value));
+                }
+            }
+        }
+    }
+    changes
+}
+
+/// The existing benchmarks by how strongly their changes from `data_a` to
+/// `data_b` correlate with the candidate's, most similar first.
+fn similar_benchmarks(
+    candidate_a: &[Patch],
+    candidate_b: &[Patch],
+    data_a: &CommitData,
+    data_b: &CommitData,
+) -> Vec<(String, f64)> {
+    let candidate = changes(candidate_a, candidate_b);
+    let mut similar = Vec::new();
+    for (name, a) in &data_a.benchmarks {
+        let (a, b) = match (a, data_b.benchmarks.get(name)) {
+            (&Ok(ref a), Some(&Ok(ref b))) => (a, b),
+            _ => continue,
+        };
+        let changes = changes(a, b);
+        let (xs, ys): (Vec<f64>, Vec<f64>) = candidate
+            .iter()
+            .filter_map(|(key, &x)| changes.get(key).map(|&y| (x, y)))
+            .unzip();
+        if let Some(r) = correlation(&xs, &ys) {
+            similar.push((name.clone(), r));
+        }
+    }
+    similar.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    similar
+}
+
+/// Pearson's correlation coefficient of `xs` and `ys`, if there are enough
+/// values for it to mean anything.
+fn correlation(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    if xs.len() < 3 {
+        return None;
+    }
+    let n = xs.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+    let mut cov = 0.0;
+    let mut var_x = 0.0;
+    let mut var_y = 0.0;
+    for (x, y) in xs.iter().zip(ys) {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return None;
+    }
+    Some(cov / (var_x * var_y).sqrt())
+}
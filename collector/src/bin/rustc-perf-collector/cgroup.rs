@@ -0,0 +1,233 @@
+//! Running benchmark builds in a cgroup v2 with fixed CPUs and memory.
+//!
+//! Other processes on the machine and the scheduler moving builds between
+//! cores add noise to the measurements, and how much memory rustc gets to
+//! keep depends on what else the machine has to hold. With `"cgroup"`
+//! configured, the cgroup `/sys/fs/cgroup/<name>` is set up before
+//! benchmarking: its `cpuset` is the configured CPUs, its memory is capped
+//! without swap, and, if asked for, the SMT siblings of the CPUs are taken
+//! offline until benchmarking ends. Every build joins the cgroup before it
+//! runs, together with everything it starts.
+//!
+//! The CPUs taken offline are listed in `smt-offline` in the working
+//! directory while they are. A collector killed before it could bring them
+//! back online leaves the file behind, and the next one started there brings
+//! them back.
+//!
+//! This needs write access to the cgroup hierarchy and, for taking CPUs
+//! offline, to `/sys/devices/system/cpu`, i.e. usually root.
+
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use libc;
+
+use config::CgroupConfig;
+use errors::*;
+
+/// Root of the cgroup v2 hierarchy.
+const ROOT: &str = "/sys/fs/cgroup";
+
+/// Where the kernel lists the CPUs and their state.
+const CPUS: &str = "/sys/devices/system/cpu";
+
+/// The CPUs taken offline and not yet brought back online, in the working
+/// directory.
+const OFFLINE: &str = "smt-offline";
+
+/// The cgroup, set up for as long as this is alive.
+pub struct Cgroup {
+    /// The SMT siblings taken offline, to be brought back online
+    offline: Vec<usize>,
+}
+
+impl Cgroup {
+    /// Create or update the cgroup configured in `config` and take the SMT
+    /// siblings of its CPUs offline if configured.
+    pub fn setup(config: &CgroupConfig) -> Result<Cgroup> {
+        let root = Path::new(ROOT);
+        if !root.join("cgroup.controllers").exists() {
+            bail!("{} is not a cgroup v2 hierarchy", ROOT);
+        }
+        write(&root.join("cgroup.subtree_control"), "+cpuset +memory")?;
+        let dir = path(config);
+        if !dir.exists() {
+            fs::create_dir(&dir).chain_err(|| format!("failed to create {}", dir.display()))?;
+        }
+
+        let cpus = config.cpu_list();
+        let mut cgroup = Cgroup { offline: Vec::new() };
+        if config.disable_smt {
+            for &cpu in &cpus {
+                for sibling in siblings(cpu)? {
+                    if sibling == cpu || cgroup.offline.contains(&sibling) {
+                        continue;
+                    }
+                    if cpus.contains(&sibling) {
+                        bail!(
+                            "CPUs {} and {} share a core; configure one CPU per core",
+                            cpu,
+                            sibling
+                        );
+                    }
+                    info!("taking SMT sibling {} of CPU {} offline", sibling, cpu);
+                    // Recorded first, so that it's brought back online even if
+                    // the collector is killed right after.
+                    cgroup.offline.push(sibling);
+                    fs::File::create(OFFLINE)?.write_all(format_cpus(&cgroup.offline).as_bytes())?;
+                    write(&cpu_dir(sibling).join("online"), "0")?;
+                }
+            }
+        }
+
+        write(&dir.join("cpuset.cpus"), &config.cpus)?;
+        if let Some(ref max) = config.memory_max {
+            write(&dir.join("memory.max"), max)?;
+            write(&dir.join("memory.swap.max"), "0")?;
+        }
+        info!("running builds in cgroup {} on CPUs {}", dir.display(), config.cpus);
+        Ok(cgroup)
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        if !self.offline.is_empty() {
+            bring_online(&self.offline);
+        }
+    }
+}
+
+/// Bring back online the CPUs an earlier collector took offline and was
+/// killed before it brought them back.
+pub fn restore_offline() -> Result<()> {
+    if !Path::new(OFFLINE).exists() {
+        return Ok(());
+    }
+    let mut list = String::new();
+    fs::File::open(OFFLINE)?.read_to_string(&mut list)?;
+    let cpus = parse_cpus(&list).chain_err(|| format!("invalid {}", OFFLINE))?;
+    info!("bringing CPUs {} left offline by an earlier run back online", list.trim());
+    bring_online(&cpus);
+    Ok(())
+}
+
+/// Bring `cpus` back online, and forget about them unless that failed.
+fn bring_online(cpus: &[usize]) {
+    let mut failed = false;
+    for &cpu in cpus {
+        if let Err(err) = write(&cpu_dir(cpu).join("online"), "1") {
+            warn!("failed to bring CPU {} back online: {:?}", cpu, err);
+            failed = true;
+        }
+    }
+    if !failed {
+        if let Err(err) = fs::remove_file(OFFLINE) {
+            warn!("failed to remove {}: {:?}", OFFLINE, err);
+        }
+    }
+}
+
+/// Have `command` join the cgroup configured in `config` before it runs.
+pub fn join(command: &mut Command, config: &CgroupConfig) {
+    let procs = path(config).join("cgroup.procs");
+    let procs = CString::new(procs.as_os_str().as_bytes()).unwrap();
+    // Between fork and exec, only async-signal-safe functions may be called.
+    command.before_exec(move || {
+        unsafe {
+            let fd = libc::open(procs.as_ptr(), libc::O_WRONLY);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // `0` is the writing process.
+            let written = libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+            libc::close(fd);
+            if written != 1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    });
+}
+
+/// The CPUs in a list like `0-3,8`, as used by `cpuset.cpus` and sysfs.
+pub fn parse_cpus(list: &str) -> Result<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let invalid = || format!("invalid CPU range `{}`", range);
+        let mut bounds = range.splitn(2, '-');
+        let first = bounds.next().unwrap().trim().parse::<usize>().chain_err(&invalid)?;
+        let last = match bounds.next() {
+            Some(last) => last.trim().parse::<usize>().chain_err(&invalid)?,
+            None => first,
+        };
+        if last < first {
+            bail!("invalid CPU range `{}`", range);
+        }
+        cpus.extend(first..last + 1);
+    }
+    cpus.sort();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// `cpus` as a list in the format `parse_cpus` reads.
+fn format_cpus(cpus: &[usize]) -> String {
+    cpus.iter().map(|cpu| cpu.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn path(config: &CgroupConfig) -> PathBuf {
+    Path::new(ROOT).join(&config.name)
+}
+
+fn cpu_dir(cpu: usize) -> PathBuf {
+    Path::new(CPUS).join(format!("cpu{}", cpu))
+}
+
+/// The CPUs sharing a core with `cpu`, including itself.
+fn siblings(cpu: usize) -> Result<Vec<usize>> {
+    let path = cpu_dir(cpu).join("topology/thread_siblings_list");
+    let mut list = String::new();
+    fs::File::open(&path)
+        .chain_err(|| format!("failed to open {}", path.display()))?
+        .read_to_string(&mut list)?;
+    parse_cpus(&list)
+}
+
+fn write(path: &Path, value: &str) -> Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(value.as_bytes()))
+        .chain_err(|| format!("failed to write `{}` to {}", value, path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cpu_lists() {
+        assert_eq!(parse_cpus("0-3,8").unwrap(), [0, 1, 2, 3, 8]);
+        assert_eq!(parse_cpus(" 5 , 2-3\n").unwrap(), [2, 3, 5]);
+        assert_eq!(parse_cpus("4,1-4,4-4").unwrap(), [1, 2, 3, 4]);
+        assert!(parse_cpus("").unwrap().is_empty());
+        assert!(parse_cpus("\n").unwrap().is_empty());
+        assert!(parse_cpus("3-1").is_err());
+        assert!(parse_cpus("0-").is_err());
+        assert!(parse_cpus("a,1").is_err());
+        assert!(parse_cpus("-1").is_err());
+    }
+
+    #[test]
+    fn format_and_parse_agree() {
+        assert_eq!(format_cpus(&[]), "");
+        assert_eq!(format_cpus(&[7, 3]), "7,3");
+        assert_eq!(parse_cpus(&format_cpus(&[7, 3, 15])).unwrap(), [3, 7, 15]);
+    }
+}
@@ -0,0 +1,87 @@
+//! Progress of benchmarking a commit, persisted after every benchmark so
+//! that a crash or reboot doesn't throw away the benchmarks already done.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde_json;
+
+use collector::Patch;
+use errors::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct State {
+    sha: String,
+    triple: String,
+    /// Successfully benchmarked benchmarks; failures are retried on resume.
+    benchmarks: BTreeMap<String, Vec<Patch>>,
+}
+
+/// Cloning a `Checkpoint` gives another handle to the same file, for use by
+/// concurrent benchmark jobs.
+#[derive(Clone)]
+pub struct Checkpoint {
+    path: PathBuf,
+    state: Arc<Mutex<State>>,
+}
+
+impl Checkpoint {
+    /// Open the checkpoint at `path`. Progress recorded for a different
+    /// commit or triple is discarded.
+    pub fn open(path: PathBuf, sha: &str, triple: &str) -> Checkpoint {
+        let state = File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, State>(file).ok())
+            .and_then(|state| if state.sha == sha && state.triple == triple {
+                info!(
+                    "resuming {} from {} ({} benchmarks done)",
+                    sha,
+                    path.display(),
+                    state.benchmarks.len()
+                );
+                Some(state)
+            } else {
+                None
+            })
+            .unwrap_or_else(|| State {
+                sha: sha.to_string(),
+                triple: triple.to_string(),
+                benchmarks: BTreeMap::new(),
+            });
+        Checkpoint {
+            path: path,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// The results recorded for `benchmark`, if it completed.
+    pub fn get(&self, benchmark: &str) -> Option<Vec<Patch>> {
+        self.state.lock().unwrap().benchmarks.get(benchmark).cloned()
+    }
+
+    /// Record the successful results of `benchmark`.
+    pub fn record(&self, benchmark: &str, patches: &[Patch]) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .benchmarks
+            .insert(benchmark.to_string(), patches.to_vec());
+
+        // Write to a temporary file first, so that a crash while writing
+        // doesn't corrupt the checkpoint.
+        let tmp = self.path.with_extension("tmp");
+        serde_json::to_writer(&mut File::create(&tmp)?, &*state)?;
+        fs::rename(&tmp, &self.path)
+            .chain_err(|| format!("failed to write checkpoint {}", self.path.display()))?;
+        Ok(())
+    }
+
+    /// Delete the checkpoint once the results are stored elsewhere.
+    pub fn finish(self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
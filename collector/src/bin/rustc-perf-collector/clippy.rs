@@ -0,0 +1,16 @@
+//! Finding `clippy-driver` for the `clippy` profile.
+
+use std::path::{Path, PathBuf};
+
+use rust_sysroot::sysroot::Sysroot;
+
+/// `clippy-driver` lives next to the sysroot's `rustc`. For local toolchains
+/// it has to be built and copied there by hand.
+pub fn driver(sysroot: &Sysroot) -> PathBuf {
+    sysroot.rustc.with_file_name("clippy-driver")
+}
+
+/// The sysroot directory, which `clippy-driver` can't find on its own.
+pub fn sysroot_dir(sysroot: &Sysroot) -> &Path {
+    sysroot.rustc.parent().and_then(|bin| bin.parent()).unwrap()
+}
@@ -0,0 +1,773 @@
+//! Per-machine collector configuration.
+//!
+//! Machines differ in what they can measure (e.g. no perf counters on some
+//! ARM machines), so each collector can be given a TOML file declaring what to
+//! gather:
+//!
+//! ```toml
+//! target = "aarch64-unknown-linux-gnu"
+//! backends = ["llvm"]
+//! profiles = ["debug", "clippy"]
+//! std = ["static", "dynamic", "build-std"]
+//! components = ["llvm-tools"]
+//! metrics = ["instructions:u", "max-rss"]
+//! linkers = ["lld", "mold"]
+//! cargo = "/home/me/cargo/target/release/cargo"
+//! collector_id = "perf-arm-1"
+//! machine_class = "ampere-altra"
+//! iterations = 5
+//! wall_time_iterations = 10
+//! warm_full = true
+//! timings = true
+//! llvm_passes = 20
+//! build_timeout = 1800
+//! anomaly_factor = 5
+//! kill_anomalous = true
+//! fail_fast = true
+//! environment = "error"
+//!
+//! [flag_sets]
+//! parallel-8 = "-Zthreads=8"
+//!
+//! [custom_profiles.opt-debug-assertions]
+//! opt-level = 3
+//! debug-assertions = true
+//!
+//! [custom_profiles.opt-lto-thin]
+//! opt-level = 3
+//! lto = "thin"
+//!
+//! [cgroup]
+//! cpus = "2-5"
+//! disable_smt = true
+//! memory_max = "16G"
+//!
+//! [[sites]]
+//! url = "https://perf.rust-lang.org"
+//! priority = 1
+//!
+//! [[sites]]
+//! url = "https://staging.perf.rust-lang.org"
+//! token_env = "STAGING_TOKEN"
+//! output_repo = "/home/me/staging-timing"
+//! ```
+//!
+//! All keys are optional and default to the behavior without a config file.
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use serde_json::Value;
+use toml;
+
+use collector::{Backend, Profile, Std};
+use cgroup;
+use errors::{Result, ResultExt};
+use toolchain::EXTRA_COMPONENTS;
+
+/// All statistics the collector knows how to gather. All but `max-rss`,
+/// `wall-time`, the `PROCESS_METRICS`, the `RSS_METRICS`, the
+/// `QUERY_METRICS`, the `SECTION_METRICS`, the `ARTIFACT_METRICS` and the
+/// `CGU_METRICS` are `perf stat` events.
+pub const ALL_METRICS: &[&str] = &[
+    "instructions:u",
+    "instructions:rustc",
+    "instructions:linker",
+    "instructions:deps",
+    "instructions:build-script",
+    "instructions:cargo",
+    "cycles:u",
+    "task-clock",
+    "cpu-clock",
+    "faults",
+    "max-rss",
+    "rustc-max-rss",
+    "linker-max-rss",
+    "wall-time",
+    "query-count",
+    "query-cache-hit-ratio",
+    "incr-comp-time",
+    "macro-expansion",
+    "warning-count",
+    "diagnostics-time",
+    "frontend-time",
+    "backend-time",
+    "link-time",
+    "artifact-size",
+    "text-size",
+    "debuginfo-size",
+    "incr-cache-size",
+    "cgu-count",
+    "cgu-size-min",
+    "cgu-size-median",
+    "cgu-size-max",
+];
+
+/// The instructions of a measured build by process: the measured rustc
+/// without the linker it runs, that linker, the rustc runs of the other
+/// crates (dependencies, build scripts and proc macros), the runs of the
+/// build scripts, and everything else, i.e. Cargo and make. `instructions:u`
+/// covers only the first two. Proc macros run inside rustc, so they count as
+/// rustc.
+/// These need `instructions:u`, and the extra `perf stat` runs add a little
+/// to the other statistics, so they are opt-in.
+pub const PROCESS_METRICS: &[&str] = &[
+    "instructions:rustc",
+    "instructions:linker",
+    "instructions:deps",
+    "instructions:build-script",
+    "instructions:cargo",
+];
+
+/// The peak memory in kilobytes of rustc and of the linker it runs, which
+/// `max-rss` doesn't tell apart. `rustc-fake` runs the linker itself to
+/// measure these, which adds a little to the other statistics, so they are
+/// opt-in.
+pub const RSS_METRICS: &[&str] = &["rustc-max-rss", "linker-max-rss"];
+
+/// Statistics derived from rustc's self-profile data, and the number of
+/// warnings emitted during the same build. These need an extra, unmeasured
+/// build per patch, so they are only gathered if configured explicitly.
+pub const QUERY_METRICS: &[&str] = &[
+    "query-count",
+    "query-cache-hit-ratio",
+    "incr-comp-time",
+    "macro-expansion",
+    "warning-count",
+    "diagnostics-time",
+];
+
+/// Seconds spent in each compiler stage according to `-Ztime-passes`. Like
+/// the `QUERY_METRICS`, these need an extra build and are opt-in.
+pub const SECTION_METRICS: &[&str] = &["frontend-time", "backend-time", "link-time"];
+
+/// Sizes in bytes of the files rustc wrote, and of the code and debuginfo
+/// sections in them. `rustc-fake` reports these itself, after the build.
+pub const ARTIFACT_METRICS: &[&str] = &["artifact-size", "text-size", "debuginfo-size"];
+
+/// The number of codegen units and the distribution of the sizes of their
+/// object files in bytes, to confirm changes to CGU partitioning. Like the
+/// `QUERY_METRICS`, these need an extra build and are opt-in.
+pub const CGU_METRICS: &[&str] = &["cgu-count", "cgu-size-min", "cgu-size-median", "cgu-size-max"];
+
+/// Statistics too noisy to take the minimum of over the iterations of a
+/// benchmark. The median of the iterations' values that aren't outliers is
+/// reported instead.
+pub const SAMPLED_METRICS: &[&str] = &["wall-time"];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Target triple of the toolchains to benchmark
+    #[serde(default = "default_target")]
+    pub target: String,
+
+    /// Codegen backends to benchmark
+    #[serde(default = "default_backends")]
+    pub backends: Vec<Backend>,
+
+    /// What to build the benchmarks with
+    #[serde(default = "default_profiles")]
+    pub profiles: Vec<Profile>,
+
+    /// Where to take the standard library from
+    #[serde(default = "default_std")]
+    pub std: Vec<Std>,
+
+    /// Toolchain components to install besides rustc, the standard library
+    /// and Cargo. Those the profiles and std builds need are added anyway.
+    #[serde(default)]
+    pub components: Vec<String>,
+
+    /// Statistics this machine gathers
+    #[serde(default = "default_metrics")]
+    pub metrics: BTreeSet<String>,
+
+    /// Named sets of extra rustc flags. Every benchmark is run once without
+    /// extra flags and once with each of these.
+    #[serde(default)]
+    pub flag_sets: BTreeMap<String, String>,
+
+    /// Linkers to also link the benchmarks with, each recorded as a flag set
+    /// of its own, with the `link-time` metric
+    #[serde(default)]
+    pub linkers: Vec<Linker>,
+
+    /// Named settings of the Cargo profile the benchmarks build with, e.g.
+    /// `{"lto": "thin"}`. Every benchmark is run once with the settings of
+    /// its manifest and once with each of these, for each flag set.
+    #[serde(default)]
+    pub custom_profiles: BTreeMap<String, BTreeMap<String, Value>>,
+
+    /// Build benchmarks in a bubblewrap sandbox without network access
+    #[serde(default)]
+    pub sandbox: bool,
+
+    /// Self-profile every benchmark and record its per-query timings, even
+    /// if none of the `QUERY_METRICS` is configured
+    #[serde(default)]
+    pub self_profile: bool,
+
+    /// Cargo to build the benchmarks with instead of the toolchain's own,
+    /// e.g. a locally built one. Only used with local toolchains; those of
+    /// commits come with the Cargo they were released with.
+    #[serde(default)]
+    pub cargo: Option<PathBuf>,
+
+    /// Identifies this collector in the results; defaults to the host name
+    #[serde(default)]
+    pub collector_id: Option<String>,
+
+    /// Results are only compared with those of machines of the same class;
+    /// defaults to the CPU model
+    #[serde(default)]
+    pub machine_class: Option<String>,
+
+    /// How often to build each benchmark. The value of every iteration is
+    /// recorded; the minimum is reported.
+    #[serde(default = "default_iterations")]
+    pub iterations: usize,
+
+    /// How often to build each benchmark at least when gathering
+    /// `wall-time`. The builds beyond `iterations` only add samples of the
+    /// `SAMPLED_METRICS`.
+    #[serde(default = "default_wall_time_iterations")]
+    pub wall_time_iterations: usize,
+
+    /// Also measure each benchmark's full build repeated with a warm target
+    /// directory, recorded as the `@warm-full` patch
+    #[serde(default)]
+    pub warm_full: bool,
+
+    /// Record when each crate of every patch's build, dependencies included,
+    /// is compiled, in an extra build
+    #[serde(default)]
+    pub timings: bool,
+
+    /// Record the times of this many of the slowest LLVM passes of every
+    /// patch's own crate, in an extra build with `-Ztime-llvm-passes`
+    #[serde(default)]
+    pub llvm_passes: Option<usize>,
+
+    /// Seconds after which a build of a benchmark is killed and the
+    /// benchmark recorded as failed
+    #[serde(default = "default_build_timeout")]
+    pub build_timeout: u64,
+
+    /// A measured build taking this many times as long as it typically does
+    /// is reported as an anomaly
+    #[serde(default = "default_anomaly_factor")]
+    pub anomaly_factor: f64,
+
+    /// Kill anomalous builds as soon as they exceed their limit, recording
+    /// the benchmark as failed, instead of letting them finish
+    #[serde(default)]
+    pub kill_anomalous: bool,
+
+    /// Stop a run at the first benchmark that fails, instead of recording
+    /// the failure and going on with the others
+    #[serde(default)]
+    pub fail_fast: bool,
+
+    /// Run the builds in a cgroup with fixed CPUs and memory
+    #[serde(default)]
+    pub cgroup: Option<CgroupConfig>,
+
+    /// What to do about compiler caches and job limits in the environment
+    #[serde(default)]
+    pub environment: EnvironmentPolicy,
+
+    /// Sites whose queues `process_queue` claims commits from, unless given
+    /// one with `--site`
+    #[serde(default)]
+    pub sites: Vec<SiteConfig>,
+}
+
+/// What to do about variables in the environment that would affect the
+/// builds, see the `environment` module.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvironmentPolicy {
+    /// Remove them, with a warning
+    Sanitize,
+    /// Refuse to benchmark
+    Error,
+}
+
+impl Default for EnvironmentPolicy {
+    fn default() -> EnvironmentPolicy {
+        EnvironmentPolicy::Sanitize
+    }
+}
+
+/// A linker other than the C compiler's default, chosen with `-fuse-ld`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Linker {
+    Lld,
+    Mold,
+    Gold,
+}
+
+impl Linker {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Linker::Lld => "lld",
+            Linker::Mold => "mold",
+            Linker::Gold => "gold",
+        }
+    }
+
+    /// The name of the flag set the builds with this linker are recorded
+    /// as, e.g. `linker-lld`.
+    pub fn flag_set(&self) -> String {
+        format!("linker-{}", self.name())
+    }
+
+    /// The executable the C compiler runs for `-fuse-ld`.
+    fn executable(&self) -> String {
+        format!("ld.{}", self.name())
+    }
+}
+
+/// A site to claim commits from, see `queue::next_of`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SiteConfig {
+    /// e.g. `https://perf.rust-lang.org`
+    pub url: String,
+
+    /// The environment variable holding this site's collector token
+    #[serde(default = "default_token_env")]
+    pub token_env: String,
+
+    /// Sites are asked for work highest priority first; a site is only
+    /// asked if those before it have nothing queued
+    #[serde(default)]
+    pub priority: i32,
+
+    /// The output repository this site reads its results from, if not the
+    /// one given with `--output-repo`
+    #[serde(default)]
+    pub output_repo: Option<PathBuf>,
+}
+
+impl SiteConfig {
+    /// The site at `url`, with its token in `SITE_TOKEN`.
+    pub fn new(url: &str) -> SiteConfig {
+        SiteConfig {
+            url: url.to_string(),
+            token_env: default_token_env(),
+            priority: 0,
+            output_repo: None,
+        }
+    }
+}
+
+/// The cgroup v2 builds run in, see the `cgroup` module.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CgroupConfig {
+    /// Name of the cgroup below the root of the hierarchy
+    #[serde(default = "default_cgroup_name")]
+    pub name: String,
+
+    /// CPUs to run the builds on, in `cpuset.cpus` format, e.g. `2-5,8`
+    pub cpus: String,
+
+    /// Take the SMT siblings of `cpus` offline while benchmarking, so that
+    /// nothing else runs on their cores
+    #[serde(default)]
+    pub disable_smt: bool,
+
+    /// Memory the builds may use, in `memory.max` format, e.g. `16G`. Swap
+    /// is disabled if set.
+    #[serde(default)]
+    pub memory_max: Option<String>,
+}
+
+impl CgroupConfig {
+    /// The configured CPUs, which were checked when loading the config.
+    pub fn cpu_list(&self) -> Vec<usize> {
+        cgroup::parse_cpus(&self.cpus).unwrap_or_default()
+    }
+}
+
+/// A setting's value as TOML, if it is a number, a boolean or a string that
+/// needs no escaping.
+fn toml_value(value: &Value) -> Option<String> {
+    match *value {
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(ref n) => Some(n.to_string()),
+        Value::String(ref s) if s.chars().all(|c| c.is_alphanumeric() || "-_.".contains(c)) => {
+            Some(format!("\"{}\"", s))
+        }
+        _ => None,
+    }
+}
+
+fn default_target() -> String {
+    String::from("x86_64-unknown-linux-gnu")
+}
+
+fn default_backends() -> Vec<Backend> {
+    vec![Backend::Llvm]
+}
+
+fn default_profiles() -> Vec<Profile> {
+    vec![Profile::Debug]
+}
+
+fn default_iterations() -> usize {
+    3
+}
+
+fn default_std() -> Vec<Std> {
+    vec![Std::Static]
+}
+
+fn default_wall_time_iterations() -> usize {
+    5
+}
+
+fn default_build_timeout() -> u64 {
+    30 * 60
+}
+
+fn default_anomaly_factor() -> f64 {
+    5.0
+}
+
+fn default_token_env() -> String {
+    String::from("SITE_TOKEN")
+}
+
+fn default_cgroup_name() -> String {
+    String::from("rustc-perf")
+}
+
+fn default_metrics() -> BTreeSet<String> {
+    ALL_METRICS
+        .iter()
+        .filter(|m| {
+            !PROCESS_METRICS.contains(m) && !RSS_METRICS.contains(m) && !QUERY_METRICS.contains(m)
+                && !SECTION_METRICS.contains(m) && !CGU_METRICS.contains(m)
+        })
+        .map(|m| m.to_string())
+        .collect()
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            target: default_target(),
+            backends: default_backends(),
+            profiles: default_profiles(),
+            std: default_std(),
+            components: Vec::new(),
+            metrics: default_metrics(),
+            flag_sets: BTreeMap::new(),
+            linkers: Vec::new(),
+            custom_profiles: BTreeMap::new(),
+            sandbox: false,
+            self_profile: false,
+            cargo: None,
+            collector_id: None,
+            machine_class: None,
+            iterations: default_iterations(),
+            wall_time_iterations: default_wall_time_iterations(),
+            warm_full: false,
+            timings: false,
+            llvm_passes: None,
+            build_timeout: default_build_timeout(),
+            anomaly_factor: default_anomaly_factor(),
+            kill_anomalous: false,
+            fail_fast: false,
+            cgroup: None,
+            environment: EnvironmentPolicy::default(),
+            sites: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let mut contents = String::new();
+        File::open(path)
+            .chain_err(|| format!("failed to open config {}", path.display()))?
+            .read_to_string(&mut contents)?;
+        let mut config: Config = toml::from_str(&contents)
+            .chain_err(|| format!("failed to parse config {}", path.display()))?;
+
+        for metric in &config.metrics {
+            if !ALL_METRICS.contains(&&**metric) {
+                bail!("unknown metric `{}` in {}", metric, path.display());
+            }
+        }
+        if config.wants_split_instructions() && !config.metrics.contains("instructions:u") {
+            bail!("the instructions:* metrics need instructions:u in {}", path.display());
+        }
+        if config.backends.is_empty() {
+            bail!("no backends configured in {}", path.display());
+        }
+        if config.profiles.is_empty() {
+            bail!("no profiles configured in {}", path.display());
+        }
+        if config.std.is_empty() {
+            bail!("no std configured in {}", path.display());
+        }
+        for component in &config.components {
+            if !EXTRA_COMPONENTS.contains(&&**component) {
+                bail!("unknown component `{}` in {}", component, path.display());
+            }
+        }
+        if config.sandbox && config.std.contains(&Std::BuildStd) {
+            // The sandbox only has the benchmark's own dependencies.
+            bail!("build-std can't be used with the sandbox in {}", path.display());
+        }
+        if config.flag_sets.keys().any(|name| name.is_empty()) {
+            bail!("flag sets must have a name in {}", path.display());
+        }
+        for linker in &config.linkers {
+            if Command::new(linker.executable()).arg("--version").output().is_err() {
+                bail!("linker {} needs {} in PATH", linker.name(), linker.executable());
+            }
+            if config.flag_sets.contains_key(&linker.flag_set()) {
+                bail!("flag set {} clashes with linker {}", linker.flag_set(), linker.name());
+            }
+            // Only the benchmark's own crate gets the flags, which is all
+            // that is linked with the linker measured.
+            let flags = format!("-Clink-arg=-fuse-ld={}", linker.name());
+            config.flag_sets.insert(linker.flag_set(), flags);
+        }
+        if !config.linkers.is_empty() {
+            config.metrics.insert(String::from("link-time"));
+        }
+        for (name, settings) in &config.custom_profiles {
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || "-_".contains(c)) {
+                bail!("invalid custom profile name `{}` in {}", name, path.display());
+            }
+            if settings.is_empty() {
+                bail!("custom profile `{}` has no settings in {}", name, path.display());
+            }
+            for (key, value) in settings {
+                if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '-') {
+                    bail!("invalid setting `{}` of custom profile `{}`", key, name);
+                }
+                if toml_value(value).is_none() {
+                    bail!(
+                        "setting `{}` of custom profile `{}` must be a number, boolean or \
+                         simple string",
+                        key,
+                        name
+                    );
+                }
+            }
+        }
+        if config.iterations == 0 {
+            bail!("iterations must be at least 1 in {}", path.display());
+        }
+        if config.wall_time_iterations == 0 {
+            bail!("wall_time_iterations must be at least 1 in {}", path.display());
+        }
+        if config.llvm_passes == Some(0) {
+            bail!("llvm_passes must be at least 1 in {}", path.display());
+        }
+        if config.build_timeout == 0 {
+            bail!("build_timeout must be at least 1 in {}", path.display());
+        }
+        if config.anomaly_factor.is_nan() || config.anomaly_factor <= 1.0 {
+            bail!("anomaly_factor must be greater than 1 in {}", path.display());
+        }
+        if let Some(ref cgroup) = config.cgroup {
+            let valid_name = !cgroup.name.is_empty()
+                && cgroup.name.chars().all(|c| c.is_alphanumeric() || "-_".contains(c));
+            if !valid_name {
+                bail!("invalid cgroup name `{}` in {}", cgroup.name, path.display());
+            }
+            match cgroup::parse_cpus(&cgroup.cpus) {
+                Ok(ref cpus) if !cpus.is_empty() => {}
+                _ => bail!("invalid cgroup cpus `{}` in {}", cgroup.cpus, path.display()),
+            }
+        }
+        for site in &config.sites {
+            if !site.url.starts_with("http://") && !site.url.starts_with("https://") {
+                bail!("invalid site url `{}` in {}", site.url, path.display());
+            }
+            if site.token_env.is_empty() {
+                bail!("site {} has an empty token_env in {}", site.url, path.display());
+            }
+        }
+        if let Some(ref cargo) = config.cargo {
+            if !cargo.is_file() {
+                bail!("cargo {} in {} doesn't exist", cargo.display(), path.display());
+            }
+        }
+        Ok(config)
+    }
+
+    /// The configured metrics that are `perf stat` events, in `perf stat -e`
+    /// format.
+    pub fn perf_events(&self) -> String {
+        self.metrics
+            .iter()
+            .filter(|m| {
+                *m != "max-rss" && *m != "wall-time" && !PROCESS_METRICS.contains(&&***m)
+                    && !RSS_METRICS.contains(&&***m)
+                    && !QUERY_METRICS.contains(&&***m)
+                    && !SECTION_METRICS.contains(&&***m)
+                    && !ARTIFACT_METRICS.contains(&&***m)
+                    && !CGU_METRICS.contains(&&***m)
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// How often to build each benchmark; only the first `iterations` builds
+    /// count for the metrics other than the `SAMPLED_METRICS`.
+    pub fn iterations(&self) -> usize {
+        if self.metrics.contains("wall-time") {
+            cmp::max(self.iterations, self.wall_time_iterations)
+        } else {
+            self.iterations
+        }
+    }
+
+    /// How long a single build of a benchmark may take.
+    pub fn build_timeout(&self) -> Duration {
+        Duration::from_secs(self.build_timeout)
+    }
+
+    /// The flag sets to run each backend with `profile` with: `None` for no
+    /// extra flags, followed by the configured flag sets. Those of the
+    /// `linkers` are left out for clippy, which doesn't link.
+    pub fn flag_set_names(&self, profile: Profile) -> Vec<Option<&str>> {
+        let mut names = vec![None];
+        names.extend(
+            self.flag_sets
+                .keys()
+                .filter(|name| {
+                    profile != Profile::Clippy
+                        || !self.linkers.iter().any(|linker| linker.flag_set() == **name)
+                })
+                .map(|name| Some(&**name)),
+        );
+        names
+    }
+
+    /// The custom profiles to run each flag set with: `None` for the Cargo
+    /// profile of the benchmark's manifest, followed by the configured
+    /// custom profiles.
+    pub fn custom_profile_names(&self) -> Vec<Option<&str>> {
+        let mut names = vec![None];
+        names.extend(self.custom_profiles.keys().map(|name| Some(&**name)));
+        names
+    }
+
+    /// The Cargo arguments applying the settings of the custom profile
+    /// `name` to both the `dev` and `release` profiles, whichever the
+    /// benchmark builds with. They are expanded by `make`, and so quoted for
+    /// the shell.
+    pub fn custom_profile_opts(&self, name: &str) -> String {
+        let mut opts = Vec::new();
+        for (key, value) in &self.custom_profiles[name] {
+            // Checked when loading the config.
+            let value = toml_value(value).unwrap();
+            for profile in &["dev", "release"] {
+                opts.push(format!("--config 'profile.{}.{}={}'", profile, key, value));
+            }
+        }
+        opts.join(" ")
+    }
+
+    /// Whether benchmarks need an extra, self-profiled build: for any of the
+    /// `QUERY_METRICS`, or for the query timings alone.
+    pub fn wants_query_stats(&self) -> bool {
+        self.self_profile || QUERY_METRICS.iter().any(|m| self.metrics.contains(*m))
+    }
+
+    /// Whether any of the `-Ztime-passes` based `SECTION_METRICS` is
+    /// configured.
+    pub fn wants_section_stats(&self) -> bool {
+        SECTION_METRICS.iter().any(|m| self.metrics.contains(*m))
+    }
+
+    /// Whether any of the `PROCESS_METRICS` is configured.
+    pub fn wants_split_instructions(&self) -> bool {
+        PROCESS_METRICS.iter().any(|m| self.metrics.contains(*m))
+    }
+
+    /// Whether any of the `RSS_METRICS` is configured.
+    pub fn wants_split_rss(&self) -> bool {
+        RSS_METRICS.iter().any(|m| self.metrics.contains(*m))
+    }
+
+    /// Whether any of the `CGU_METRICS` is configured.
+    pub fn wants_cgu_stats(&self) -> bool {
+        CGU_METRICS.iter().any(|m| self.metrics.contains(*m))
+    }
+
+    /// The extra, unmeasured builds of every patch needed for the configured
+    /// statistics, named after the tool `rustc-fake` runs them with.
+    pub fn extra_builds(&self) -> Vec<&'static str> {
+        let mut builds = Vec::new();
+        if self.wants_query_stats() {
+            builds.push("self-profile");
+        }
+        if self.wants_section_stats() {
+            builds.push("time-passes");
+        }
+        if self.wants_cgu_stats() {
+            builds.push("cgu-sizes");
+        }
+        if self.timings {
+            builds.push("timings");
+        }
+        if self.llvm_passes.is_some() {
+            builds.push("llvm-passes");
+        }
+        builds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_toml() {
+        let config: Config = toml::from_str(
+            r#"
+            backends = ["llvm"]
+            std = ["static", "build-std"]
+            metrics = ["instructions:u", "wall-time"]
+            anomaly_factor = 5
+            environment = "error"
+
+            [custom_profiles.opt-lto-thin]
+            opt-level = 3
+            lto = "thin"
+
+            [[sites]]
+            url = "https://perf.rust-lang.org"
+            priority = 1
+            "#,
+        ).unwrap();
+        assert_eq!(config.backends, vec![Backend::Llvm]);
+        assert_eq!(config.std, vec![Std::Static, Std::BuildStd]);
+        assert_eq!(config.metrics.len(), 2);
+        assert_eq!(config.anomaly_factor, 5.0);
+        assert_eq!(config.environment, EnvironmentPolicy::Error);
+        assert_eq!(config.iterations, default_iterations());
+        assert_eq!(
+            config.custom_profile_opts("opt-lto-thin"),
+            "--config 'profile.dev.lto=\"thin\"' --config 'profile.release.lto=\"thin\"' \
+             --config 'profile.dev.opt-level=3' --config 'profile.release.opt-level=3'"
+        );
+        assert_eq!(config.sites[0].token_env, "SITE_TOKEN");
+        assert_eq!(config.sites[0].priority, 1);
+    }
+}
@@ -0,0 +1,93 @@
+//! How long each build of each benchmark typically takes on this machine, to
+//! notice builds that take far longer, e.g. because rustc hangs with one
+//! toolchain, instead of silently stalling collection until the build
+//! timeout.
+//!
+//! A build is identified by its patch, profile, backend, std and flag set.
+//! The durations of its most recent successful builds are persisted after
+//! every build.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde_json;
+
+use collector::{Backend, Profile, Std};
+use errors::*;
+
+/// How many durations of each build are kept.
+const HISTORY: usize = 10;
+
+/// Builds with fewer durations recorded have no typical duration yet.
+const MIN_SAMPLES: usize = 3;
+
+/// Cloning `Durations` gives another handle to the same file, for use by
+/// concurrent benchmark jobs.
+#[derive(Clone)]
+pub struct Durations {
+    path: PathBuf,
+    /// Seconds, oldest first
+    builds: Arc<Mutex<BTreeMap<String, Vec<f64>>>>,
+}
+
+impl Durations {
+    /// Open the durations recorded at `path`. Unreadable ones are discarded.
+    pub fn open(path: PathBuf) -> Durations {
+        let builds = File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default();
+        Durations {
+            path: path,
+            builds: Arc::new(Mutex::new(builds)),
+        }
+    }
+
+    /// The median of the recorded durations of `build`, in seconds.
+    pub fn typical(&self, build: &str) -> Option<f64> {
+        let builds = self.builds.lock().unwrap();
+        let mut seconds = builds.get(build)?.clone();
+        if seconds.len() < MIN_SAMPLES {
+            return None;
+        }
+        seconds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Some(seconds[seconds.len() / 2])
+    }
+
+    /// Record that `build` took `seconds`.
+    pub fn record(&self, build: &str, seconds: f64) -> Result<()> {
+        let mut builds = self.builds.lock().unwrap();
+        {
+            let history = builds.entry(build.to_string()).or_insert_with(Vec::new);
+            history.push(seconds);
+            let excess = history.len().saturating_sub(HISTORY);
+            history.drain(..excess);
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        serde_json::to_writer(&mut File::create(&tmp)?, &*builds)?;
+        fs::rename(&tmp, &self.path)
+            .chain_err(|| format!("failed to write durations {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// Identifies the builds of the patch `name` with the given profile, backend,
+/// std, flag set and custom profile.
+pub fn build_key(
+    name: &str,
+    profile: Profile,
+    backend: Backend,
+    std: Std,
+    flags: Option<&str>,
+    custom_profile: Option<&str>,
+) -> String {
+    let key = format!("{} {} {} {} {}", name, profile, backend, std, flags.unwrap_or("-"));
+    // Keys of builds without a custom profile predate them.
+    match custom_profile {
+        Some(custom_profile) => format!("{} {}", key, custom_profile),
+        None => key,
+    }
+}
@@ -0,0 +1,129 @@
+//! Keeping the environment the collector was started in from skewing the
+//! builds it measures.
+//!
+//! A compiler cache like sccache or ccache wrapping rustc or the C compiler
+//! serves builds from its cache instead of running them, and job limits like
+//! `CARGO_BUILD_JOBS` or `MAKEFLAGS` change how much of a build runs in
+//! parallel. Every build inherits the collector's environment, so these are
+//! checked for before benchmarking starts. By default they are removed from
+//! the environment with a warning; with `"environment": "error"` configured,
+//! the collector refuses to run instead. Wrappers configured in Cargo's own
+//! config files can't be removed and are always an error.
+//!
+//! The variables that are left and can affect builds are hashed, and the
+//! hash is recorded with the machine, so that runs in differing environments
+//! can be told apart.
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use config::EnvironmentPolicy;
+use errors::*;
+use manifest::Fnv;
+
+/// Variables that change how builds run, none of which the collector sets
+/// itself.
+const CONTAMINATING: &[&str] = &[
+    "RUSTC_WRAPPER",
+    "RUSTC_WORKSPACE_WRAPPER",
+    "CARGO_BUILD_RUSTC_WRAPPER",
+    "CARGO_BUILD_RUSTC_WORKSPACE_WRAPPER",
+    "CARGO_BUILD_JOBS",
+    "CARGO_INCREMENTAL",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "CARGO_MAKEFLAGS",
+    "MAKEFLAGS",
+    "MFLAGS",
+    "RUSTFLAGS",
+];
+
+/// Prefixes of the settings of compiler caches.
+const CACHE_PREFIXES: &[&str] = &["SCCACHE_", "CCACHE_"];
+
+/// Variables naming a compiler, which may be a cache wrapping one, e.g.
+/// `CC="ccache gcc"`.
+const COMPILERS: &[&str] = &["CC", "CXX", "RUSTC", "RUSTC_LINKER", "CARGO_BUILD_RUSTC"];
+
+/// Prefixes of the variables that may affect builds and are hashed.
+const HASHED_PREFIXES: &[&str] = &[
+    "CARGO", "RUST", "CC", "CXX", "CFLAGS", "CXXFLAGS", "LDFLAGS", "LD_", "PATH", "MAKE", "LANG",
+    "LC_",
+];
+
+/// Remove the variables contaminating builds from the environment, or fail
+/// if `policy` says so.
+pub fn sanitize(policy: EnvironmentPolicy) -> Result<()> {
+    if let Some(config) = cargo_config_wrapper() {
+        bail!(
+            "{} configures a rustc wrapper, which would wrap the benchmarked rustc; remove it",
+            config.display()
+        );
+    }
+    let found = contaminants();
+    if found.is_empty() {
+        return Ok(());
+    }
+    if policy == EnvironmentPolicy::Error {
+        let found = found
+            .iter()
+            .map(|&(ref name, ref value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>();
+        bail!("the environment would affect the builds: {}", found.join(", "));
+    }
+    for (name, value) in found {
+        warn!("removing {}={} from the environment", name, value);
+        env::remove_var(name);
+    }
+    Ok(())
+}
+
+/// Hash of the variables that may affect builds, as hex.
+pub fn hash() -> String {
+    let mut vars = env::vars()
+        .filter(|&(ref name, _)| HASHED_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+        .collect::<Vec<_>>();
+    vars.sort();
+    let mut hasher = Fnv::new();
+    for (name, value) in vars {
+        hasher.write(name.as_bytes());
+        hasher.write(b"=");
+        hasher.write(value.as_bytes());
+        hasher.write(b"\n");
+    }
+    format!("{:016x}", hasher.0)
+}
+
+fn contaminants() -> Vec<(String, String)> {
+    env::vars()
+        .filter(|&(ref name, ref value)| {
+            CONTAMINATING.contains(&&**name)
+                || CACHE_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+                || (COMPILERS.contains(&&**name)
+                    && (value.contains("sccache") || value.contains("ccache")))
+        })
+        .collect()
+}
+
+/// The Cargo config file setting `build.rustc-wrapper`, if any. Only the
+/// config in Cargo's home is checked; the benchmarks have none of their own.
+fn cargo_config_wrapper() -> Option<PathBuf> {
+    let home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|home| home.join(".cargo")))?;
+    for name in &["config", "config.toml"] {
+        let path = home.join(name);
+        let mut contents = String::new();
+        if File::open(&path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .is_err()
+        {
+            continue;
+        }
+        if contents.lines().any(|line| line.trim_left().starts_with("rustc-wrapper")) {
+            return Some(path);
+        }
+    }
+    None
+}
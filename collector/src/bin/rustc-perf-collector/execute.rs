@@ -1,88 +1,481 @@
 //! Execute benchmarks in a sysroot.
 
 use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::str;
-use std::collections::BTreeMap;
+use std::str::{self, FromStr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::collections::{BTreeMap, HashMap};
 
+use serde_json;
 use tempdir::TempDir;
 
-use collector::{Patch, Run, Stat};
+use collector::{Backend, Category, CrateTiming, DurationAnomaly, LlvmPassTiming, Name, Patch,
+                Profile, QueryTiming, Run, Stat, Std};
 
-use errors::{Result, ResultExt};
+use cache::Cache;
+use cgroup;
+use clippy;
+use config::{Config, SAMPLED_METRICS};
+use durations::{self, Durations};
+use llvm_lines;
+use memory;
+use perf_record;
+use sandbox;
+use supervise;
+use errors::{Error, Result, ResultExt};
 use rust_sysroot::sysroot::Sysroot;
 
+#[derive(Debug, Clone)]
 pub struct Benchmark {
     pub name: String,
     pub path: PathBuf,
+    pub config: BenchmarkConfig,
+    /// Only run this many of the benchmark's patches, to fit a time budget
+    pub scenarios: Option<usize>,
+}
+
+/// Per-benchmark settings, read from `perf-config.json` in the benchmark's
+/// directory. The file is optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BenchmarkConfig {
+    #[serde(default)]
+    pub category: Category,
+
+    /// What the benchmark is and why it is in the suite, for the site's
+    /// catalog of benchmarks
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Environment variables to build the benchmark with
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Cargo features of the benchmarked crate to enable
+    #[serde(default)]
+    pub features: Vec<String>,
+
+    #[serde(default, rename = "no-default-features")]
+    pub no_default_features: bool,
+
+    /// Extra arguments to `cargo rustc`
+    #[serde(default, rename = "cargo-args")]
+    pub cargo_args: Vec<String>,
+
+    /// Only check the benchmark, like `cargo check`, without generating code
+    #[serde(default)]
+    pub check: bool,
+
+    /// Named subsets of the benchmark's workspace, with the Cargo arguments
+    /// selecting their packages, e.g. `"parser": ["-p", "syntax"]`. Each
+    /// subset is checked as a benchmark of its own, `<benchmark>:<subset>`.
+    #[serde(default)]
+    pub subsets: BTreeMap<String, Vec<String>>,
+}
+
+/// Environment variables the collector and the makefiles set themselves.
+const RESERVED_ENV: &[&str] = &[
+    "CARGO",
+    "CARGO_OPTS",
+    "CARGO_RUSTC_OPTS",
+    "RUSTC",
+    "RUSTC_REAL",
+    "RUSTFLAGS",
+    "SYSROOT",
+    "USE_PERF",
+];
+
+impl BenchmarkConfig {
+    pub fn load(dir: &Path) -> Result<BenchmarkConfig> {
+        let path = dir.join("perf-config.json");
+        if !path.exists() {
+            return Ok(BenchmarkConfig::default());
+        }
+        let file = File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+        let config: BenchmarkConfig = serde_json::from_reader(file)
+            .chain_err(|| format!("failed to parse {}", path.display()))?;
+
+        for name in config.env.keys() {
+            if name.is_empty() || name.contains('=') {
+                bail!("invalid environment variable `{}` in {}", name, path.display());
+            }
+            if RESERVED_ENV.contains(&&**name) || name.starts_with("PERF_")
+                || name.starts_with("RUSTC_FAKE_")
+            {
+                bail!("{} can't be set in {}", name, path.display());
+            }
+        }
+        // The makefiles pass the features and arguments to Cargo through the
+        // shell, unquoted.
+        let subset_args = config.subsets.values().flat_map(|args| args);
+        for arg in config.features.iter().chain(&config.cargo_args).chain(subset_args) {
+            let valid = !arg.is_empty()
+                && arg.chars().all(|c| c.is_alphanumeric() || "-_=./:,+@".contains(c));
+            if !valid {
+                bail!("invalid cargo argument or feature `{}` in {}", arg, path.display());
+            }
+        }
+        if config.cargo_args.iter().any(|arg| arg == "--") {
+            bail!("`cargo-args` can't contain `--` in {}", path.display());
+        }
+        for (subset, args) in &config.subsets {
+            let valid = !subset.is_empty()
+                && subset.chars().all(|c| c.is_alphanumeric() || "-_".contains(c));
+            if !valid {
+                bail!("invalid subset name `{}` in {}", subset, path.display());
+            }
+            if args.is_empty() || args.iter().any(|arg| arg == "--") {
+                bail!(
+                    "subset `{}` needs Cargo arguments other than `--` in {}",
+                    subset,
+                    path.display()
+                );
+            }
+        }
+        Ok(config)
+    }
+
+    /// Whether any setting changes how the benchmark is built.
+    pub fn affects_build(&self) -> bool {
+        !self.env.is_empty() || !self.features.is_empty() || self.no_default_features
+            || !self.cargo_args.is_empty() || self.check
+    }
+}
+
+/// Suffix of the results of rebuilding a benchmark from scratch, without
+/// incremental compilation, with its dependencies already built and its
+/// sources in the page cache, like after a `cargo update` or a change to a
+/// build script.
+pub const WARM_FULL: &str = "@warm-full";
+
+/// Where a benchmark runs when several benchmarks are run at once.
+pub struct Slot {
+    /// CPUs to pin the run to, in `taskset --cpu-list` format
+    pub cpus: String,
+    /// Held by `rustc-fake` while measuring, so that measurements get
+    /// exclusive use of the hardware counters
+    pub lock: PathBuf,
 }
 
 impl Benchmark {
+    /// The workspace subsets of the benchmark, as benchmarks of their own.
+    pub fn subsets(&self) -> Vec<Benchmark> {
+        self.config
+            .subsets
+            .iter()
+            .map(|(subset, args)| {
+                let mut config = self.config.clone();
+                config.subsets.clear();
+                config.cargo_args.extend(args.iter().cloned());
+                config.check = true;
+                Benchmark {
+                    name: format!("{}:{}", self.name, subset),
+                    path: self.path.clone(),
+                    config: config,
+                    scenarios: self.scenarios,
+                }
+            })
+            .collect()
+    }
+
     pub fn command<P: AsRef<Path>>(&self, sysroot: &Sysroot, path: P) -> Command {
         let mut command = sysroot.command(path);
         command.current_dir(&self.path);
         command
     }
 
-    /// Run a specific benchmark on a specific commit
-    pub fn run(&self, sysroot: &Sysroot) -> Result<Vec<Patch>> {
-        info!("processing {}", self.name);
+    /// Copy the benchmark into a fresh temporary directory, so that builds
+    /// don't pollute the source tree.
+    fn make_temp_dir(&self, sysroot: &Sysroot) -> Result<TempDir> {
+        let tmp_dir = TempDir::new(&format!("rustc-benchmark-{}", self.name))?;
+        info!("temporary directory is {}", tmp_dir.path().display());
 
-        let mut patch_runs = BTreeMap::new();
-        let has_perf = Command::new("perf").output().is_ok();
-        let mut fake_rustc = env::current_exe().unwrap();
-        fake_rustc.pop();
-        fake_rustc.push("rustc-fake");
-        for _ in 0..3 {
-            let tmp_dir = TempDir::new(&format!("rustc-benchmark-{}", self.name))?;
-            info!("temporary directory is {}", tmp_dir.path().display());
-
-            info!("copying files to temporary directory");
-            let output = self.command(sysroot, "cp")
-                .arg("-r")
-                .arg("-T")
-                .arg("--")
-                .arg(".")
-                .arg(tmp_dir.path())
-                .output()?;
+        info!("copying files to temporary directory");
+        let output = self.command(sysroot, "cp")
+            .arg("-r")
+            .arg("-T")
+            .arg("--")
+            .arg(".")
+            .arg(tmp_dir.path())
+            .output()?;
+
+        if !output.status.success() {
+            bail!("copy failed: {}", String::from_utf8_lossy(&output.stderr));
+        }
+        Ok(tmp_dir)
+    }
+
+    /// A fresh copy of the benchmark to build in. For sandboxed builds, the
+    /// dependencies are downloaded too.
+    fn build_dir(&self, sysroot: &Sysroot, config: &Config) -> Result<TempDir> {
+        let tmp_dir = self.make_temp_dir(sysroot)?;
+        if config.sandbox {
+            sandbox::fetch(sysroot, tmp_dir.path())?;
+        }
+        Ok(tmp_dir)
+    }
+
+    /// The `CARGO_OPTS` of the makefiles: the benchmark's features and extra
+    /// Cargo arguments. Sandboxed builds can't reach the network, so Cargo
+    /// must not try to. `-Zbuild-std` needs an explicit target. `cargo rustc`
+    /// checks with the `check` profile.
+    fn cargo_opts(&self, sandbox: bool, std: Std, target: &str) -> String {
+        let mut opts = Vec::new();
+        if sandbox {
+            opts.push(String::from("--frozen"));
+        }
+        if std == Std::BuildStd {
+            opts.push(String::from("-Zbuild-std"));
+            opts.push(format!("--target={}", target));
+        }
+        if !self.config.features.is_empty() {
+            opts.push(format!("--features={}", self.config.features.join(",")));
+        }
+        if self.config.no_default_features {
+            opts.push(String::from("--no-default-features"));
+        }
+        if self.config.check {
+            opts.push(String::from("--profile=check"));
+        }
+        opts.extend(self.config.cargo_args.iter().cloned());
+        opts.join(" ")
+    }
 
+    /// Build the benchmark's first patch once with the plain `rustc` of
+    /// `sysroot`, with Cargo kept off the network after fetching the
+    /// dependencies, to check that it builds offline. With the sandbox
+    /// configured, build scripts can't reach the network either.
+    pub fn build_offline(&self, sysroot: &Sysroot, config: &Config) -> Result<()> {
+        let tmp_dir = self.make_temp_dir(sysroot)?;
+        let dir = tmp_dir.path();
+        if config.sandbox {
+            sandbox::fetch(sysroot, dir)?;
+        } else {
+            let output = sysroot
+                .command(&sysroot.cargo)
+                .current_dir(dir)
+                .arg("fetch")
+                .output()
+                .chain_err(|| "could not spawn cargo fetch")?;
             if !output.status.success() {
-                bail!("copy failed: {}", String::from_utf8_lossy(&output.stderr));
+                bail!("cargo fetch failed:\n{}", String::from_utf8_lossy(&output.stderr));
             }
-            let make = || {
-                let mut command = sysroot.command("make");
-                command.current_dir(tmp_dir.path());
-                command
-            };
+        }
+        let patch = self.patches(sysroot, dir)?.remove(0);
+        let mut make = build_command(sysroot, dir, config, &[], "make")?;
+        make.arg(&format!("all{}", patch))
+            .envs(&self.config.env)
+            .env("CARGO_OPTS", self.cargo_opts(true, Std::Static, &sysroot.triple))
+            .env("CARGO_RUSTC_OPTS", "");
+        info!("running `{:?}`", make);
+        let output = make.output()?;
+        if !output.status.success() {
+            bail!(
+                "build failed with {}:\n{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(())
+    }
+
+    /// The patches of this benchmark, as returned by `make patches`. A
+    /// non-incremental benchmark has a single empty patch.
+    fn patches(&self, sysroot: &Sysroot, dir: &Path) -> Result<Vec<String>> {
+        let output = make(sysroot, dir).arg("patches").output()?;
+        self.parse_patches(&output.stdout)
+    }
 
-            let output = make().arg("patches").output()?;
-            let mut patches = str::from_utf8(&output.stdout)
-                .chain_err(|| {
-                    format!(
-                        "make patches in {} returned non UTF-8 output",
-                        self.path.display()
-                    )
-                })?
-                .split_whitespace()
+    /// The patches of this benchmark, asked of its source directory rather
+    /// than a copy, for planning a run without building anything.
+    pub fn source_patches(&self) -> Result<Vec<String>> {
+        let output = Command::new("make")
+            .current_dir(&self.path)
+            .arg("patches")
+            .output()
+            .chain_err(|| format!("could not run make in {}", self.path.display()))?;
+        self.parse_patches(&output.stdout)
+    }
+
+    fn parse_patches(&self, stdout: &[u8]) -> Result<Vec<String>> {
+        let mut patches = str::from_utf8(stdout)
+            .chain_err(|| {
+                format!(
+                    "make patches in {} returned non UTF-8 output",
+                    self.path.display()
+                )
+            })?
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        if patches.is_empty() {
+            patches.push(String::new());
+        }
+        if let Some(scenarios) = self.scenarios {
+            patches.truncate(scenarios);
+        }
+        Ok(patches)
+    }
+
+    /// Run the benchmark once for each configured profile, backend, std, flag
+    /// set and custom profile.
+    pub fn run_all(
+        &self,
+        sysroot: &Sysroot,
+        config: &Config,
+        slot: Option<&Slot>,
+        durations: Option<&Durations>,
+    ) -> Result<Vec<Patch>> {
+        let mut patches = Vec::new();
+        for &profile in &config.profiles {
+            for &backend in &config.backends {
+                for &std in &config.std {
+                    for flags in config.flag_set_names(profile) {
+                        for custom_profile in config.custom_profile_names() {
+                            patches.extend(self.run(
+                                sysroot,
+                                profile,
+                                backend,
+                                std,
+                                flags,
+                                custom_profile,
+                                config,
+                                slot,
+                                durations,
+                            )?);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(patches)
+    }
+
+    /// Run a specific benchmark on a specific commit. The measured builds
+    /// are checked against and recorded in `durations`, if given.
+    pub fn run(
+        &self,
+        sysroot: &Sysroot,
+        profile: Profile,
+        backend: Backend,
+        std: Std,
+        flags: Option<&str>,
+        custom_profile: Option<&str>,
+        config: &Config,
+        slot: Option<&Slot>,
+        durations: Option<&Durations>,
+    ) -> Result<Vec<Patch>> {
+        info!(
+            "processing {} ({}) with backend {}, std {}, flags {} and custom profile {}",
+            self.name,
+            profile,
+            backend,
+            std,
+            flags.unwrap_or("(none)"),
+            custom_profile.unwrap_or("(none)")
+        );
+        let rustc_opts = format!(
+            "-Ztime-passes {} {} {}",
+            backend.rustc_flags(),
+            std.rustc_flags(),
+            flags.map_or("", |name| &*config.flag_sets[name])
+        );
+        let mut cargo_opts = self.cargo_opts(config.sandbox, std, &sysroot.triple);
+        if let Some(name) = custom_profile {
+            cargo_opts.push(' ');
+            cargo_opts.push_str(&config.custom_profile_opts(name));
+        }
+
+        let mut patch_runs = BTreeMap::new();
+        let perf_events = config.perf_events();
+        let use_perf = !perf_events.is_empty() && Command::new("perf").output().is_ok();
+        let split_instructions = use_perf && config.wants_split_instructions();
+        for _ in 0..config.iterations() {
+            let tmp_dir = self.build_dir(sysroot, config)?;
+            let patches = self.patches(sysroot, tmp_dir.path())?;
+            let mut builds = patches
+                .iter()
+                .map(|patch| (patch, self.name.clone() + patch, false))
                 .collect::<Vec<_>>();
-            if patches.is_empty() {
-                patches.push("");
+            if config.warm_full {
+                // The first patch is a full build, which the makefiles can
+                // repeat once everything else has been built.
+                builds.push((&patches[0], self.name.clone() + WARM_FULL, true));
             }
 
-            for patch in &patches {
-                let name = self.name.clone() + &patch;
-                let mut make = make();
+            for (patch, name, warm_full) in builds {
+                if warm_full {
+                    touch_sources(tmp_dir.path())?;
+                }
+                // The whole build runs under `perf stat` to tell the
+                // instructions of Cargo and the build scripts apart.
+                let build_stat = tmp_dir.path().join(BUILD_STAT);
+                let build_script_stat = tmp_dir.path().join(BUILD_SCRIPT_STAT);
+                let mut program = Vec::new();
+                if split_instructions {
+                    program.extend(vec!["perf", "stat", "-x;", "-e", "instructions:u", "-o"]);
+                    program.push(build_stat.to_str().ok_or("non-UTF-8 build directory")?);
+                }
+                let mut writable = Vec::new();
+                if let Some(slot) = slot {
+                    writable.push(slot.lock.as_path());
+                    program.extend(vec!["taskset", "--cpu-list", &slot.cpus[..]]);
+                }
+                program.push("make");
+                let mut make =
+                    build_command(sysroot, tmp_dir.path(), config, &writable, program[0])?;
+                make.args(&program[1..]);
+                if let Some(slot) = slot {
+                    make.env("RUSTC_FAKE_LOCK", &slot.lock);
+                }
                 make.arg(&format!("all{}", patch))
-                    .env("CARGO_OPTS", "")
-                    .env("CARGO_RUSTC_OPTS", "-Ztime-passes")
-                    .env("RUSTC", &fake_rustc)
-                    .env("RUSTC_REAL", &sysroot.rustc);
-                if has_perf {
-                    make.env("USE_PERF", "1");
+                    .envs(&self.config.env)
+                    .env("CARGO_OPTS", &cargo_opts)
+                    .env("CARGO_RUSTC_OPTS", &rustc_opts);
+                set_rustc(&mut make, sysroot, profile);
+                if use_perf {
+                    make.env("USE_PERF", "1").env("PERF_EVENTS", &perf_events);
+                }
+                if config.wants_split_rss() {
+                    make.env("RUSTC_FAKE_SPLIT_RSS", "1");
                 }
+                if split_instructions {
+                    make.env("RUSTC_FAKE_SPLIT_INSTRUCTIONS", "1")
+                        .env("RUSTC_FAKE_BUILD_SCRIPT_LOG", &build_script_stat);
+                }
+                let build =
+                    durations::build_key(&name, profile, backend, std, flags, custom_profile);
+                let typical = durations.and_then(|durations| durations.typical(&build));
+                let limit = typical.map(|typical| typical * config.anomaly_factor);
+                let timeout = match limit {
+                    Some(limit) if config.kill_anomalous => {
+                        let limit = Duration::from_secs(limit.ceil() as u64);
+                        ::std::cmp::min(limit, config.build_timeout())
+                    }
+                    _ => config.build_timeout(),
+                };
                 info!("running `{:?}`", make);
-                let output = make.output()?;
+                let start = Instant::now();
+                let output = match supervise::output(&mut make, timeout) {
+                    Ok(output) => output,
+                    Err(err) => match typical {
+                        Some(typical) if timeout < config.build_timeout() => {
+                            return Err(err).chain_err(|| {
+                                format!(
+                                    "anomalous duration: killed {} after {} seconds, \
+                                     typically {:.1} seconds",
+                                    build,
+                                    timeout.as_secs(),
+                                    typical
+                                )
+                            });
+                        }
+                        _ => return Err(err),
+                    },
+                };
 
                 if !output.status.success() {
                     bail!(
@@ -93,48 +486,838 @@ impl Benchmark {
                     );
                 }
 
-                patch_runs
+                let elapsed = start.elapsed();
+                let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+                let anomaly = match (typical, limit) {
+                    (Some(typical), Some(limit)) if seconds > limit => {
+                        warn!(
+                            "anomalous duration: {} took {:.1} seconds, typically {:.1} seconds",
+                            build,
+                            seconds,
+                            typical
+                        );
+                        Some(DurationAnomaly {
+                            seconds: seconds,
+                            typical: typical,
+                        })
+                    }
+                    _ => None,
+                };
+                if let Some(durations) = durations {
+                    if let Err(e) = durations.record(&build, seconds) {
+                        warn!("failed to record the duration of {}: {:?}", build, e);
+                    }
+                }
+
+                let patch = patch_runs
                     .entry(name.clone())
                     .or_insert_with(|| {
                         Patch {
-                            name: name.clone(),
+                            name: Name::from(name.clone()),
+                            backend: backend,
+                            flags: flags.map(String::from),
+                            profile: profile,
+                            custom_profile: custom_profile.map(String::from),
+                            std: std,
                             runs: Vec::new(),
+                            queries: Vec::new(),
+                            collector: None,
+                            timings: Vec::new(),
+                            llvm_passes: Vec::new(),
+                            anomalies: Vec::new(),
                         }
-                    })
-                    .runs
-                    .push(Run {
-                        stats: process_output(&name, output.stdout)?,
                     });
+                patch.anomalies.extend(anomaly);
+                let mut stats = process_output(&name, output.stdout)?;
+                if split_instructions {
+                    let build = match read_instructions(&build_stat)? {
+                        Some(build) => build,
+                        None => bail!(
+                            "no instruction count for the whole build in {}",
+                            build_stat.display()
+                        ),
+                    };
+                    // Build scripts only run when Cargo deems it necessary.
+                    let build_scripts = read_instructions(&build_script_stat)?.unwrap_or(0.0);
+                    split_by_process(&mut stats, build, build_scripts);
+                }
+                patch.runs.push(Run {
+                    stats: stats
+                        .into_iter()
+                        .filter(|stat| config.metrics.contains(&*stat.name))
+                        .collect(),
+                });
             }
         }
 
         let mut patches = Vec::new();
         for (_, patch) in patch_runs {
-            let mut runs = patch.runs.into_iter();
-            let Run { mut stats } = runs.next().unwrap();
-            for run in runs {
-                for a in &mut stats {
-                    let b = match run.stats.iter().find(|p| p.name == a.name) {
-                        Some(b) => b,
-                        None => bail!("expected name {} to exist in both a and b", a.name),
-                    };
-                    a.cnt = f64::min(a.cnt, b.cnt);
-                }
-            }
+            let stats = combine_runs(patch.runs, config.iterations)?;
             patches.push(Patch {
                 name: patch.name,
+                backend: patch.backend,
+                flags: patch.flags,
+                profile: patch.profile,
+                custom_profile: patch.custom_profile,
+                std: patch.std,
                 runs: vec![Run { stats }],
+                queries: Vec::new(),
+                collector: None,
+                timings: Vec::new(),
+                llvm_passes: Vec::new(),
+                anomalies: patch.anomalies,
             });
         }
 
+        if config.wants_query_stats() {
+            self.extend_stats(sysroot, &mut patches, config, |dir, patch, result| {
+                let (summary, mut stats) = self.self_profile(
+                    sysroot,
+                    profile,
+                    &cargo_opts,
+                    &rustc_opts,
+                    config,
+                    dir,
+                    patch,
+                )?;
+                result.queries = summary.query_timings();
+                stats.extend(summary.query_stats());
+                Ok(stats)
+            })?;
+        }
+        if config.wants_section_stats() {
+            self.extend_stats(sysroot, &mut patches, config, |dir, patch, _| {
+                let output = self.build_with_tool(
+                    sysroot,
+                    profile,
+                    &cargo_opts,
+                    &rustc_opts,
+                    config,
+                    dir,
+                    patch,
+                    "time-passes",
+                    dir,
+                )?;
+                process_output(patch, output)
+            })?;
+        }
+        if config.wants_cgu_stats() {
+            self.extend_stats(sysroot, &mut patches, config, |dir, patch, _| {
+                let output = self.build_with_tool(
+                    sysroot,
+                    profile,
+                    &cargo_opts,
+                    &rustc_opts,
+                    config,
+                    dir,
+                    patch,
+                    "cgu-sizes",
+                    dir,
+                )?;
+                process_output(patch, output)
+            })?;
+        }
+        if config.timings {
+            self.extend_stats(sysroot, &mut patches, config, |dir, patch, result| {
+                let start = SystemTime::now();
+                self.build_with_tool(
+                    sysroot,
+                    profile,
+                    &cargo_opts,
+                    &rustc_opts,
+                    config,
+                    dir,
+                    patch,
+                    "timings",
+                    dir,
+                )?;
+                result.timings = read_timings(&dir.join("timings"), start)?;
+                Ok(Vec::new())
+            })?;
+        }
+        if let Some(count) = config.llvm_passes {
+            self.extend_stats(sysroot, &mut patches, config, |dir, patch, result| {
+                self.build_with_tool(
+                    sysroot,
+                    profile,
+                    &cargo_opts,
+                    &rustc_opts,
+                    config,
+                    dir,
+                    patch,
+                    "llvm-passes",
+                    dir,
+                )?;
+                result.llvm_passes = read_llvm_passes(&dir.join("llvm-passes"), count)?;
+                Ok(Vec::new())
+            })?;
+        }
+
         Ok(patches)
     }
+
+    /// Build all patches once more, unmeasured, in a fresh directory, adding
+    /// the configured statistics returned by `stats` to `patches`. `stats`
+    /// may also fill in the patch's query or crate timings.
+    fn extend_stats<F>(
+        &self,
+        sysroot: &Sysroot,
+        patches: &mut [Patch],
+        config: &Config,
+        mut stats: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&Path, &str, &mut Patch) -> Result<Vec<Stat>>,
+    {
+        let tmp_dir = self.build_dir(sysroot, config)?;
+        for patch_name in self.patches(sysroot, tmp_dir.path())? {
+            let name = self.name.clone() + &patch_name;
+            let patch = match patches.iter_mut().find(|p| p.name == name) {
+                Some(patch) => patch,
+                None => continue,
+            };
+            let stats = stats(tmp_dir.path(), &patch_name, patch)?;
+            patch.runs[0].stats.extend(
+                stats
+                    .into_iter()
+                    .filter(|stat| config.metrics.contains(&*stat.name)),
+            );
+        }
+        Ok(())
+    }
+
+    /// Build `patch` in `dir` with `rustc-fake` running rustc under `tool`,
+    /// which writes its output to `out_dir`. Returns the build's stdout.
+    fn build_with_tool(
+        &self,
+        sysroot: &Sysroot,
+        profile: Profile,
+        cargo_opts: &str,
+        rustc_opts: &str,
+        config: &Config,
+        dir: &Path,
+        patch: &str,
+        tool: &str,
+        out_dir: &Path,
+    ) -> Result<Vec<u8>> {
+        let mut make = build_command(sysroot, dir, config, &[out_dir], "make")?;
+        make.arg(&format!("all{}", patch))
+            .envs(&self.config.env)
+            .env("CARGO_OPTS", cargo_opts)
+            .env("CARGO_RUSTC_OPTS", rustc_opts)
+            .env("PERF_TOOL", tool)
+            .env("PERF_TOOL_OUT_DIR", out_dir);
+        set_rustc(&mut make, sysroot, profile);
+        info!("running `{:?}`", make);
+        let output = supervise::output(&mut make, config.build_timeout())?;
+        if !output.status.success() {
+            bail!(
+                "expected success, got {}\n\nstderr={}\n\n stdout={}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr),
+                String::from_utf8_lossy(&output.stdout)
+            );
+        }
+        Ok(output.stdout)
+    }
+
+    /// Build `patch` in `dir` with self-profiling enabled and summarize the
+    /// profile. Also returns the `warning-count` of the build.
+    fn self_profile(
+        &self,
+        sysroot: &Sysroot,
+        profile: Profile,
+        cargo_opts: &str,
+        rustc_opts: &str,
+        config: &Config,
+        dir: &Path,
+        patch: &str,
+    ) -> Result<(SelfProfileSummary, Vec<Stat>)> {
+        let profile_dir = TempDir::new(&format!("rustc-self-profile-{}", self.name))?;
+        let output = self.build_with_tool(
+            sysroot,
+            profile,
+            cargo_opts,
+            rustc_opts,
+            config,
+            dir,
+            patch,
+            Profiler::SelfProfile.name(),
+            profile_dir.path(),
+        )?;
+
+        // `summarize --json` writes `<profile>.json` next to the profile.
+        let profile = self_profile_data(profile_dir.path())?;
+        let status = Command::new("summarize")
+            .arg("summarize")
+            .arg("--json")
+            .arg(&profile)
+            .status()
+            .chain_err(|| "could not spawn summarize; is measureme installed?")?;
+        if !status.success() {
+            bail!("summarize failed on {}", profile.display());
+        }
+        let summary = profile.with_extension("json");
+        let summary = serde_json::from_reader(
+            File::open(&summary).chain_err(|| format!("failed to open {}", summary.display()))?,
+        )?;
+        Ok((summary, process_output(patch, output)?))
+    }
+
+    /// Build the benchmark once per patch with `profiler` enabled, storing
+    /// the profiler output in `out_dir/<patch name>`. `rustc_log` is the
+    /// `RUSTC_LOG` filter used by the `eprintln` profiler.
+    pub fn profile(
+        &self,
+        sysroot: &Sysroot,
+        profiler: Profiler,
+        out_dir: &Path,
+        rustc_log: Option<&str>,
+        cache: Option<&Cache>,
+    ) -> Result<()> {
+        info!("profiling {} with {}", self.name, profiler.name());
+
+        let tmp_dir = self.make_temp_dir(sysroot)?;
+        if let Some(cache) = cache {
+            cache.restore(&self.name, tmp_dir.path())?;
+        }
+        for patch in self.patches(sysroot, tmp_dir.path())? {
+            let name = self.name.clone() + &patch;
+            let patch_dir = out_dir.join(&name);
+            fs::create_dir_all(&patch_dir)?;
+            let patch_dir = patch_dir.canonicalize()?;
+
+            let mut make = make(sysroot, tmp_dir.path());
+            make.arg(&format!("all{}", patch))
+                .envs(&self.config.env)
+                .env("CARGO_OPTS", self.cargo_opts(false, Std::Static, &sysroot.triple))
+                .env("CARGO_RUSTC_OPTS", "-Ztime-passes")
+                .env("RUSTC", fake_rustc())
+                .env("RUSTC_REAL", &sysroot.rustc)
+                .env("PERF_TOOL", profiler.name())
+                .env("PERF_TOOL_OUT_DIR", &patch_dir);
+            if let Some(rustc_log) = rustc_log {
+                make.env("PERF_TOOL_RUSTC_LOG", rustc_log);
+            }
+            info!("running `{:?}`", make);
+            let output = make.output()?;
+
+            if !output.status.success() {
+                bail!(
+                    "expected success, got {}\n\nstderr={}\n\n stdout={}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr),
+                    String::from_utf8_lossy(&output.stdout)
+                );
+            }
+
+            profiler.postprocess(&patch_dir)?;
+            info!("profile for {} written to {}", name, patch_dir.display());
+        }
+        if let Some(cache) = cache {
+            cache.store(&self.name, tmp_dir.path())?;
+        }
+        Ok(())
+    }
+}
+
+/// The profilers supported by `profile_local`. The profiler itself is run by
+/// `rustc-fake`, selected via the `PERF_TOOL` environment variable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Profiler {
+    SelfProfile,
+    Eprintln,
+    LlvmLines,
+    LlvmIr,
+    Massif,
+    Heaptrack,
+    /// `perf record -g`; `symbolicate` resolves inlined functions from the
+    /// toolchain's debuginfo when post-processing
+    PerfRecord { symbolicate: bool },
+}
+
+impl Profiler {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Profiler::SelfProfile => "self-profile",
+            Profiler::Eprintln => "eprintln",
+            Profiler::LlvmLines => "llvm-lines",
+            Profiler::LlvmIr => "llvm-ir",
+            Profiler::Massif => "massif",
+            Profiler::Heaptrack => "heaptrack",
+            Profiler::PerfRecord { .. } => "perf-record",
+        }
+    }
+
+    /// The file of folded stacks the profiler leaves in each profile
+    /// directory, if any, and the unit of their costs.
+    pub fn folded_stacks(&self) -> Option<(&'static str, &'static str)> {
+        match *self {
+            Profiler::SelfProfile => Some(("out.stacks_folded", "ns")),
+            Profiler::Massif => Some(("massif-peak.folded", "bytes")),
+            Profiler::Heaptrack => Some(("heaptrack-peak.folded", "bytes")),
+            Profiler::PerfRecord { .. } => Some(("perf-record.folded", "samples")),
+            Profiler::Eprintln | Profiler::LlvmLines | Profiler::LlvmIr => None,
+        }
+    }
+
+    /// Turn the raw profiler output in `dir` into something more readily
+    /// consumable.
+    fn postprocess(&self, dir: &Path) -> Result<()> {
+        match *self {
+            Profiler::SelfProfile => {
+                // `crox` writes `chrome_profiler.json`, `flamegraph` writes
+                // `rustc.svg` and `stack_collapse` writes `out.stacks_folded`,
+                // all into the current directory.
+                let profile = self_profile_data(dir)?;
+                for tool in &["crox", "flamegraph", "stack_collapse"] {
+                    let status = Command::new(tool)
+                        .current_dir(dir)
+                        .arg(&profile)
+                        .status()
+                        .chain_err(|| format!("could not spawn {}; is measureme installed?", tool))?;
+                    if !status.success() {
+                        bail!("{} failed on {}", tool, profile.display());
+                    }
+                }
+                Ok(())
+            }
+            Profiler::Eprintln => count_lines(&dir.join("eprintln"), &dir.join("eprintln-counts")),
+            Profiler::LlvmLines => llvm_lines::summarize(dir),
+            Profiler::LlvmIr => llvm_lines::total_ir_lines(dir).map(|_| ()),
+            Profiler::Massif => memory::summarize_massif(dir),
+            Profiler::Heaptrack => memory::summarize_heaptrack(dir),
+            Profiler::PerfRecord { symbolicate } => perf_record::collapse(dir, symbolicate),
+        }
+    }
+}
+
+impl FromStr for Profiler {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Profiler> {
+        match s {
+            "self-profile" => Ok(Profiler::SelfProfile),
+            "eprintln" => Ok(Profiler::Eprintln),
+            "llvm-lines" => Ok(Profiler::LlvmLines),
+            "llvm-ir" => Ok(Profiler::LlvmIr),
+            "massif" => Ok(Profiler::Massif),
+            "heaptrack" => Ok(Profiler::Heaptrack),
+            "perf-record" => Ok(Profiler::PerfRecord { symbolicate: false }),
+            _ => bail!("unknown profiler `{}`", s),
+        }
+    }
+}
+
+/// The parts of `summarize --json` output needed for the query statistics
+/// and timings.
+#[derive(Deserialize)]
+struct SelfProfileSummary {
+    query_data: Vec<QueryData>,
 }
 
+#[derive(Deserialize)]
+struct QueryData {
+    label: String,
+    self_time: SummarizeDuration,
+    invocation_count: u64,
+    number_of_cache_hits: u64,
+}
+
+/// A duration as `summarize --json` writes it.
+#[derive(Deserialize)]
+struct SummarizeDuration {
+    secs: u64,
+    nanos: u32,
+}
+
+impl SummarizeDuration {
+    fn seconds(&self) -> f64 {
+        self.secs as f64 + self.nanos as f64 / 1e9
+    }
+}
+
+impl SelfProfileSummary {
+    fn query_timings(&self) -> Vec<QueryTiming> {
+        self.query_data
+            .iter()
+            .map(|q| QueryTiming {
+                label: q.label.clone(),
+                self_time: q.self_time.secs as f64 + q.self_time.nanos as f64 / 1e9,
+                invocations: q.invocation_count,
+                cache_hits: q.number_of_cache_hits,
+            })
+            .collect()
+    }
+
+    fn query_stats(&self) -> Vec<Stat> {
+        let invocations = self.query_data.iter().map(|q| q.invocation_count).sum::<u64>();
+        let hits = self.query_data
+            .iter()
+            .map(|q| q.number_of_cache_hits)
+            .sum::<u64>();
+        let incr_comp_time = self.query_data
+            .iter()
+            .filter(|q| q.label.starts_with("incr_comp_"))
+            .map(|q| q.self_time.seconds())
+            .sum::<f64>();
+        // Expanding proc macros is an activity nested in the expansion of
+        // the whole crate, so the self times add up to the total.
+        let macro_expansion_time = self.query_data
+            .iter()
+            .filter(|q| q.label == "expand_crate" || q.label == "expand_proc_macro")
+            .map(|q| q.self_time.secs as f64 + q.self_time.nanos as f64 / 1e9)
+            .sum::<f64>();
+        // Lint checking is where most warnings are found and emitted; rustc
+        // has no event for rendering them alone.
+        let diagnostics_time = self.query_data
+            .iter()
+            .filter(|q| q.label.contains("lint") || q.label.contains("diagnostic"))
+            .map(|q| q.self_time.secs as f64 + q.self_time.nanos as f64 / 1e9)
+            .sum::<f64>();
+        vec![
+            Stat {
+                name: Name::from("query-count"),
+                cnt: invocations as f64,
+                samples: Vec::new(),
+            },
+            Stat {
+                name: Name::from("query-cache-hit-ratio"),
+                cnt: if invocations == 0 {
+                    0.0
+                } else {
+                    hits as f64 / invocations as f64
+                },
+                samples: Vec::new(),
+            },
+            Stat {
+                name: Name::from("incr-comp-time"),
+                cnt: incr_comp_time,
+                samples: Vec::new(),
+            },
+            Stat {
+                name: Name::from("macro-expansion"),
+                cnt: macro_expansion_time,
+                samples: Vec::new(),
+            },
+            Stat {
+                name: Name::from("diagnostics-time"),
+                cnt: diagnostics_time,
+                samples: Vec::new(),
+            },
+        ]
+    }
+}
+
+/// Find the self-profile data written by rustc into `dir`. The measureme
+/// tools accept either a `.mm_profdata` file or, for the older format, the
+/// common prefix of the `.events`/`.string_data`/`.string_index` files.
+fn self_profile_data(dir: &Path) -> Result<PathBuf> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("mm_profdata") => return Ok(path),
+            Some("events") => return Ok(path.with_extension("")),
+            _ => {}
+        }
+    }
+    bail!("no self-profile data found in {}", dir.display())
+}
+
+/// Write each distinct line of `input` to `output`, prefixed with the number
+/// of times it occurs, most frequent first. Log output is very repetitive, so
+/// this is usually the best place to start reading it.
+fn count_lines(input: &Path, output: &Path) -> Result<()> {
+    let mut contents = String::new();
+    File::open(input)
+        .chain_err(|| format!("failed to open {}", input.display()))?
+        .read_to_string(&mut contents)?;
+
+    let mut counts = HashMap::new();
+    for line in contents.lines() {
+        *counts.entry(line).or_insert(0) += 1;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut output = File::create(output)?;
+    for (line, count) in counts {
+        writeln!(output, "{:>8} {}", count, line)?;
+    }
+    Ok(())
+}
+
+/// `program` (`make`, or `taskset` running it) in `dir` for a build of the
+/// benchmark, in the sandbox and the cgroup if configured. `writable` are the
+/// paths outside of `dir` the build writes to.
+fn build_command(
+    sysroot: &Sysroot,
+    dir: &Path,
+    config: &Config,
+    writable: &[&Path],
+    program: &str,
+) -> Result<Command> {
+    let mut command = if config.sandbox {
+        sandbox::command(sysroot, dir, writable, program)?
+    } else {
+        let mut command = sysroot.command(program);
+        command.current_dir(dir);
+        command
+    };
+    if let Some(ref cgroup) = config.cgroup {
+        cgroup::join(&mut command, cgroup);
+    }
+    Ok(command)
+}
+
+fn make(sysroot: &Sysroot, dir: &Path) -> Command {
+    let mut command = sysroot.command("make");
+    command.current_dir(dir);
+    command
+}
+
+/// Have `make` build with `rustc-fake` wrapping the compiler for `profile`.
+fn set_rustc(make: &mut Command, sysroot: &Sysroot, profile: Profile) {
+    make.env("RUSTC", fake_rustc());
+    match profile {
+        Profile::Debug => {
+            make.env("RUSTC_REAL", &sysroot.rustc);
+        }
+        Profile::Clippy => {
+            make.env("RUSTC_REAL", clippy::driver(sysroot))
+                .env("SYSROOT", clippy::sysroot_dir(sysroot));
+        }
+    }
+}
+
+/// Bump the modification time of all Rust sources of the benchmark in `dir`,
+/// like the makefiles do before incremental builds, so that Cargo rebuilds
+/// the crate.
+fn touch_sources(dir: &Path) -> Result<()> {
+    let status = Command::new("find")
+        .current_dir(dir)
+        .args(&[".", "-path", "./target", "-prune", "-o", "-name", "*.rs", "-exec", "touch"])
+        .args(&["{}", "+"])
+        .status()?;
+    if !status.success() {
+        bail!("failed to touch the sources in {}", dir.display());
+    }
+    Ok(())
+}
+
+/// The file in a benchmark's build directory the `perf stat` output of a
+/// whole build goes to, see `split_by_process`.
+const BUILD_STAT: &str = ".rustc-perf-build-stat";
+
+/// The file in a benchmark's build directory the build scripts' `perf stat`
+/// runs append to. `rustc-fake` wraps the build scripts to run them so.
+const BUILD_SCRIPT_STAT: &str = ".rustc-perf-build-script-stat";
+
+/// `rustc-fake` is built alongside the collector.
+fn fake_rustc() -> PathBuf {
+    let mut fake_rustc = env::current_exe().unwrap();
+    fake_rustc.pop();
+    fake_rustc.push("rustc-fake");
+    fake_rustc
+}
+
+/// The statistics of `runs` of the same patch, each with the samples of all
+/// runs: the minimum, or for `SAMPLED_METRICS` the median without outliers.
+/// Only the first `iterations` runs count for the other metrics; the runs
+/// after those were built for more samples of the `SAMPLED_METRICS`.
+fn combine_runs(runs: Vec<Run>, iterations: usize) -> Result<Vec<Stat>> {
+    let mut runs = runs.into_iter();
+    let Run { mut stats } = runs.next().unwrap();
+    for stat in &mut stats {
+        stat.samples.push(stat.cnt);
+    }
+    for (idx, run) in runs.enumerate() {
+        let extra = idx + 1 >= iterations;
+        for a in &mut stats {
+            let sampled = SAMPLED_METRICS.contains(&&*a.name);
+            if extra && !sampled {
+                continue;
+            }
+            let b = match run.stats.iter().find(|p| p.name == a.name) {
+                Some(b) => b,
+                None => bail!("expected name {} to exist in both a and b", a.name),
+            };
+            a.samples.push(b.cnt);
+            a.cnt = f64::min(a.cnt, b.cnt);
+        }
+    }
+    for stat in &mut stats {
+        if SAMPLED_METRICS.contains(&&*stat.name) {
+            stat.cnt = median_without_outliers(&stat.samples);
+        }
+    }
+    Ok(stats)
+}
+
+/// The median of the `samples` within 1.5 interquartile ranges of the
+/// first and third quartile, so that e.g. a run slowed down by a background
+/// job doesn't skew the result. NaN if there are no samples but NaNs.
+fn median_without_outliers(samples: &[f64]) -> f64 {
+    let mut sorted = samples.iter().cloned().filter(|x| !x.is_nan()).collect::<Vec<_>>();
+    if sorted.is_empty() {
+        return ::std::f64::NAN;
+    }
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let (q1, q3) = (quantile(&sorted, 0.25), quantile(&sorted, 0.75));
+    let iqr = q3 - q1;
+    let kept = sorted
+        .into_iter()
+        .filter(|&x| q1 - 1.5 * iqr <= x && x <= q3 + 1.5 * iqr)
+        .collect::<Vec<_>>();
+    quantile(&kept, 0.5)
+}
+
+/// The `q` quantile of the non-empty, sorted `sorted`, interpolating
+/// between neighbouring values.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let pos = q * (sorted.len() - 1) as f64;
+    let (lower, upper) = (pos.floor() as usize, pos.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (pos - lower as f64)
+}
+
+/// Read and remove the crate timings `rustc-fake` wrote to `path` for a build
+/// started at `start`, earliest first.
+fn read_timings(path: &Path, start: SystemTime) -> Result<Vec<CrateTiming>> {
+    let mut contents = String::new();
+    File::open(path)
+        .chain_err(|| format!("no crate timings in {}", path.display()))?
+        .read_to_string(&mut contents)?;
+    fs::remove_file(path)?;
+
+    let since_epoch = start.duration_since(UNIX_EPOCH).unwrap();
+    let build_start = since_epoch.as_secs() as f64 + since_epoch.subsec_nanos() as f64 / 1e9;
+    let mut timings = Vec::new();
+    for line in contents.lines() {
+        // package;crate;leaf;start;end
+        let parts = line.split(';').collect::<Vec<_>>();
+        let times = match (parts.get(3), parts.get(4)) {
+            (Some(a), Some(b)) => (a.parse::<f64>(), b.parse::<f64>()),
+            _ => bail!("unexpected crate timing `{}`", line),
+        };
+        let (begin, end) = match times {
+            (Ok(begin), Ok(end)) => (begin, end),
+            _ => bail!("unexpected crate timing `{}`", line),
+        };
+        let (package, krate) = (parts[0], parts[1]);
+        timings.push(CrateTiming {
+            name: if krate == package.replace('-', "_") {
+                package.to_string()
+            } else {
+                format!("{}/{}", package, krate)
+            },
+            leaf: parts[2] == "1",
+            start: begin - build_start,
+            duration: end - begin,
+        });
+    }
+    timings.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+    Ok(timings)
+}
+
+/// Read and remove the LLVM pass timings `rustc-fake` wrote to `path`, and
+/// keep the `count` slowest passes, slowest first.
+fn read_llvm_passes(path: &Path, count: usize) -> Result<Vec<LlvmPassTiming>> {
+    let mut contents = String::new();
+    File::open(path)
+        .chain_err(|| format!("no LLVM pass timings in {}", path.display()))?
+        .read_to_string(&mut contents)?;
+    fs::remove_file(path)?;
+
+    let mut passes: Vec<LlvmPassTiming> = Vec::new();
+    for line in contents.lines() {
+        // seconds;name
+        let mut parts = line.splitn(2, ';');
+        let (seconds, name) = match (parts.next().map(|s| s.parse::<f64>()), parts.next()) {
+            (Some(Ok(seconds)), Some(name)) => (seconds, name),
+            _ => bail!("unexpected LLVM pass timing `{}`", line),
+        };
+        match passes.iter().position(|pass| pass.name == name) {
+            Some(idx) => passes[idx].seconds += seconds,
+            None => passes.push(LlvmPassTiming {
+                name: name.to_string(),
+                seconds: seconds,
+            }),
+        }
+    }
+    passes.sort_by(|a, b| b.seconds.partial_cmp(&a.seconds).unwrap());
+    passes.truncate(count);
+    Ok(passes)
+}
+
+/// The instructions counted by the `perf stat` runs whose output is in
+/// `path`, summed, and removes the file. `None` if nothing was counted.
+fn read_instructions(path: &Path) -> Result<Option<f64>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .chain_err(|| format!("failed to read {}", path.display()))?;
+    fs::remove_file(path)?;
+    let counts = contents
+        .lines()
+        .map(|line| line.split(';').collect::<Vec<_>>())
+        .filter(|parts| parts.len() > 2 && parts[2] == "instructions:u")
+        .filter_map(|parts| parts[0].parse::<f64>().ok())
+        .collect::<Vec<_>>();
+    if counts.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(counts.iter().sum()))
+    }
+}
+
+/// Add the `PROCESS_METRICS` to `stats` of a build that took `build`
+/// instructions in all, `build_scripts` of them in build scripts.
+/// `rustc-fake` reports those of the linker and of the other crates' rustc
+/// runs; the measured rustc is what's left of `instructions:u`, and Cargo is
+/// what's left of the whole build.
+fn split_by_process(stats: &mut Vec<Stat>, build: f64, build_scripts: f64) {
+    let get = |stats: &[Stat], name: &str| {
+        stats.iter().find(|stat| stat.name == name).map(|stat| stat.cnt)
+    };
+    let total = match get(&stats[..], "instructions:u") {
+        Some(total) => total,
+        None => return,
+    };
+    let linker = get(&stats[..], "instructions:linker");
+    let deps = get(&stats[..], "instructions:deps");
+    let cargo = build - total - deps.unwrap_or(0.0) - build_scripts;
+    let mut split = vec![
+        ("instructions:rustc", total - linker.unwrap_or(0.0)),
+        ("instructions:build-script", build_scripts),
+        ("instructions:cargo", cargo.max(0.0)),
+    ];
+    // Nothing was linked, or there are no other crates.
+    if linker.is_none() {
+        split.push(("instructions:linker", 0.0));
+    }
+    if deps.is_none() {
+        split.push(("instructions:deps", 0.0));
+    }
+    for (name, cnt) in split {
+        stats.push(Stat {
+            name: Name::from(name),
+            cnt: cnt,
+            samples: Vec::new(),
+        });
+    }
+}
+
+/// The statistics `rustc-fake` printed for the measured rustc runs of a
+/// build. Benchmarks measuring every crate of a workspace have a run per
+/// crate; their statistics are combined, see `combine`.
 fn process_output(name: &str, output: Vec<u8>) -> Result<Vec<Stat>> {
     let output = String::from_utf8(output)
         .chain_err(|| format!("unable to convert output of {} to UTF-8", name))?;
-    let mut stats = Vec::new();
+    let mut stats: Vec<Stat> = Vec::new();
+    // How many runs reported each of `stats`
+    let mut runs = HashMap::new();
 
     for line in output.lines() {
         // https://github.com/torvalds/linux/blob/bc78d646e708/tools/perf/Documentation/perf-stat.txt#L281
@@ -163,12 +1346,149 @@ fn process_output(name: &str, output: Vec<u8>) -> Result<Vec<Stat>> {
                 pct
             );
         }
-        stats.push(Stat {
-            name: name.to_string(),
-            cnt: cnt.parse()
-                .chain_err(|| format!("failed to parse `{}` as an float", cnt))?,
-        });
+        let cnt: f64 = cnt.parse()
+            .chain_err(|| format!("failed to parse `{}` as an float", cnt))?;
+        let n = runs.entry(name.to_string()).or_insert(0);
+        *n += 1;
+        match stats.iter_mut().find(|stat| stat.name == name) {
+            Some(stat) => stat.cnt = combine(name, stat.cnt, cnt, *n),
+            None => stats.push(Stat {
+                name: Name::from(name),
+                cnt: cnt,
+                samples: Vec::new(),
+            }),
+        }
     }
 
     Ok(stats)
 }
+
+/// `total`, the statistic `name` combined over `n - 1` rustc runs, combined
+/// with `cnt` of the `n`th: the peaks of memory and codegen unit sizes are
+/// the largest (or smallest) of any run, ratios and medians are averaged,
+/// and everything else adds up.
+fn combine(name: &str, total: f64, cnt: f64, n: usize) -> f64 {
+    match name {
+        _ if name.ends_with("max-rss") => total.max(cnt),
+        "cgu-size-max" => total.max(cnt),
+        "cgu-size-min" => total.min(cnt),
+        "query-cache-hit-ratio" | "cgu-size-median" => total + (cnt - total) / n as f64,
+        _ => total + cnt,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cnt(stats: &[Stat], name: &str) -> Option<f64> {
+        stats.iter().find(|stat| stat.name == name).map(|stat| stat.cnt)
+    }
+
+    #[test]
+    fn process_output_combines_runs() {
+        let output = "\
+100;;instructions:u;1;100.00
+2048;;max-rss;1;100.00
+0.5;;query-cache-hit-ratio;1;100.00
+<not supported>;;cycles:u;1;100.00
+300;;instructions:u;1;100.00
+1024;;max-rss;1;100.00
+0.8;;query-cache-hit-ratio;1;100.00
+";
+        let stats = process_output("tiny-crates", output.as_bytes().to_vec()).unwrap();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(cnt(&stats, "instructions:u"), Some(400.0));
+        assert_eq!(cnt(&stats, "max-rss"), Some(2048.0));
+        assert!((cnt(&stats, "query-cache-hit-ratio").unwrap() - 0.65).abs() < 1e-9);
+        assert_eq!(cnt(&stats, "cycles:u"), None);
+    }
+
+    #[test]
+    fn combine_averages_ratios_over_runs() {
+        assert_eq!(combine("cgu-size-max", 5.0, 7.0, 2), 7.0);
+        assert_eq!(combine("cgu-size-min", 5.0, 7.0, 2), 5.0);
+        assert_eq!(combine("cgu-size-median", 2.0, 5.0, 3), 3.0);
+        assert_eq!(combine("faults", 2.0, 5.0, 3), 7.0);
+    }
+
+    #[test]
+    fn median_without_outliers_drops_outliers() {
+        assert!(median_without_outliers(&[]).is_nan());
+        assert!(median_without_outliers(&[::std::f64::NAN]).is_nan());
+        assert_eq!(median_without_outliers(&[2.0, 2.0, 2.0, 2.0]), 2.0);
+        assert_eq!(median_without_outliers(&[3.0]), 3.0);
+        // 9.0 is more than 1.5 interquartile ranges above the third quartile.
+        let median = median_without_outliers(&[1.0, 1.2, 9.0, 1.1, 1.3]);
+        assert!((median - 1.15).abs() < 1e-9, "{}", median);
+        let median = median_without_outliers(&[1.0, 1.2, ::std::f64::NAN, 1.1]);
+        assert!((median - 1.1).abs() < 1e-9, "{}", median);
+    }
+
+    #[test]
+    fn combine_runs_counts_extra_runs_for_sampled_metrics_only() {
+        let runs = [(100.0, 2.0), (90.0, 2.2), (95.0, 2.1), (50.0, 9.0), (60.0, 2.3)]
+            .iter()
+            .map(|&(instructions, wall_time)| Run {
+                stats: vec![stat("instructions:u", instructions), stat("wall-time", wall_time)],
+            })
+            .collect();
+        let stats = combine_runs(runs, 3).unwrap();
+        assert_eq!(cnt(&stats, "instructions:u"), Some(90.0));
+        assert_eq!(stats[0].samples, vec![100.0, 90.0, 95.0]);
+        assert!((cnt(&stats, "wall-time").unwrap() - 2.15).abs() < 1e-9);
+        assert_eq!(stats[1].samples.len(), 5);
+    }
+
+    fn stat(name: &str, cnt: f64) -> Stat {
+        Stat {
+            name: Name::from(name),
+            cnt: cnt,
+            samples: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn split_by_process_attributes_the_rest_to_cargo() {
+        let mut stats = vec![
+            stat("instructions:u", 1000.0),
+            stat("instructions:linker", 100.0),
+            stat("instructions:deps", 500.0),
+        ];
+        split_by_process(&mut stats, 1800.0, 200.0);
+        assert_eq!(cnt(&stats, "instructions:rustc"), Some(900.0));
+        assert_eq!(cnt(&stats, "instructions:build-script"), Some(200.0));
+        assert_eq!(cnt(&stats, "instructions:cargo"), Some(100.0));
+        assert_eq!(stats.len(), 6);
+    }
+
+    #[test]
+    fn split_by_process_without_linker_or_deps() {
+        let mut stats = vec![stat("instructions:u", 1000.0)];
+        // Counted separately, the build may come out below its parts.
+        split_by_process(&mut stats, 900.0, 0.0);
+        assert_eq!(cnt(&stats, "instructions:rustc"), Some(1000.0));
+        assert_eq!(cnt(&stats, "instructions:cargo"), Some(0.0));
+        assert_eq!(cnt(&stats, "instructions:linker"), Some(0.0));
+        assert_eq!(cnt(&stats, "instructions:deps"), Some(0.0));
+
+        let mut stats = vec![stat("max-rss", 1024.0)];
+        split_by_process(&mut stats, 900.0, 0.0);
+        assert_eq!(stats.len(), 1);
+    }
+
+    #[test]
+    fn read_instructions_sums_and_removes() {
+        let dir = TempDir::new("rustc-perf-test").unwrap();
+        let path = dir.path().join(BUILD_SCRIPT_STAT);
+        assert_eq!(read_instructions(&path).unwrap(), None);
+
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"# started on Mon\n\n300;;instructions:u;1;100.00\n\
+                         # started on Mon\n\n200;;instructions:u;1;100.00\n")
+            .unwrap();
+        drop(file);
+        assert_eq!(read_instructions(&path).unwrap(), Some(500.0));
+        assert!(!path.exists());
+    }
+}
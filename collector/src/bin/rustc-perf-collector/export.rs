@@ -0,0 +1,485 @@
+//! Flat JSON export of a run's results, for tools that don't want to know
+//! the layout of `CommitData`, a report of the benchmarks that failed, and
+//! comparison of two exports.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{stderr, Write};
+use std::path::Path;
+
+use serde_json;
+
+use collector::{Backend, CommitData, Failure, Profile, Std};
+use errors::*;
+use execute::Benchmark;
+
+#[derive(Debug, Serialize)]
+struct Export<'a> {
+    /// The commit or local toolchain the results are for
+    artifact: &'a str,
+    target: &'a str,
+    stats: Vec<ExportedStat<'a>>,
+    /// Only present if the run recorded self-profile data
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    queries: Vec<ExportedQuery<'a>>,
+    errors: Vec<ExportedError<'a>>,
+}
+
+/// A single measurement.
+#[derive(Debug, Serialize)]
+struct ExportedStat<'a> {
+    benchmark: &'a str,
+    profile: Profile,
+    /// The patch applied for an incremental build, e.g. `000-base`; empty
+    /// for a plain build.
+    scenario: &'a str,
+    backend: Backend,
+    std: Std,
+    flags: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_profile: Option<&'a str>,
+    metric: &'a str,
+    /// The minimum over the iterations, or their median without outliers
+    /// for noisy statistics like `wall-time`
+    value: f64,
+    /// Each iteration's value, if recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    samples: Option<&'a [f64]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    median: Option<f64>,
+}
+
+/// The timings of one query in one build, aggregated over its invocations.
+#[derive(Debug, Serialize)]
+struct ExportedQuery<'a> {
+    benchmark: &'a str,
+    profile: Profile,
+    scenario: &'a str,
+    backend: Backend,
+    std: Std,
+    flags: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_profile: Option<&'a str>,
+    query: &'a str,
+    /// In seconds
+    self_time: f64,
+    invocations: u64,
+    cache_hits: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportedError<'a> {
+    benchmark: &'a str,
+    error: &'a str,
+}
+
+/// Write the statistics and failures in `data` to `path`.
+pub fn write(data: &CommitData, path: &Path) -> Result<()> {
+    let mut export = Export {
+        artifact: &data.commit.sha,
+        target: &data.triple,
+        stats: Vec::new(),
+        queries: Vec::new(),
+        errors: Vec::new(),
+    };
+    for (benchmark, result) in &data.benchmarks {
+        let patches = match *result {
+            Ok(ref patches) => patches,
+            Err(ref error) => {
+                export.errors.push(ExportedError {
+                    benchmark: benchmark,
+                    error: error,
+                });
+                continue;
+            }
+        };
+        for patch in patches {
+            let scenario = patch.name[benchmark.len()..].trim_left_matches('@');
+            for stat in &patch.run().stats {
+                export.stats.push(ExportedStat {
+                    benchmark: benchmark,
+                    profile: patch.profile,
+                    scenario: scenario,
+                    backend: patch.backend,
+                    std: patch.std,
+                    flags: patch.flags.as_ref().map(|f| &**f),
+                    custom_profile: patch.custom_profile.as_ref().map(|c| &**c),
+                    metric: &stat.name,
+                    value: stat.cnt,
+                    samples: if stat.samples.is_empty() {
+                        None
+                    } else {
+                        Some(&stat.samples[..])
+                    },
+                    median: if stat.samples.is_empty() {
+                        None
+                    } else {
+                        Some(stat.median())
+                    },
+                });
+            }
+            for timing in &patch.queries {
+                export.queries.push(ExportedQuery {
+                    benchmark: benchmark,
+                    profile: patch.profile,
+                    scenario: scenario,
+                    backend: patch.backend,
+                    std: patch.std,
+                    flags: patch.flags.as_ref().map(|f| &**f),
+                    custom_profile: patch.custom_profile.as_ref().map(|c| &**c),
+                    query: &timing.label,
+                    self_time: timing.self_time,
+                    invocations: timing.invocations,
+                    cache_hits: timing.cache_hits,
+                });
+            }
+        }
+    }
+    let file = File::create(path).chain_err(|| format!("failed to create {}", path.display()))?;
+    serde_json::to_writer_pretty(file, &export)?;
+    Ok(())
+}
+
+/// Which benchmarks of a run succeeded, which failed and why, and which
+/// weren't run because an earlier one failed with `--fail-fast`.
+#[derive(Debug, Serialize)]
+pub struct FailureReport<'a> {
+    pub artifact: &'a str,
+    pub succeeded: Vec<&'a str>,
+    pub failed: BTreeMap<&'a str, &'a Failure>,
+    pub skipped: Vec<&'a str>,
+}
+
+impl<'a> FailureReport<'a> {
+    /// The report of the run of `benchmarks` with the results `data`.
+    pub fn of(data: &'a CommitData, benchmarks: &'a [Benchmark]) -> FailureReport<'a> {
+        let mut report = FailureReport {
+            artifact: &data.commit.sha,
+            succeeded: Vec::new(),
+            failed: BTreeMap::new(),
+            skipped: Vec::new(),
+        };
+        for benchmark in benchmarks {
+            let name = &*benchmark.name;
+            match data.benchmarks.get(name) {
+                Some(&Ok(_)) => report.succeeded.push(name),
+                Some(&Err(_)) => {
+                    report.failed.insert(name, &data.failures[name]);
+                }
+                None => report.skipped.push(name),
+            }
+        }
+        report
+    }
+
+    /// Print how many benchmarks succeeded, and each failure, to stderr.
+    pub fn summarize(&self) -> Result<()> {
+        let mut out = stderr();
+        writeln!(
+            out,
+            "{} benchmarks: {} succeeded, {} failed, {} skipped",
+            self.succeeded.len() + self.failed.len() + self.skipped.len(),
+            self.succeeded.len(),
+            self.failed.len(),
+            self.skipped.len()
+        )?;
+        for (benchmark, failure) in &self.failed {
+            let last = failure.log.lines().rev().find(|line| !line.trim().is_empty());
+            writeln!(out, "  {} ({}): {}", benchmark, failure.kind, last.unwrap_or(""))?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file =
+            File::create(path).chain_err(|| format!("failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+/// An export read back, owning its strings.
+#[derive(Debug, Deserialize)]
+struct Imported {
+    artifact: String,
+    target: String,
+    stats: Vec<ImportedStat>,
+    #[serde(default)]
+    errors: Vec<ImportedError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportedStat {
+    benchmark: String,
+    profile: Profile,
+    scenario: String,
+    backend: Backend,
+    std: Std,
+    flags: Option<String>,
+    #[serde(default)]
+    custom_profile: Option<String>,
+    metric: String,
+    value: f64,
+    #[serde(default)]
+    samples: Option<Vec<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportedError {
+    benchmark: String,
+}
+
+/// Identifies a measured build across exports.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct TestCase {
+    benchmark: String,
+    profile: Profile,
+    scenario: String,
+    backend: Backend,
+    std: Std,
+    flags: Option<String>,
+    custom_profile: Option<String>,
+}
+
+impl TestCase {
+    /// e.g. `syn debug @020-incr-patched (flags: parallel-8)`; the backend
+    /// and std only if they aren't the defaults.
+    fn describe(&self) -> String {
+        let mut name = format!("{} {}", self.benchmark, self.profile);
+        if !self.scenario.is_empty() {
+            name.push_str(&format!(" @{}", self.scenario));
+        }
+        if self.backend != Backend::default() {
+            name.push_str(&format!(" ({})", self.backend));
+        }
+        if self.std != Std::default() {
+            name.push_str(&format!(" (std: {})", self.std));
+        }
+        if let Some(ref flags) = self.flags {
+            name.push_str(&format!(" (flags: {})", flags));
+        }
+        if let Some(ref custom_profile) = self.custom_profile {
+            name.push_str(&format!(" (custom profile: {})", custom_profile));
+        }
+        name
+    }
+}
+
+fn import(path: &Path) -> Result<Imported> {
+    let file = File::open(path).chain_err(|| format!("failed to open {}", path.display()))?;
+    serde_json::from_reader(file).chain_err(|| format!("failed to parse {}", path.display()))
+}
+
+/// The value and the samples, if any, of each test case measuring `metric`.
+fn values(export: &Imported, metric: &str) -> BTreeMap<TestCase, Value> {
+    export
+        .stats
+        .iter()
+        .filter(|stat| stat.metric == metric)
+        .map(|stat| {
+            let case = TestCase {
+                benchmark: stat.benchmark.clone(),
+                profile: stat.profile,
+                scenario: stat.scenario.clone(),
+                backend: stat.backend,
+                std: stat.std,
+                flags: stat.flags.clone(),
+                custom_profile: stat.custom_profile.clone(),
+            };
+            (case, (stat.value, stat.samples.clone()))
+        })
+        .collect()
+}
+
+/// The value and the samples, if any, of a test case in an export
+type Value = (f64, Option<Vec<f64>>);
+
+/// The change in percent of each test case measured in both `a` and `b`,
+/// whether it's significant, and the values before and after, largest change
+/// first.
+fn changes<'a>(
+    a: &'a BTreeMap<TestCase, Value>,
+    b: &BTreeMap<TestCase, Value>,
+    threshold: f64,
+) -> Vec<(f64, bool, f64, f64, &'a TestCase)> {
+    let mut changes = Vec::new();
+    for (case, &(value_a, ref samples_a)) in a {
+        if let Some(&(value_b, ref samples_b)) = b.get(case) {
+            let percent = if value_a == 0.0 {
+                0.0
+            } else {
+                (value_b - value_a) / value_a * 100.0
+            };
+            let significant =
+                percent.abs() >= threshold && !samples_overlap(samples_a, samples_b);
+            changes.push((percent, significant, value_a, value_b, case));
+        }
+    }
+    // Stable, so equal changes stay sorted by test case.
+    changes.sort_by(|x, y| y.0.abs().partial_cmp(&x.0.abs()).unwrap());
+    changes
+}
+
+/// Whether a change from `a` to `b` is noise judging by the samples of both:
+/// if the ranges of their samples overlap, another run could well have
+/// measured the opposite. Without samples, nothing can be told.
+fn samples_overlap(a: &Option<Vec<f64>>, b: &Option<Vec<f64>>) -> bool {
+    let range = |samples: &[f64]| {
+        samples.iter().fold((::std::f64::INFINITY, ::std::f64::NEG_INFINITY), |(lo, hi), &s| {
+            (lo.min(s), hi.max(s))
+        })
+    };
+    match (a.as_ref(), b.as_ref()) {
+        (Some(a), Some(b)) if !a.is_empty() && !b.is_empty() => {
+            let (a_lo, a_hi) = range(a);
+            let (b_lo, b_hi) = range(b);
+            a_lo <= b_hi && b_lo <= a_hi
+        }
+        _ => false,
+    }
+}
+
+/// Print the changes of `metric` between the exports `before` and `after`,
+/// largest first. Like on the site, changes of less than `threshold`
+/// percent are insignificant; so are those within the noise of the samples,
+/// if recorded.
+pub fn compare(before: &Path, after: &Path, metric: &str, threshold: f64) -> Result<()> {
+    let a = import(before)?;
+    let b = import(after)?;
+    if a.target != b.target {
+        warn!("comparing results for {} with results for {}", a.target, b.target);
+    }
+    let values_a = values(&a, metric);
+    let values_b = values(&b, metric);
+    if values_a.is_empty() && values_b.is_empty() {
+        bail!("neither {} nor {} has results for {}", before.display(), after.display(), metric);
+    }
+
+    let changes = changes(&values_a, &values_b, threshold);
+    println!("{}: {} -> {}", metric, a.artifact, b.artifact);
+    println!("{:>8} {:>16} {:>16}  {}", "Change", "Before", "After", "Test case");
+    let (mut regressions, mut improvements) = (0, 0);
+    for &(percent, significant, value_a, value_b, case) in &changes {
+        let mark = if !significant {
+            ' '
+        } else if percent > 0.0 {
+            regressions += 1;
+            '+'
+        } else {
+            improvements += 1;
+            '-'
+        };
+        println!(
+            "{:>+7.2}%{} {:>16.2} {:>16.2}  {}",
+            percent,
+            mark,
+            value_a,
+            value_b,
+            case.describe()
+        );
+    }
+    println!("");
+    println!(
+        "{} regressions, {} improvements, {} insignificant changes",
+        regressions,
+        improvements,
+        changes.len() - regressions - improvements
+    );
+
+    for case in values_a.keys().filter(|case| !values_b.contains_key(*case)) {
+        println!("only in {}: {}", before.display(), case.describe());
+    }
+    for case in values_b.keys().filter(|case| !values_a.contains_key(*case)) {
+        println!("only in {}: {}", after.display(), case.describe());
+    }
+    for error in &a.errors {
+        println!("failed in {}: {}", before.display(), error.benchmark);
+    }
+    for error in &b.errors {
+        println!("failed in {}: {}", after.display(), error.benchmark);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(benchmark: &str, scenario: &str, value: f64, samples: Option<&[f64]>) -> String {
+        format!(
+            r#"{{"benchmark": "{}", "profile": "debug", "scenario": "{}", "backend": "llvm",
+                "std": "static", "flags": null, "metric": "instructions:u", "value": {},
+                "samples": {}}}"#,
+            benchmark,
+            scenario,
+            value,
+            serde_json::to_string(&samples).unwrap()
+        )
+    }
+
+    fn export(artifact: &str, stats: &[String]) -> Imported {
+        let json = format!(
+            r#"{{"artifact": "{}", "target": "x86_64-unknown-linux-gnu", "stats": [{}]}}"#,
+            artifact,
+            stats.join(",")
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn changes_largest_first() {
+        let a = export(
+            "before",
+            &[
+                stat("helloworld", "", 100.0, None),
+                stat("regex", "", 100.0, None),
+                stat("regex", "010-incr", 100.0, None),
+                stat("syn", "", 100.0, Some(&[100.0, 104.0])),
+                stat("tuple-stress", "", 0.0, None),
+                stat("only-before", "", 1.0, None),
+            ],
+        );
+        let b = export(
+            "after",
+            &[
+                stat("helloworld", "", 100.5, None),
+                stat("regex", "", 90.0, None),
+                stat("regex", "010-incr", 105.0, None),
+                stat("syn", "", 103.0, Some(&[103.0, 106.0])),
+                stat("tuple-stress", "", 10.0, None),
+                stat("only-after", "", 1.0, None),
+            ],
+        );
+        let values_a = values(&a, "instructions:u");
+        let values_b = values(&b, "instructions:u");
+        assert!(values(&a, "wall-time").is_empty());
+        let changes = changes(&values_a, &values_b, 1.0)
+            .into_iter()
+            .map(|(percent, significant, _, _, case)| {
+                (case.describe(), (percent * 100.0).round() / 100.0, significant)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                (String::from("regex debug"), -10.0, true),
+                (String::from("regex debug @010-incr"), 5.0, true),
+                // Within the noise of the samples
+                (String::from("syn debug"), 3.0, false),
+                (String::from("helloworld debug"), 0.5, false),
+                // Nothing to compare to
+                (String::from("tuple-stress debug"), 0.0, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn samples_overlap_only_with_samples() {
+        let samples = |s: &[f64]| Some(s.to_vec());
+        assert!(samples_overlap(&samples(&[1.0, 3.0]), &samples(&[2.0, 4.0])));
+        assert!(samples_overlap(&samples(&[1.0, 3.0]), &samples(&[3.0])));
+        assert!(!samples_overlap(&samples(&[1.0, 2.0]), &samples(&[3.0, 4.0])));
+        assert!(!samples_overlap(&samples(&[1.0, 2.0]), &None));
+        assert!(!samples_overlap(&samples(&[]), &samples(&[1.0])));
+    }
+}
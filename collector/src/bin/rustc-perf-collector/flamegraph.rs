@@ -0,0 +1,65 @@
+//! Flamegraphs of folded stacks, and differential flamegraphs of two
+//! `profile_local` runs, drawn with [inferno](https://github.com/jonhoo/inferno).
+//!
+//! A differential flamegraph has the shape of the second profile, with each
+//! frame colored by how much its own cost grew (red) or shrank (blue)
+//! compared to the first.
+
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::Command;
+
+use errors::*;
+use execute::Profiler;
+
+/// Draw the folded stacks in `folded` into `svg`, with the costs counted in
+/// `unit`.
+pub fn draw(folded: &Path, svg: &Path, unit: &str) -> Result<()> {
+    let status = Command::new("inferno-flamegraph")
+        .arg(format!("--countname={}", unit))
+        .arg(folded)
+        .stdout(File::create(svg)?)
+        .status()
+        .chain_err(|| "could not spawn inferno-flamegraph; is inferno installed?")?;
+    if !status.success() {
+        bail!("inferno-flamegraph failed on {}", folded.display());
+    }
+    Ok(())
+}
+
+/// Draw a differential flamegraph of the folded stacks of every benchmark
+/// and patch profiled in both `before` and `after` by `profiler` into
+/// `out_dir`, as `<benchmark and patch>/<profiler>-diff.svg`.
+pub fn diff(before: &Path, after: &Path, profiler: Profiler, out_dir: &Path) -> Result<()> {
+    let (file, unit) = match profiler.folded_stacks() {
+        Some(stacks) => stacks,
+        None => bail!("the {} profiler doesn't record stacks", profiler.name()),
+    };
+    let stem = profiler.name();
+    let mut names = Vec::new();
+    for entry in fs::read_dir(before)? {
+        let name = entry?.file_name();
+        if before.join(&name).join(file).exists() && after.join(&name).join(file).exists() {
+            names.push(name);
+        }
+    }
+    names.sort();
+    for name in names {
+        let dir = out_dir.join(&name);
+        fs::create_dir_all(&dir)?;
+        let folded = dir.join(format!("{}-diff.folded", stem));
+        let status = Command::new("inferno-diff-folded")
+            .arg(before.join(&name).join(file))
+            .arg(after.join(&name).join(file))
+            .stdout(File::create(&folded)?)
+            .status()
+            .chain_err(|| "could not spawn inferno-diff-folded; is inferno installed?")?;
+        if !status.success() {
+            bail!("inferno-diff-folded failed on {}", name.to_string_lossy());
+        }
+        let svg = dir.join(format!("{}-diff.svg", stem));
+        draw(&folded, &svg, unit)?;
+        println!("{}", svg.display());
+    }
+    Ok(())
+}
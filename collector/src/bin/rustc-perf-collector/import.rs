@@ -0,0 +1,113 @@
+//! Merging the results of another output repository into this one, to
+//! consolidate results collected on several machines, or to recover results
+//! from a partial backup.
+//!
+//! Results are identified by their commit and target. Those this repository
+//! has already are kept, and the other repository's skipped, so importing
+//! twice changes nothing. Bootstrap timings and calibration runs are copied
+//! unless a file of the same name exists. Benchmark renames recorded only in
+//! the other repository are added, unless they contradict ones recorded
+//! here. Both repositories must be in the current layout, so that files can
+//! be copied as they are.
+
+use std::collections::HashSet;
+use std::fs::{self, read_dir};
+use std::path::{Path, PathBuf};
+
+use collector;
+use errors::*;
+use outrepo::Repo;
+
+/// Copy what `other` has and `repo` lacks into `repo`, and commit it. With
+/// `dry_run`, only print the files that would be copied.
+pub fn run(repo: &Repo, other: &Path, dry_run: bool) -> Result<()> {
+    let other_repo = Repo::open(other.to_path_buf(), false)
+        .chain_err(|| format!("{} isn't an output repository", other.display()))?;
+    for &(name, r) in &[("this repository", repo), ("the imported one", &other_repo)] {
+        let pending = collector::pending_migrations(&r.applied_migrations()?)
+            .map_err(Error::from)?;
+        if !pending.is_empty() {
+            bail!("{} has pending migrations; run `migrate` on it first", name);
+        }
+    }
+
+    let have = repo.times_files()?
+        .into_iter()
+        .map(|file| (file.sha, file.triple))
+        .collect::<HashSet<_>>();
+    // Pairs of files to copy, from the other repository to this one
+    let mut copy = Vec::new();
+    let mut results = 0;
+    let mut skipped = 0;
+    for file in other_repo.times_files()? {
+        if have.contains(&(file.sha.clone(), file.triple.clone())) {
+            skipped += 1;
+            continue;
+        }
+        results += 1;
+        let name = file.path.file_name().unwrap().to_owned();
+        copy.push((file.path.clone(), repo.times().join(&name)));
+        let self_profile = other_repo.self_profile().join(&name);
+        if self_profile.exists() {
+            copy.push((self_profile, repo.self_profile().join(&name)));
+        }
+    }
+    copy.extend(missing_files(&other_repo.bootstrap(), &repo.bootstrap())?);
+    copy.extend(missing_files(&other_repo.calibration(), &repo.calibration())?);
+
+    let ours = repo.renames()?;
+    let mut renames = Vec::new();
+    for rename in other_repo.renames()? {
+        match ours.iter().find(|r| r.old == rename.old) {
+            Some(r) if *r == rename => {}
+            Some(r) => warn!(
+                "not importing the rename of {} to {}; it was renamed to {} here",
+                rename.old,
+                rename.new,
+                r.new
+            ),
+            None => renames.push(rename),
+        }
+    }
+
+    if dry_run {
+        for &(ref from, ref to) in &copy {
+            println!("{} -> {}", from.display(), to.display());
+        }
+        for rename in &renames {
+            println!("rename {} to {}", rename.old, rename.new);
+        }
+        println!("{} results already present", skipped);
+        return Ok(());
+    }
+    for &(ref from, ref to) in &copy {
+        info!("copying {} to {}", from.display(), to.display());
+        fs::copy(from, to).chain_err(|| format!("failed to copy {}", from.display()))?;
+    }
+    info!("skipped {} results already present", skipped);
+    repo.imported(
+        renames,
+        &format!("import {} files from {}", copy.len(), other.display()),
+    )?;
+    println!(
+        "imported {} files, {} of them results; {} results were present already",
+        copy.len(),
+        results,
+        skipped
+    );
+    Ok(())
+}
+
+/// Pairs of the files in `from` without a file of the same name in `to`,
+/// and where to copy them.
+fn missing_files(from: &Path, to: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut missing = Vec::new();
+    for entry in read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if !target.exists() {
+            missing.push((entry.path(), target));
+        }
+    }
+    Ok(missing)
+}
@@ -0,0 +1,412 @@
+//! Lines of LLVM IR per function, like `cargo llvm-lines`, to track code
+//! bloat from monomorphization.
+//!
+//! The `llvm-lines` profiler has `rustc-fake` copy the unoptimized LLVM IR
+//! of the benchmarked crate into the profile directory. Functions are
+//! grouped by their demangled name without the hash, so the instances of a
+//! generic function count as copies of one function.
+//!
+//! The `llvm-ir` profiler keeps the IR as optimized for the build's
+//! profile instead, for reading; only its total size is compared here.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde_json;
+
+use errors::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Function {
+    name: String,
+    lines: u64,
+    copies: u64,
+}
+
+/// Count the lines of the functions in the `.ll` files in `dir`, writing a
+/// table to `llvm-lines` and the same data to `llvm-lines.json`, largest
+/// first.
+pub fn summarize(dir: &Path) -> Result<()> {
+    let mut functions: HashMap<String, Function> = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "ll") {
+            continue;
+        }
+        let mut ir = String::new();
+        File::open(&path)?.read_to_string(&mut ir)?;
+        for (name, lines) in count_lines(&ir) {
+            let function = functions.entry(name.clone()).or_insert_with(|| Function {
+                name: name,
+                lines: 0,
+                copies: 0,
+            });
+            function.lines += lines;
+            function.copies += 1;
+        }
+    }
+    if functions.is_empty() {
+        bail!("no LLVM IR in {}", dir.display());
+    }
+    let mut functions = functions.into_iter().map(|(_, f)| f).collect::<Vec<_>>();
+    functions.sort_by(|a, b| b.lines.cmp(&a.lines).then_with(|| a.name.cmp(&b.name)));
+
+    let total_lines = functions.iter().map(|f| f.lines).sum::<u64>();
+    let total_copies = functions.iter().map(|f| f.copies).sum::<u64>();
+    let mut table = File::create(dir.join("llvm-lines"))?;
+    writeln!(table, "{:>8} {:>7}  {}", "Lines", "Copies", "Function name")?;
+    writeln!(table, "{:>8} {:>7}  (TOTAL)", total_lines, total_copies)?;
+    for f in &functions {
+        writeln!(table, "{:>8} {:>7}  {}", f.lines, f.copies, f.name)?;
+    }
+    serde_json::to_writer(&mut File::create(dir.join("llvm-lines.json"))?, &functions)?;
+    Ok(())
+}
+
+/// Compare the `llvm-lines.json` of each benchmark and patch profiled in
+/// both `before` and `after`, printing the `limit` functions whose line
+/// counts changed most.
+pub fn diff(before: &Path, after: &Path, limit: usize) -> Result<()> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(before)? {
+        let name = entry?.file_name();
+        if before.join(&name).join("llvm-lines.json").exists()
+            && after.join(&name).join("llvm-lines.json").exists()
+        {
+            names.push(name);
+        }
+    }
+    if names.is_empty() {
+        bail!(
+            "no llvm-lines profiles in both {} and {}",
+            before.display(),
+            after.display()
+        );
+    }
+    names.sort();
+
+    for name in names {
+        let a = load(&before.join(&name))?;
+        let b = load(&after.join(&name))?;
+        let functions = a.keys().chain(b.keys()).collect::<BTreeSet<_>>();
+        let mut changes = functions
+            .into_iter()
+            .map(|function| {
+                let (lines_a, copies_a) = a.get(function).map_or((0, 0), |f| (f.lines, f.copies));
+                let (lines_b, copies_b) = b.get(function).map_or((0, 0), |f| (f.lines, f.copies));
+                (
+                    lines_b as i64 - lines_a as i64,
+                    copies_b as i64 - copies_a as i64,
+                    function,
+                )
+            })
+            .filter(|&(lines, copies, _)| lines != 0 || copies != 0)
+            .collect::<Vec<_>>();
+        // Stable, so equal changes stay sorted by name.
+        changes.sort_by_key(|&(lines, _, _)| -lines.abs());
+
+        let total = |functions: &HashMap<String, Function>| -> i64 {
+            functions.values().map(|f| f.lines as i64).sum()
+        };
+        println!(
+            "{}: {} -> {} lines ({:+})",
+            name.to_string_lossy(),
+            total(&a),
+            total(&b),
+            total(&b) - total(&a)
+        );
+        for &(lines, copies, function) in changes.iter().take(limit) {
+            println!("{:>+8} {:>+7}  {}", lines, copies, function);
+        }
+        println!("");
+    }
+    Ok(())
+}
+
+/// The number of lines of the `.ll` files in `dir`, an error if there are
+/// none.
+pub fn total_ir_lines(dir: &Path) -> Result<u64> {
+    let mut total = 0;
+    let mut found = false;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "ll") {
+            continue;
+        }
+        let mut ir = String::new();
+        File::open(&path)?.read_to_string(&mut ir)?;
+        total += ir.lines().count() as u64;
+        found = true;
+    }
+    if !found {
+        bail!("no LLVM IR in {}", dir.display());
+    }
+    Ok(total)
+}
+
+/// Print the total lines of LLVM IR of each benchmark and patch profiled
+/// with the `llvm-ir` profiler in both `before` and `after`.
+pub fn diff_totals(before: &Path, after: &Path) -> Result<()> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(before)? {
+        let name = entry?.file_name();
+        if after.join(&name).is_dir() {
+            names.push(name);
+        }
+    }
+    names.sort();
+    println!("{:>10} {:>10} {:>8}  {}", "Before", "After", "Change", "Benchmark");
+    for name in names {
+        let a = total_ir_lines(&before.join(&name))?;
+        let b = total_ir_lines(&after.join(&name))?;
+        println!(
+            "{:>10} {:>10} {:>+7.1}%  {}",
+            a,
+            b,
+            (b as f64 - a as f64) / a as f64 * 100.0,
+            name.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+fn load(dir: &Path) -> Result<HashMap<String, Function>> {
+    let path = dir.join("llvm-lines.json");
+    let file = File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+    let functions: Vec<Function> = serde_json::from_reader(file)?;
+    Ok(functions.into_iter().map(|f| (f.name.clone(), f)).collect())
+}
+
+/// The demangled name and number of lines of each function defined in `ir`.
+fn count_lines(ir: &str) -> Vec<(String, u64)> {
+    let mut functions = Vec::new();
+    let mut current: Option<(String, u64)> = None;
+    for line in ir.lines() {
+        if current.is_none() {
+            if line.starts_with("define ") {
+                current = Some((function_name(line), 0));
+            } else {
+                continue;
+            }
+        }
+        let done = {
+            let function = current.as_mut().unwrap();
+            function.1 += 1;
+            line == "}"
+        };
+        if done {
+            let (name, lines) = current.take().unwrap();
+            functions.push((demangle(&name), lines));
+        }
+    }
+    functions
+}
+
+/// The symbol in `define internal void @_ZN4core3ptr13drop_in_place17h0123E(...`,
+/// which may be quoted.
+fn function_name(define: &str) -> String {
+    let symbol = match define.find('@') {
+        Some(idx) => &define[idx + 1..],
+        None => return String::new(),
+    };
+    if symbol.starts_with('"') {
+        symbol[1..].split('"').next().unwrap_or("").to_string()
+    } else {
+        symbol.split('(').next().unwrap_or("").to_string()
+    }
+}
+
+/// Demangle a legacy Rust symbol such as `_ZN4core3ptr13drop_in_place17h0123456789abcdefE`
+/// to `core::ptr::drop_in_place`, dropping the hash. Other symbols are
+/// returned unchanged.
+fn demangle(symbol: &str) -> String {
+    if !symbol.starts_with("_ZN") {
+        return symbol.to_string();
+    }
+    let mut rest = &symbol[3..];
+    let mut components = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.chars().take_while(|c| c.is_digit(10)).count();
+        let len = match rest[..digits].parse::<usize>() {
+            Ok(len) if digits + len <= rest.len() => len,
+            _ => return symbol.to_string(),
+        };
+        components.push(&rest[digits..digits + len]);
+        rest = &rest[digits + len..];
+    }
+    let is_hash = |c: &&str| {
+        c.len() == 17 && c.starts_with('h') && c[1..].chars().all(|c| c.is_digit(16))
+    };
+    if components.last().map_or(false, is_hash) {
+        components.pop();
+    }
+    components
+        .iter()
+        .map(|c| unescape(c))
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// Undo the escaping of characters that aren't valid in symbols, e.g.
+/// `$LT$` for `<` and `$u20$` for a space.
+fn unescape(component: &str) -> String {
+    let component = if component.starts_with("_$") {
+        &component[1..]
+    } else {
+        component
+    };
+    let mut out = String::new();
+    let mut rest = component;
+    while !rest.is_empty() {
+        if rest.starts_with("..") {
+            out.push_str("::");
+            rest = &rest[2..];
+        } else if rest.starts_with('$') {
+            let end = match rest[1..].find('$') {
+                Some(end) => end + 1,
+                None => {
+                    out.push_str(rest);
+                    break;
+                }
+            };
+            let escaped = match &rest[1..end] {
+                "SP" => Some('@'),
+                "BP" => Some('*'),
+                "RF" => Some('&'),
+                "LT" => Some('<'),
+                "GT" => Some('>'),
+                "LP" => Some('('),
+                "RP" => Some(')'),
+                "C" => Some(','),
+                code if code.starts_with('u') => {
+                    u32::from_str_radix(&code[1..], 16).ok().and_then(::std::char::from_u32)
+                }
+                _ => None,
+            };
+            match escaped {
+                Some(c) => out.push(c),
+                None => out.push_str(&rest[..end + 1]),
+            }
+            rest = &rest[end + 1..];
+        } else {
+            let c = rest.chars().next().unwrap();
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Excerpt of the unoptimized IR rustc emits for the `llvm-lines`
+    /// profiler, with legacy symbol mangling.
+    const IR: &str = r#"; ModuleID = 'foo.f3f9b5a1d8c3a2b1-cgu.0'
+source_filename = "foo.f3f9b5a1d8c3a2b1-cgu.0"
+
+; <u64 as core::convert::From<u8>>::from
+; Function Attrs: alwaysinline nonlazybind uwtable
+define internal i64 @_RNvXs12_NtNtCsgEmfK2I1SDS_4core7convert3numyINtB8_4FromhE4from(i8 %small) unnamed_addr #0 {
+start:
+  %_0 = zext i8 %small to i64
+  ret i64 %_0
+}
+
+; foo::sum
+; Function Attrs: nonlazybind uwtable
+define i64 @_ZN3foo3sum17hc34bbd880bbdf1f7E(ptr align 2 %xs.0, i64 %xs.1) unnamed_addr #1 {
+start:
+; call core::slice::<impl [T]>::iter
+  %0 = call { ptr, ptr } @"_ZN4core5slice29_$LT$impl$u20$$u5b$T$u5d$$GT$4iter17h75c60ea52c4c5e60E"(ptr align 2 %xs.0, i64 %xs.1) #7
+  %_3.0 = extractvalue { ptr, ptr } %0, 0
+  %_3.1 = extractvalue { ptr, ptr } %0, 1
+; call core::iter::traits::iterator::Iterator::map
+  %1 = call { ptr, ptr } @_ZN4core4iter6traits8iterator8Iterator3map17h2851c1abde1fa519E(ptr %_3.0, ptr %_3.1) #7
+  %_2.0 = extractvalue { ptr, ptr } %1, 0
+  %_2.1 = extractvalue { ptr, ptr } %1, 1
+; call core::iter::traits::iterator::Iterator::sum
+  %_0 = call i64 @_ZN4core4iter6traits8iterator8Iterator3sum17hea767fac8d7ae54bE(ptr %_2.0, ptr %_2.1)
+  ret i64 %_0
+}
+
+; foo::sum::{{closure}}
+; Function Attrs: inlinehint nonlazybind uwtable
+define i64 @"_ZN3foo3sum28_$u7b$$u7b$closure$u7d$$u7d$17hb4580fb01bf78301E"(ptr align 1 %_1, ptr align 1 %_2) unnamed_addr #2 {
+start:
+  %x = load i8, ptr %_2, align 1
+; call <T as core::convert::Into<U>>::into
+  %_0 = call i64 @"_ZN50_$LT$T$u20$as$u20$core..convert..Into$LT$U$GT$$GT$4into17h7312c8b9e49a316bE"(i8 %x, ptr align 8 @alloc_74ca7cfd8c76881f5d719f960cc1062a) #7
+  ret i64 %_0
+}
+
+declare void @_RNvNtCsgEmfK2I1SDS_4core9panicking18panic_nounwind_fmt(ptr, ptr, i1 zeroext, ptr align 8) unnamed_addr #5
+"#;
+
+    #[test]
+    fn count_lines_of_functions() {
+        assert_eq!(
+            count_lines(IR),
+            vec![
+                (
+                    String::from(
+                        "_RNvXs12_NtNtCsgEmfK2I1SDS_4core7convert3numyINtB8_4FromhE4from"
+                    ),
+                    5,
+                ),
+                (String::from("foo::sum"), 14),
+                (String::from("foo::sum::{{closure}}"), 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn demangle_legacy_symbols() {
+        assert_eq!(
+            demangle("_ZN4core3ptr13drop_in_place17h0123456789abcdefE"),
+            "core::ptr::drop_in_place"
+        );
+        assert_eq!(
+            demangle(
+                "_ZN102_$LT$core..iter..adapters..map..Map$LT$I$C$F$GT$$u20$as$u20$core..iter..\
+                 traits..iterator..Iterator$GT$4fold17h3a01bbd89948d331E"
+            ),
+            "<core::iter::adapters::map::Map<I,F> as core::iter::traits::iterator::Iterator>::fold"
+        );
+        assert_eq!(
+            demangle(
+                "_ZN4core3num23_$LT$impl$u20$usize$GT$13unchecked_add18precondition_check\
+                 17h43749cdef3df2bf7E"
+            ),
+            "core::num::<impl usize>::unchecked_add::precondition_check"
+        );
+        assert_eq!(
+            demangle(
+                "_ZN4core3ptr9const_ptr33_$LT$impl$u20$$BP$const$u20$T$GT$20offset_from_\
+                 unsigned18precondition_check17h2275738c04687b3bE"
+            ),
+            "core::ptr::const_ptr::<impl *const T>::offset_from_unsigned::precondition_check"
+        );
+        // Not legacy Rust symbols, or malformed ones
+        assert_eq!(demangle("main"), "main");
+        assert_eq!(demangle("_ZN3foo99sumE"), "_ZN3foo99sumE");
+    }
+
+    #[test]
+    fn function_names() {
+        assert_eq!(
+            function_name("define i64 @_ZN3foo1a17he9a10c55a6e91343E() unnamed_addr #1 {"),
+            "_ZN3foo1a17he9a10c55a6e91343E"
+        );
+        assert_eq!(
+            function_name(
+                "define i64 @\"_ZN3foo3sum28_$u7b$$u7b$closure$u7d$$u7d$17hb4580fb01bf78301E\"\
+                 (ptr align 1 %_1, ptr align 1 %_2) unnamed_addr #2 {"
+            ),
+            "_ZN3foo3sum28_$u7b$$u7b$closure$u7d$$u7d$17hb4580fb01bf78301E"
+        );
+    }
+}
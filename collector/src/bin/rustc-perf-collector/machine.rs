@@ -0,0 +1,85 @@
+//! Identifying the collector and the machine it runs on, which is recorded
+//! with its results.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+use collector::Machine;
+use config::Config;
+use environment;
+
+/// The configured collector ID, or the host name.
+pub fn collector_id(config: &Config) -> String {
+    match config.collector_id {
+        Some(ref id) => id.clone(),
+        None => read_trimmed(Path::new("/proc/sys/kernel/hostname"))
+            .unwrap_or_else(|| String::from("unknown")),
+    }
+}
+
+/// Describe this machine, about to benchmark `rustc`. Parts that can't be
+/// determined, e.g. on other platforms than Linux, are `unknown` or missing.
+pub fn detect(config: &Config, rustc: &Path) -> Machine {
+    let cpu = cpu_model().unwrap_or_else(|| String::from("unknown"));
+    // The first CPU builds run on; they should all be set up alike.
+    let build_cpu = config
+        .cgroup
+        .as_ref()
+        .and_then(|cgroup| cgroup.cpu_list().first().cloned())
+        .unwrap_or(0);
+    let governor = format!("/sys/devices/system/cpu/cpu{}/cpufreq/scaling_governor", build_cpu);
+    Machine {
+        class: config.machine_class.clone().unwrap_or_else(|| cpu.clone()),
+        cpu: cpu,
+        kernel: read_trimmed(Path::new("/proc/sys/kernel/osrelease"))
+            .unwrap_or_else(|| String::from("unknown")),
+        mitigations: mitigations(),
+        environment: Some(environment::hash()),
+        microcode: cpuinfo_field("microcode"),
+        aslr: read_trimmed(Path::new("/proc/sys/kernel/randomize_va_space")),
+        governor: read_trimmed(Path::new(&governor)),
+        host: host_triple(rustc),
+    }
+}
+
+fn cpu_model() -> Option<String> {
+    cpuinfo_field("model name")
+}
+
+/// The value of `field` for the first CPU in `/proc/cpuinfo`.
+fn cpuinfo_field(field: &str) -> Option<String> {
+    let cpuinfo = read_trimmed(Path::new("/proc/cpuinfo"))?;
+    let line = cpuinfo.lines().find(|line| line.starts_with(field))?;
+    Some(line.splitn(2, ':').nth(1)?.trim().to_string())
+}
+
+/// The `host` of `rustc -vV`.
+fn host_triple(rustc: &Path) -> Option<String> {
+    let output = Command::new(rustc).arg("-vV").output().ok()?;
+    let version = String::from_utf8(output.stdout).ok()?;
+    let line = version.lines().find(|line| line.starts_with("host: "))?;
+    Some(line["host: ".len()..].trim().to_string())
+}
+
+fn mitigations() -> BTreeMap<String, String> {
+    let mut mitigations = BTreeMap::new();
+    let entries = match fs::read_dir("/sys/devices/system/cpu/vulnerabilities") {
+        Ok(entries) => entries,
+        Err(_) => return mitigations,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if let Some(state) = read_trimmed(&entry.path()) {
+            mitigations.insert(entry.file_name().to_string_lossy().into_owned(), state);
+        }
+    }
+    mitigations
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let mut contents = String::new();
+    File::open(path).ok()?.read_to_string(&mut contents).ok()?;
+    Some(contents.trim().to_string())
+}
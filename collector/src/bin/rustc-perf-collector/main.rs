@@ -6,13 +6,18 @@ extern crate clap;
 extern crate env_logger;
 #[macro_use]
 extern crate error_chain;
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate reqwest;
 extern crate rust_sysroot;
 extern crate collector;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate serde_json;
 extern crate tempdir;
+extern crate toml;
 
 mod errors {
     // Create the Error, ErrorKind, ResultExt, and Result types
@@ -31,30 +36,100 @@ use errors::*;
 
 quick_main!(run);
 
+use std::cmp;
+use std::env;
 use std::fs;
+use std::ffi::OsStr;
 use std::str;
 use std::path::{Path, PathBuf};
 use std::io::{stderr, stdout, Write};
 use std::collections::BTreeMap;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 
-use collector::{Commit, CommitData, Date};
+use collector::{Backend, BootstrapData, CalibrationRun, Commit, CommitData, Date, Failure, Patch,
+                Profile, Rename, Std};
 use rust_sysroot::git::Commit as GitCommit;
 use rust_sysroot::sysroot::Sysroot;
+use tempdir::TempDir;
 
+mod bisect;
+mod bootstrap;
+mod budget;
+mod cache;
+mod cgroup;
+mod candidate;
+mod checkpoint;
+mod clippy;
+mod config;
+mod durations;
+mod environment;
 mod git;
+mod import;
 mod execute;
+mod export;
+mod flamegraph;
+mod llvm_lines;
+mod machine;
+mod manifest;
+mod memory;
+mod migrate;
 mod outrepo;
+mod parallel;
+mod perf_record;
+mod plan;
+mod prune;
+mod queue;
+mod sandbox;
+mod shell;
+mod supervise;
+mod toolchain;
+mod validate;
+
+use cache::Cache;
+use checkpoint::Checkpoint;
+use config::{Config, SiteConfig};
+use durations::Durations;
+use execute::{Benchmark, BenchmarkConfig, Profiler};
+
+/// Whether `patches` has results for every profile, backend, std, flag set and
+/// custom profile in `config`.
+fn covers_config(patches: &[Patch], config: &Config) -> bool {
+    config.profiles.iter().all(|profile| {
+        config.backends.iter().all(|b| {
+            config.std.iter().all(|std| {
+                config.flag_set_names(*profile).into_iter().all(|flags| {
+                    config.custom_profile_names().into_iter().all(|custom| {
+                        patches.iter().any(|p| {
+                            p.profile == *profile && p.backend == *b && p.std == *std
+                                && p.flags.as_ref().map(|f| &**f) == flags
+                                && p.custom_profile.as_ref().map(|c| &**c) == custom
+                        })
+                    })
+                })
+            })
+        })
+    })
+}
 
-use execute::Benchmark;
+/// Never cancel a run; for runs not claimed from the site's queue.
+fn never() -> bool {
+    false
+}
 
+/// Benchmark `commit`. Before each benchmark of a sequential run, `canceled`
+/// is asked whether the results are still wanted; if not, `None` is
+/// returned.
 fn bench_commit(
     commit: &GitCommit,
     repo: Option<&outrepo::Repo>,
     sysroot: Sysroot,
     benchmarks: &[Benchmark],
-) -> CommitData {
+    config: &Config,
+    jobs: usize,
+    checkpoint: Option<&Checkpoint>,
+    canceled: &Fn() -> bool,
+) -> Option<CommitData> {
     info!(
         "benchmarking commit {} ({}) for triple {}",
         commit.sha,
@@ -62,42 +137,127 @@ fn bench_commit(
         sysroot.triple
     );
 
+    let manifest = match manifest::compute(benchmarks) {
+        Ok(manifest) => Some(manifest),
+        Err(e) => {
+            warn!("failed to compute suite manifest: {:?}", e);
+            None
+        }
+    };
+
+    let mut benchmark_info = BTreeMap::new();
+    for benchmark in benchmarks {
+        match manifest::describe(benchmark) {
+            Ok(info) => {
+                benchmark_info.insert(benchmark.name.clone(), info);
+            }
+            Err(e) => warn!("failed to describe {}: {:?}", benchmark.name, e),
+        }
+    }
+
+    // Before benchmarking, so that the machine is described as it was when
+    // the builds started.
+    let machine = machine::detect(config, &sysroot.rustc);
     let existing_data = repo.and_then(|r| r.load_commit_data(&commit, &sysroot.triple).ok());
+    let durations = repo.map(|r| Durations::open(r.durations_file()));
+    let triple = sysroot.triple.clone();
 
-    let results: BTreeMap<_, _> = benchmarks
-        .iter()
-        .map(|benchmark| {
-            if let Some(ref data) = existing_data {
-                if let Some(result) = data.benchmarks.get(&benchmark.name) {
-                    return (benchmark.name.clone(), result.clone());
+    let mut results = BTreeMap::new();
+    // Benchmarks this collector ran before it was interrupted
+    let mut restored = Vec::new();
+    let mut to_run = Vec::new();
+    for benchmark in benchmarks {
+        if let Some(patches) = checkpoint.and_then(|c| c.get(&benchmark.name)) {
+            if covers_config(&patches, config) {
+                restored.push((benchmark.name.clone(), Ok(patches)));
+                continue;
+            }
+        }
+        if let Some(ref data) = existing_data {
+            if let Some(result) = data.benchmarks.get(&benchmark.name) {
+                // Rerun if the existing data lacks one of the requested profiles,
+                // backends or flag sets.
+                let complete = result
+                    .as_ref()
+                    .map_or(true, |patches| covers_config(patches, config));
+                if complete {
+                    results.insert(benchmark.name.clone(), result.clone());
+                    continue;
                 }
             }
+        }
+        to_run.push(benchmark.clone());
+    }
 
-            let result = benchmark.run(&sysroot);
-
-            if result.is_err() {
-                info!(
-                    "failure to benchmark {}, recorded: {:?}",
-                    benchmark.name,
-                    result
-                );
+    let ran = if jobs > 1 {
+        parallel::run(sysroot, to_run, config, jobs, checkpoint.cloned(), durations)
+    } else {
+        let mut ran = Vec::new();
+        for benchmark in &to_run {
+            if canceled() {
+                info!("abandoning commit {}: canceled", commit.sha);
+                return None;
+            }
+            let result = benchmark.run_all(&sysroot, config, None, durations.as_ref());
+            if let (Some(checkpoint), &Ok(ref patches)) = (checkpoint, &result) {
+                if let Err(e) = checkpoint.record(&benchmark.name, patches) {
+                    warn!("failed to checkpoint {}: {:?}", benchmark.name, e);
+                }
+            }
+            let failed = result.is_err();
+            ran.push((benchmark.name.clone(), result.map_err(|e| format!("{:?}", e))));
+            if failed && config.fail_fast {
+                info!("not running the remaining benchmarks: {} failed", benchmark.name);
+                break;
             }
+        }
+        ran
+    };
 
-            (
-                benchmark.name.clone(),
-                result.map_err(|e| format!("{:?}", e)),
-            )
+    // Results kept from earlier runs keep the collectors that measured them.
+    let mut collectors = existing_data
+        .map(|data| data.collectors)
+        .unwrap_or_default();
+    if !restored.is_empty() || !ran.is_empty() {
+        let collector_id = machine::collector_id(config);
+        collectors.insert(collector_id.clone(), machine);
+        for (name, mut result) in restored.into_iter().chain(ran) {
+            match result {
+                Ok(ref mut patches) => for patch in patches {
+                    patch.collector = Some(collector_id.clone());
+                },
+                Err(ref err) => info!("failure to benchmark {}, recorded: {:?}", name, err),
+            }
+            results.insert(name, result);
+        }
+    }
+    let failures = results
+        .iter()
+        .filter_map(|(name, result)| {
+            result
+                .as_ref()
+                .err()
+                .map(|err| (name.clone(), Failure::of(err)))
         })
         .collect();
 
-    CommitData {
+    Some(CommitData {
         commit: Commit {
             sha: commit.sha.clone(),
             date: Date(commit.date),
         },
-        triple: sysroot.triple.clone(),
+        triple: triple,
         benchmarks: results,
-    }
+        manifest: manifest,
+        metrics: Some(config.metrics.clone()),
+        categories: benchmarks
+            .iter()
+            .map(|b| (b.name.clone(), b.config.category))
+            .collect(),
+        benchmark_info: benchmark_info,
+        collectors: collectors,
+        failures: failures,
+    })
 }
 
 fn get_benchmarks(benchmark_dir: &Path, filter: Option<&str>) -> Result<Vec<Benchmark>> {
@@ -115,42 +275,118 @@ fn get_benchmarks(benchmark_dir: &Path, filter: Option<&str>) -> Result<Vec<Benc
             continue;
         }
 
-        if let Some(filter) = filter {
-            if !name.contains(filter) {
-                info!("benchmark {} - filtered", name);
-                continue;
-            }
-        }
-
-        info!("benchmark {} - REGISTERED", name);
-        benchmarks.push(Benchmark {
+        let benchmark = Benchmark {
+            config: BenchmarkConfig::load(&path)?,
             path: path,
             name: name,
-        });
+            scenarios: None,
+        };
+        let subsets = benchmark.subsets();
+        for benchmark in Some(benchmark).into_iter().chain(subsets) {
+            if let Some(filter) = filter {
+                if !benchmark.name.contains(filter) {
+                    info!("benchmark {} - filtered", benchmark.name);
+                    continue;
+                }
+            }
+
+            info!("benchmark {} - REGISTERED", benchmark.name);
+            benchmarks.push(benchmark);
+        }
     }
     Ok(benchmarks)
 }
 
+/// The toolchain of a local `rustc`, with the configured Cargo if any.
+fn local_sysroot(
+    commit: &GitCommit,
+    rustc: &str,
+    config: &Config,
+    preserve_sysroot: bool,
+) -> Result<Sysroot> {
+    let mut sysroot =
+        Sysroot::with_local_rustc(commit, rustc, &config.target, preserve_sysroot, false)?;
+    if let Some(ref cargo) = config.cargo {
+        sysroot.cargo = cargo
+            .canonicalize()
+            .chain_err(|| format!("cargo {} doesn't exist", cargo.display()))?;
+        info!("building with {}", sysroot.cargo.display());
+    }
+    Ok(sysroot)
+}
+
+/// Benchmark `commit`, with the toolchain of `local_rustc` if it is a
+/// locally built one, and record the results, unless `canceled` says they
+/// are no longer wanted. The checkpoint of a canceled run is kept, in case
+/// the commit is queued again.
 fn process_commit(
+    repo: &outrepo::Repo,
+    commit: &GitCommit,
+    local_rustc: Option<&str>,
+    benchmarks: &[Benchmark],
+    config: &Config,
+    preserve_sysroot: bool,
+    canceled: &Fn() -> bool,
+) -> Result<()> {
+    let sysroot = match local_rustc {
+        Some(rustc) => local_sysroot(commit, rustc, config, preserve_sysroot)?,
+        None => toolchain::install(commit, config, preserve_sysroot)?,
+    };
+    let checkpoint = Checkpoint::open(repo.checkpoint_file(), &commit.sha, &config.target);
+    let data = bench_commit(
+        commit,
+        Some(repo),
+        sysroot,
+        benchmarks,
+        config,
+        1,
+        Some(&checkpoint),
+        canceled,
+    );
+    match data {
+        Some(data) => {
+            repo.success(&data)?;
+            checkpoint.finish()
+        }
+        None => Ok(()),
+    }
+}
+
+/// Benchmark the calibration artifact `commit` from scratch and record the
+/// results as a calibration run, unless `canceled` says they are no longer
+/// wanted.
+fn process_calibration(
     repo: &outrepo::Repo,
     commit: &GitCommit,
     benchmarks: &[Benchmark],
+    config: &Config,
     preserve_sysroot: bool,
+    canceled: &Fn() -> bool,
 ) -> Result<()> {
-    let sysroot = Sysroot::install(commit, "x86_64-unknown-linux-gnu", preserve_sysroot, false)?;
-    repo.success(&bench_commit(commit, Some(repo), sysroot, benchmarks))
+    let sysroot = toolchain::install(commit, config, preserve_sysroot)?;
+    let date = Utc::now();
+    // Neither earlier results nor a checkpoint may be reused: the point is
+    // to measure again.
+    match bench_commit(commit, None, sysroot, benchmarks, config, 1, None, canceled) {
+        Some(data) => repo.calibration_success(&CalibrationRun {
+            date: date,
+            data: data,
+        }),
+        None => Ok(()),
+    }
 }
 
 fn process_retries(
     commits: &[GitCommit],
     repo: &mut outrepo::Repo,
     benchmarks: &[Benchmark],
+    config: &Config,
     preserve_sysroot: bool,
 ) -> Result<()> {
     while let Some(retry) = repo.next_retry() {
         info!("retrying {}", retry);
         let commit = commits.iter().find(|commit| commit.sha == retry).unwrap();
-        process_commit(repo, commit, benchmarks, preserve_sysroot)?;
+        process_commit(repo, commit, None, benchmarks, config, preserve_sysroot, &never)?;
     }
     Ok(())
 }
@@ -159,17 +395,18 @@ fn process_commits(
     commits: &[GitCommit],
     repo: &outrepo::Repo,
     benchmarks: &[Benchmark],
+    config: &Config,
     preserve_sysroot: bool,
 ) -> Result<()> {
     println!("processing commits");
     if !commits.is_empty() {
         let to_process =
-            repo.find_missing_commits(commits, benchmarks, "x86_64-unknown-linux-gnu")?;
+            repo.find_missing_commits(commits, benchmarks, &config.target)?;
         // take 3 from the end -- this means that for each bors commit (which takes ~3 hours) we
         // test 3, which should allow us to eventually test all commits, but also keep up with the
         // latest rustc
         for commit in to_process.iter().rev().take(3) {
-            process_commit(repo, &commit, &benchmarks, preserve_sysroot)?;
+            process_commit(repo, &commit, None, &benchmarks, config, preserve_sysroot, &never)?;
         }
     } else {
         info!("Nothing to do; no commits.");
@@ -179,52 +416,391 @@ fn process_commits(
 
 fn run() -> Result<i32> {
     env_logger::init().expect("logger initialization successful");
-    git::fetch_rust(Path::new("rust.git"))?;
 
     let matches = clap_app!(rustc_perf_collector =>
        (version: "0.1")
        (author: "The Rust Compiler Team")
        (about: "Collects Rust performance data")
-       (@arg benchmarks_dir: --benchmarks +required +takes_value "Sets the directory benchmarks are found in")
+       (@arg benchmarks_dir: --benchmarks +takes_value "Sets the directory benchmarks are found in; required by all subcommands but diff_llvm_lines and compare_local")
        (@arg filter: --filter +takes_value "Run only benchmarks that contain this")
+       (@arg config: --config +takes_value "Collector configuration file declaring the metrics and backends to gather")
+       (@arg target: --target +takes_value "Target triple to benchmark; overrides the config")
+       (@arg backend: --backend +takes_value "Comma-separated codegen backends to benchmark (llvm, cranelift); overrides the config")
+       (@arg profile: --profile +takes_value "Comma-separated profiles to benchmark (debug, clippy); overrides the config")
+       (@arg std: --std +takes_value "Comma-separated ways to get the standard library (static, dynamic, build-std); overrides the config")
+       (@arg iterations: --iterations +takes_value "How often to build each benchmark; overrides the config")
        (@arg preserve_sysroots: -p --preserve "Don't delete sysroots after running.")
        (@arg sync_git: --("sync-git") "Synchronize repository with remote")
-       (@arg output_repo: --("output-repo") +required +takes_value "Repository to output to")
+       (@arg output_repo: --("output-repo") +takes_value "Repository to output to; required by all subcommands but validate_benchmarks, diff_llvm_lines and compare_local")
        (@subcommand process =>
            (about: "syncs to git and collects performance data for all versions")
        )
+       (@subcommand process_queue =>
+           (about: "claims the next commit queued on the site and benchmarks it")
+           (@arg SITE: --site +takes_value "URL of the site, e.g. https://perf.rust-lang.org; SITE_TOKEN must hold a collector token. Defaults to the configured sites")
+       )
+       (@subcommand register_toolchain =>
+           (about: "queues a locally built rustc on the site, to be benchmarked by this collector with process_queue")
+           (@arg NAME: +required +takes_value "Name of the toolchain; its results are recorded as the artifact local-<NAME>")
+           (@arg RUSTC: +required +takes_value "The rustc to benchmark, or the sysroot or rust checkout it was built in, e.g. build/x86_64-unknown-linux-gnu/stage2")
+           (@arg BASE: --base +takes_value "The master commit the toolchain was built from; its date is recorded, and the site compares to it")
+           (@arg SITE: --site +takes_value "URL of the site; SITE_TOKEN must hold a collector token. Defaults to the configured site of the highest priority")
+       )
        (@subcommand bench_commit =>
            (about: "benchmark a bors merge from AWS and output data to stdout")
            (@arg COMMIT: +required +takes_value "Commit hash to bench")
        )
+       (@subcommand calibrate =>
+           (about: "benchmark the calibration artifact again, recording the results as a calibration run")
+           (@arg COMMIT: +required +takes_value "Commit hash of the calibration artifact")
+       )
        (@subcommand bench_local =>
            (about: "benchmark a bors merge from AWS and output data to stdout")
            (@arg COMMIT: --commit +required +takes_value "Commit hash to associate benchmark results with")
            (@arg DATE: --date +required +takes_value "Date to associate benchmark result with, in the RFC3339 \"YYYY-MM-DDTHH:MM:SS-HH:MM\" format.")
-           (@arg RUSTC: +required +takes_value "the path to the local rustc to benchmark")
+           (@arg RUSTC: +required +takes_value "the path to the local rustc to benchmark, or to the sysroot or rust checkout it was built in")
+           (@arg JOBS: --jobs +takes_value "Number of benchmarks to run concurrently, each pinned to its own CPUs")
+           (@arg CHECKPOINT: --checkpoint +takes_value "File to record progress in; an interrupted run with the same file resumes where it stopped")
+           (@arg JSON_OUTPUT: --("json-output") +takes_value "Also write the results to this file as a flat list of statistics, for external tools")
+           (@arg SELF_PROFILE: --("self-profile") "Also record the per-query self-profile timings of every benchmark and patch")
+           (@arg CARGO: --cargo +takes_value "The cargo to build the benchmarks with, e.g. a local build; overrides the config")
+           (@arg PLAN: --plan "Only print the toolchain, the builds that would run and an estimate of their duration from the newest results in the output repository")
+           (@arg TIME_BUDGET: --("time-budget") +takes_value "Only run the benchmarks and scenarios expected to fit into this time, e.g. 20min or 1h30m, primary benchmarks first")
+           (@arg FAIL_FAST: --("fail-fast") "Stop at the first benchmark that fails and exit with 1; by default failures are recorded and the run goes on")
+           (@arg FAILURES_REPORT: --("failures-report") +takes_value "Write which benchmarks succeeded, failed and were skipped, and why, to this file as JSON")
+       )
+       (@subcommand shell =>
+           (about: "an interactive shell to pick benchmarks, scenarios, profiles and toolchains, measure them and compare with the previous run")
+           (@arg RUSTC: --rustc +takes_value "The toolchain to start with: a local rustc, its sysroot or rust checkout, or the hash of a master commit")
+       )
+       (@subcommand profile_local =>
+           (about: "profile a local rustc, writing the profiles to a directory")
+           (@arg PROFILER: --profiler +required +takes_value "The profiler to use: self-profile, eprintln, llvm-lines, llvm-ir, massif, heaptrack, perf-record")
+           (@arg RUSTC_LOG: --("rustc-log") +takes_value "The RUSTC_LOG filter for the eprintln profiler")
+           (@arg SYMBOLICATE: --symbolicate "Resolve inlined functions of perf-record profiles from the toolchain's debuginfo; slow")
+           (@arg OUT_DIR: --("out-dir") +takes_value "Directory to write the profiles to; defaults to `results`")
+           (@arg CACHE_DIR: --("cache-dir") +takes_value "Keep the benchmarks' build directories here across runs")
+           (@arg CACHE_SIZE: --("cache-size") +takes_value "Maximum size of the cache in MB; defaults to 10240")
+           (@arg CARGO: --cargo +takes_value "The cargo to build the benchmarks with, e.g. a local build; overrides the config")
+           (@arg RUSTC2: --rustc2 +takes_value "A second rustc to profile, for comparison; the profiles of the two go to subdirectories 1 and 2 of the output directory")
+           (@arg RUSTC: +required +takes_value "the path to the local rustc to profile")
+       )
+       (@subcommand diff_llvm_lines =>
+           (about: "compare the llvm-lines profiles of two profile_local runs, e.g. with two toolchains")
+           (@arg BEFORE: +required +takes_value "The --out-dir of the first run")
+           (@arg AFTER: +required +takes_value "The --out-dir of the second run")
+           (@arg LIMIT: --limit +takes_value "Number of functions to print per benchmark; defaults to 20")
+       )
+       (@subcommand compare_local =>
+           (about: "compare the --json-output files of two bench_local runs, e.g. of a fork and its upstream")
+           (@arg BEFORE: +required +takes_value "The --json-output file of the first run")
+           (@arg AFTER: +required +takes_value "The --json-output file of the second run")
+           (@arg METRIC: --metric +takes_value "The statistic to compare; defaults to instructions:u")
+           (@arg THRESHOLD: --threshold +takes_value "Minimum change in percent counting as significant; defaults to 1")
+       )
+       (@subcommand rename_benchmark =>
+           (about: "record that a benchmark was renamed, so that the site graphs the old and new results as one series")
+           (@arg OLD: +required +takes_value "The benchmark's previous name")
+           (@arg NEW: +required +takes_value "The benchmark's new name")
+           (@arg COMMIT: +required +takes_value "The first commit benchmarked under the new name")
+       )
+       (@subcommand ingest_bootstrap =>
+           (about: "record the bootstrap step timings of a commit from CI's metrics.json")
+           (@arg COMMIT: +required +takes_value "Commit hash the metrics belong to")
+           (@arg METRICS: +required +takes_value "Path to the metrics.json written by x.py")
+       )
+       (@subcommand bisect =>
+           (about: "find the first commit between GOOD and BAD that regresses a benchmark")
+           (@arg GOOD: --good +required +takes_value "Last commit known not to regress")
+           (@arg BAD: --bad +required +takes_value "A commit known to regress")
+           (@arg BENCHMARK: --benchmark +required +takes_value "The benchmark to bisect")
+           (@arg STAT: --stat +takes_value "The statistic to compare; defaults to instructions:u")
+           (@arg THRESHOLD: --threshold +takes_value "Minimum increase in percent counting as a regression; defaults to 1")
+       )
+       (@subcommand analyze_candidate =>
+           (about: "benchmark a crate from crates.io with the two newest toolchains with results, to evaluate it as an addition to the suite")
+           (@arg CRATE: +required +takes_value "Name of the crate")
+           (@arg VERSION: --("crate-version") +takes_value "Version of the crate; defaults to the newest")
+       )
+       (@subcommand prune =>
+           (about: "thin out old data according to a retention policy")
+           (@arg KEEP_MONTHS: --("keep-months") +takes_value "Keep all master data of the last N months; older data is reduced to one commit per week. Defaults to 6")
+           (@arg TRY_DAYS: --("try-days") +takes_value "Delete try build data older than N days. Defaults to 30")
+           (@arg DRY_RUN: --("dry-run") "Only print the files that would be removed")
+       )
+       (@subcommand import =>
+           (about: "merge the results of another output repository into this one, keeping results present in both from this one")
+           (@arg OTHER: +required +takes_value "Path to the other output repository, e.g. a copy from another machine or a backup")
+           (@arg DRY_RUN: --("dry-run") "Only print the files that would be copied")
+       )
+       (@subcommand validate_benchmarks =>
+           (about: "check that every benchmark has a valid perf-config.json, a Cargo.lock and a license, and builds with the stable toolchain without Cargo using the network")
+           (@arg RUSTC: --rustc +takes_value "The rustc to build with; defaults to that of rustup's stable toolchain")
+           (@arg NO_BUILD: --("no-build") "Only check the files of the benchmarks, without building them")
+           (@arg REPORT: --report +takes_value "Also write the outcome of every check to this file as JSON")
        )
        (@subcommand remove_errs =>
            (about: "remove errored data")
        )
+       (@subcommand migrate =>
+           (about: "apply pending migrations of the output repository's layout")
+           (@arg DRY_RUN: --("dry-run") "Only print the files each pending migration would change")
+       )
        (@subcommand remove_benchmark =>
            (about: "remove data for a benchmark")
            (@arg BENCHMARK: --benchmark +required +takes_value "benchmark name to remove data for")
        )
     ).get_matches();
-    let benchmark_dir = PathBuf::from(matches.value_of_os("benchmarks_dir").unwrap());
+
+    // These only read files of earlier runs, so they need neither rust.git, the benchmarks nor
+    // an output repository.
+    match matches.subcommand() {
+        ("diff_llvm_lines", Some(sub_m)) => {
+            let before = Path::new(sub_m.value_of_os("BEFORE").unwrap());
+            let after = Path::new(sub_m.value_of_os("AFTER").unwrap());
+            let limit = value_t!(sub_m, "LIMIT", usize).unwrap_or(20);
+            llvm_lines::diff(before, after, limit)?;
+            return Ok(0);
+        }
+        ("compare_local", Some(sub_m)) => {
+            let before = Path::new(sub_m.value_of_os("BEFORE").unwrap());
+            let after = Path::new(sub_m.value_of_os("AFTER").unwrap());
+            let metric = sub_m.value_of("METRIC").unwrap_or("instructions:u");
+            let threshold = value_t!(sub_m, "THRESHOLD", f64).unwrap_or(1.0);
+            export::compare(before, after, metric, threshold)?;
+            return Ok(0);
+        }
+        _ => {}
+    }
+
+    git::fetch_rust(Path::new("rust.git"))?;
+    let benchmark_dir = match matches.value_of_os("benchmarks_dir") {
+        Some(dir) => PathBuf::from(dir),
+        None => bail!("--benchmarks is required"),
+    };
     let filter = matches.value_of("filter");
-    let benchmarks = get_benchmarks(&benchmark_dir, filter)?;
+    let mut config = match matches.value_of_os("config") {
+        Some(path) => Config::load(Path::new(path))?,
+        None => Config::default(),
+    };
+    if let Some(target) = matches.value_of("target") {
+        config.target = target.to_string();
+    }
+    if let Some(backends) = matches.value_of("backend") {
+        config.backends = backends
+            .split(',')
+            .map(|b| b.trim().parse::<Backend>())
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+    }
+    if let Some(profiles) = matches.value_of("profile") {
+        config.profiles = profiles
+            .split(',')
+            .map(|p| p.trim().parse::<Profile>())
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+    }
+    if let Some(std) = matches.value_of("std") {
+        config.std = std.split(',')
+            .map(|s| s.trim().parse::<Std>())
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+        if config.sandbox && config.std.contains(&Std::BuildStd) {
+            bail!("build-std can't be used with the sandbox");
+        }
+    }
+    if matches.is_present("iterations") {
+        config.iterations = value_t_or_exit!(matches, "iterations", usize);
+        if config.iterations == 0 {
+            bail!("--iterations must be at least 1");
+        }
+    }
     let preserve_sysroots = matches.is_present("preserve_sysroots");
+
+    // Before loading the benchmarks, which stops at the first invalid one.
+    if let ("validate_benchmarks", Some(sub_m)) = matches.subcommand() {
+        let sysroot = if sub_m.is_present("NO_BUILD") {
+            None
+        } else {
+            let rustc = match sub_m.value_of("RUSTC") {
+                Some(rustc) => rustc.to_string(),
+                None => validate::stable_rustc()?,
+            };
+            let commit = GitCommit {
+                sha: String::from("stable"),
+                date: Utc::now(),
+                summary: String::new(),
+            };
+            Some(local_sysroot(&commit, &rustc, &config, preserve_sysroots)?)
+        };
+        let report = sub_m.value_of_os("REPORT").map(Path::new);
+        let valid = validate::run(&benchmark_dir, filter, sysroot.as_ref(), &config, report)?;
+        return Ok(if valid { 0 } else { 1 });
+    }
+    let benchmarks = get_benchmarks(&benchmark_dir, filter)?;
+
     let use_remote = matches.is_present("sync_git");
-    let out_repo = PathBuf::from(matches.value_of_os("output_repo").unwrap());
+    let out_repo = match matches.value_of_os("output_repo") {
+        Some(path) => PathBuf::from(path),
+        None => bail!("--output-repo is required"),
+    };
     let mut out_repo = outrepo::Repo::open(out_repo, use_remote)?;
 
+    // Results must only be written in the current layout.
+    match matches.subcommand_name() {
+        Some("process") | Some("process_queue") | Some("bench_commit") | Some("calibrate") => {
+            migrate::run(&out_repo, false)?
+        }
+        _ => {}
+    }
+
+    match matches.subcommand_name() {
+        Some("process")
+        | Some("process_queue")
+        | Some("bench_commit")
+        | Some("calibrate")
+        | Some("bench_local")
+        | Some("shell")
+        | Some("profile_local")
+        | Some("bisect")
+        | Some("analyze_candidate") => environment::sanitize(config.environment)?,
+        _ => {}
+    }
+
+    if let Err(err) = cgroup::restore_offline() {
+        warn!("failed to restore CPUs left offline: {:?}", err);
+    }
+    // Set up for as long as anything is benchmarked.
+    let _cgroup = match (matches.subcommand_name(), config.cgroup.as_ref()) {
+        (Some("process"), Some(cgroup))
+        | (Some("process_queue"), Some(cgroup))
+        | (Some("bench_commit"), Some(cgroup))
+        | (Some("calibrate"), Some(cgroup))
+        | (Some("bench_local"), Some(cgroup))
+        | (Some("shell"), Some(cgroup))
+        | (Some("profile_local"), Some(cgroup))
+        | (Some("bisect"), Some(cgroup))
+        | (Some("analyze_candidate"), Some(cgroup)) => Some(cgroup::Cgroup::setup(cgroup)?),
+        _ => None,
+    };
+
     let commits = rust_sysroot::get_commits()?;
 
     match matches.subcommand() {
         ("process", Some(_)) => {
-            process_retries(&commits, &mut out_repo, &benchmarks, preserve_sysroots)?;
-            process_commits(&commits, &out_repo, &benchmarks, preserve_sysroots)?;
+            process_retries(&commits, &mut out_repo, &benchmarks, &config, preserve_sysroots)?;
+            process_commits(&commits, &out_repo, &benchmarks, &config, preserve_sysroots)?;
+            Ok(0)
+        }
+        ("process_queue", Some(sub_m)) => {
+            let sites = match sub_m.value_of("SITE") {
+                Some(site) => vec![SiteConfig::new(site)],
+                None => config.sites.clone(),
+            };
+            if sites.is_empty() {
+                bail!("no site to take work from; pass --site or configure `sites`");
+            }
+            let claim = match queue::next_of(&sites, &config.metrics, &out_repo.backoff_file())? {
+                Some(claim) => claim,
+                None => {
+                    info!("queue is empty");
+                    return Ok(0);
+                }
+            };
+            let site_repo = match claim.output_repo {
+                Some(ref path) => {
+                    let repo = outrepo::Repo::open(path.clone(), use_remote)?;
+                    migrate::run(&repo, false)?;
+                    Some(repo)
+                }
+                None => None,
+            };
+            let out_repo = site_repo.as_ref().unwrap_or(&out_repo);
+            // The bors commit list may not have caught up with the webhook
+            // yet.
+            let commit = commits
+                .iter()
+                .find(|c| c.sha == claim.commit.sha)
+                .cloned()
+                .unwrap_or_else(|| claim.commit.clone());
+            if let Some(experiment) = claim.experiment.clone() {
+                // Benchmarks lacking the experiment's flag set are rerun with
+                // it, next to the existing results.
+                if config.flag_sets.contains_key(&experiment.name) {
+                    claim.fail()?;
+                    bail!("experiment {} clashes with a configured flag set", experiment.name);
+                }
+                info!("running experiment {}: {}", experiment.name, experiment.flags);
+                config.flag_sets.insert(experiment.name, experiment.flags);
+            }
+            let result = {
+                let canceled = || claim.canceled();
+                if claim.calibration {
+                    process_calibration(
+                        &out_repo,
+                        &commit,
+                        &benchmarks,
+                        &config,
+                        preserve_sysroots,
+                        &canceled,
+                    )
+                } else {
+                    process_commit(
+                        &out_repo,
+                        &commit,
+                        claim.local_rustc.as_ref().map(|rustc| &**rustc),
+                        &benchmarks,
+                        &config,
+                        preserve_sysroots,
+                        &canceled,
+                    )
+                }
+            };
+            if let Err(err) = result {
+                if let Err(fail_err) = claim.fail() {
+                    warn!("failed to report the failure to the site: {:?}", fail_err);
+                }
+                return Err(err);
+            }
+            // The results are pushed; the site would otherwise only retire
+            // the claim once it reloads its data.
+            if let Err(err) = claim.done() {
+                warn!("failed to report the results to the site: {:?}", err);
+            }
+            Ok(0)
+        }
+        ("register_toolchain", Some(sub_m)) => {
+            let name = sub_m.value_of("NAME").unwrap();
+            let rustc = sub_m.value_of("RUSTC").unwrap();
+            let rustc = toolchain::find_local_rustc(Path::new(rustc), &config.target)?;
+            let mut sites = match sub_m.value_of("SITE") {
+                Some(site) => vec![SiteConfig::new(site)],
+                None => config.sites.clone(),
+            };
+            sites.sort_by_key(|site| cmp::Reverse(site.priority));
+            let site = match sites.into_iter().next() {
+                Some(site) => site,
+                None => bail!("no site to register with; pass --site or configure `sites`"),
+            };
+            let token = env::var(&site.token_env)
+                .chain_err(|| format!("{} is not set, for {}", site.token_env, site.url))?;
+            let base = match sub_m.value_of("BASE") {
+                Some(base) => match commits.iter().find(|c| c.sha.starts_with(base)) {
+                    Some(commit) => Some(commit),
+                    None => bail!("{} is not a master commit with published artifacts", base),
+                },
+                None => None,
+            };
+            // Recorded with the date of its base, it sorts next to that.
+            let date = base.map_or_else(Utc::now, |base| base.date);
+            let base = base.map(|base| &*base.sha);
+            let key = queue::register(&site.url, &token, name, &rustc, base, date)?;
+            println!("queued {} on {}", key, site.url);
+            if let Some(base) = base {
+                println!(
+                    "once benchmarked: {}/perf/summary?a={}&b={}",
+                    site.url.trim_right_matches('/'),
+                    base,
+                    key
+                );
+            }
             Ok(0)
         }
         ("bench_commit", Some(sub_m)) => {
@@ -237,38 +813,294 @@ fn run() -> Result<i32> {
                     summary: String::new(),
                 }
             });
-            process_commit(&out_repo, &commit, &benchmarks, preserve_sysroots)?;
+            process_commit(
+                &out_repo,
+                &commit,
+                None,
+                &benchmarks,
+                &config,
+                preserve_sysroots,
+                &never,
+            )?;
+            Ok(0)
+        }
+        ("calibrate", Some(sub_m)) => {
+            let commit = sub_m.value_of("COMMIT").unwrap();
+            let commit = match commits.iter().find(|c| c.sha == commit) {
+                Some(commit) => commit,
+                None => bail!("commit {} has no published artifacts", commit),
+            };
+            process_calibration(
+                &out_repo,
+                commit,
+                &benchmarks,
+                &config,
+                preserve_sysroots,
+                &never,
+            )?;
             Ok(0)
         }
         ("bench_local", Some(sub_m)) => {
             let commit = sub_m.value_of("COMMIT").unwrap();
             let date = sub_m.value_of("DATE").unwrap();
             let rustc = sub_m.value_of("RUSTC").unwrap();
+            let rustc = &toolchain::find_local_rustc(Path::new(rustc), &config.target)?;
+            let jobs = value_t!(sub_m, "JOBS", usize).unwrap_or(1);
+            if sub_m.is_present("SELF_PROFILE") {
+                config.self_profile = true;
+            }
+            if sub_m.is_present("FAIL_FAST") {
+                config.fail_fast = true;
+            }
+            if let Some(cargo) = sub_m.value_of_os("CARGO") {
+                config.cargo = Some(PathBuf::from(cargo));
+            }
             let commit = GitCommit {
                 sha: commit.to_string(),
                 date: DateTime::parse_from_rfc3339(date)?.with_timezone(&Utc),
                 summary: String::new(),
             };
-            let sysroot = Sysroot::with_local_rustc(
+            let checkpoint = sub_m.value_of_os("CHECKPOINT").map(|path| {
+                Checkpoint::open(PathBuf::from(path), &commit.sha, &config.target)
+            });
+            let within_budget;
+            let benchmarks = match sub_m.value_of("TIME_BUDGET") {
+                Some(budget) => {
+                    let budget = budget::parse(budget)?;
+                    let history = out_repo.newest_commit_data(&config.target)?;
+                    let durations = Durations::open(out_repo.durations_file());
+                    within_budget = budget::select(
+                        &benchmarks,
+                        &config,
+                        &durations,
+                        history.as_ref(),
+                        budget,
+                        jobs,
+                    )?;
+                    &within_budget[..]
+                }
+                None => &benchmarks[..],
+            };
+            if sub_m.is_present("PLAN") {
+                let history = out_repo.newest_commit_data(&config.target)?;
+                plan::print(
+                    rustc,
+                    &config,
+                    benchmarks,
+                    history.as_ref(),
+                    checkpoint.as_ref(),
+                    jobs,
+                )?;
+                return Ok(0);
+            }
+            let sysroot = local_sysroot(&commit, rustc, &config, preserve_sysroots)?;
+            let result = bench_commit(
                 &commit,
-                rustc,
-                "x86_64-unknown-linux-gnu",
+                None,
+                sysroot,
+                benchmarks,
+                &config,
+                jobs,
+                checkpoint.as_ref(),
+                &never,
+            ).expect("local runs are never canceled");
+            serde_json::to_writer(&mut stdout(), &result)?;
+            if let Some(path) = sub_m.value_of_os("JSON_OUTPUT") {
+                export::write(&result, Path::new(path))?;
+            }
+            let report = export::FailureReport::of(&result, benchmarks);
+            report.summarize()?;
+            if let Some(path) = sub_m.value_of_os("FAILURES_REPORT") {
+                report.write(Path::new(path))?;
+            }
+            if config.fail_fast && !report.failed.is_empty() {
+                // Keeping the checkpoint, so that the run resumes once the
+                // failure is fixed.
+                return Ok(1);
+            }
+            if let Some(checkpoint) = checkpoint {
+                checkpoint.finish()?;
+            }
+            Ok(if report.succeeded.is_empty() && !report.failed.is_empty() {
+                1
+            } else {
+                0
+            })
+        }
+        ("shell", Some(sub_m)) => {
+            shell::run(
+                &benchmarks,
+                &commits,
+                &config,
+                sub_m.value_of("RUSTC"),
+                &out_repo.shell_history_file(),
                 preserve_sysroots,
-                false,
             )?;
-            let result = bench_commit(&commit, None, sysroot, &benchmarks);
-            serde_json::to_writer(&mut stdout(), &result)?;
+            Ok(0)
+        }
+        ("profile_local", Some(sub_m)) => {
+            let mut profiler = sub_m.value_of("PROFILER").unwrap().parse::<Profiler>()?;
+            if sub_m.is_present("SYMBOLICATE") {
+                match profiler {
+                    Profiler::PerfRecord { .. } => {
+                        profiler = Profiler::PerfRecord { symbolicate: true };
+                    }
+                    _ => bail!("--symbolicate only applies to the perf-record profiler"),
+                }
+            }
+            if let Some(cargo) = sub_m.value_of_os("CARGO") {
+                config.cargo = Some(PathBuf::from(cargo));
+            }
+            let rustc_log = sub_m.value_of("RUSTC_LOG");
+            if profiler == Profiler::Eprintln && rustc_log.is_none() {
+                bail!("the eprintln profiler needs a --rustc-log filter");
+            }
+            let out_dir = PathBuf::from(sub_m.value_of_os("OUT_DIR").unwrap_or(OsStr::new("results")));
+            let rustc = sub_m.value_of("RUSTC").unwrap();
+            let runs = match sub_m.value_of("RUSTC2") {
+                Some(rustc2) => vec![
+                    ("profile", rustc, out_dir.join("1")),
+                    ("profile2", rustc2, out_dir.join("2")),
+                ],
+                None => vec![("profile", rustc, out_dir.clone())],
+            };
+            for &(sha, rustc, ref out_dir) in &runs {
+                let commit = GitCommit {
+                    sha: String::from(sha),
+                    date: Utc::now(),
+                    summary: String::new(),
+                };
+                let sysroot = local_sysroot(&commit, rustc, &config, preserve_sysroots)?;
+                let cache = match sub_m.value_of_os("CACHE_DIR") {
+                    Some(dir) => {
+                        let size = match sub_m.value_of("CACHE_SIZE") {
+                            Some(size) => size.parse::<u64>()
+                                .chain_err(|| format!("invalid --cache-size `{}`", size))?,
+                            None => 10240,
+                        };
+                        Some(Cache::open(Path::new(dir), size * 1024 * 1024, &sysroot)?)
+                    }
+                    None => None,
+                };
+                fs::create_dir_all(out_dir)?;
+                for benchmark in &benchmarks {
+                    benchmark.profile(&sysroot, profiler, out_dir, rustc_log, cache.as_ref())?;
+                }
+            }
+            if runs.len() == 2 {
+                match profiler {
+                    Profiler::LlvmIr => llvm_lines::diff_totals(&runs[0].2, &runs[1].2)?,
+                    Profiler::LlvmLines => llvm_lines::diff(&runs[0].2, &runs[1].2, 20)?,
+                    _ => {}
+                }
+                if profiler.folded_stacks().is_some() {
+                    flamegraph::diff(&runs[0].2, &runs[1].2, profiler, &out_dir.join("diff"))?;
+                }
+            }
+            Ok(0)
+        }
+        ("rename_benchmark", Some(sub_m)) => {
+            let rename = Rename {
+                old: sub_m.value_of("OLD").unwrap().to_string(),
+                new: sub_m.value_of("NEW").unwrap().to_string(),
+                commit: sub_m.value_of("COMMIT").unwrap().to_string(),
+            };
+            if !commits.iter().any(|c| c.sha == rename.commit) {
+                bail!("unknown commit {}", rename.commit);
+            }
+            if !benchmarks.iter().any(|b| b.name == rename.new) {
+                warn!("{} isn't a benchmark of this suite", rename.new);
+            }
+            if out_repo.renames()?.iter().any(|r| r.old == rename.old) {
+                bail!("{} was already renamed", rename.old);
+            }
+            out_repo.add_rename(rename)?;
+            Ok(0)
+        }
+        ("ingest_bootstrap", Some(sub_m)) => {
+            let commit = sub_m.value_of("COMMIT").unwrap();
+            let commit = match commits.iter().find(|c| c.sha == commit) {
+                Some(commit) => commit,
+                None => bail!("unknown commit {}", commit),
+            };
+            let metrics = Path::new(sub_m.value_of_os("METRICS").unwrap());
+            out_repo.bootstrap_success(&BootstrapData {
+                commit: Commit {
+                    sha: commit.sha.clone(),
+                    date: Date(commit.date),
+                },
+                steps: bootstrap::step_timings(metrics)?,
+            })?;
+            Ok(0)
+        }
+        ("bisect", Some(sub_m)) => {
+            let name = sub_m.value_of("BENCHMARK").unwrap();
+            let benchmark = match benchmarks.iter().find(|b| b.name == name) {
+                Some(benchmark) => benchmark,
+                None => bail!("unknown benchmark {}", name),
+            };
+            let criterion = bisect::Criterion {
+                stat: sub_m.value_of("STAT").unwrap_or("instructions:u"),
+                threshold: value_t!(sub_m, "THRESHOLD", f64).unwrap_or(1.0),
+            };
+            let first_bad = bisect::bisect(
+                &commits,
+                sub_m.value_of("GOOD").unwrap(),
+                sub_m.value_of("BAD").unwrap(),
+                benchmark,
+                &criterion,
+                &config,
+                preserve_sysroots,
+            )?;
+            println!("first regressing commit: {} {}", first_bad.sha, first_bad.summary);
+            Ok(0)
+        }
+        ("analyze_candidate", Some(sub_m)) => {
+            let tmp_dir = TempDir::new("candidate")?;
+            let candidate = candidate::download(
+                sub_m.value_of("CRATE").unwrap(),
+                sub_m.value_of("VERSION"),
+                tmp_dir.path(),
+            )?;
+            candidate::analyze(&candidate, &commits, &out_repo, &config, preserve_sysroots)?;
+            Ok(0)
+        }
+        ("prune", Some(sub_m)) => {
+            let keep_months = value_t!(sub_m, "KEEP_MONTHS", i64).unwrap_or(6);
+            let try_days = value_t!(sub_m, "TRY_DAYS", i64).unwrap_or(30);
+            let policy = prune::Policy {
+                full_resolution: Duration::days(30 * keep_months),
+                try_retention: Duration::days(try_days),
+            };
+            let remove = prune::select(&out_repo, &commits, &policy, Utc::now())?;
+            if sub_m.is_present("DRY_RUN") {
+                for file in &remove {
+                    println!("{}", file.display());
+                }
+            } else if !remove.is_empty() {
+                out_repo.remove_times(&remove, &format!("pruned {} old results", remove.len()))?;
+            }
+            Ok(0)
+        }
+        ("migrate", Some(sub_m)) => {
+            migrate::run(&out_repo, sub_m.is_present("DRY_RUN"))?;
+            Ok(0)
+        }
+        ("import", Some(sub_m)) => {
+            let other = Path::new(sub_m.value_of_os("OTHER").unwrap());
+            import::run(&out_repo, other, sub_m.is_present("DRY_RUN"))?;
             Ok(0)
         }
         ("remove_errs", Some(_)) => {
             for commit in &commits {
-                if let Ok(mut data) = out_repo.load_commit_data(&commit, "x86_64-unknown-linux-gnu")
+                if let Ok(mut data) = out_repo.load_commit_data(&commit, &config.target)
                 {
                     let benchmarks = data.benchmarks
                         .into_iter()
                         .filter(|&(_, ref v)| v.is_ok())
                         .collect();
                     data.benchmarks = benchmarks;
+                    data.failures.clear();
                     out_repo.add_commit_data(&data)?;
                 }
             }
@@ -277,11 +1109,12 @@ fn run() -> Result<i32> {
         ("remove_benchmark", Some(sub_m)) => {
             let benchmark = sub_m.value_of("BENCHMARK").unwrap();
             for commit in &commits {
-                if let Ok(mut data) = out_repo.load_commit_data(&commit, "x86_64-unknown-linux-gnu")
+                if let Ok(mut data) = out_repo.load_commit_data(&commit, &config.target)
                 {
                     if data.benchmarks.remove(&*benchmark).is_none() {
                         warn!("could not remove {} from {}", benchmark, commit.sha);
                     }
+                    data.failures.remove(&*benchmark);
                     out_repo.add_commit_data(&data)?;
                 }
             }
@@ -0,0 +1,149 @@
+//! Compute the manifest of the benchmark suite, and describe its benchmarks.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use collector::{BenchmarkInfo, SuiteManifest};
+
+use errors::{Result, ResultExt};
+use execute::Benchmark;
+
+pub fn compute(benchmarks: &[Benchmark]) -> Result<SuiteManifest> {
+    let mut manifest = SuiteManifest {
+        benchmarks: Default::default(),
+    };
+    for benchmark in benchmarks {
+        let hash = hash_dir(&benchmark.path, &build_settings(benchmark))
+            .chain_err(|| format!("failed to hash sources of {}", benchmark.name))?;
+        manifest
+            .benchmarks
+            .insert(benchmark.name.clone(), format!("{:016x}", hash));
+    }
+    Ok(manifest)
+}
+
+/// Describe `benchmark` for the site's catalog: its description, how many
+/// crates and lines of code it has, and its incremental patches.
+pub fn describe(benchmark: &Benchmark) -> Result<BenchmarkInfo> {
+    let mut files = Vec::new();
+    collect_files(&benchmark.path, &mut files)?;
+    let mut lines = 0;
+    let mut packages = BTreeSet::new();
+    let mut has_lockfile = false;
+    let mut contents = String::new();
+    for file in &files {
+        let is_lockfile = file.ends_with("Cargo.lock");
+        if !is_lockfile && file.extension().map_or(true, |ext| ext != "rs") {
+            continue;
+        }
+        contents.clear();
+        // Some test files aren't UTF-8; they don't count as code.
+        if File::open(file)?.read_to_string(&mut contents).is_err() {
+            continue;
+        }
+        if is_lockfile {
+            // Both `[root]` of old lockfiles and `[[package]]` are followed
+            // by the package's name.
+            has_lockfile = true;
+            let names = contents
+                .lines()
+                .filter(|line| line.starts_with("name = "))
+                .map(|line| line.to_string());
+            packages.extend(names);
+        } else {
+            lines += contents.lines().count();
+        }
+    }
+    Ok(BenchmarkInfo {
+        description: benchmark.config.description.clone(),
+        crates: if has_lockfile {
+            Some(packages.len())
+        } else {
+            None
+        },
+        lines: lines,
+        patches: benchmark
+            .source_patches()?
+            .into_iter()
+            .filter(|patch| !patch.is_empty())
+            .collect(),
+    })
+}
+
+/// The settings of the benchmark's `perf-config.json` that change how it is
+/// built, or nothing if there are none.
+fn build_settings(benchmark: &Benchmark) -> String {
+    let config = &benchmark.config;
+    if !config.affects_build() {
+        return String::new();
+    }
+    let mut settings = format!(
+        "env={:?} features={:?} no-default-features={} cargo-args={:?}",
+        config.env,
+        config.features,
+        config.no_default_features,
+        config.cargo_args
+    );
+    // Only appended when set, so that the hashes of other benchmarks stay
+    // the same.
+    if config.check {
+        settings.push_str(" check=true");
+    }
+    settings
+}
+
+/// Hashes the relative paths and contents of all files below `root`, in
+/// sorted order, followed by `settings`. Build output (`target`) and the
+/// benchmark's `perf-config.json`, whose settings affecting the build are
+/// passed in `settings`, are ignored.
+fn hash_dir(root: &Path, settings: &str) -> Result<u64> {
+    let mut files = Vec::new();
+    collect_files(root, &mut files)?;
+    files.retain(|file| *file != root.join("perf-config.json"));
+    files.sort();
+
+    let mut hasher = Fnv::new();
+    let mut contents = Vec::new();
+    for file in files {
+        hasher.write(file.strip_prefix(root).unwrap().to_string_lossy().as_bytes());
+        contents.clear();
+        File::open(&file)?.read_to_end(&mut contents)?;
+        hasher.write(&contents);
+    }
+    hasher.write(settings.as_bytes());
+    Ok(hasher.0)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if !path.ends_with("target") {
+                collect_files(&path, files)?;
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// 64-bit FNV-1a. `DefaultHasher` isn't guaranteed to be stable across Rust
+/// releases, and the hashes are compared across collector builds.
+pub struct Fnv(pub u64);
+
+impl Fnv {
+    pub fn new() -> Fnv {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
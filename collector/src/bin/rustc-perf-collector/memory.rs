@@ -0,0 +1,142 @@
+//! Summaries of the heap profiles written by the `massif` and `heaptrack`
+//! profilers, to find what rustc's peak memory is made of.
+//!
+//! Both profilers have `rustc-fake` run the benchmarked rustc under the
+//! tool, writing the raw profile into the profile directory. Next to it go a
+//! textual summary and the allocations live at the peak as folded stacks
+//! (`<tool>-peak.folded`), which `inferno-flamegraph` turns into
+//! `<tool>-peak.svg`.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::Command;
+
+use errors::*;
+use flamegraph;
+
+/// Summarize the massif profile `massif.out` in `dir` with `ms_print` into
+/// `massif.txt` and draw its peak snapshot.
+pub fn summarize_massif(dir: &Path) -> Result<()> {
+    let profile = dir.join("massif.out");
+    let summary = File::create(dir.join("massif.txt"))?;
+    let status = Command::new("ms_print")
+        .arg(&profile)
+        .stdout(summary)
+        .status()
+        .chain_err(|| "could not spawn ms_print; is valgrind installed?")?;
+    if !status.success() {
+        bail!("ms_print failed on {}", profile.display());
+    }
+
+    let mut contents = String::new();
+    File::open(&profile)
+        .chain_err(|| format!("failed to open {}", profile.display()))?
+        .read_to_string(&mut contents)?;
+    let stacks = match peak_stacks(&contents) {
+        Some(stacks) => stacks,
+        None => bail!("no peak snapshot in {}", profile.display()),
+    };
+    let folded = dir.join("massif-peak.folded");
+    let mut file = File::create(&folded)?;
+    for (stack, bytes) in stacks {
+        writeln!(file, "{} {}", stack, bytes)?;
+    }
+    flamegraph::draw(&folded, &dir.join("massif-peak.svg"), "bytes")
+}
+
+/// Summarize the heaptrack profile in `dir` with `heaptrack_print` into
+/// `heaptrack.txt` and draw the allocations at the peak.
+pub fn summarize_heaptrack(dir: &Path) -> Result<()> {
+    // heaptrack adds the extension of the compression it used.
+    let mut profile = None;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if name == "heaptrack.gz" || name == "heaptrack.zst" {
+            profile = Some(path);
+        }
+    }
+    let profile = match profile {
+        Some(profile) => profile,
+        None => bail!("no heaptrack profile in {}", dir.display()),
+    };
+    let folded = dir.join("heaptrack-peak.folded");
+    let summary = File::create(dir.join("heaptrack.txt"))?;
+    let status = Command::new("heaptrack_print")
+        .arg("--print-flamegraph")
+        .arg(&folded)
+        .arg("--flamegraph-cost-type")
+        .arg("peak")
+        .arg(&profile)
+        .stdout(summary)
+        .status()
+        .chain_err(|| "could not spawn heaptrack_print; is heaptrack installed?")?;
+    if !status.success() {
+        bail!("heaptrack_print failed on {}", profile.display());
+    }
+    flamegraph::draw(&folded, &dir.join("heaptrack-peak.svg"), "bytes")
+}
+
+/// The heap tree of the peak snapshot of a massif profile as folded stacks,
+/// outermost frame first, with the bytes allocated by each stack itself.
+fn peak_stacks(profile: &str) -> Option<Vec<(String, u64)>> {
+    let lines = profile
+        .lines()
+        .skip_while(|line| *line != "heap_tree=peak")
+        .skip(1);
+    // `n<children>: <bytes> <description>`, indented by its depth. The root
+    // is all heap allocations.
+    let mut nodes = Vec::new();
+    for line in lines {
+        let depth = line.len() - line.trim_left().len();
+        let mut parts = line.trim_left().splitn(3, ' ');
+        let bytes = match (parts.next(), parts.next()) {
+            (Some(n), Some(bytes)) if n.starts_with('n') && n.ends_with(':') => {
+                bytes.parse::<u64>().ok()?
+            }
+            _ => break,
+        };
+        let name = if depth == 0 {
+            String::from("heap")
+        } else {
+            frame(parts.next().unwrap_or(""))
+        };
+        nodes.push((depth, bytes, name));
+    }
+    if nodes.is_empty() {
+        return None;
+    }
+
+    let mut stacks = Vec::new();
+    let mut path: Vec<String> = Vec::new();
+    for (idx, &(depth, bytes, ref frame)) in nodes.iter().enumerate() {
+        path.truncate(depth);
+        path.push(frame.clone());
+        let below = nodes[idx + 1..]
+            .iter()
+            .take_while(|node| node.0 > depth)
+            .filter(|node| node.0 == depth + 1)
+            .map(|node| node.1)
+            .sum::<u64>();
+        if bytes > below {
+            stacks.push((path.join(";"), bytes - below));
+        }
+    }
+    Some(stacks)
+}
+
+/// The function of a massif heap tree entry like
+/// `0x4C2DB8F: malloc (in /usr/lib/valgrind/vgpreload_massif.so)`, without
+/// the `;` that separates frames in folded stacks.
+fn frame(description: &str) -> String {
+    let description = match description.find(": ") {
+        Some(idx) if description.starts_with("0x") => &description[idx + 2..],
+        _ => description,
+    };
+    let description = match description.rfind(" (") {
+        Some(idx) if description.ends_with(')') && idx > 0 => &description[..idx],
+        _ => description,
+    };
+    description.replace(';', ":")
+}
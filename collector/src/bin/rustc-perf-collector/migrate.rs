@@ -0,0 +1,84 @@
+//! Migrations of the output repository's layout.
+//!
+//! Each migration in `collector::MIGRATIONS` is applied once, in order, and
+//! committed together with the files it changed and its entry in
+//! `migrations.json`. The subcommands that write results apply pending
+//! migrations first.
+
+use std::fs::File;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use serde_json;
+
+use collector::{self, AppliedMigration, CommitData, Date, MIGRATIONS};
+use errors::*;
+use outrepo::Repo;
+
+/// Apply the pending migrations to `repo`. With `dry_run`, only print the
+/// files each would change; since nothing is applied, later migrations are
+/// checked against the unmigrated repository.
+pub fn run(repo: &Repo, dry_run: bool) -> Result<()> {
+    let applied = repo.applied_migrations()?;
+    let pending = collector::pending_migrations(&applied).map_err(Error::from)?;
+    if pending.is_empty() && dry_run {
+        println!("no pending migrations");
+    }
+    for version in pending {
+        let name = MIGRATIONS[version - 1];
+        info!("applying migration {} ({})", version, name);
+        let changed = match name {
+            "split-self-profile" => split_self_profile(repo, dry_run)?,
+            "drop-names" => drop_names(repo, dry_run)?,
+            _ => unreachable!("migration `{}` isn't implemented", name),
+        };
+        if dry_run {
+            println!("migration {} ({}) changes {} files", version, name, changed.len());
+            for path in &changed {
+                println!("  {}", path.display());
+            }
+        } else {
+            repo.migrated(AppliedMigration {
+                version: version,
+                name: name.to_string(),
+                applied: Date(Utc::now()),
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Move query timings stored inline in `times/`, for example by copying the
+/// output of `bench_local` there, to `self-profile/`.
+fn split_self_profile(repo: &Repo, dry_run: bool) -> Result<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+    for file in repo.times_files()? {
+        let data: CommitData = serde_json::from_reader(File::open(&file.path)?)
+            .chain_err(|| format!("failed to parse {}", file.path.display()))?;
+        let has_queries = data.benchmarks.values().any(|result| match *result {
+            Ok(ref patches) => patches.iter().any(|patch| !patch.queries.is_empty()),
+            Err(_) => false,
+        });
+        if !has_queries {
+            continue;
+        }
+        if !dry_run {
+            repo.add_commit_data(&data)?;
+        }
+        changed.push(file.path);
+    }
+    Ok(changed)
+}
+
+/// Remove `names.json`, where collectors recorded ids of the patch and
+/// statistic names that nothing read back from the results.
+fn drop_names(repo: &Repo, dry_run: bool) -> Result<Vec<PathBuf>> {
+    let path = repo.names_file();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    if !dry_run {
+        repo.remove_names()?;
+    }
+    Ok(vec![path])
+}
@@ -9,11 +9,20 @@ use std::process::Command;
 use std::str;
 use std::collections::HashSet;
 
+use chrono::{DateTime, Utc};
 use serde_json;
-use collector::CommitData;
+use collector::{AppliedMigration, BootstrapData, CalibrationRun, CommitData, Rename};
 use rust_sysroot::git::Commit as GitCommit;
 use execute::Benchmark;
 
+/// A file in `times/`, named `<date>-<sha>-<triple>.json`.
+pub struct TimesFile {
+    pub path: PathBuf,
+    pub date: DateTime<Utc>,
+    pub sha: String,
+    pub triple: String,
+}
+
 pub struct Repo {
     path: PathBuf,
     use_remote: bool,
@@ -57,6 +66,9 @@ impl Repo {
         }
 
         fs::create_dir_all(result.times()).chain_err(|| "can't create `times/`")?;
+        fs::create_dir_all(result.bootstrap()).chain_err(|| "can't create `bootstrap/`")?;
+        fs::create_dir_all(result.self_profile()).chain_err(|| "can't create `self-profile/`")?;
+        fs::create_dir_all(result.calibration()).chain_err(|| "can't create `calibration/`")?;
         result.load_retries()?;
 
         Ok(result)
@@ -68,6 +80,38 @@ impl Repo {
         Ok(())
     }
 
+    pub fn bootstrap_success(&self, data: &BootstrapData) -> Result<()> {
+        let commit = &data.commit;
+        let filepath = self.bootstrap()
+            .join(format!("{}-{}.json", commit.date, commit.sha));
+        info!("creating file {}", filepath.display());
+        let mut file = File::create(&filepath)?;
+        serde_json::to_writer(&mut file, &data)?;
+
+        self.git(&["add", "bootstrap"])?;
+        self.commit_and_push(&format!("{} - bootstrap timings", commit.sha))?;
+        Ok(())
+    }
+
+    /// Add a calibration run, named after the date it ran rather than the
+    /// date of the artifact.
+    pub fn calibration_success(&self, run: &CalibrationRun) -> Result<()> {
+        let data = &run.data;
+        let filepath = self.calibration().join(format!(
+            "{}-{}-{}.json",
+            run.date.to_rfc3339(),
+            data.commit.sha,
+            data.triple
+        ));
+        info!("creating file {}", filepath.display());
+        let mut file = File::create(&filepath)?;
+        serde_json::to_writer(&mut file, run)?;
+
+        self.git(&["add", "calibration"])?;
+        self.commit_and_push(&format!("{} - calibration", data.commit.sha))?;
+        Ok(())
+    }
+
     pub fn find_missing_commits<'a>(
         &self,
         commits: &'a [GitCommit],
@@ -76,11 +120,15 @@ impl Repo {
     ) -> Result<Vec<&'a GitCommit>> {
         let mut have = HashSet::new();
         let path = self.times();
+        let suffix = format!("-{}.json", triple);
         for entry in read_dir(path)? {
             let entry = entry?;
             let filename = entry.file_name().to_string_lossy().to_string();
-            let sha =
-                &filename[filename.find("00:00").unwrap() + 6..filename.find("-x86").unwrap()];
+            // Files are named `<date>-<sha>-<triple>.json`, with dates in UTC.
+            if !filename.ends_with(&suffix) {
+                continue;
+            }
+            let sha = &filename[filename.find("00:00").unwrap() + 6..filename.len() - suffix.len()];
             have.insert(sha.to_string());
         }
 
@@ -112,9 +160,51 @@ impl Repo {
         Ok(missing)
     }
 
+    /// All result files in `times/`.
+    pub fn times_files(&self) -> Result<Vec<TimesFile>> {
+        let mut files = Vec::new();
+        for entry in read_dir(self.times())? {
+            let entry = entry?;
+            let filename = entry.file_name().to_string_lossy().to_string();
+            if !filename.ends_with(".json") {
+                continue;
+            }
+            // `YYYY-MM-DDTHH:MM:SS+00:00`, followed by `-<40 hex digit sha>-`
+            let date_len = "YYYY-MM-DDTHH:MM:SS+00:00".len();
+            let triple_start = date_len + 1 + 40 + 1;
+            if filename.len() <= triple_start + ".json".len() {
+                warn!("ignoring unexpected file {}", filename);
+                continue;
+            }
+            let date = DateTime::parse_from_rfc3339(&filename[..date_len])
+                .chain_err(|| format!("bad date in {}", filename))?;
+            files.push(TimesFile {
+                path: entry.path(),
+                date: date.with_timezone(&Utc),
+                sha: filename[date_len + 1..triple_start - 1].to_string(),
+                triple: filename[triple_start..filename.len() - ".json".len()].to_string(),
+            });
+        }
+        Ok(files)
+    }
+
+    /// Delete `files` from `times/`, and the corresponding self-profile
+    /// data, and commit the deletion.
+    pub fn remove_times(&self, files: &[PathBuf], message: &str) -> Result<()> {
+        for file in files {
+            info!("removing {}", file.display());
+            fs::remove_file(file)?;
+            let self_profile = self.self_profile().join(file.file_name().unwrap());
+            if self_profile.exists() {
+                fs::remove_file(self_profile)?;
+            }
+        }
+        self.commit_and_push(message)
+    }
+
     fn commit_and_push(&self, message: &str) -> Result<()> {
         self.write_retries()?;
-        self.git(&["add", "retries", "times"])?;
+        self.git(&["add", "retries", "times", "self-profile"])?;
 
         // dirty index
         if let Err(_) = self.git(&["diff-index", "--quiet", "--cached", "HEAD"]) {
@@ -143,20 +233,110 @@ impl Repo {
         Ok(data)
     }
 
+    /// The most recent results for `triple`, if any.
+    pub fn newest_commit_data(&self, triple: &str) -> Result<Option<CommitData>> {
+        let newest = self.times_files()?
+            .into_iter()
+            .filter(|file| file.triple == triple)
+            .max_by_key(|file| file.date);
+        let file = match newest {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        info!("loading file {}", file.path.display());
+        let reader = File::open(&file.path)
+            .chain_err(|| format!("failed to open {}", file.path.display()))?;
+        Ok(Some(serde_json::from_reader(reader)
+            .chain_err(|| format!("failed to parse {}", file.path.display()))?))
+    }
+
+    /// Write `data` to `times/`, and its query timings, if any, to the file
+    /// of the same name in `self-profile/`.
     pub fn add_commit_data(&self, data: &CommitData) -> Result<()> {
-        let commit = &data.commit;
-        let filepath = self.times().join(format!(
-            "{}-{}-{}.json",
-            commit.date,
-            commit.sha,
-            data.triple
-        ));
+        let mut data = data.clone();
+        let commit = data.commit.clone();
+        let filename = format!("{}-{}-{}.json", commit.date, commit.sha, data.triple);
+        if let Some(self_profile) = data.split_self_profile() {
+            let filepath = self.self_profile().join(&filename);
+            info!("creating file {}", filepath.display());
+            serde_json::to_writer(&mut File::create(&filepath)?, &self_profile)?;
+        }
+        let filepath = self.times().join(&filename);
         info!("creating file {}", filepath.display());
         let mut file = File::create(&filepath)?;
         serde_json::to_writer(&mut file, &data)?;
         Ok(())
     }
 
+    /// Remove `names.json`, in which collectors used to record ids of the
+    /// patch and statistic names, to be committed with the migration that
+    /// drops it.
+    pub fn remove_names(&self) -> Result<()> {
+        self.git(&["rm", "--quiet", "--ignore-unmatch", "names.json"])?;
+        let path = self.names_file();
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// The migrations recorded in `migrations.json`; none if it's missing.
+    pub fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+        let path = self.migrations_file();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = File::open(&path)?;
+        Ok(serde_json::from_reader(file)
+            .chain_err(|| format!("failed to parse {}", path.display()))?)
+    }
+
+    /// Record `migration` as applied and commit it, with the files it
+    /// changed.
+    pub fn migrated(&self, migration: AppliedMigration) -> Result<()> {
+        let mut applied = self.applied_migrations()?;
+        let message = format!("migration {}: {}", migration.version, migration.name);
+        applied.push(migration);
+        serde_json::to_writer_pretty(&mut File::create(self.migrations_file())?, &applied)?;
+        self.git(&["add", "migrations.json"])?;
+        self.commit_and_push(&message)
+    }
+
+    /// The benchmark renames recorded in `renames.json`; none if it's
+    /// missing.
+    pub fn renames(&self) -> Result<Vec<Rename>> {
+        let path = self.renames_file();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = File::open(&path)?;
+        Ok(serde_json::from_reader(file)
+            .chain_err(|| format!("failed to parse {}", path.display()))?)
+    }
+
+    /// Record `rename` and commit it.
+    pub fn add_rename(&self, rename: Rename) -> Result<()> {
+        let mut renames = self.renames()?;
+        let message = format!("rename benchmark {} to {}", rename.old, rename.new);
+        renames.push(rename);
+        serde_json::to_writer_pretty(&mut File::create(self.renames_file())?, &renames)?;
+        self.git(&["add", "renames.json"])?;
+        self.commit_and_push(&message)
+    }
+
+    /// Commit the files copied into the repository by `import`, together
+    /// with the `renames` it found.
+    pub fn imported(&self, renames: Vec<Rename>, message: &str) -> Result<()> {
+        if !renames.is_empty() {
+            let mut all = self.renames()?;
+            all.extend(renames);
+            serde_json::to_writer_pretty(&mut File::create(self.renames_file())?, &all)?;
+            self.git(&["add", "renames.json"])?;
+        }
+        self.git(&["add", "bootstrap", "calibration"])?;
+        self.commit_and_push(message)
+    }
+
     fn load_retries(&mut self) -> Result<()> {
         let mut retries = OpenOptions::new()
             .read(true)
@@ -207,6 +387,39 @@ impl Repo {
         self.path.join("broken-commits-log")
     }
 
+    fn migrations_file(&self) -> PathBuf {
+        self.path.join("migrations.json")
+    }
+
+    fn renames_file(&self) -> PathBuf {
+        self.path.join("renames.json")
+    }
+
+    pub fn names_file(&self) -> PathBuf {
+        self.path.join("names.json")
+    }
+
+    /// Progress of the commit currently being benchmarked. Not committed.
+    pub fn checkpoint_file(&self) -> PathBuf {
+        self.path.join("checkpoint.json")
+    }
+
+    /// How long the builds of each benchmark took on this machine recently.
+    /// Not committed.
+    pub fn durations_file(&self) -> PathBuf {
+        self.path.join("durations.json")
+    }
+
+    /// The commands entered in `shell`. Not committed.
+    pub fn shell_history_file(&self) -> PathBuf {
+        self.path.join("shell-history")
+    }
+
+    /// When to ask each site for work again after failing to. Not committed.
+    pub fn backoff_file(&self) -> PathBuf {
+        self.path.join("site-backoff.json")
+    }
+
     fn retries_file(&self) -> PathBuf {
         self.path.join("retries")
     }
@@ -214,4 +427,16 @@ impl Repo {
     pub fn times(&self) -> PathBuf {
         self.path.join("times")
     }
+
+    pub fn bootstrap(&self) -> PathBuf {
+        self.path.join("bootstrap")
+    }
+
+    pub fn self_profile(&self) -> PathBuf {
+        self.path.join("self-profile")
+    }
+
+    pub fn calibration(&self) -> PathBuf {
+        self.path.join("calibration")
+    }
 }
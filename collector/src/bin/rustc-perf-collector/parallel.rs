@@ -0,0 +1,123 @@
+//! Run several benchmarks at once.
+//!
+//! Each worker is pinned to its own set of CPUs, those of the cgroup if one
+//! is configured, so that concurrent builds don't compete for cores. The
+//! measured rustc invocations are still serialized through a lock held by
+//! `rustc-fake`, as the hardware counters need the machine to themselves;
+//! only the surrounding work (copying, building dependencies) overlaps.
+
+use std::any::Any;
+use std::cmp;
+use std::fs::File;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use libc;
+use tempdir::TempDir;
+
+use collector::Patch;
+use checkpoint::Checkpoint;
+use config::Config;
+use durations::Durations;
+use execute::{Benchmark, Slot};
+use rust_sysroot::sysroot::Sysroot;
+
+pub fn run(
+    sysroot: Sysroot,
+    benchmarks: Vec<Benchmark>,
+    config: &Config,
+    jobs: usize,
+    checkpoint: Option<Checkpoint>,
+    durations: Option<Durations>,
+) -> Vec<(String, Result<Vec<Patch>, String>)> {
+    let cpus = match config.cgroup {
+        Some(ref cgroup) => cgroup.cpu_list(),
+        None => (0..num_cpus()).collect(),
+    };
+    let per_job = cmp::max(1, cpus.len() / jobs);
+    // Removed with the directory once all workers are done.
+    let lock_dir = TempDir::new("rustc-perf-lock").expect("failed to create lock directory");
+    let lock = lock_dir.path().join("measurement.lock");
+    // Created up front, so that sandboxed builds can be given access to it.
+    File::create(&lock).expect("failed to create lock file");
+    info!("running {} jobs with {} CPUs each", jobs, per_job);
+
+    let sysroot = Arc::new(sysroot);
+    let config = Arc::new(config.clone());
+    let queue = Arc::new(Mutex::new(benchmarks));
+    let results = Arc::new(Mutex::new(Vec::new()));
+
+    let workers = (0..jobs)
+        .map(|job| {
+            let first = (job * per_job) % cpus.len();
+            let slot = Slot {
+                cpus: cpus.iter()
+                    .cycle()
+                    .skip(first)
+                    .take(per_job)
+                    .map(|cpu| cpu.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+                lock: lock.clone(),
+            };
+            let sysroot = sysroot.clone();
+            let config = config.clone();
+            let queue = queue.clone();
+            let results = results.clone();
+            let checkpoint = checkpoint.clone();
+            let durations = durations.clone();
+            thread::spawn(move || loop {
+                let benchmark = match queue.lock().unwrap().pop() {
+                    Some(benchmark) => benchmark,
+                    None => break,
+                };
+                info!("job {} (cpus {}): {}", job, slot.cpus, benchmark.name);
+                // A panic fails the benchmark, not the worker and its other
+                // benchmarks.
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    benchmark.run_all(&sysroot, &config, Some(&slot), durations.as_ref())
+                }));
+                let result = match result {
+                    Ok(result) => result.map_err(|e| format!("{:?}", e)),
+                    Err(payload) => Err(format!("panicked: {}", panic_message(&*payload))),
+                };
+                if let (Some(checkpoint), &Ok(ref patches)) = (checkpoint.as_ref(), &result) {
+                    if let Err(e) = checkpoint.record(&benchmark.name, patches) {
+                        warn!("failed to checkpoint {}: {:?}", benchmark.name, e);
+                    }
+                }
+                // The benchmarks already running are left to finish.
+                if result.is_err() && config.fail_fast {
+                    info!("not starting the remaining benchmarks: {} failed", benchmark.name);
+                    queue.lock().unwrap().clear();
+                }
+                results.lock().unwrap().push((benchmark.name, result));
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for (job, worker) in workers.into_iter().enumerate() {
+        if worker.join().is_err() {
+            error!("job {} stopped unexpectedly", job);
+        }
+    }
+
+    let results = results.lock().unwrap();
+    results.clone()
+}
+
+/// The message a panic was started with, if it was given one.
+fn panic_message(payload: &(Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map_or("unknown cause", |message| &message[..]),
+    }
+}
+
+fn num_cpus() -> usize {
+    let n = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    cmp::max(n, 1) as usize
+}
@@ -0,0 +1,49 @@
+//! Post-processing of the call-graph profiles the `perf-record` profiler
+//! records with `perf record -g`, for looking into where rustc spends its
+//! time in more detail than its self-profile shows.
+//!
+//! The raw profile is kept as `perf.data`. `perf script` turns it into
+//! samples, which `inferno-collapse-perf` folds into `perf-record.folded`,
+//! drawn as a flamegraph in `perf-record.svg`.
+
+use std::fs::File;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use errors::*;
+use flamegraph;
+
+/// Fold the samples of `perf.data` in `dir` into stacks and draw them. With
+/// `symbolicate`, inlined functions and source lines are resolved from the
+/// toolchain's debuginfo, which is much slower.
+pub fn collapse(dir: &Path, symbolicate: bool) -> Result<()> {
+    let profile = dir.join("perf.data");
+    if !profile.exists() {
+        bail!("no perf profile in {}", dir.display());
+    }
+    let mut script = Command::new("perf");
+    script.arg("script").arg("-i").arg(&profile);
+    if symbolicate {
+        script.arg("--inline");
+    } else {
+        script.arg("--no-inline");
+    }
+    let mut script = script
+        .stdout(Stdio::piped())
+        .spawn()
+        .chain_err(|| "could not spawn perf; is it installed?")?;
+
+    let folded = dir.join("perf-record.folded");
+    let collapsed = Command::new("inferno-collapse-perf")
+        .stdin(script.stdout.take().unwrap())
+        .stdout(File::create(&folded)?)
+        .status()
+        .chain_err(|| "could not spawn inferno-collapse-perf; is inferno installed?")?;
+    if !script.wait()?.success() {
+        bail!("perf script failed on {}", profile.display());
+    }
+    if !collapsed.success() {
+        bail!("inferno-collapse-perf failed on {}", profile.display());
+    }
+    flamegraph::draw(&folded, &dir.join("perf-record.svg"), "samples")
+}
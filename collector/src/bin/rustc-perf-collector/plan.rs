@@ -0,0 +1,262 @@
+//! What `bench_local --plan` prints instead of running the benchmarks: the
+//! toolchain, every build of every benchmark, and an estimate of how long the
+//! run takes, based on the newest results in the output repository.
+
+use std::path::Path;
+use std::process::Command;
+
+use collector::{Backend, CommitData, Profile, Std};
+
+use checkpoint::Checkpoint;
+use config::Config;
+use errors::*;
+use execute::{Benchmark, WARM_FULL};
+
+/// Builds of a patch with the same profile, backend, std, flags and custom
+/// profile.
+struct Step {
+    benchmark: String,
+    profile: Profile,
+    backend: Backend,
+    std: Std,
+    flags: Option<String>,
+    custom_profile: Option<String>,
+    scenario: String,
+    iterations: usize,
+    /// Seconds the last measured build took, if known
+    seconds: Option<f64>,
+}
+
+pub fn print(
+    rustc: &str,
+    config: &Config,
+    benchmarks: &[Benchmark],
+    history: Option<&CommitData>,
+    checkpoint: Option<&Checkpoint>,
+    jobs: usize,
+) -> Result<()> {
+    print_toolchain(rustc, config)?;
+    match history {
+        Some(data) => println!(
+            "timings: {} ({})",
+            data.commit.sha,
+            data.commit.date.0.format("%Y-%m-%d")
+        ),
+        None => println!("timings: none, there are no results for {}", config.target),
+    }
+    println!();
+
+    let mut steps = Vec::new();
+    let mut done = Vec::new();
+    for benchmark in benchmarks {
+        if let Some(patches) = checkpoint.and_then(|c| c.get(&benchmark.name)) {
+            if ::covers_config(&patches, config) {
+                done.push(&*benchmark.name);
+                continue;
+            }
+        }
+        let patches = benchmark.source_patches()?;
+        for &profile in &config.profiles {
+            for &backend in &config.backends {
+                for &std in &config.std {
+                    for (flags, custom) in build_variants(config, profile) {
+                        let step = |scenario: String, patch: &str, iterations| Step {
+                            benchmark: benchmark.name.clone(),
+                            profile: profile,
+                            backend: backend,
+                            std: std,
+                            flags: flags.map(String::from),
+                            custom_profile: custom.map(String::from),
+                            scenario: scenario,
+                            iterations: iterations,
+                            seconds: last_seconds(
+                                history,
+                                &benchmark.name,
+                                &(benchmark.name.clone() + patch),
+                                profile,
+                                backend,
+                                std,
+                                flags,
+                                custom,
+                            ),
+                        };
+                        for patch in &patches {
+                            steps.push(step(scenario(patch), patch, config.iterations()));
+                        }
+                        if config.warm_full {
+                            // Estimated like the full build it repeats.
+                            let warm_full = String::from(WARM_FULL);
+                            steps.push(step(warm_full, &patches[0], config.iterations()));
+                        }
+                        for tool in config.extra_builds() {
+                            for patch in &patches {
+                                let name = format!("{} ({})", scenario(patch), tool);
+                                steps.push(step(name, patch, 1));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let width = steps.iter().map(|s| s.benchmark.len()).max().unwrap_or(0);
+    let profile_width = steps.iter().map(|s| profile_name(s).len()).max().unwrap_or(0);
+    let scenario_width = steps.iter().map(|s| s.scenario.len()).max().unwrap_or(0);
+    println!(
+        "{:<w$}  {:<pw$}  {:<9}  {:<9}  {:<12}  {:<sw$}  {:>10}  {:>9}",
+        "benchmark",
+        "profile",
+        "backend",
+        "std",
+        "flags",
+        "scenario",
+        "iterations",
+        "estimate",
+        w = width,
+        pw = profile_width.max(7),
+        sw = scenario_width
+    );
+    let mut builds = 0;
+    let mut seconds = 0.0;
+    let mut unknown = 0;
+    for step in &steps {
+        let estimate = step.seconds.map(|s| s * step.iterations as f64);
+        println!(
+            "{:<w$}  {:<pw$}  {:<9}  {:<9}  {:<12}  {:<sw$}  {:>10}  {:>9}",
+            step.benchmark,
+            profile_name(step),
+            step.backend.to_string(),
+            step.std.to_string(),
+            step.flags.as_ref().map_or("-", |f| &**f),
+            step.scenario,
+            step.iterations,
+            estimate.map_or(String::from("?"), format_seconds),
+            w = width,
+            pw = profile_width.max(7),
+            sw = scenario_width
+        );
+        builds += step.iterations;
+        match estimate {
+            Some(estimate) => seconds += estimate,
+            None => unknown += step.iterations,
+        }
+    }
+
+    println!();
+    if !done.is_empty() {
+        println!("already done according to the checkpoint: {}", done.join(", "));
+    }
+    println!(
+        "{} builds, estimated to take {}{}",
+        builds,
+        format_seconds(seconds / jobs as f64),
+        if jobs > 1 {
+            format!(" with {} jobs", jobs)
+        } else {
+            String::new()
+        }
+    );
+    if unknown > 0 {
+        println!("plus {} builds without earlier timings", unknown);
+    }
+    println!("Estimates don't include building the benchmarks' dependencies.");
+    Ok(())
+}
+
+fn print_toolchain(rustc: &str, config: &Config) -> Result<()> {
+    let path = Path::new(rustc)
+        .canonicalize()
+        .chain_err(|| format!("rustc {} doesn't exist", rustc))?;
+    let output = Command::new(&path)
+        .arg("--version")
+        .output()
+        .chain_err(|| format!("could not run {}", path.display()))?;
+    println!(
+        "rustc:   {} ({})",
+        path.display(),
+        String::from_utf8_lossy(&output.stdout).trim()
+    );
+    match config.cargo {
+        Some(ref cargo) => println!("cargo:   {}", cargo.display()),
+        None => println!("cargo:   the one of the rustc's toolchain"),
+    }
+    if config.profiles.contains(&Profile::Clippy) {
+        let driver = path.with_file_name("clippy-driver");
+        if driver.exists() {
+            println!("clippy:  {}", driver.display());
+        } else {
+            println!("clippy:  missing, expected {}", driver.display());
+        }
+    }
+    println!("target:  {}", config.target);
+    Ok(())
+}
+
+/// Each flag set with each custom profile, in the order they are run with
+/// `profile`.
+fn build_variants(config: &Config, profile: Profile) -> Vec<(Option<&str>, Option<&str>)> {
+    let mut variants = Vec::new();
+    for flags in config.flag_set_names(profile) {
+        for custom in config.custom_profile_names() {
+            variants.push((flags, custom));
+        }
+    }
+    variants
+}
+
+/// The profile as shown in the plan, e.g. `debug/opt-lto-thin` with a custom
+/// profile.
+fn profile_name(step: &Step) -> String {
+    match step.custom_profile {
+        Some(ref custom) => format!("{}/{}", step.profile, custom),
+        None => step.profile.to_string(),
+    }
+}
+
+/// The patch as shown in the plan; non-incremental benchmarks have a single,
+/// unnamed patch.
+fn scenario(patch: &str) -> String {
+    if patch.is_empty() {
+        String::from("full")
+    } else {
+        patch.to_string()
+    }
+}
+
+/// How long the build of the patch `name` took in `history`, according to
+/// the wall time if measured, or else the task clock.
+pub fn last_seconds(
+    history: Option<&CommitData>,
+    benchmark: &str,
+    name: &str,
+    profile: Profile,
+    backend: Backend,
+    std: Std,
+    flags: Option<&str>,
+    custom_profile: Option<&str>,
+) -> Option<f64> {
+    let patches = match *history?.benchmarks.get(benchmark)? {
+        Ok(ref patches) => patches,
+        Err(_) => return None,
+    };
+    let patch = patches.iter().find(|p| {
+        p.name == name && p.profile == profile && p.backend == backend && p.std == std
+            && p.flags.as_ref().map(|f| &**f) == flags
+            && p.custom_profile.as_ref().map(|c| &**c) == custom_profile
+    })?;
+    let run = patch.runs.first()?;
+    run.get_stat("wall-time")
+        .or_else(|| run.get_stat("task-clock").map(|ms| ms / 1000.0))
+}
+
+fn format_seconds(seconds: f64) -> String {
+    let whole = seconds.round() as u64;
+    if whole >= 60 * 60 {
+        format!("{}h {:02}m", whole / 3600, whole % 3600 / 60)
+    } else if whole >= 60 {
+        format!("{}m {:02}s", whole / 60, whole % 60)
+    } else {
+        format!("{:.1}s", seconds)
+    }
+}
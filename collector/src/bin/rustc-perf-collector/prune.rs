@@ -0,0 +1,131 @@
+//! Retention policy for the output repository, which otherwise grows
+//! without bound.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Datelike, Duration, Utc};
+use rust_sysroot::git::Commit as GitCommit;
+
+use errors::*;
+use outrepo::{Repo, TimesFile};
+
+pub struct Policy {
+    /// Master data newer than this is kept in full.
+    pub full_resolution: Duration,
+    /// Try build data older than this is deleted.
+    pub try_retention: Duration,
+}
+
+/// Select the files in `times/` that `policy` says to delete: master data
+/// older than `policy.full_resolution` is downsampled to the last commit of
+/// each week, and data for commits that aren't on master (try builds) is
+/// deleted once older than `policy.try_retention`.
+pub fn select(
+    repo: &Repo,
+    master: &[GitCommit],
+    policy: &Policy,
+    now: DateTime<Utc>,
+) -> Result<Vec<PathBuf>> {
+    let master = master.iter().map(|c| &*c.sha).collect::<HashSet<_>>();
+    let full_resolution_cutoff = now - policy.full_resolution;
+    let try_cutoff = now - policy.try_retention;
+
+    let mut remove = Vec::new();
+    // (triple, ISO year, ISO week) -> files in that week, to be downsampled
+    let mut weeks: BTreeMap<(String, i32, u32), Vec<TimesFile>> = BTreeMap::new();
+    for file in repo.times_files()? {
+        if !master.contains(&*file.sha) {
+            if file.date < try_cutoff {
+                remove.push(file.path);
+            }
+        } else if file.date < full_resolution_cutoff {
+            let week = file.date.iso_week();
+            weeks
+                .entry((file.triple.clone(), week.year(), week.week()))
+                .or_insert_with(Vec::new)
+                .push(file);
+        }
+    }
+
+    for (_, mut files) in weeks {
+        files.sort_by_key(|file| file.date);
+        files.pop();
+        remove.extend(files.into_iter().map(|file| file.path));
+    }
+    remove.sort();
+    Ok(remove)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+
+    use chrono::TimeZone;
+    use tempdir::TempDir;
+
+    const LINUX: &str = "x86_64-unknown-linux-gnu";
+
+    fn sha(c: char) -> String {
+        ::std::iter::repeat(c).take(40).collect()
+    }
+
+    fn date(month: u32, day: u32) -> DateTime<Utc> {
+        Utc.ymd(2017, month, day).and_hms(12, 0, 0)
+    }
+
+    /// Creates the results of `sha` on `triple` and returns their path.
+    fn add(repo: &Repo, sha: &str, date: DateTime<Utc>, triple: &str) -> PathBuf {
+        let name = format!("{}-{}-{}.json", date.to_rfc3339(), sha, triple);
+        let path = repo.times().join(name);
+        File::create(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn select_downsamples_old_master_and_drops_old_try_builds() {
+        let dir = TempDir::new("rustc-perf-prune").unwrap();
+        File::create(dir.path().join("retries")).unwrap();
+        let repo = Repo::open(dir.path().to_path_buf(), false).unwrap();
+        let master = [
+            ('1', date(8, 1)),
+            ('2', date(8, 3)),
+            ('3', date(8, 4)),
+            ('4', date(8, 8)),
+            ('5', date(10, 1)),
+            ('6', date(10, 2)),
+        ].iter()
+            .map(|&(c, date)| GitCommit {
+                sha: sha(c),
+                date: date,
+                summary: String::new(),
+            })
+            .collect::<Vec<_>>();
+
+        // Three commits in the week of August 1st, of which the last is kept.
+        let week_1 = add(&repo, &sha('1'), date(8, 1), LINUX);
+        let week_2 = add(&repo, &sha('2'), date(8, 3), LINUX);
+        add(&repo, &sha('3'), date(8, 4), LINUX);
+        // Weeks are downsampled for each target on its own.
+        add(&repo, &sha('1'), date(8, 1), "i686-unknown-linux-gnu");
+        add(&repo, &sha('4'), date(8, 8), LINUX);
+        // Recent master data is kept in full.
+        add(&repo, &sha('5'), date(10, 1), LINUX);
+        add(&repo, &sha('6'), date(10, 2), LINUX);
+        // Try builds are kept for a week.
+        let old_try = add(&repo, &sha('a'), date(10, 1), LINUX);
+        add(&repo, &sha('b'), date(10, 10), LINUX);
+        File::create(repo.times().join("README")).unwrap();
+
+        let policy = Policy {
+            full_resolution: Duration::days(30),
+            try_retention: Duration::days(7),
+        };
+        let remove = select(&repo, &master, &policy, date(10, 12)).unwrap();
+        let mut expected = vec![week_1, week_2, old_try];
+        expected.sort();
+        assert_eq!(remove, expected);
+    }
+}
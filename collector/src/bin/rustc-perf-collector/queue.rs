@@ -0,0 +1,349 @@
+//! Claiming commits to benchmark from the site's queue.
+//!
+//! A claim is a lease, which expires unless it is renewed. While a `Claim` is
+//! alive, a background thread renews it; once the results are pushed to the
+//! output repository, the collector ends it by reporting the commit done. A
+//! member may cancel the entry meanwhile, which the collector checks for
+//! between benchmarks.
+//!
+//! Asking for work, a collector tells the site which metrics it gathers, so
+//! that the site knows which statistics to expect in its results.
+//!
+//! A collector may take work from several sites, e.g. production and a
+//! staging instance that uses the machine while production has nothing
+//! queued. A site that can't be reached or returns an error isn't asked
+//! again for a while, twice as long after every consecutive failure.
+//!
+//! A toolchain built on the collector can be registered with a site, which
+//! queues it as the pseudo-artifact `local-<name>` for this collector only.
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{self, DateTime, Utc};
+use reqwest;
+use rust_sysroot::git::Commit as GitCommit;
+use serde_json;
+
+use config::SiteConfig;
+use errors::*;
+
+/// Well within the site's lease of an hour.
+const RENEW_EVERY: u64 = 10 * 60;
+
+/// Seconds a site isn't asked after its first failure.
+const BACKOFF_BASE: i64 = 60;
+
+/// The most seconds a site isn't asked after consecutive failures.
+const BACKOFF_MAX: i64 = 60 * 60;
+
+/// The consecutive failures of a site, and when to ask it again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Backoff {
+    failures: u32,
+    until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Next {
+    commit: Option<QueuedCommit>,
+    key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueuedCommit {
+    sha: String,
+    date: DateTime<Utc>,
+    summary: String,
+    #[serde(default)] experiment: Option<Experiment>,
+    #[serde(default)] calibration: bool,
+    #[serde(default)] local: Option<LocalToolchain>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocalToolchain {
+    rustc: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RegisterRequest<'a> {
+    name: &'a str,
+    rustc: &'a str,
+    base: Option<&'a str>,
+    date: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegisterResponse {
+    queued: Option<QueuedCommit>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaimResponse {
+    held: bool,
+    #[serde(default)] canceled: bool,
+}
+
+/// Extra rustc flags to benchmark a master commit with, recorded as a flag
+/// set named after the experiment.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Experiment {
+    pub name: String,
+    pub flags: String,
+}
+
+/// A commit claimed from the queue, and the experiment to benchmark it with,
+/// if any.
+pub struct Claim {
+    pub commit: GitCommit,
+    pub experiment: Option<Experiment>,
+    /// Whether the commit is the calibration artifact, to be benchmarked
+    /// again and recorded as a calibration run
+    pub calibration: bool,
+    /// The rustc of the toolchain to benchmark if the entry is a toolchain
+    /// built on this collector, instead of the CI artifacts of the commit
+    pub local_rustc: Option<String>,
+    /// Where the site reads the results from, if not the default output
+    /// repository
+    pub output_repo: Option<PathBuf>,
+    site: String,
+    token: String,
+    key: String,
+    /// Dropping it stops the renewals
+    _renewing: Sender<()>,
+}
+
+impl Claim {
+    /// Tell the site that the results were pushed, so that the commit leaves
+    /// the queue.
+    pub fn done(self) -> Result<()> {
+        if !request(&self.site, "done", &self.token, &self.key)?.held {
+            warn!("claim on {} had already ended", self.key);
+        }
+        Ok(())
+    }
+
+    /// Tell the site that benchmarking failed, so that the commit is queued
+    /// again or given up on.
+    pub fn fail(self) -> Result<()> {
+        if !request(&self.site, "fail", &self.token, &self.key)?.held {
+            warn!("claim on {} had already expired", self.key);
+        }
+        Ok(())
+    }
+
+    /// Whether a member canceled the entry on the site, so that its results
+    /// are no longer wanted. Asking renews the claim.
+    pub fn canceled(&self) -> bool {
+        match request(&self.site, "renew", &self.token, &self.key) {
+            Ok(claim) => claim.canceled,
+            Err(err) => {
+                warn!("failed to renew claim on {}: {:?}", self.key, err);
+                false
+            }
+        }
+    }
+}
+
+/// Claim the commit the site at `site` (e.g. `https://perf.rust-lang.org`)
+/// wants benchmarked next, telling it that this collector gathers `metrics`.
+/// `None` if its queue is empty. `token` must have the collector role.
+pub fn next(site: &str, token: &str, metrics: &BTreeSet<String>) -> Result<Option<Claim>> {
+    let site = site.trim_right_matches('/').to_string();
+    let metrics = metrics.iter().cloned().collect::<Vec<_>>().join(",");
+    let params = [("token", token), ("metrics", &metrics)];
+    let url = site_url(&site, "/perf/queue/next", &params)?;
+    let mut response = reqwest::get(url)?;
+    if !response.status().is_success() {
+        bail!("{}/perf/queue/next returned {}", site, response.status());
+    }
+    let next: Next = response.json()?;
+    let (commit, key) = match (next.commit, next.key) {
+        (Some(commit), Some(key)) => (commit, key),
+        _ => return Ok(None),
+    };
+    info!("claimed {}", key);
+
+    let (sender, receiver) = mpsc::channel::<()>();
+    {
+        let (site, token, key) = (site.clone(), token.to_string(), key.clone());
+        thread::spawn(move || loop {
+            match receiver.recv_timeout(Duration::from_secs(RENEW_EVERY)) {
+                Err(RecvTimeoutError::Timeout) => match request(&site, "renew", &token, &key) {
+                    Ok(ref claim) if claim.held => {}
+                    Ok(ref claim) if claim.canceled => info!("{} was canceled", key),
+                    Ok(_) => warn!("claim on {} expired; another collector may run it", key),
+                    Err(err) => warn!("failed to renew claim on {}: {:?}", key, err),
+                },
+                _ => return,
+            }
+        });
+    }
+
+    Ok(Some(Claim {
+        commit: GitCommit {
+            sha: commit.sha,
+            date: commit.date,
+            summary: commit.summary,
+        },
+        experiment: commit.experiment,
+        calibration: commit.calibration,
+        local_rustc: commit.local.map(|local| local.rustc),
+        output_repo: None,
+        site: site,
+        token: token.to_string(),
+        key: key,
+        _renewing: sender,
+    }))
+}
+
+/// Claim the next commit from the first of `sites`, by decreasing priority,
+/// that has one queued. Sites backing off after failures are skipped; their
+/// backoff is kept in the file `backoff`. Sites without a token are skipped
+/// too. Fails if every site asked failed.
+pub fn next_of(
+    sites: &[SiteConfig],
+    metrics: &BTreeSet<String>,
+    backoff: &Path,
+) -> Result<Option<Claim>> {
+    let mut state: BTreeMap<String, Backoff> = File::open(backoff)
+        .ok()
+        .and_then(|file| serde_json::from_reader(file).ok())
+        .unwrap_or_default();
+    let mut sites = sites.iter().collect::<Vec<_>>();
+    sites.sort_by_key(|site| cmp::Reverse(site.priority));
+
+    let now = Utc::now();
+    let mut claim = None;
+    let mut failed = 0;
+    let mut asked = 0;
+    for site in sites {
+        if let Some(until) = state.get(&site.url).and_then(|backoff| backoff.until) {
+            if now < until {
+                info!("not asking {} until {}", site.url, until);
+                continue;
+            }
+        }
+        asked += 1;
+        let token = match env::var(&site.token_env) {
+            Ok(token) => token,
+            Err(_) => {
+                failed += 1;
+                warn!("not asking {}: {} is not set", site.url, site.token_env);
+                continue;
+            }
+        };
+        match next(&site.url, &token, metrics) {
+            Ok(claimed) => {
+                state.remove(&site.url);
+                if let Some(mut claimed) = claimed {
+                    claimed.output_repo = site.output_repo.clone();
+                    claim = Some(claimed);
+                    break;
+                }
+                info!("queue of {} is empty", site.url);
+            }
+            Err(err) => {
+                failed += 1;
+                let backoff = state.entry(site.url.clone()).or_insert_with(Backoff::default);
+                backoff.failures += 1;
+                let doublings = cmp::min(backoff.failures - 1, 6);
+                let seconds = cmp::min(BACKOFF_BASE << doublings, BACKOFF_MAX);
+                backoff.until = Some(now + chrono::Duration::seconds(seconds));
+                warn!("failed to ask {}, not asking again for {}s: {:?}", site.url, seconds, err);
+            }
+        }
+    }
+
+    let tmp = backoff.with_extension("tmp");
+    serde_json::to_writer(&mut File::create(&tmp)?, &state)?;
+    fs::rename(&tmp, backoff)
+        .chain_err(|| format!("failed to write backoff {}", backoff.display()))?;
+    if asked > 0 && failed == asked {
+        bail!("none of the sites could be asked for work");
+    }
+    Ok(claim)
+}
+
+/// Register the toolchain of `rustc`, built on this collector from the
+/// master commit `base` if known, with the site at `site` as `local-<name>`.
+/// Its results are recorded with `date`. Returns the key of the queue entry.
+pub fn register(
+    site: &str,
+    token: &str,
+    name: &str,
+    rustc: &str,
+    base: Option<&str>,
+    date: DateTime<Utc>,
+) -> Result<String> {
+    let site = site.trim_right_matches('/');
+    let url = site_url(site, "/perf/local-toolchain", &[("token", token)])?;
+    let body = RegisterRequest {
+        name: name,
+        rustc: rustc,
+        base: base,
+        date: date,
+    };
+    let mut response = reqwest::Client::new()?.post(url).json(&body).send()?;
+    if !response.status().is_success() {
+        bail!("{}/perf/local-toolchain returned {}", site, response.status());
+    }
+    let registered: RegisterResponse = response.json()?;
+    match (registered.queued, registered.error) {
+        (_, Some(error)) => bail!("{} rejected the toolchain: {}", site, error),
+        (Some(queued), None) => Ok(queued.sha),
+        (None, None) => bail!("{} queued nothing", site),
+    }
+}
+
+/// Renew (`action` `renew`), end (`done`) or fail (`fail`) the claim `key`. Returns
+/// whether the claim was still held, and whether the entry was canceled.
+fn request(site: &str, action: &str, token: &str, key: &str) -> Result<ClaimResponse> {
+    let path = format!("/perf/queue/{}", action);
+    let url = site_url(site, &path, &[("token", token), ("key", key)])?;
+    let mut response = reqwest::get(url)?;
+    if !response.status().is_success() {
+        bail!("{}/perf/queue/{} returned {}", site, action, response.status());
+    }
+    Ok(response.json()?)
+}
+
+/// The URL of `path` on `site` with the query `params`, which are encoded as
+/// tokens and experiment names may contain `+`, `&` or spaces.
+fn site_url(site: &str, path: &str, params: &[(&str, &str)]) -> Result<reqwest::Url> {
+    let url = format!("{}{}", site, path);
+    reqwest::Url::parse_with_params(&url, params).chain_err(|| format!("invalid URL {}", url))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn sites_without_tokens_are_skipped() {
+        let dir = TempDir::new("rustc-perf-backoff").unwrap();
+        let backoff = dir.path().join("backoff.json");
+        let mut sites = vec![
+            SiteConfig::new("https://perf.example.org"),
+            SiteConfig::new("https://staging.example.org"),
+        ];
+        for site in &mut sites {
+            site.token_env = String::from("RUSTC_PERF_TEST_UNSET_TOKEN");
+        }
+        let err = next_of(&sites, &BTreeSet::new(), &backoff).err().unwrap();
+        assert_eq!(err.to_string(), "none of the sites could be asked for work");
+        // Not a failure of the sites, so they aren't backed off from.
+        let state: BTreeMap<String, Backoff> =
+            serde_json::from_reader(File::open(&backoff).unwrap()).unwrap();
+        assert!(state.is_empty());
+    }
+}
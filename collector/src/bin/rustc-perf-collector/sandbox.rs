@@ -0,0 +1,71 @@
+//! Running benchmark builds in a bubblewrap sandbox.
+//!
+//! Benchmarks build third-party crates, whose build scripts and procedural
+//! macros run arbitrary code. In the sandbox, the file system is read-only
+//! except for the build directory and Cargo's cache, and there is no
+//! network access. Dependencies are therefore fetched beforehand, outside
+//! the sandbox.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rust_sysroot::sysroot::Sysroot;
+
+use errors::*;
+
+/// `CARGO_HOME` of sandboxed builds. It's writable from inside the sandbox,
+/// so it's kept separate from the collector user's own Cargo cache.
+fn cargo_home() -> Result<PathBuf> {
+    let path = Path::new("sandbox-cargo-home");
+    fs::create_dir_all(path).chain_err(|| "can't create `sandbox-cargo-home`")?;
+    Ok(path.canonicalize()?)
+}
+
+/// Download the dependencies of the benchmark copied to `dir`.
+pub fn fetch(sysroot: &Sysroot, dir: &Path) -> Result<()> {
+    let mut command = sysroot.command(&sysroot.cargo);
+    command
+        .current_dir(dir)
+        .arg("fetch")
+        .env("CARGO_HOME", cargo_home()?);
+    info!("running `{:?}`", command);
+    let output = command.output().chain_err(|| "could not spawn cargo fetch")?;
+    if !output.status.success() {
+        bail!(
+            "cargo fetch failed in {}:\n{}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+/// Run `program` in `dir` inside the sandbox. Besides `dir` and Cargo's
+/// cache, only the paths in `writable`, which must exist, can be written.
+/// Cargo has to be run with `--frozen`, as it can't reach the network.
+pub fn command(
+    sysroot: &Sysroot,
+    dir: &Path,
+    writable: &[&Path],
+    program: &str,
+) -> Result<Command> {
+    let cargo_home = cargo_home()?;
+    let mut command = sysroot.command("bwrap");
+    command
+        .args(&["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"])
+        .args(&["--unshare-net", "--unshare-ipc", "--unshare-uts", "--die-with-parent"]);
+    for path in [dir, &*cargo_home].iter().chain(writable) {
+        command.arg("--bind").arg(path).arg(path);
+    }
+    command
+        .arg("--chdir")
+        .arg(dir)
+        .arg("--")
+        .arg(program)
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env("TMPDIR", dir)
+        .env("CARGO_HOME", &cargo_home);
+    Ok(command)
+}
@@ -0,0 +1,501 @@
+//! An interactive shell for ad-hoc measurements, for iterating on a change to
+//! the compiler without retyping long `bench_local` commands.
+//!
+//! The benchmarks, scenario, profile, backend, std and toolchain to measure
+//! are picked one command at a time and kept for the following `run`s, which
+//! print the statistics of every patch built and how they changed since the
+//! previous run of the same patch. Results aren't recorded anywhere.
+//!
+//! Commands are kept in a history file across sessions, up to the newest
+//! `HISTORY_KEPT`; `history` lists the newest ones, `!N` repeats the Nth and
+//! `!!` the last.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use rust_sysroot::git::Commit as GitCommit;
+
+use collector::{Backend, Profile, Stat, Std};
+
+use config::Config;
+use errors::*;
+use execute::Benchmark;
+use toolchain;
+
+/// How many commands `history` lists.
+const HISTORY_SHOWN: usize = 20;
+
+/// How many commands the history file keeps.
+const HISTORY_KEPT: usize = 1000;
+
+/// The statistics shown by default.
+const DEFAULT_STATS: &[&str] = &["instructions:u", "wall-time", "max-rss"];
+
+const HELP: &str = "\
+list [FILTER]          list the benchmarks, or those whose name contains FILTER
+bench NAME...          measure these benchmarks; a name matches exactly, or else as a filter
+scenarios              list the scenarios (patches) of the benchmark
+scenario PATCH|all     only build up to PATCH, e.g. @010-change, or every scenario
+profile PROFILE        build with this profile, e.g. debug
+backend BACKEND        build with this codegen backend, e.g. llvm
+std STD                build with this std, e.g. static
+toolchain RUSTC|SHA    measure a local rustc (or its sysroot or checkout), or the CI artifacts of
+                       a master commit
+stats STAT...          show these statistics, e.g. instructions:u wall-time
+show                   show the current settings
+run                    measure, and compare with the previous run of each patch
+history                list the newest commands
+!N, !!                 repeat the Nth or the last command
+quit                   leave the shell";
+
+enum Toolchain {
+    /// The path of a local rustc
+    Local(String),
+    Commit(GitCommit),
+}
+
+struct Shell<'a> {
+    benchmarks: &'a [Benchmark],
+    commits: &'a [GitCommit],
+    /// The configuration, with the one profile, backend and std to build with
+    config: Config,
+    preserve_sysroot: bool,
+    selected: Vec<Benchmark>,
+    /// Run the patches up to this one only
+    scenario: Option<String>,
+    toolchain: Option<Toolchain>,
+    stats: Vec<String>,
+    /// The statistics of the previous run of each build, see `build_key`
+    previous: HashMap<String, Vec<Stat>>,
+    history: Vec<String>,
+    history_file: PathBuf,
+}
+
+/// Read commands from stdin until `quit` or the end of input, measuring the
+/// `benchmarks` with `config`. `rustc` is the toolchain to start with, if
+/// any; commits given with `toolchain` have to be among `commits`.
+pub fn run(
+    benchmarks: &[Benchmark],
+    commits: &[GitCommit],
+    config: &Config,
+    rustc: Option<&str>,
+    history_file: &Path,
+    preserve_sysroot: bool,
+) -> Result<()> {
+    let mut config = config.clone();
+    config.profiles.truncate(1);
+    config.backends.truncate(1);
+    config.std.truncate(1);
+    let mut history = File::open(history_file)
+        .map(|file| {
+            BufReader::new(file)
+                .lines()
+                .filter_map(|line| line.ok())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let excess = history.len().saturating_sub(HISTORY_KEPT);
+    history.drain(..excess);
+    let mut shell = Shell {
+        benchmarks: benchmarks,
+        commits: commits,
+        config: config,
+        preserve_sysroot: preserve_sysroot,
+        selected: Vec::new(),
+        scenario: None,
+        toolchain: None,
+        stats: DEFAULT_STATS.iter().map(|stat| stat.to_string()).collect(),
+        previous: HashMap::new(),
+        history: history,
+        history_file: history_file.to_path_buf(),
+    };
+    if let Some(rustc) = rustc {
+        shell.toolchain(rustc)?;
+    }
+    println!("{} benchmarks; `help` lists the commands", benchmarks.len());
+
+    let stdin = io::stdin();
+    loop {
+        print!("collector> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+        let line = match shell.expand(line.trim()) {
+            Ok(line) => line,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        if line.is_empty() {
+            continue;
+        }
+        shell.remember(&line)?;
+        if line == "quit" || line == "exit" {
+            return Ok(());
+        }
+        if let Err(e) = shell.execute(&line) {
+            let error = e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ");
+            println!("error: {}", error);
+        }
+    }
+}
+
+impl<'a> Shell<'a> {
+    /// `line` with a `!N` or `!!` replaced by the command it repeats.
+    fn expand(&self, line: &str) -> Result<String> {
+        if !line.starts_with('!') {
+            return Ok(line.to_string());
+        }
+        let command = match &line[1..] {
+            "!" => self.history.last(),
+            n => {
+                let n = n.parse::<usize>()
+                    .chain_err(|| format!("`{}` names no command of the history", line))?;
+                self.history.get(n.wrapping_sub(1))
+            }
+        };
+        match command {
+            Some(command) => {
+                println!("{}", command);
+                Ok(command.clone())
+            }
+            None => bail!("`{}` names no command of the history", line),
+        }
+    }
+
+    /// Add `line` to the history, dropping the oldest commands once there
+    /// are more than `HISTORY_KEPT`.
+    fn remember(&mut self, line: &str) -> Result<()> {
+        self.history.push(line.to_string());
+        if self.history.len() <= HISTORY_KEPT {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.history_file)
+                .chain_err(|| format!("failed to open {}", self.history_file.display()))?;
+            writeln!(file, "{}", line)?;
+            return Ok(());
+        }
+        let excess = self.history.len() - HISTORY_KEPT;
+        self.history.drain(..excess);
+        let mut file = File::create(&self.history_file)
+            .chain_err(|| format!("failed to create {}", self.history_file.display()))?;
+        for command in &self.history {
+            writeln!(file, "{}", command)?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, line: &str) -> Result<()> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let args = words.collect::<Vec<_>>();
+        match (command, args.len()) {
+            ("help", _) => println!("{}", HELP),
+            ("list", 0) | ("list", 1) => self.list(args.get(0).cloned()),
+            ("bench", n) if n > 0 => self.select(&args)?,
+            ("scenarios", 0) => {
+                for patch in self.benchmark()?.source_patches()? {
+                    println!("{}", if patch.is_empty() { "(full build only)" } else { &*patch });
+                }
+            }
+            ("scenario", 1) if args[0] == "all" => self.scenario = None,
+            ("scenario", 1) => {
+                if !self.benchmark()?.source_patches()?.iter().any(|p| *p == args[0]) {
+                    bail!("no scenario {}; `scenarios` lists them", args[0]);
+                }
+                self.scenario = Some(args[0].to_string());
+            }
+            ("profile", 1) => self.config.profiles = vec![args[0].parse::<Profile>()?],
+            ("backend", 1) => self.config.backends = vec![args[0].parse::<Backend>()?],
+            ("std", 1) => self.config.std = vec![args[0].parse::<Std>()?],
+            ("toolchain", 1) => self.toolchain(args[0])?,
+            ("stats", n) if n > 0 => {
+                self.stats = args.iter().map(|stat| stat.to_string()).collect();
+            }
+            ("show", 0) => self.show(),
+            ("run", 0) => self.measure()?,
+            ("history", 0) => {
+                let skip = self.history.len().saturating_sub(HISTORY_SHOWN);
+                for (idx, command) in self.history.iter().enumerate().skip(skip) {
+                    println!("{:>5}  {}", idx + 1, command);
+                }
+            }
+            _ => bail!("unknown command `{}`; `help` lists the commands", line),
+        }
+        Ok(())
+    }
+
+    fn list(&self, filter: Option<&str>) {
+        for benchmark in self.benchmarks {
+            if filter.map_or(true, |filter| benchmark.name.contains(filter)) {
+                let category = format!("{:?}", benchmark.config.category).to_lowercase();
+                println!("{:<50}{}", benchmark.name, category);
+            }
+        }
+    }
+
+    fn select(&mut self, names: &[&str]) -> Result<()> {
+        let mut selected = Vec::new();
+        for name in names {
+            let exact = self.benchmarks.iter().filter(|b| b.name == *name).collect::<Vec<_>>();
+            let matching = if exact.is_empty() {
+                self.benchmarks.iter().filter(|b| b.name.contains(name)).collect()
+            } else {
+                exact
+            };
+            if matching.is_empty() {
+                bail!("no benchmark matches `{}`", name);
+            }
+            selected.extend(matching.into_iter().cloned());
+        }
+        self.selected = selected;
+        self.scenario = None;
+        let names = self.selected.iter().map(|b| &*b.name).collect::<Vec<_>>();
+        println!("selected {}", names.join(", "));
+        Ok(())
+    }
+
+    /// The one benchmark selected, whose scenarios can be picked.
+    fn benchmark(&self) -> Result<&Benchmark> {
+        match self.selected.len() {
+            1 => Ok(&self.selected[0]),
+            0 => bail!("no benchmark selected; use `bench NAME`"),
+            _ => bail!("scenarios can only be picked with a single benchmark selected"),
+        }
+    }
+
+    /// Measure the master commit with the hash `name` starts with, or else
+    /// the local rustc at `name`.
+    fn toolchain(&mut self, name: &str) -> Result<()> {
+        let commit = if name.len() >= 7 && name.chars().all(|c| c.is_digit(16)) {
+            self.commits.iter().find(|c| c.sha.starts_with(name))
+        } else {
+            None
+        };
+        self.toolchain = Some(match commit {
+            Some(commit) => Toolchain::Commit(commit.clone()),
+            None => Toolchain::Local(toolchain::find_local_rustc(
+                Path::new(name),
+                &self.config.target,
+            )?),
+        });
+        Ok(())
+    }
+
+    fn show(&self) {
+        let names = self.selected.iter().map(|b| &*b.name).collect::<Vec<_>>();
+        println!("benchmarks: {}", names.join(", "));
+        println!("scenario:   {}", self.scenario.as_ref().map_or("all", |s| &**s));
+        println!("profile:    {}", self.config.profiles[0]);
+        println!("backend:    {}", self.config.backends[0]);
+        println!("std:        {}", self.config.std[0]);
+        let toolchain = match self.toolchain {
+            Some(Toolchain::Local(ref rustc)) => rustc.clone(),
+            Some(Toolchain::Commit(ref commit)) => commit.sha.clone(),
+            None => String::from("(none)"),
+        };
+        println!("toolchain:  {}", toolchain);
+        println!("stats:      {}", self.stats.join(" "));
+    }
+
+    fn measure(&mut self) -> Result<()> {
+        if self.selected.is_empty() {
+            bail!("no benchmark selected; use `bench NAME`");
+        }
+        let mut benchmarks = self.selected.clone();
+        if let Some(ref scenario) = self.scenario {
+            let benchmark = &mut benchmarks[0];
+            let patches = benchmark.source_patches()?;
+            benchmark.scenarios = patches.iter().position(|p| p == scenario).map(|idx| idx + 1);
+        }
+        let (commit, sysroot) = match self.toolchain {
+            Some(Toolchain::Local(ref rustc)) => {
+                let commit = GitCommit {
+                    sha: String::from("shell"),
+                    date: Utc::now(),
+                    summary: String::new(),
+                };
+                let sysroot =
+                    ::local_sysroot(&commit, rustc, &self.config, self.preserve_sysroot)?;
+                (commit, sysroot)
+            }
+            Some(Toolchain::Commit(ref commit)) => {
+                let sysroot = toolchain::install(commit, &self.config, self.preserve_sysroot)?;
+                (commit.clone(), sysroot)
+            }
+            None => bail!("no toolchain; use `toolchain RUSTC` or `toolchain SHA`"),
+        };
+        let data = match ::bench_commit(
+            &commit,
+            None,
+            sysroot,
+            &benchmarks,
+            &self.config,
+            1,
+            None,
+            &::never,
+        ) {
+            Some(data) => data,
+            None => bail!("the run was abandoned"),
+        };
+
+        for (name, result) in data.benchmarks {
+            let patches = match result {
+                Ok(patches) => patches,
+                Err(output) => {
+                    let last = output.lines().rev().find(|l| !l.trim().is_empty());
+                    println!("{}: failed: {}", name, last.unwrap_or("").trim());
+                    continue;
+                }
+            };
+            for patch in patches {
+                println!("{}", patch.name);
+                let stats = patch.run().stats.clone();
+                let key = build_key(&patch.name, &self.config);
+                for name in &self.stats {
+                    let stat = match stats.iter().find(|stat| stat.name == *name) {
+                        Some(stat) => stat,
+                        None => continue,
+                    };
+                    let before = self.previous
+                        .get(&key)
+                        .and_then(|stats| stats.iter().find(|stat| stat.name == *name));
+                    match before {
+                        Some(before) if before.cnt != 0.0 => println!(
+                            "  {:<24}{:>18}  {:+.2}%",
+                            name,
+                            format_count(stat.cnt),
+                            100.0 * (stat.cnt - before.cnt) / before.cnt
+                        ),
+                        _ => println!("  {:<24}{:>18}", name, format_count(stat.cnt)),
+                    }
+                }
+                self.previous.insert(key, stats);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Identifies the build of `patch` with the settings of `config`, so that a
+/// run is only compared with one of the same build.
+fn build_key(patch: &str, config: &Config) -> String {
+    format!("{} {} {} {}", patch, config.profiles[0], config.backends[0], config.std[0])
+}
+
+/// Whole numbers, e.g. instruction counts, without decimals.
+fn format_count(cnt: f64) -> String {
+    if cnt.fract() == 0.0 {
+        format!("{:.0}", cnt)
+    } else {
+        format!("{:.3}", cnt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+
+    use tempdir::TempDir;
+
+    fn new_shell(history_file: &Path, history: &[&str]) -> Shell<'static> {
+        Shell {
+            benchmarks: &[],
+            commits: &[],
+            config: Config::default(),
+            preserve_sysroot: false,
+            selected: Vec::new(),
+            scenario: None,
+            toolchain: None,
+            stats: Vec::new(),
+            previous: HashMap::new(),
+            history: history.iter().map(|command| command.to_string()).collect(),
+            history_file: history_file.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn expand_history() {
+        let shell = new_shell(Path::new("history"), &["bench helloworld", "run"]);
+        assert_eq!(shell.expand("show").unwrap(), "show");
+        assert_eq!(shell.expand("!1").unwrap(), "bench helloworld");
+        assert_eq!(shell.expand("!2").unwrap(), "run");
+        assert_eq!(shell.expand("!!").unwrap(), "run");
+        assert!(shell.expand("!0").is_err());
+        assert!(shell.expand("!3").is_err());
+        assert!(shell.expand("!").is_err());
+        assert!(shell.expand("!run").is_err());
+        assert!(shell.expand("!-1").is_err());
+
+        let empty = new_shell(Path::new("history"), &[]);
+        assert!(empty.expand("!!").is_err());
+        assert!(empty.expand("!1").is_err());
+    }
+
+    #[test]
+    fn execute_matches_arguments() {
+        let mut shell = new_shell(Path::new("history"), &[]);
+        shell.execute("profile clippy").unwrap();
+        assert_eq!(shell.config.profiles, vec![Profile::Clippy]);
+        shell.execute("std build-std").unwrap();
+        assert_eq!(shell.config.std, vec![Std::BuildStd]);
+        shell.execute("stats instructions:u  wall-time").unwrap();
+        assert_eq!(shell.stats, vec!["instructions:u", "wall-time"]);
+        shell.scenario = Some(String::from("@010-change"));
+        shell.execute("scenario all").unwrap();
+        assert_eq!(shell.scenario, None);
+        shell.execute("list").unwrap();
+        shell.execute("list hello").unwrap();
+
+        // Too few or too many arguments
+        assert!(shell.execute("profile").is_err());
+        assert!(shell.execute("profile debug clippy").is_err());
+        assert!(shell.execute("stats").is_err());
+        assert!(shell.execute("bench").is_err());
+        assert!(shell.execute("list hello world").is_err());
+        assert!(shell.execute("run now").is_err());
+        assert!(shell.execute("frobnicate").is_err());
+        assert!(shell.execute("").is_err());
+        // Invalid arguments
+        assert!(shell.execute("profile fast").is_err());
+        assert!(shell.execute("bench helloworld").is_err());
+        // Nothing selected to pick scenarios of or run
+        assert!(shell.execute("scenarios").is_err());
+        assert!(shell.execute("scenario @010-change").is_err());
+        assert!(shell.execute("run").is_err());
+        assert_eq!(shell.config.profiles, vec![Profile::Clippy]);
+        assert_eq!(shell.stats, vec!["instructions:u", "wall-time"]);
+    }
+
+    #[test]
+    fn remember_keeps_the_newest_commands() {
+        let dir = TempDir::new("rustc-perf-shell").unwrap();
+        let path = dir.path().join("history");
+        let mut shell = new_shell(&path, &[]);
+        for idx in 0..HISTORY_KEPT + 5 {
+            shell.remember(&format!("bench {}", idx)).unwrap();
+        }
+        assert_eq!(shell.history.len(), HISTORY_KEPT);
+        assert_eq!(shell.history[0], "bench 5");
+
+        let mut file = String::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_string(&mut file)
+            .unwrap();
+        let lines = file.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), HISTORY_KEPT);
+        assert_eq!(lines[0], "bench 5");
+        assert_eq!(lines[HISTORY_KEPT - 1], format!("bench {}", HISTORY_KEPT + 4));
+    }
+}
@@ -0,0 +1,85 @@
+//! Running the builds of benchmarks with a time limit.
+//!
+//! A build that runs longer than the limit, e.g. because rustc hangs, is
+//! killed together with everything it started, and fails like any other build
+//! of the benchmark: the error, with the output of the build up to then, is
+//! recorded in the benchmark's results and the collection moves on to the
+//! next benchmark.
+
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Output, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use libc;
+
+use errors::*;
+
+/// How often to check whether the build has finished, in milliseconds.
+const POLL_INTERVAL: u64 = 100;
+
+/// Run `command` to completion and collect its output like
+/// `Command::output`, but kill it and all of its descendants if it takes
+/// longer than `timeout`.
+pub fn output(command: &mut Command, timeout: Duration) -> Result<Output> {
+    // In a process group of its own, make, Cargo and every rustc they start
+    // can be killed at once.
+    command.before_exec(|| {
+        unsafe {
+            libc::setpgid(0, 0);
+        }
+        Ok(())
+    });
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = command
+        .spawn()
+        .chain_err(|| format!("failed to run `{:?}`", command))?;
+    // Read both pipes while waiting, so that a build that fills them doesn't
+    // block, and what it printed before being killed isn't lost.
+    let stdout = read_in_background(child.stdout.take().unwrap());
+    let stderr = read_in_background(child.stderr.take().unwrap());
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if start.elapsed() >= timeout {
+            warn!("`{:?}` timed out, killing it", command);
+            unsafe {
+                libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+            }
+            child.wait()?;
+            break None;
+        }
+        thread::sleep(Duration::from_millis(POLL_INTERVAL));
+    };
+    let stdout = stdout.join().unwrap();
+    let stderr = stderr.join().unwrap();
+
+    match status {
+        Some(status) => Ok(Output {
+            status: status,
+            stdout: stdout,
+            stderr: stderr,
+        }),
+        None => bail!(
+            "timed out after {} seconds\n\nstderr={}\n\n stdout={}",
+            timeout.as_secs(),
+            String::from_utf8_lossy(&stderr),
+            String::from_utf8_lossy(&stdout)
+        ),
+    }
+}
+
+fn read_in_background<R: Read + Send + 'static>(mut pipe: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        buf
+    })
+}
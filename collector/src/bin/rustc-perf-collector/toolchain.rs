@@ -0,0 +1,340 @@
+//! Assembling the toolchain of a CI commit from its dist tarballs.
+//!
+//! CI uploads every component of a merged commit as a tarball of the form
+//! `<component>-nightly-<triple>.tar.xz`. Each tarball lists its components
+//! in a `components` file, and each component lists its files in
+//! `manifest.in`; installing a component means copying those files into the
+//! sysroot. This works for any host CI builds, not only those rustup serves.
+//! Each tarball is checked against the SHA-256 checksum CI publishes next to
+//! it, in `<tarball>.sha256`, before it is unpacked.
+//!
+//! Toolchains are assembled in `toolchains/<sha>-<triple>` and only used
+//! once they have been verified: every file of every component has to be
+//! there, and `rustc -vV` has to name the commit.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use reqwest;
+use rust_sysroot::git::Commit as GitCommit;
+use rust_sysroot::sysroot::Sysroot;
+use tempdir::TempDir;
+
+use collector::{Profile, Std};
+
+use config::Config;
+use errors::*;
+
+/// Components every toolchain has.
+const REQUIRED_COMPONENTS: &[&str] = &["rustc", "rust-std", "cargo"];
+
+/// Components that can be added with `"components"` in the config.
+pub const EXTRA_COMPONENTS: &[&str] = &["llvm-tools", "clippy", "rust-src"];
+
+/// Directory in a toolchain with an empty file named after each component
+/// that has been installed and verified.
+const VERIFIED: &str = ".verified";
+
+fn toolchains_dir() -> PathBuf {
+    PathBuf::from("toolchains")
+}
+
+/// The components to install for `config`: the required ones, those
+/// configured, and those the configured profiles and std builds need.
+fn components(config: &Config) -> BTreeSet<&str> {
+    let mut components = REQUIRED_COMPONENTS.iter().cloned().collect::<BTreeSet<_>>();
+    components.extend(config.components.iter().map(|c| &**c));
+    if config.profiles.contains(&Profile::Clippy) {
+        components.insert("clippy");
+    }
+    if config.std.contains(&Std::BuildStd) {
+        components.insert("rust-src");
+    }
+    components
+}
+
+/// Assemble and verify the toolchain of `commit` for the configured target,
+/// unless that has been done already. Unless `preserve` is set, the
+/// toolchains of other commits are removed first.
+pub fn install(commit: &GitCommit, config: &Config, preserve: bool) -> Result<Sysroot> {
+    let triple = &config.target;
+    let dir = toolchains_dir().join(format!("{}-{}", commit.sha, triple));
+    if !preserve {
+        remove_others(&dir)?;
+    }
+
+    let components = components(config);
+    let missing = components
+        .iter()
+        .filter(|component| !dir.join(VERIFIED).join(component).exists())
+        .cloned()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        fs::create_dir_all(dir.join(VERIFIED))?;
+        for component in missing {
+            install_component(&commit.sha, component, triple, &dir)?;
+            File::create(dir.join(VERIFIED).join(component))?;
+        }
+    }
+    verify_version(&dir, &commit.sha)?;
+
+    let rustc = dir.join("bin/rustc");
+    // The directory is ours to remove, not the sysroot's.
+    let mut sysroot = Sysroot::with_local_rustc(
+        commit,
+        rustc.to_str().unwrap(),
+        triple,
+        true,
+        false,
+    )?;
+    sysroot.cargo = dir.join("bin/cargo").canonicalize()?;
+    Ok(sysroot)
+}
+
+/// Remove all toolchains but the one in `keep`.
+fn remove_others(keep: &Path) -> Result<()> {
+    let dir = toolchains_dir();
+    if !dir.exists() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path != keep {
+            info!("removing toolchain {}", path.display());
+            fs::remove_dir_all(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Download the tarball of `component` built by CI for `sha`, check it
+/// against its published checksum, install its files into `dir` and check
+/// that all of them are there.
+fn install_component(sha: &str, component: &str, triple: &str, dir: &Path) -> Result<()> {
+    // The sources are the same for every target.
+    let name = if component == "rust-src" {
+        String::from("rust-src-nightly")
+    } else {
+        format!("{}-nightly-{}", component, triple)
+    };
+    let url = format!(
+        "https://s3.amazonaws.com/rust-lang-ci/rustc-builds/{}/{}.tar.xz",
+        sha,
+        name
+    );
+    info!("downloading {}", url);
+    let mut response = reqwest::get(&url)?;
+    if !response.status().is_success() {
+        bail!("no {} build for {}: {} returned {}", component, sha, url, response.status());
+    }
+    let tmp_dir = TempDir::new(component)?;
+    let tarball = tmp_dir.path().join("component.tar.xz");
+    io::copy(&mut response, &mut File::create(&tarball)?)?;
+
+    let checksum_url = format!("{}.sha256", url);
+    let mut response = reqwest::get(&checksum_url)?;
+    if !response.status().is_success() {
+        bail!("no checksum of {}: {} returned {}", url, checksum_url, response.status());
+    }
+    let mut published = String::new();
+    response.read_to_string(&mut published)?;
+    let expected = parse_checksum(&published)
+        .chain_err(|| format!("invalid checksum file {}", checksum_url))?;
+    let actual = sha256(&tarball)?;
+    if actual != expected {
+        bail!("{} has checksum {}, but {} says {}", url, actual, checksum_url, expected);
+    }
+
+    let status = Command::new("tar")
+        .arg("-xJf")
+        .arg(&tarball)
+        .arg("-C")
+        .arg(tmp_dir.path())
+        .status()
+        .chain_err(|| "could not spawn tar")?;
+    if !status.success() {
+        bail!("failed to extract {}", url);
+    }
+
+    // E.g. `rust-std-nightly-<triple>/` lists `rust-std-<triple>`, the
+    // directory with the component's files.
+    let package = tmp_dir.path().join(&name);
+    for installed in lines(&package.join("components"))? {
+        let source = package.join(&installed);
+        let files = manifest(&source.join("manifest.in"))?;
+        if files.is_empty() {
+            bail!("{} of {} has no files", installed, url);
+        }
+        for file in &files {
+            let from = source.join(file);
+            let to = dir.join(file);
+            fs::create_dir_all(to.parent().unwrap())?;
+            let status = Command::new("cp")
+                .arg("-a")
+                .arg("-T")
+                .arg(&from)
+                .arg(&to)
+                .status()
+                .chain_err(|| "could not spawn cp")?;
+            if !status.success() {
+                bail!("failed to install {} from {}", file, url);
+            }
+        }
+        if let Some(file) = files.iter().find(|file| !dir.join(file).exists()) {
+            bail!("{} of {} is missing {} after installing it", installed, url, file);
+        }
+    }
+    Ok(())
+}
+
+/// The checksum in a `.sha256` file, `<hex digest>  <file name>` as written
+/// by `sha256sum`, in lower case.
+fn parse_checksum(contents: &str) -> Result<String> {
+    let digest = contents.split_whitespace().next().unwrap_or("");
+    if digest.len() != 64 || !digest.chars().all(|c| c.is_digit(16)) {
+        bail!("expected a SHA-256 digest, not `{}`", contents.trim());
+    }
+    Ok(digest.to_lowercase())
+}
+
+/// The SHA-256 digest of the file at `path`, in lower case.
+fn sha256(path: &Path) -> Result<String> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .chain_err(|| "could not spawn sha256sum")?;
+    if !output.status.success() {
+        bail!("failed to hash {}", path.display());
+    }
+    parse_checksum(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The files and directories `manifest.in` lists, as `file:<path>` and
+/// `dir:<path>`, relative to the component.
+fn manifest(path: &Path) -> Result<Vec<String>> {
+    lines(path)?
+        .into_iter()
+        .map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some("file"), Some(file)) | (Some("dir"), Some(file)) => Ok(file.to_string()),
+                _ => bail!("unexpected line `{}` in {}", line, path.display()),
+            }
+        })
+        .collect()
+}
+
+fn lines(path: &Path) -> Result<Vec<String>> {
+    let file = File::open(path).chain_err(|| format!("failed to open {}", path.display()))?;
+    let mut lines = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            lines.push(line.trim().to_string());
+        }
+    }
+    Ok(lines)
+}
+
+/// The rustc of a locally built toolchain at `path`: the rustc itself, a
+/// sysroot such as `build/<host>/stage2`, the `build/<host>` directory or the
+/// `build` directory of a rust checkout, or the checkout. The newest stage
+/// wins. The path is made absolute, so it stays valid for later runs.
+pub fn find_local_rustc(path: &Path, target: &str) -> Result<String> {
+    let mut candidates = vec![path.to_path_buf(), path.join("bin/rustc")];
+    for build in &[path.to_path_buf(), path.join(target), path.join("build").join(target)] {
+        for stage in &["stage2", "stage1"] {
+            candidates.push(build.join(stage).join("bin/rustc"));
+        }
+    }
+    let rustc = match candidates.into_iter().find(|candidate| candidate.is_file()) {
+        Some(rustc) => rustc,
+        None => bail!("found no rustc in {}", path.display()),
+    };
+    let rustc = rustc
+        .canonicalize()
+        .chain_err(|| format!("failed to resolve {}", rustc.display()))?;
+    info!("using {}", rustc.display());
+    match rustc.to_str() {
+        Some(rustc) => Ok(rustc.to_string()),
+        None => bail!("non-UTF-8 path {}", rustc.display()),
+    }
+}
+
+/// Check that the `rustc` in `dir` runs and was built from `sha`.
+fn verify_version(dir: &Path, sha: &str) -> Result<()> {
+    let rustc = dir.join("bin/rustc");
+    let output = Command::new(&rustc)
+        .arg("-vV")
+        .output()
+        .chain_err(|| format!("could not run {}", rustc.display()))?;
+    if !output.status.success() {
+        bail!("`{} -vV` failed", rustc.display());
+    }
+    let version = String::from_utf8_lossy(&output.stdout);
+    let hash = version
+        .lines()
+        .find(|line| line.starts_with("commit-hash: "))
+        .map(|line| line["commit-hash: ".len()..].trim());
+    if hash != Some(sha) {
+        bail!("{} was built from {:?}, not {}", rustc.display(), hash, sha);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    /// `contents` written to a file in `dir`.
+    fn write(dir: &TempDir, contents: &str) -> PathBuf {
+        let path = dir.path().join("manifest.in");
+        File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn manifest_lists_files_and_dirs() {
+        let dir = TempDir::new("rustc-perf-manifest").unwrap();
+        let path = write(
+            &dir,
+            "file:bin/rustc\n\n  file:lib/librustc_driver-1a2b.so  \ndir:share/doc/rust\n",
+        );
+        assert_eq!(
+            manifest(&path).unwrap(),
+            ["bin/rustc", "lib/librustc_driver-1a2b.so", "share/doc/rust"]
+        );
+        // Only the first colon separates the kind from the path.
+        let path = write(&dir, "file:lib/rustlib/etc/a:b.py\n");
+        assert_eq!(manifest(&path).unwrap(), ["lib/rustlib/etc/a:b.py"]);
+        assert!(manifest(&write(&dir, "")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn manifest_rejects_unknown_lines() {
+        let dir = TempDir::new("rustc-perf-manifest").unwrap();
+        assert!(manifest(&write(&dir, "file:bin/rustc\nlink:bin/cargo\n")).is_err());
+        assert!(manifest(&write(&dir, "bin/rustc\n")).is_err());
+        assert!(manifest(&dir.path().join("missing.in")).is_err());
+    }
+
+    #[test]
+    fn checksums() {
+        let digest = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+        let published = format!("{}  rustc-nightly-x86_64-unknown-linux-gnu.tar.xz\n", digest);
+        assert_eq!(parse_checksum(&published).unwrap(), digest.to_lowercase());
+        assert!(parse_checksum("").is_err());
+        assert!(parse_checksum("<Error><Code>NoSuchKey</Code></Error>").is_err());
+        assert!(parse_checksum(&digest[1..]).is_err());
+
+        let dir = TempDir::new("rustc-perf-checksum").unwrap();
+        let path = dir.path().join("test");
+        File::create(&path).unwrap().write_all(b"test").unwrap();
+        assert_eq!(sha256(&path).unwrap(), digest.to_lowercase());
+    }
+}
@@ -0,0 +1,219 @@
+//! Checking the benchmarks of the suite, for maintaining it: that each has a
+//! valid `perf-config.json`, a `Cargo.lock` pinning its dependencies and a
+//! license, and that it builds with the stable toolchain without Cargo
+//! reaching the network once its dependencies are fetched.
+//!
+//! Unlike loading the benchmarks for a run, which stops at the first invalid
+//! one, all benchmarks are checked and the outcome of every check reported.
+//! A missing license is only a warning, as the suite's own small benchmarks
+//! have none.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+use serde_json;
+
+use config::Config;
+use errors::*;
+use execute::{Benchmark, BenchmarkConfig};
+use rust_sysroot::sysroot::Sysroot;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    Warning,
+    Failed,
+    /// Not checked, because an earlier check failed or builds weren't asked
+    /// for
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    check: &'static str,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl Check {
+    fn new(check: &'static str, status: Status, detail: Option<String>) -> Check {
+        Check {
+            check: check,
+            status: status,
+            detail: detail,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    /// Benchmark -> its checks
+    benchmarks: BTreeMap<String, Vec<Check>>,
+    /// The benchmarks with a failed check
+    failed: Vec<String>,
+}
+
+/// Check the benchmarks in `benchmark_dir` whose name contains `filter`,
+/// building them with `sysroot` unless it's `None`, print the outcome and
+/// write it to `report` as JSON if given. Returns whether all passed.
+pub fn run(
+    benchmark_dir: &Path,
+    filter: Option<&str>,
+    sysroot: Option<&Sysroot>,
+    config: &Config,
+    report: Option<&Path>,
+) -> Result<bool> {
+    let mut checked = Report {
+        benchmarks: BTreeMap::new(),
+        failed: Vec::new(),
+    };
+    let mut entries = fs::read_dir(benchmark_dir)
+        .chain_err(|| "failed to list benchmarks")?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(name) => bail!("non-utf8 benchmark name: {:?}", name),
+        };
+        if path.ends_with(".git") || path.ends_with("scripts") || !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if filter.map_or(false, |filter| !name.contains(filter)) {
+            continue;
+        }
+        info!("validating {}", name);
+        let checks = validate(&name, &path, sysroot, config);
+        if checks.iter().any(|check| check.status == Status::Failed) {
+            checked.failed.push(name.clone());
+        }
+        checked.benchmarks.insert(name, checks);
+    }
+
+    for (name, checks) in &checked.benchmarks {
+        println!("{}", name);
+        for check in checks {
+            let status = format!("{:?}", check.status).to_lowercase();
+            match check.detail {
+                Some(ref detail) => println!("  {:<14}{}: {}", check.check, status, detail),
+                None => println!("  {:<14}{}", check.check, status),
+            }
+        }
+    }
+    println!(
+        "{} benchmarks, {} failed",
+        checked.benchmarks.len(),
+        checked.failed.len()
+    );
+    if let Some(path) = report {
+        let file =
+            File::create(path).chain_err(|| format!("failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, &checked)?;
+    }
+    Ok(checked.failed.is_empty())
+}
+
+/// The rustc of the stable toolchain installed with rustup.
+pub fn stable_rustc() -> Result<String> {
+    let output = Command::new("rustup")
+        .args(&["which", "--toolchain", "stable", "rustc"])
+        .output()
+        .chain_err(|| "could not run rustup to find the stable toolchain")?;
+    if !output.status.success() {
+        bail!(
+            "no stable toolchain installed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn validate(name: &str, path: &Path, sysroot: Option<&Sysroot>, config: &Config) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let benchmark_config = match BenchmarkConfig::load(path) {
+        Ok(benchmark_config) => {
+            checks.push(Check::new("perf-config", Status::Ok, None));
+            Some(benchmark_config)
+        }
+        Err(e) => {
+            let error = e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ");
+            checks.push(Check::new("perf-config", Status::Failed, Some(error)));
+            None
+        }
+    };
+
+    let locked = path.join("Cargo.lock").is_file();
+    checks.push(if locked {
+        Check::new("lockfile", Status::Ok, None)
+    } else {
+        Check::new(
+            "lockfile",
+            Status::Failed,
+            Some(String::from("no Cargo.lock")),
+        )
+    });
+
+    checks.push(if has_license(path) {
+        Check::new("license", Status::Ok, None)
+    } else {
+        Check::new(
+            "license",
+            Status::Warning,
+            Some(String::from("no license file or `license` in Cargo.toml")),
+        )
+    });
+
+    let build = match (benchmark_config, sysroot) {
+        (_, None) => Check::new("offline-build", Status::Skipped, None),
+        (None, _) => Check::new(
+            "offline-build",
+            Status::Skipped,
+            Some(String::from("invalid perf-config.json")),
+        ),
+        _ if !locked => Check::new(
+            "offline-build",
+            Status::Skipped,
+            Some(String::from("no Cargo.lock")),
+        ),
+        (Some(benchmark_config), Some(sysroot)) => {
+            let benchmark = Benchmark {
+                name: name.to_string(),
+                path: path.to_path_buf(),
+                config: benchmark_config,
+                scenarios: None,
+            };
+            match benchmark.build_offline(sysroot, config) {
+                Ok(()) => Check::new("offline-build", Status::Ok, None),
+                Err(e) => Check::new("offline-build", Status::Failed, Some(e.to_string())),
+            }
+        }
+    };
+    checks.push(build);
+    checks
+}
+
+/// Whether the benchmark in `path` has a license file, or names its license
+/// in its `Cargo.toml`.
+fn has_license(path: &Path) -> bool {
+    let license_file = fs::read_dir(path).ok().map_or(false, |entries| {
+        entries.filter_map(|entry| entry.ok()).any(|entry| {
+            let name = entry.file_name().to_string_lossy().to_uppercase();
+            ["LICENSE", "LICENCE", "COPYING", "COPYRIGHT"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+    });
+    let mut manifest = String::new();
+    let _ = File::open(path.join("Cargo.toml")).and_then(|mut f| f.read_to_string(&mut manifest));
+    license_file || manifest.lines().any(|line| {
+        let line = line.trim_left();
+        line.starts_with("license ") || line.starts_with("license=")
+            || line.starts_with("license-file")
+    })
+}
@@ -4,21 +4,239 @@ extern crate chrono;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[cfg(test)]
+extern crate serde_json;
 
+use std::borrow::Borrow;
 use std::cmp::{Ord, Ordering, PartialOrd};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
-use std::ops::{Add, Sub};
+use std::mem;
+use std::ops::{Add, Deref, Sub};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
 use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Stat {
-    pub name: String,
+    pub name: Name,
     pub cnt: f64,
+    /// The value measured in each iteration. `cnt` is their minimum or, for
+    /// statistics too noisy to take the minimum of, the median of the samples
+    /// that aren't outliers. Empty for statistics of extra, unmeasured builds,
+    /// and for data gathered before every iteration was recorded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub samples: Vec<f64>,
+}
+
+impl Stat {
+    /// The median of the iterations' values, or `cnt` if they weren't
+    /// recorded.
+    pub fn median(&self) -> f64 {
+        if self.samples.is_empty() {
+            return self.cnt;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mid = sorted.len() / 2;
+        if sorted.len() % 2 == 0 {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        }
+    }
+}
+
+/// The id an `Interner` gives a name.
+pub type NameId = u32;
+
+/// The name of a patch, e.g. `regex@010-change`, or of a statistic. Every
+/// commit's results repeat the same few names in each of their patches and
+/// runs, so the names of loaded results are interned: equal names from the
+/// same `Interner` share one allocation and its id. Names that are built or
+/// deserialized directly have no id until they are interned.
+#[derive(Clone)]
+pub struct Name {
+    name: Arc<String>,
+    id: Option<NameId>,
+}
+
+impl Name {
+    /// The id of the name in the `Interner` it came from, if any.
+    pub fn id(&self) -> Option<NameId> {
+        self.id
+    }
+}
+
+impl Deref for Name {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Borrow<str> for Name {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        Arc::ptr_eq(&self.name, &other.name) || self.name == other.name
+    }
+}
+
+impl Eq for Name {}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Name) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Name {
+    fn cmp(&self, other: &Name) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl PartialEq<str> for Name {
+    fn eq(&self, other: &str) -> bool {
+        **self == *other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for Name {
+    fn eq(&self, other: &&'a str) -> bool {
+        **self == **other
+    }
+}
+
+impl PartialEq<String> for Name {
+    fn eq(&self, other: &String) -> bool {
+        **self == **other
+    }
+}
+
+impl PartialEq<Name> for String {
+    fn eq(&self, other: &Name) -> bool {
+        **self == **other
+    }
+}
+
+impl From<String> for Name {
+    fn from(name: String) -> Name {
+        Name {
+            name: Arc::new(name),
+            id: None,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Name {
+    fn from(name: &'a str) -> Name {
+        Name::from(name.to_string())
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl Serialize for Name {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        serializer.serialize_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Name {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Name, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(Name::from)
+    }
+}
+
+/// One table of names shared by every thread that interns into it. Names are
+/// given ids in the order they are first interned, so an id only identifies
+/// a name within its interner.
+pub struct Interner {
+    table: Mutex<NameTable>,
+}
+
+#[derive(Default)]
+struct NameTable {
+    names: HashMap<String, Name>,
+    next_id: NameId,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner {
+            table: Mutex::new(NameTable::default()),
+        }
+    }
+
+    pub fn intern(&self, name: &str) -> Name {
+        let mut table = self.table.lock().unwrap();
+        if let Some(interned) = table.names.get(name) {
+            return interned.clone();
+        }
+        let interned = Name {
+            name: Arc::new(name.to_string()),
+            id: Some(table.next_id),
+        };
+        table.next_id += 1;
+        table.names.insert(name.to_string(), interned.clone());
+        interned
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.lock().unwrap().names.len()
+    }
+
+    /// Replace the patch and statistic names of `data` with interned ones.
+    pub fn intern_commit(&self, data: &mut CommitData) {
+        let patches = data.benchmarks
+            .values_mut()
+            .filter_map(|patches| patches.as_mut().ok())
+            .flat_map(|patches| patches.iter_mut());
+        for patch in patches {
+            patch.name = self.intern(&patch.name);
+            for stat in patch.runs.iter_mut().flat_map(|run| run.stats.iter_mut()) {
+                stat.name = self.intern(&stat.name);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Interner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Interner({} names)", self.len())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -32,11 +250,268 @@ impl Run {
     }
 }
 
+/// The codegen backend a benchmark was compiled with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Llvm,
+    Cranelift,
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Backend::Llvm => "llvm",
+            Backend::Cranelift => "cranelift",
+        }
+    }
+
+    /// Flags passed to rustc to select this backend.
+    pub fn rustc_flags(&self) -> &'static str {
+        match *self {
+            Backend::Llvm => "",
+            Backend::Cranelift => "-Zcodegen-backend=cranelift",
+        }
+    }
+}
+
+impl Default for Backend {
+    fn default() -> Backend {
+        Backend::Llvm
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Backend, String> {
+        match s {
+            "llvm" => Ok(Backend::Llvm),
+            "cranelift" => Ok(Backend::Cranelift),
+            _ => Err(format!("unknown codegen backend `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Where a benchmark's standard library comes from: the toolchain's rlibs,
+/// linked statically as usual or dynamically with `-Cprefer-dynamic`, or a
+/// build from source along with the benchmark, with Cargo's `-Zbuild-std`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Std {
+    Static,
+    Dynamic,
+    #[serde(rename = "build-std")] BuildStd,
+}
+
+impl Std {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Std::Static => "static",
+            Std::Dynamic => "dynamic",
+            Std::BuildStd => "build-std",
+        }
+    }
+
+    /// Flags passed to rustc for the benchmarked crate.
+    pub fn rustc_flags(&self) -> &'static str {
+        match *self {
+            Std::Static | Std::BuildStd => "",
+            Std::Dynamic => "-Cprefer-dynamic",
+        }
+    }
+}
+
+impl Default for Std {
+    fn default() -> Std {
+        Std::Static
+    }
+}
+
+impl FromStr for Std {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Std, String> {
+        match s {
+            "static" => Ok(Std::Static),
+            "dynamic" => Ok(Std::Dynamic),
+            "build-std" => Ok(Std::BuildStd),
+            _ => Err(format!("unknown std `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Std {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What a benchmark was built with: a regular debug build, or `cargo clippy`
+/// to track the performance of clippy itself.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    Debug,
+    Clippy,
+}
+
+impl Profile {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Profile::Debug => "debug",
+            Profile::Clippy => "clippy",
+        }
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Profile {
+        Profile::Debug
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Profile, String> {
+        match s {
+            "debug" => Ok(Profile::Debug),
+            "clippy" => Ok(Profile::Clippy),
+            _ => Err(format!("unknown profile `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How much weight a benchmark's results carry, declared in its
+/// `perf-config.json`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    /// Real-world crates; what users actually compile
+    Primary,
+    /// Stress tests and microbenchmarks exercising specific compiler paths
+    Secondary,
+    /// Old crate versions kept unchanged for long-term comparisons
+    Stable,
+}
+
+impl Default for Category {
+    fn default() -> Category {
+        Category::Primary
+    }
+}
+
+impl FromStr for Category {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Category, String> {
+        match s {
+            "primary" => Ok(Category::Primary),
+            "secondary" => Ok(Category::Secondary),
+            "stable" => Ok(Category::Stable),
+            _ => Err(format!("unknown category `{}`", s)),
+        }
+    }
+}
+
+/// What a benchmark is, for the site's catalog of benchmarks.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BenchmarkInfo {
+    /// From the benchmark's `perf-config.json`
+    #[serde(default)] pub description: Option<String>,
+    /// Packages in the benchmark's `Cargo.lock`, its own included; `None`
+    /// without a lockfile
+    #[serde(default)] pub crates: Option<usize>,
+    /// Lines of Rust code in the benchmark's directory
+    pub lines: usize,
+    /// The incremental patches the benchmark is built with after its full
+    /// build; empty for non-incremental benchmarks
+    #[serde(default)] pub patches: Vec<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Patch {
     // Full name: benchmark@000-patch
-    pub name: String,
+    pub name: Name,
+    // Data gathered before backends were recorded is always LLVM.
+    #[serde(default)] pub backend: Backend,
+    // Name of the extra rustc flag set (see the collector config), if any.
+    #[serde(default)] pub flags: Option<String>,
+    #[serde(default)] pub profile: Profile,
+    // Name of the custom Cargo profile (see the collector config) it was
+    // built with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub custom_profile: Option<String>,
+    // Data gathered before the standard library was varied always linked it
+    // statically.
+    #[serde(default)] pub std: Std,
     pub runs: Vec<Run>,
+    // Per-query self-profile timings. Moved out into a `SelfProfile` before
+    // the results are stored, so they're only present in transit.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")] pub queries: Vec<QueryTiming>,
+    // ID of the collector that measured it, a key of `CommitData::collectors`.
+    // Not recorded for data gathered before collectors were identified.
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub collector: Option<String>,
+    // When each crate of an extra, unmeasured build of the patch was compiled,
+    // if the collector was configured to record it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")] pub timings: Vec<CrateTiming>,
+    // The slowest LLVM passes of an extra, unmeasured build of the patch's own
+    // crate, slowest first, if the collector was configured to record them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")] pub llvm_passes: Vec<LlvmPassTiming>,
+    // Measured builds of the patch that took far longer than they typically
+    // do on the collector.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")] pub anomalies: Vec<DurationAnomaly>,
+}
+
+/// A build that took far longer than it typically does on its collector,
+/// e.g. because rustc nearly hung.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DurationAnomaly {
+    /// Seconds the build took
+    #[serde(with = "round_float")] pub seconds: f64,
+    /// The median of the seconds its recent builds took
+    #[serde(with = "round_float")] pub typical: f64,
+}
+
+/// Aggregated `-Zself-profile` data of a single query.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct QueryTiming {
+    pub label: String,
+    /// Seconds spent in the query itself, excluding the queries it called
+    pub self_time: f64,
+    pub invocations: u64,
+    pub cache_hits: u64,
+}
+
+/// When rustc compiled one crate of a build, dependencies included.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CrateTiming {
+    /// The package's name, followed by the crate's if it differs, e.g.
+    /// `serde/build_script_build`
+    pub name: String,
+    /// Whether this is the benchmark's own crate rather than a dependency
+    pub leaf: bool,
+    /// Seconds since the start of the build
+    #[serde(with = "round_float")] pub start: f64,
+    #[serde(with = "round_float")] pub duration: f64,
+}
+
+/// Seconds spent in one LLVM pass according to `-Ztime-llvm-passes`, summed
+/// over the codegen units.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LlvmPassTiming {
+    /// e.g. `X86 DAG->DAG Instruction Selection`
+    pub name: String,
+    /// Wall time
+    #[serde(with = "round_float")] pub seconds: f64,
 }
 
 impl Patch {
@@ -80,9 +555,277 @@ pub struct CommitData {
     // String in Result is the output of the command that failed
     pub benchmarks: BTreeMap<String, Result<Vec<Patch>, String>>,
     pub triple: String,
+    // Not recorded for data gathered before suites were versioned.
+    #[serde(default)] pub manifest: Option<SuiteManifest>,
+    // The statistics the collector was configured to gather; not recorded
+    // for data gathered before collectors were configurable.
+    #[serde(default)] pub metrics: Option<BTreeSet<String>>,
+    // Benchmark name -> category; not recorded for data gathered before
+    // benchmarks were categorized.
+    #[serde(default)] pub categories: BTreeMap<String, Category>,
+    // Benchmark name -> what the benchmark is; not recorded for data
+    // gathered before the site had a catalog of benchmarks.
+    #[serde(default)] pub benchmark_info: BTreeMap<String, BenchmarkInfo>,
+    // Collector ID -> the machine it ran on, for the collectors that measured
+    // the patches.
+    #[serde(default)] pub collectors: BTreeMap<String, Machine>,
+    // Benchmark name -> why it failed, for the benchmarks that failed; not
+    // recorded for data gathered before failures were classified.
+    #[serde(default)] pub failures: BTreeMap<String, Failure>,
+}
+
+/// How much of the error output of a failed benchmark a `Failure` keeps, in
+/// bytes.
+pub const FAILURE_LOG_LEN: usize = 4096;
+
+/// What made a benchmark fail.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureKind {
+    /// rustc crashed
+    Ice,
+    /// The benchmark doesn't compile, e.g. because of a new error or lint
+    #[serde(rename = "compile-error")]
+    CompileError,
+    /// The build was killed for taking too long
+    Timeout,
+    /// Anything else, e.g. a dependency failing to download
+    Other,
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            FailureKind::Ice => "ICE",
+            FailureKind::CompileError => "compile error",
+            FailureKind::Timeout => "timeout",
+            FailureKind::Other => "other",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Why a benchmark failed, classified from its error output.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Failure {
+    pub kind: FailureKind,
+    /// The end of the error output, where the error usually is
+    pub log: String,
+}
+
+impl Failure {
+    /// Classify the error output `error` of a failed benchmark.
+    pub fn of(error: &str) -> Failure {
+        // The partial output of a build that was killed may show anything.
+        let kind = if error.contains("timed out after") || error.contains("anomalous duration") {
+            FailureKind::Timeout
+        } else if error.contains("internal compiler error")
+            || error.contains("the compiler unexpectedly panicked")
+        {
+            FailureKind::Ice
+        } else if error.contains("error[E") || error.contains("error: aborting due to")
+            || error.contains("error: could not compile")
+        {
+            FailureKind::CompileError
+        } else {
+            FailureKind::Other
+        };
+        let mut start = error.len().saturating_sub(FAILURE_LOG_LEN);
+        while !error.is_char_boundary(start) {
+            start += 1;
+        }
+        Failure {
+            kind: kind,
+            log: error[start..].to_string(),
+        }
+    }
+}
+
+/// The machine a collector runs on.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Machine {
+    /// Machines of the same class give comparable results, and results are
+    /// only compared within a class. The CPU model unless configured
+    /// otherwise.
+    pub class: String,
+    pub cpu: String,
+    /// Kernel release
+    pub kernel: String,
+    /// CPU vulnerability -> state of its mitigation, as listed in
+    /// `/sys/devices/system/cpu/vulnerabilities`
+    pub mitigations: BTreeMap<String, String>,
+    /// Microcode revision of the CPU, e.g. `0xf0`; this and the rest are
+    /// not recorded for data gathered before they were
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub microcode: Option<String>,
+    /// `kernel.randomize_va_space`; `0` if ASLR is off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aslr: Option<String>,
+    /// Frequency governor of the CPUs builds run on, e.g. `performance`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub governor: Option<String>,
+    /// Host triple of the benchmarked rustc
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Hash of the environment variables that may affect builds, after
+    /// removing compiler caches and job limits; not recorded for data
+    /// gathered before the environment was checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+}
+
+/// Describes the version of the benchmark suite a commit was benchmarked
+/// with, so that results of differing suites aren't silently compared.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SuiteManifest {
+    /// Benchmark name -> hash of its sources
+    pub benchmarks: BTreeMap<String, String>,
+}
+
+impl SuiteManifest {
+    /// Names of the benchmarks that were added, removed or changed between
+    /// the two manifests.
+    pub fn differences(&self, other: &SuiteManifest) -> Vec<String> {
+        let mut names = self.benchmarks
+            .keys()
+            .chain(other.benchmarks.keys())
+            .filter(|name| self.benchmarks.get(*name) != other.benchmarks.get(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// The migrations of the data repository's layout, in the order they are
+/// applied. The collector records the applied ones in `migrations.json` at
+/// the root of the repository.
+pub const MIGRATIONS: &[&str] = &["split-self-profile", "drop-names"];
+
+/// An entry of `renames.json`: a benchmark continued under a new name, e.g.
+/// after updating its crate to a new version. The site graphs the results
+/// of `old` from before the cutover as part of the series of `new`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Rename {
+    pub old: String,
+    pub new: String,
+    /// The first commit benchmarked with `new` instead of `old`
+    pub commit: String,
+}
+
+/// An entry of `migrations.json`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AppliedMigration {
+    /// One-based index into `MIGRATIONS`
+    pub version: usize,
+    /// Must match the name in `MIGRATIONS`, so that reordered or replaced
+    /// migrations are noticed
+    pub name: String,
+    pub applied: Date,
+}
+
+/// Check the `applied` migrations of a data repository against
+/// `MIGRATIONS`. Returns the versions still to apply.
+pub fn pending_migrations(applied: &[AppliedMigration]) -> Result<Vec<usize>, String> {
+    for (idx, migration) in applied.iter().enumerate() {
+        if migration.version != idx + 1 {
+            return Err(format!(
+                "migration {} is recorded as version {}",
+                idx + 1,
+                migration.version
+            ));
+        }
+        match MIGRATIONS.get(idx) {
+            Some(name) if *name == migration.name => {}
+            Some(name) => {
+                return Err(format!(
+                    "migration {} is `{}`, but `{}` was applied",
+                    migration.version,
+                    name,
+                    migration.name
+                ))
+            }
+            None => {
+                return Err(format!(
+                    "migration {} (`{}`) is unknown; the data repository was migrated by a \
+                     newer collector",
+                    migration.version,
+                    migration.name
+                ))
+            }
+        }
+    }
+    Ok((applied.len() + 1..MIGRATIONS.len() + 1).collect())
 }
 
 impl CommitData {
+    /// Benchmarks whose sources differ between the suites `self` and `other`
+    /// were benchmarked with. Data gathered before suites were versioned
+    /// can't be checked and is assumed to be comparable.
+    pub fn suite_changes(&self, other: &CommitData) -> Vec<String> {
+        match (&self.manifest, &other.manifest) {
+            (&Some(ref a), &Some(ref b)) => a.differences(b),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Move the per-query timings of all patches out into a `SelfProfile`,
+    /// which is stored separately since it's large and rarely needed.
+    /// Returns `None` if no query timings were gathered.
+    pub fn split_self_profile(&mut self) -> Option<SelfProfile> {
+        let mut patches = Vec::new();
+        for patch in self.benchmarks
+            .values_mut()
+            .filter_map(|v| v.as_mut().ok())
+            .flat_map(|v| v.iter_mut())
+            .filter(|p| !p.queries.is_empty())
+        {
+            patches.push(Patch {
+                name: patch.name.clone(),
+                backend: patch.backend,
+                flags: patch.flags.clone(),
+                profile: patch.profile,
+                custom_profile: patch.custom_profile.clone(),
+                std: patch.std,
+                runs: Vec::new(),
+                queries: mem::replace(&mut patch.queries, Vec::new()),
+                collector: patch.collector.clone(),
+                timings: Vec::new(),
+                llvm_passes: Vec::new(),
+                anomalies: Vec::new(),
+            });
+        }
+        if patches.is_empty() {
+            return None;
+        }
+        Some(SelfProfile {
+            commit: self.commit.clone(),
+            triple: self.triple.clone(),
+            patches: patches,
+        })
+    }
+
+    /// The class of the machine `patch` of this commit was measured on.
+    pub fn machine_class(&self, patch: &Patch) -> Option<&str> {
+        let collector = patch.collector.as_ref()?;
+        self.collectors.get(collector).map(|machine| &*machine.class)
+    }
+
+    /// Why `benchmark` failed, if it did. Failures recorded before they were
+    /// classified are classified now.
+    pub fn failure(&self, benchmark: &str) -> Option<Failure> {
+        match self.benchmarks.get(benchmark) {
+            Some(&Err(ref error)) => Some(
+                self.failures
+                    .get(benchmark)
+                    .cloned()
+                    .unwrap_or_else(|| Failure::of(error)),
+            ),
+            _ => None,
+        }
+    }
+
     pub fn benchmarks<'a>(&'a self) -> impl Iterator<Item = Option<(&'a str, &'a [Patch])>> + 'a {
         self.benchmarks
             .iter()
@@ -90,6 +833,34 @@ impl CommitData {
     }
 }
 
+/// A re-benchmark of the pinned calibration artifact, stored in
+/// `calibration/`. The artifact doesn't change, so differences between runs
+/// are changes of the machines that measured them, e.g. a kernel update.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CalibrationRun {
+    /// When the artifact was benchmarked
+    pub date: DateTime<Utc>,
+    pub data: CommitData,
+}
+
+/// The per-query timings of a commit's patches, stored in `self-profile/`
+/// next to the commit's results in `times/`. The patches' `runs` are empty.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SelfProfile {
+    pub commit: Commit,
+    pub triple: String,
+    pub patches: Vec<Patch>,
+}
+
+/// How long it took to build rustc itself at a commit, as recorded by the
+/// bootstrap's `metrics.json` on CI.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BootstrapData {
+    pub commit: Commit,
+    /// Bootstrap step -> seconds spent in it, excluding child steps
+    pub steps: BTreeMap<String, f64>,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct DeltaTime(#[serde(with = "round_float")] pub f64);
 
@@ -340,3 +1111,81 @@ pub mod round_float {
         Ok((n * 100.0).round() / 100.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Results of `sha` with one patch and statistic.
+    fn results(sha: &str) -> CommitData {
+        serde_json::from_str(&format!(
+            r#"{{
+                "commit": {{ "sha": "{}", "date": "2017-10-01T00:00:00Z" }},
+                "benchmarks": {{
+                    "helloworld": {{ "Ok": [{{
+                        "name": "helloworld",
+                        "runs": [{{ "stats": [{{ "name": "instructions:u", "cnt": 1.0 }}] }}]
+                    }}] }}
+                }},
+                "triple": "x86_64-unknown-linux-gnu"
+            }}"#,
+            sha
+        )).unwrap()
+    }
+
+    fn patch(data: &CommitData) -> &Patch {
+        &data.benchmarks["helloworld"].as_ref().unwrap()[0]
+    }
+
+    #[test]
+    fn intern_gives_equal_names_one_id() {
+        let names = Interner::new();
+        let a = names.intern("instructions:u");
+        let b = names.intern("instructions:u");
+        let c = names.intern("wall-time");
+        assert_eq!(a.id(), Some(0));
+        assert_eq!(b.id(), Some(0));
+        assert_eq!(c.id(), Some(1));
+        assert!(Arc::ptr_eq(&a.name, &b.name));
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn intern_shares_names_across_threads() {
+        let names = Arc::new(Interner::new());
+        let name = names.intern("instructions:u");
+        let other = {
+            let names = names.clone();
+            thread::spawn(move || names.intern("instructions:u"))
+                .join()
+                .unwrap()
+        };
+        assert_eq!(other.id(), name.id());
+        assert!(Arc::ptr_eq(&other.name, &name.name));
+        assert_eq!(names.len(), 1);
+    }
+
+    #[test]
+    fn intern_commit_shares_names_between_commits() {
+        let names = Interner::new();
+        let mut a = results("aaa");
+        let mut b = results("bbb");
+        assert_eq!(patch(&a).name.id(), None);
+        names.intern_commit(&mut a);
+        names.intern_commit(&mut b);
+        assert!(Arc::ptr_eq(&patch(&a).name.name, &patch(&b).name.name));
+        let stat = &patch(&b).runs[0].stats[0].name;
+        assert_eq!(*stat, "instructions:u");
+        assert_eq!(stat.id(), names.intern("instructions:u").id());
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn names_serialize_as_strings() {
+        let name = Interner::new().intern("instructions:u");
+        assert_eq!(serde_json::to_string(&name).unwrap(), r#""instructions:u""#);
+        let name: Name = serde_json::from_str(r#""instructions:u""#).unwrap();
+        assert_eq!(name, Name::from("instructions:u"));
+    }
+}
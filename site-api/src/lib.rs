@@ -0,0 +1,1389 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The requests and responses of the HTTP API of the rustc-perf site, shared
+//! by the site and its client.
+//!
+//! Each API endpoint has its own module. The modules contain Request and/or
+//! Response structs; these contain the specifications for how to interact
+//! with the API.
+//!
+//! The responses are calculated in the site's server.rs file.
+
+extern crate chrono;
+extern crate collector;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+use std::collections::BTreeSet;
+
+pub use collector::{Backend, Bound, Date, End, OptionalDate, Profile, Start, Std};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "list", content = "content")]
+pub enum List {
+    All,
+    List(BTreeSet<String>),
+}
+
+impl From<Vec<String>> for List {
+    fn from(s: Vec<String>) -> List {
+        List::List(s.into_iter().collect())
+    }
+}
+
+/// Target used by requests that don't specify one; the only target
+/// benchmarked before multiple targets were supported.
+pub fn default_target() -> String {
+    String::from("x86_64-unknown-linux-gnu")
+}
+
+impl List {
+    pub fn contains(&self, item: &str) -> bool {
+        match *self {
+            List::All => true,
+            List::List(ref x) => x.contains(item),
+        }
+    }
+
+    pub fn into_set(&self, all: &BTreeSet<String>) -> BTreeSet<String> {
+        match *self {
+            List::All => all.clone(),
+            List::List(ref x) => x.clone(),
+        }
+    }
+}
+
+pub mod info {
+    use collector::{Backend, BenchmarkInfo, Category, Commit, Date, Profile, Std};
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Response {
+        /// Sorted vector of crate names
+        pub crates: BTreeSet<String>,
+
+        /// Sorted list of statistic names known
+        pub stats: BTreeSet<String>,
+
+        /// Codegen backends with data
+        pub backends: BTreeSet<Backend>,
+
+        /// Ways of building the standard library with data
+        pub stds: BTreeSet<Std>,
+
+        /// Target triples with data
+        pub targets: BTreeSet<String>,
+
+        /// Names of the extra rustc flag sets with data
+        pub flag_sets: BTreeSet<String>,
+
+        /// Profiles with data
+        pub profiles: BTreeSet<Profile>,
+
+        /// Names of the custom Cargo profiles with data
+        #[serde(default)] pub custom_profiles: BTreeSet<String>,
+
+        /// Classes of the machines with data
+        #[serde(default)] pub machines: BTreeSet<String>,
+
+        /// Every benchmark with results, for the catalog of benchmarks
+        #[serde(default)] pub benchmarks: BTreeMap<String, Benchmark>,
+
+        /// Chronologically last loaded run date.
+        pub as_of: Date,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Benchmark {
+        pub category: Category,
+        /// Description, size and patches, as recorded with the newest
+        /// results; `None` if none of its results have them
+        pub info: Option<BenchmarkInfo>,
+        /// The first commit with results for the benchmark
+        pub first_commit: Commit,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitResponse {
+    pub commit: Option<String>,
+}
+
+pub mod data {
+    use super::List;
+    use super::annotations::Annotation;
+    use collector::{Backend, Date, End, OptionalDate, Profile, Start, Std};
+    use std::collections::{BTreeSet, HashMap};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        #[serde(rename = "start")] pub start_date: OptionalDate<Start>,
+
+        #[serde(rename = "end")] pub end_date: OptionalDate<End>,
+
+        /// Which crates to return data for
+        pub crates: List,
+
+        /// Which statistic to return data for
+        pub stat: String,
+
+        #[serde(default)] pub profile: Profile,
+
+        #[serde(default)] pub backend: Backend,
+
+        /// How the standard library was built
+        #[serde(default)] pub std: Std,
+
+        /// Extra rustc flag set; `None` for results without extra flags
+        #[serde(default)] pub flags: Option<String>,
+
+        /// Custom Cargo profile; `None` for results built with the profile
+        /// settings of the benchmarks' manifests
+        #[serde(default)] pub custom_profile: Option<String>,
+
+        #[serde(default = "super::default_target")] pub target: String,
+
+        /// Replace each point with the mean of this many points, ending with
+        /// it. 0 and 1 leave the data unchanged.
+        #[serde(default)] pub smoothing: usize,
+
+        /// Report each point as the percent change from the crate's first
+        /// point instead of the absolute value.
+        #[serde(default)] pub relative: bool,
+
+        /// Class of the machines to graph the results of; by default, that
+        /// of the newest results
+        #[serde(default)] pub machine: Option<String>,
+    }
+
+    /// List of DateData's from oldest to newest
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct Response {
+        pub data: Vec<DateData>,
+        pub start: Date,
+        pub end: Date,
+        pub crates: BTreeSet<String>,
+        /// Class of the machines the results are from
+        #[serde(default)] pub machine: Option<String>,
+        /// Renamed benchmarks whose earlier results are in the series of
+        /// their new name
+        #[serde(default)] pub renames: Vec<Rename>,
+        /// Known events between `start` and `end` to mark in the graphs
+        #[serde(default)] pub annotations: Vec<Annotation>,
+    }
+
+    /// Where the series of a renamed benchmark switches from the results
+    /// of `old` to those of `new`.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct Rename {
+        pub old: String,
+        pub new: String,
+        pub date: Date,
+    }
+
+    /// Data associated with a specific date
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct DateData {
+        pub date: Date,
+        pub commit: String,
+        pub data: HashMap<String, f64>,
+    }
+}
+
+pub mod days {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::List;
+    use super::data::DateData;
+    use collector::{Backend, Category, Failure, Profile, Std};
+
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct Request {
+        #[serde(default)] pub commit_a: String,
+        pub commit_b: String,
+
+        /// Name of a baseline to compare `commit_b` against, instead of
+        /// `commit_a`
+        #[serde(default)] pub baseline: Option<String>,
+
+        /// Which crates to return data for
+        pub crates: List,
+        pub stat: String,
+
+        /// Statistics to compare alongside `stat`, e.g. `wall-time` and
+        /// `max-rss`. Only `stat` is summarized.
+        #[serde(default)] pub extra_stats: Vec<String>,
+
+        #[serde(default)] pub profile: Profile,
+
+        #[serde(default)] pub backend: Backend,
+
+        /// How the standard library was built
+        #[serde(default)] pub std: Std,
+
+        /// Extra rustc flag set; `None` for results without extra flags
+        #[serde(default)] pub flags: Option<String>,
+
+        /// Flag set for `commit_b` if it differs from `flags`, e.g. to
+        /// compare a flag experiment against the same commit without it
+        #[serde(default)] pub flags_b: Option<String>,
+
+        /// Custom Cargo profile; `None` for results built with the profile
+        /// settings of the benchmarks' manifests
+        #[serde(default)] pub custom_profile: Option<String>,
+
+        #[serde(default = "super::default_target")] pub target: String,
+
+        /// Class of the machines to compare the results of; by default, the
+        /// one both commits have the most results from
+        #[serde(default)] pub machine: Option<String>,
+
+        /// Only return and summarize results of benchmarks in these
+        /// categories; all if empty
+        #[serde(default)] pub categories: Vec<Category>,
+
+        /// Only return and summarize results of patches whose scenario
+        /// contains one of these, e.g. `incr`; all if empty
+        #[serde(default)] pub scenarios: Vec<String>,
+
+        /// Also summarize the changes weighted by build time
+        #[serde(default)] pub weighted: bool,
+    }
+
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct Response {
+        pub a: DateData,
+        pub b: DateData,
+
+        /// Benchmarks whose sources differ between the two commits' benchmark
+        /// suites. Results for these shouldn't be compared.
+        #[serde(default)] pub suite_changes: Vec<String>,
+
+        /// Significant changes between `a` and `b`, grouped for display.
+        pub summary: Summary,
+
+        /// How noisy the results of each compared patch are, for those with
+        /// enough recent results to tell
+        #[serde(default)] pub noise: HashMap<String, Score>,
+
+        /// All changes between `a` and `b` weighted by build time, if
+        /// requested and the build times of `a` are known
+        #[serde(default)] pub weighted: Option<WeightedSummary>,
+
+        /// Category of each benchmark, for ordering and labeling results.
+        #[serde(default)] pub categories: BTreeMap<String, Category>,
+
+        /// The values of each of the requested `extra_stats`, keyed like
+        /// the data of `a` and `b`.
+        #[serde(default)] pub extra_stats: BTreeMap<String, StatData>,
+
+        /// Class of the machines the compared results are from; `None` if
+        /// either commit's results predate recording it
+        #[serde(default)] pub machine: Option<String>,
+
+        /// Benchmarks that built at `a` but fail at `b`
+        #[serde(default)] pub newly_failing: Vec<NewFailure>,
+
+        /// The pull requests rolled up into `b`, if it is a rollup; changes
+        /// are probably due to one of them
+        #[serde(default)] pub rollup: Option<Vec<RollupPr>>,
+    }
+
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct NewFailure {
+        pub benchmark: String,
+        pub failure: Failure,
+    }
+
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct StatData {
+        pub a: HashMap<String, f64>,
+        pub b: HashMap<String, f64>,
+    }
+
+    /// The change of a single statistic of a single patch between two commits.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Change {
+        /// Full patch name, e.g. `helloworld@010-baseline`
+        pub name: String,
+        pub a: f64,
+        pub b: f64,
+        pub percent: f64,
+        /// The smallest significant change (in percent), from the noise of the
+        /// patch's results
+        pub threshold: f64,
+    }
+
+    impl Change {
+        pub fn is_significant(&self) -> bool {
+            self.percent.abs() >= self.threshold
+        }
+
+        pub fn is_regression(&self) -> bool {
+            self.percent > 0.0
+        }
+    }
+
+    /// The canonical summary of the significant changes between two commits,
+    /// shared by the compare page and triage reports.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Summary {
+        pub primary_regressions: Vec<Change>,
+        pub primary_improvements: Vec<Change>,
+        /// Significant changes to secondary and stable benchmarks.
+        pub secondary: Vec<Change>,
+        /// One line human readable conclusion, e.g. "regression in 3 primary benchmarks".
+        pub verdict: String,
+    }
+
+    /// The changes between two commits weighted by how long each patch takes to
+    /// build, so that a small regression of a slow benchmark isn't drowned out by
+    /// many small improvements of fast ones.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct WeightedSummary {
+        /// The mean of the percent changes of all compared patches, weighted by
+        /// their build time with the first commit; for time statistics, the
+        /// change of the time it takes to build all of them in turn
+        pub percent: f64,
+        /// The part of `percent` due to regressions, in percentage points
+        pub regressions: f64,
+        /// The part of `percent` due to improvements, in percentage points
+        pub improvements: f64,
+        /// Seconds the weighted patches took to build with the first commit
+        pub seconds: f64,
+        /// How many of the changes had a known build time and were weighted
+        pub weighted: usize,
+        /// e.g. "regression of +0.4% weighted by build time"
+        pub verdict: String,
+    }
+
+    /// The noise of a series.
+    #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Score {
+        /// Median absolute change (in percent) between consecutive commits
+        pub score: f64,
+        /// The smallest change (in percent) that is significant
+        pub threshold: f64,
+        /// Whether the threshold is above the global significance threshold
+        pub noisy: bool,
+    }
+
+    /// A pull request merged as part of a rollup.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct RollupPr {
+        pub number: u64,
+        pub title: String,
+    }
+}
+
+pub mod stats {
+    use std::collections::HashMap;
+
+    use collector::{null_means_nan, Backend, Date, End, OptionalDate, Profile, Start, Std};
+    use super::List;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        #[serde(rename = "start")] pub start_date: OptionalDate<Start>,
+        #[serde(rename = "end")] pub end_date: OptionalDate<End>,
+        pub crates: List,
+        pub stat: String,
+        #[serde(default)] pub profile: Profile,
+
+        #[serde(default)] pub backend: Backend,
+
+        /// How the standard library was built
+        #[serde(default)] pub std: Std,
+
+        /// Extra rustc flag set; `None` for results without extra flags
+        #[serde(default)] pub flags: Option<String>,
+
+        #[serde(default = "super::default_target")] pub target: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Response {
+        #[serde(rename = "startDate")] pub start_date: Date,
+        #[serde(rename = "endDate")] pub end_date: Date,
+        // crate -> statistics over date range
+        pub data: HashMap<String, Stats>,
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, Default)]
+    pub struct Stats {
+        pub first: f64,
+        pub last: f64,
+        pub min: f64,
+        pub max: f64,
+        pub mean: f64,
+        pub variance: f64,
+        #[serde(deserialize_with = "null_means_nan")] pub trend: f64,
+        #[serde(deserialize_with = "null_means_nan")] pub trend_b: f64,
+        pub n: usize,
+    }
+}
+
+pub mod triage {
+    use collector::{Backend, End, OptionalDate, Profile, Start, Std};
+
+    /// The response is a Markdown document, suitable for pasting into the
+    /// weekly triage issue.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        #[serde(rename = "start")] pub start_date: OptionalDate<Start>,
+        #[serde(rename = "end")] pub end_date: OptionalDate<End>,
+
+        /// Which statistic to base the report on
+        pub stat: String,
+
+        #[serde(default)] pub profile: Profile,
+
+        #[serde(default)] pub backend: Backend,
+
+        /// How the standard library was built
+        #[serde(default)] pub std: Std,
+
+        /// Extra rustc flag set; `None` for results without extra flags
+        #[serde(default)] pub flags: Option<String>,
+
+        #[serde(default = "super::default_target")] pub target: String,
+    }
+}
+
+pub mod collectors {
+    use collector::{Date, Machine};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Response {
+        pub collectors: Vec<Collector>,
+    }
+
+    /// A collector and the commit of its newest results
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Collector {
+        pub id: String,
+        pub machine: Machine,
+        pub target: String,
+        pub commit: String,
+        pub date: Date,
+    }
+}
+
+pub mod self_profile {
+    use collector::{Backend, Profile, Std};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        #[serde(default)] pub commit_a: String,
+        pub commit_b: String,
+
+        /// Name of a baseline to compare `commit_b` against, instead of
+        /// `commit_a`
+        #[serde(default)] pub baseline: Option<String>,
+
+        /// Full patch name, e.g. `helloworld@010-baseline`
+        pub benchmark: String,
+
+        #[serde(default)] pub profile: Profile,
+
+        #[serde(default)] pub backend: Backend,
+
+        /// How the standard library was built
+        #[serde(default)] pub std: Std,
+
+        /// Extra rustc flag set; `None` for results without extra flags
+        #[serde(default)] pub flags: Option<String>,
+
+        #[serde(default = "super::default_target")] pub target: String,
+    }
+
+    /// Per-query differences between the two commits, for queries present
+    /// in either. Queries missing from one side count as zero there.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Response {
+        pub commit_a: String,
+        pub commit_b: String,
+        pub queries: Vec<QueryDiff>,
+
+        /// Commits without query timings for the benchmark, in which case
+        /// `queries` is empty.
+        pub missing: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct QueryDiff {
+        pub label: String,
+        /// Self time in seconds
+        pub a_time: f64,
+        pub b_time: f64,
+        pub a_invocations: u64,
+        pub b_invocations: u64,
+    }
+}
+
+pub mod llvm_passes {
+    use collector::{Backend, Profile, Std};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        pub commit_a: String,
+        pub commit_b: String,
+
+        /// Full patch name, e.g. `regex@010-incr-unchanged`
+        pub benchmark: String,
+
+        #[serde(default)] pub profile: Profile,
+
+        #[serde(default)] pub backend: Backend,
+
+        /// How the standard library was built
+        #[serde(default)] pub std: Std,
+
+        /// Extra rustc flag set; `None` for results without extra flags
+        #[serde(default)] pub flags: Option<String>,
+
+        #[serde(default = "super::default_target")] pub target: String,
+    }
+
+    /// How long the slowest LLVM passes of the patch's own crate took with
+    /// either commit, to tell which passes an optimized build regressed in.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Response {
+        pub commit_a: String,
+        pub commit_b: String,
+        /// The passes recorded for `commit_b`, slowest first, followed by
+        /// those only recorded for `commit_a`
+        pub passes: Vec<PassDiff>,
+        /// Commits without LLVM pass timings for the benchmark, in which case
+        /// `passes` is empty.
+        pub missing: Vec<String>,
+    }
+
+    /// Seconds a pass took with each commit; `None` if it wasn't among the
+    /// slowest passes recorded for that commit.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct PassDiff {
+        pub name: String,
+        pub a: Option<f64>,
+        pub b: Option<f64>,
+    }
+}
+
+pub mod timings {
+    use collector::{Backend, Profile, Std};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        #[serde(default)] pub commit_a: String,
+        pub commit_b: String,
+
+        /// Name of a baseline to compare `commit_b` against, instead of
+        /// `commit_a`
+        #[serde(default)] pub baseline: Option<String>,
+
+        /// Full patch name, e.g. `helloworld@010-baseline`
+        pub benchmark: String,
+
+        #[serde(default)] pub profile: Profile,
+
+        #[serde(default)] pub backend: Backend,
+
+        /// How the standard library was built
+        #[serde(default)] pub std: Std,
+
+        /// Extra rustc flag set; `None` for results without extra flags
+        #[serde(default)] pub flags: Option<String>,
+
+        #[serde(default = "super::default_target")] pub target: String,
+    }
+
+    /// When each crate of the patch's build was compiled with either commit,
+    /// to tell a regression in the benchmark's own crate from one in a
+    /// dependency.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Response {
+        pub commit_a: String,
+        pub commit_b: String,
+        /// In the order they were compiled with `commit_b`, followed by those
+        /// only compiled with `commit_a`
+        pub crates: Vec<CrateDiff>,
+        pub a: Option<Build>,
+        pub b: Option<Build>,
+
+        /// Commits without crate timings for the benchmark, in which case
+        /// `crates` is empty.
+        pub missing: Vec<String>,
+    }
+
+    /// A crate's start and duration in seconds with each commit; `None` if
+    /// it wasn't compiled with that commit, e.g. due to changed features.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct CrateDiff {
+        pub name: String,
+        pub leaf: bool,
+        pub a_start: Option<f64>,
+        pub a_duration: Option<f64>,
+        pub b_start: Option<f64>,
+        pub b_duration: Option<f64>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Build {
+        /// Seconds from the start of the first crate to the end of the last
+        pub duration: f64,
+        /// Average number of crates compiled at once
+        pub parallelism: f64,
+    }
+}
+
+pub mod bootstrap {
+    use collector::{BootstrapData, Date, End, OptionalDate, Start};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        #[serde(rename = "start")] pub start_date: OptionalDate<Start>,
+        #[serde(rename = "end")] pub end_date: OptionalDate<End>,
+    }
+
+    /// Bootstrap timings from oldest to newest
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Response {
+        pub data: Vec<BootstrapData>,
+        pub start: Date,
+        pub end: Date,
+    }
+}
+
+pub mod calibration {
+    use std::collections::BTreeMap;
+
+    use chrono::{DateTime, Utc};
+
+    use super::days::Change;
+    use collector::{Backend, Profile, Std};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        /// Which statistic to compare the runs on
+        pub stat: String,
+
+        #[serde(default)] pub profile: Profile,
+
+        #[serde(default)] pub backend: Backend,
+
+        /// How the standard library was built
+        #[serde(default)] pub std: Std,
+    }
+
+    /// The calibration runs of each class of machine, oldest first.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Response {
+        pub machines: BTreeMap<String, Vec<Run>>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Run {
+        pub date: DateTime<Utc>,
+        /// The calibration artifact
+        pub commit: String,
+        /// Kernel release of the machine
+        pub kernel: String,
+        /// How the machine differs from that of the previous run, e.g.
+        /// `microcode: 0xf0 -> 0xf4`
+        #[serde(default)] pub machine_changes: Vec<String>,
+        /// Geometric mean of the changes since the first run of the same
+        /// artifact on this class of machine, in percent
+        pub drift: f64,
+        /// Significant changes since the previous run, largest first
+        pub changes: Vec<Change>,
+    }
+}
+
+pub mod snapshot {
+    use chrono::{DateTime, Utc};
+
+    /// The comparison to snapshot is requested like `/perf/get`.
+    pub use super::days::Request;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Snapshot {
+        pub id: String,
+        /// When the comparison was taken
+        pub taken: DateTime<Utc>,
+        pub request: Request,
+        pub response: super::days::Response,
+    }
+
+    /// Either the new snapshot's id and permalink, or why it couldn't be
+    /// taken.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct CreateResponse {
+        pub id: Option<String>,
+        /// Path of the compare page showing the snapshot
+        pub permalink: Option<String>,
+        pub error: Option<String>,
+    }
+
+    /// The snapshot, or why there is none.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Response {
+        pub snapshot: Option<Snapshot>,
+        pub error: Option<String>,
+    }
+}
+
+pub mod experiment {
+    use super::queue::QueuedCommit;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        /// Becomes the name of the flag set the results are recorded under
+        pub name: String,
+        /// Extra rustc flags, as for a collector's flag sets
+        pub flags: String,
+    }
+
+    /// Either the queued commit or why the experiment was rejected.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Response {
+        pub queued: Option<QueuedCommit>,
+        pub error: Option<String>,
+    }
+}
+
+pub mod local_toolchain {
+    use chrono::{DateTime, Utc};
+    use super::queue::QueuedCommit;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        /// The artifact is named `local-<name>`
+        pub name: String,
+        /// Path of the toolchain's rustc on the registering collector
+        pub rustc: String,
+        /// The master commit the toolchain was built from, if known
+        #[serde(default)] pub base: Option<String>,
+        /// Date to record the results with, e.g. that of `base`
+        pub date: DateTime<Utc>,
+    }
+
+    /// Either the queued entry or why the toolchain was rejected.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Response {
+        pub queued: Option<QueuedCommit>,
+        pub error: Option<String>,
+    }
+}
+
+pub mod annotations {
+    use collector::Date;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Annotation {
+        pub id: u32,
+        /// When the event took effect; for a commit, its date
+        pub date: Date,
+        /// The commit that brought the event, if any
+        pub commit: Option<String>,
+        pub label: String,
+        /// Where to read more, e.g. the pull request
+        pub link: Option<String>,
+        /// Name of the admin who added the annotation
+        pub added_by: String,
+    }
+
+    /// All annotations, oldest first
+    pub type Response = Vec<Annotation>;
+
+    /// Either adds an annotation, dated by `date` or else by the date of
+    /// `commit`, or removes the annotation `remove`.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        #[serde(default)] pub date: Option<Date>,
+        /// Commit (or a prefix of its hash) with results
+        #[serde(default)] pub commit: Option<String>,
+        #[serde(default)] pub label: String,
+        #[serde(default)] pub link: Option<String>,
+        #[serde(default)] pub remove: Option<u32>,
+    }
+
+    /// Either the annotation added, `None` if one was removed, or why the
+    /// request was rejected.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct SetResponse {
+        pub annotation: Option<Annotation>,
+        pub error: Option<String>,
+    }
+}
+
+pub mod exclusions {
+    use chrono::{DateTime, Utc};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Exclusion {
+        pub id: u32,
+        /// Full hash of the commit whose results are excluded
+        pub commit: String,
+        /// The target the results are excluded for; all targets if `None`
+        pub target: Option<String>,
+        /// The benchmark whose results are excluded; all of the commit's results
+        /// if `None`
+        pub benchmark: Option<String>,
+        pub reason: String,
+        /// Name of the admin who added the exclusion
+        pub added_by: String,
+        pub added: DateTime<Utc>,
+    }
+
+    /// All exclusions, oldest first
+    pub type Response = Vec<Exclusion>;
+
+    /// Either excludes the results of `benchmark`, or all results, of
+    /// `commit` on `target`, or on all targets, or removes the exclusion
+    /// `remove`.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        /// Commit (or a prefix of its hash) with results
+        #[serde(default)] pub commit: String,
+        #[serde(default)] pub target: Option<String>,
+        #[serde(default)] pub benchmark: Option<String>,
+        #[serde(default)] pub reason: String,
+        #[serde(default)] pub remove: Option<u32>,
+    }
+
+    /// Either the exclusion added, `None` if one was removed, or why the
+    /// request was rejected.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct SetResponse {
+        pub exclusion: Option<Exclusion>,
+        pub error: Option<String>,
+    }
+}
+
+pub mod baselines {
+    use std::collections::BTreeMap;
+
+    use chrono::{DateTime, Utc};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Baseline {
+        pub sha: String,
+        /// Name of the user or team that set the baseline
+        pub owner: String,
+        /// When the baseline was last set
+        pub set: DateTime<Utc>,
+    }
+
+    /// All baselines, by name
+    pub type Response = BTreeMap<String, Baseline>;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Request {
+        pub name: String,
+        /// Commit (or a prefix of its hash) with results to point the
+        /// baseline at; `None` removes the baseline
+        #[serde(default)] pub commit: Option<String>,
+    }
+
+    /// Either the baseline as set, `None` if it was removed, or why the
+    /// request was rejected.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct SetResponse {
+        pub baseline: Option<Baseline>,
+        pub error: Option<String>,
+    }
+}
+
+pub mod queue {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use chrono::{DateTime, Utc};
+
+    pub use self::State as Response;
+
+    /// What a commit is queued for, which decides the order in which commits are
+    /// handed out. Later variants go first.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Priority {
+        /// Master commits without results that weren't queued by a webhook
+        Backfill,
+        /// Weekly re-benchmarks of the calibration artifact
+        Calibration,
+        /// Flag experiments
+        Experiment,
+        /// Toolchains built locally on a collector
+        Local,
+        /// Try builds of the pull requests of regressed rollups
+        Try,
+        /// Fresh master merges
+        Master,
+    }
+
+    fn default_priority() -> Priority {
+        Priority::Master
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct QueuedCommit {
+        pub sha: String,
+        /// Commit date, which orders the queue within a priority
+        pub date: DateTime<Utc>,
+        pub summary: String,
+        /// When the commit was queued
+        pub queued: DateTime<Utc>,
+        #[serde(default = "default_priority")] pub priority: Priority,
+        /// When a collector picked the commit up, if one did
+        #[serde(default)] pub started: Option<DateTime<Utc>>,
+        /// The collector holding the claim on the commit
+        #[serde(default)] pub collector: Option<String>,
+        /// When the claim expires unless it is renewed
+        #[serde(default)] pub lease_expires: Option<DateTime<Utc>>,
+        /// How often a collector failed to benchmark the commit
+        #[serde(default)] pub attempts: u32,
+        /// Set if the commit is to be benchmarked with an experiment's flags
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub experiment: Option<Experiment>,
+        /// Set if the commit is the calibration artifact, whose results are
+        /// recorded as a calibration run
+        #[serde(default)] pub calibration: bool,
+        /// Set if the entry is a locally built toolchain rather than a commit
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub local: Option<LocalToolchain>,
+    }
+
+    impl QueuedCommit {
+        pub fn new(
+            sha: String,
+            date: DateTime<Utc>,
+            summary: String,
+            priority: Priority,
+            now: DateTime<Utc>,
+        ) -> QueuedCommit {
+            QueuedCommit {
+                sha: sha,
+                date: date,
+                summary: summary,
+                queued: now,
+                priority: priority,
+                started: None,
+                collector: None,
+                lease_expires: None,
+                attempts: 0,
+                experiment: None,
+                calibration: false,
+                local: None,
+            }
+        }
+
+        /// Identifies the entry: the commit, and the experiment if any. Names the
+        /// claim when renewing it or reporting a failure. Each week's calibration
+        /// is an entry of its own.
+        pub fn key(&self) -> String {
+            if self.calibration {
+                return format!("{}:calibration-{}", self.sha, self.queued.format("%Y-%m-%d"));
+            }
+            match self.experiment {
+                Some(ref experiment) => format!("{}:{}", self.sha, experiment.name),
+                None => self.sha.clone(),
+            }
+        }
+    }
+
+    /// A toolchain built on a collector, benchmarked as the pseudo-artifact
+    /// that is the entry's `sha`.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct LocalToolchain {
+        /// Path of its rustc on the collector
+        pub rustc: String,
+        /// The collector that registered it, the only one that can claim it
+        pub collector: String,
+        /// The master commit it was built from, if known
+        #[serde(default)] pub base: Option<String>,
+    }
+
+    /// A named set of extra rustc flags to benchmark a master commit with. The
+    /// results are recorded as a flag set of that name.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Experiment {
+        pub name: String,
+        pub flags: String,
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    pub struct State {
+        /// In the order they are handed out
+        pub pending: Vec<QueuedCommit>,
+        pub in_progress: Vec<QueuedCommit>,
+        /// Entries given up on after too many failures, oldest first. They
+        /// aren't queued again.
+        #[serde(default)] pub failed: Vec<QueuedCommit>,
+        /// Regressed rollups, newest last
+        #[serde(default)] pub rollups: Vec<Rollup>,
+        /// Experiments with results, oldest first
+        #[serde(default)] pub finished_experiments: Vec<QueuedCommit>,
+        /// Entries canceled by a member, oldest first. They aren't queued again.
+        /// Those canceled while being benchmarked keep their collector.
+        #[serde(default)] pub canceled: Vec<QueuedCommit>,
+        /// Seconds from the claim to the results of the most recent runs, oldest
+        /// first
+        #[serde(default)] pub run_durations: Vec<i64>,
+        /// The metrics each collector said it gathers when it last asked for
+        /// work
+        #[serde(default)] pub collector_metrics: BTreeMap<String, BTreeSet<String>>,
+    }
+
+    impl State {
+        /// The state without the paths of local toolchains on their collectors,
+        /// for anyone to see.
+        pub fn redacted(mut self) -> State {
+            for commit in self.pending
+                .iter_mut()
+                .chain(&mut self.in_progress)
+                .chain(&mut self.failed)
+                .chain(&mut self.finished_experiments)
+                .chain(&mut self.canceled)
+            {
+                if let Some(ref mut local) = commit.local {
+                    local.rustc = String::new();
+                }
+            }
+            self
+        }
+    }
+
+    /// An entry being benchmarked or pending, with when it is expected to start
+    /// and how many seconds it is expected to take. Both are unknown until a run
+    /// finished.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Estimate {
+        pub key: String,
+        pub commit: QueuedCommit,
+        pub start: Option<DateTime<Utc>>,
+        pub duration: Option<i64>,
+    }
+
+    /// A regressed rollup merge, whose pull requests need to be benchmarked
+    /// individually to find the one responsible.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Rollup {
+        pub sha: String,
+        pub detected: DateTime<Utc>,
+        pub prs: Vec<UnrolledPr>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct UnrolledPr {
+        pub number: u64,
+        pub title: String,
+        /// The bors try build of the pull request, once one was pushed
+        #[serde(default)] pub try_sha: Option<String>,
+    }
+
+    /// The commit a collector should benchmark next, now claimed by it;
+    /// `None` if the queue is empty.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Next {
+        pub commit: Option<QueuedCommit>,
+        /// Names the claim, to renew it or to report a failure
+        #[serde(default)] pub key: Option<String>,
+    }
+
+    /// Whether the collector held the claim it renewed or gave up. If not,
+    /// its lease expired and the commit may have gone to another collector,
+    /// or a member canceled the entry.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Claim {
+        pub held: bool,
+        /// Set if the entry was canceled; the collector should abandon it
+        #[serde(default)] pub canceled: bool,
+    }
+
+    /// Cancel the entry `key`, move it to `priority` if it is pending, or
+    /// `retry` it if it failed or was canceled.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Update {
+        pub key: String,
+        #[serde(default)] pub cancel: bool,
+        #[serde(default)] pub retry: bool,
+        #[serde(default)] pub priority: Option<Priority>,
+    }
+
+    /// Either the updated entry or why the update was rejected.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct UpdateResponse {
+        pub commit: Option<QueuedCommit>,
+        pub error: Option<String>,
+    }
+}
+
+/// The stable API served under `/api/v1/`, for tools outside this repository.
+/// Unlike the endpoints above, which change along with the frontend, these
+/// only ever gain fields: existing fields are never removed, renamed or given
+/// a different meaning. Incompatible changes get a new version next to this
+/// one; see the site's README for how long old versions are kept.
+///
+/// All endpoints take GET requests with query parameters. Failed requests get
+/// a 4xx status and an `Error`.
+pub mod v1 {
+    use collector::Date;
+
+    pub const VERSION: u32 = 1;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Error {
+        pub error: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Commit {
+        pub sha: String,
+        pub date: Date,
+    }
+
+    /// `/api/v1/info`
+    pub mod info {
+        use collector::{Backend, Date, Profile, Std};
+        use std::collections::BTreeSet;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Response {
+            /// Version of the API, `1`
+            pub version: u32,
+            pub targets: BTreeSet<String>,
+            pub benchmarks: BTreeSet<String>,
+            pub metrics: BTreeSet<String>,
+            pub profiles: BTreeSet<Profile>,
+            pub backends: BTreeSet<Backend>,
+            pub stds: BTreeSet<Std>,
+            pub flag_sets: BTreeSet<String>,
+            #[serde(default)] pub custom_profiles: BTreeSet<String>,
+            /// Date of the newest commit with results
+            pub last_date: Date,
+        }
+    }
+
+    /// `/api/v1/commits?target=&start=&end=`: the commits with results for
+    /// `target` (default `x86_64-unknown-linux-gnu`) between the dates
+    /// `start` and `end` (default: the last 30 days), oldest first.
+    pub mod commits {
+        use super::Commit;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Response {
+            pub commits: Vec<Commit>,
+        }
+    }
+
+    /// `/api/v1/neighbors?commit=&target=`: `commit`, a full hash or a
+    /// prefix, and the master commits with results for `target` right
+    /// before and after it, with what bors merged in each.
+    pub mod neighbors {
+        use super::Commit;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Response {
+            pub commit: Neighbor,
+            pub prev: Option<Neighbor>,
+            pub next: Option<Neighbor>,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Neighbor {
+            pub commit: Commit,
+            /// Summary of the merge commit; `None` for commits that aren't on
+            /// master, e.g. try builds, or if master couldn't be listed
+            pub summary: Option<String>,
+            /// The pull request bors merged
+            pub pr: Option<u64>,
+            pub author: Option<String>,
+            /// Who approved the pull request
+            pub reviewer: Option<String>,
+        }
+    }
+
+    /// `/api/v1/results?commit=&target=&metric=`: every result of `commit`,
+    /// a full hash or a prefix, optionally only for `metric`.
+    pub mod results {
+        use super::Commit;
+        use collector::{Backend, Profile, Std};
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Response {
+            pub commit: Commit,
+            pub target: String,
+            pub results: Vec<Measurement>,
+            pub errors: Vec<BenchmarkError>,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Measurement {
+            pub benchmark: String,
+            /// The incremental patch, e.g. `@010-baseline`, or empty for a
+            /// plain build
+            pub scenario: String,
+            pub profile: Profile,
+            pub backend: Backend,
+            pub std: Std,
+            /// Extra rustc flag set, if any
+            pub flags: Option<String>,
+            /// Custom Cargo profile, if any
+            #[serde(default)] pub custom_profile: Option<String>,
+            /// Class of the machine that measured it, if recorded
+            pub machine: Option<String>,
+            pub metrics: BTreeMap<String, f64>,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct BenchmarkError {
+            pub benchmark: String,
+            pub error: String,
+        }
+    }
+
+    /// `/api/v1/compare?a=&b=&metric=&target=&profile=&backend=&std=&flags=
+    /// &custom_profile=`: the changes from commit `a` to commit `b` in `metric` (default
+    /// `instructions:u`), largest first. `baseline=` names a baseline to
+    /// compare against instead of `a`.
+    pub mod compare {
+        use super::Commit;
+        use collector::Category;
+        use days::{RollupPr, WeightedSummary};
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Response {
+            pub a: Commit,
+            pub b: Commit,
+            /// The baseline `a` was taken from, if any
+            #[serde(default)] pub baseline: Option<String>,
+            pub target: String,
+            pub metric: String,
+            /// Class of the machine whose results are compared, if recorded
+            pub machine: Option<String>,
+            /// e.g. "regression in 3 primary benchmarks"
+            pub verdict: String,
+            /// The changes weighted by build time, with `weighted=true`
+            #[serde(default)] pub weighted: Option<WeightedSummary>,
+            pub changes: Vec<Change>,
+            /// The pull requests rolled up into `b`, if it is a rollup
+            #[serde(default)] pub rollup: Option<Vec<RollupPr>>,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Change {
+            pub benchmark: String,
+            pub scenario: String,
+            pub category: Category,
+            pub a: f64,
+            pub b: f64,
+            pub percent: f64,
+            /// The smallest significant change (in percent), scaled with
+            /// the noise of the series
+            pub threshold: f64,
+            /// Whether the series is noisier than usual
+            pub noisy: bool,
+            pub significant: bool,
+        }
+    }
+
+    /// `/api/v1/threshold?benchmark=&scenario=&target=&metric=&profile=&backend=&std=&flags=
+    /// &custom_profile=&count=`: why changes of one test case are judged
+    /// significant or not: the threshold derived from its noise, and its last
+    /// `count` (default 10) changes between consecutive commits, newest
+    /// first.
+    pub mod threshold {
+        use super::Commit;
+        use collector::Profile;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Response {
+            pub benchmark: String,
+            pub scenario: String,
+            pub target: String,
+            pub metric: String,
+            pub profile: Profile,
+            /// The smallest significant change, in percent
+            pub threshold: f64,
+            /// Median absolute change (in percent) between consecutive
+            /// commits among the newest 30, if there were enough to score
+            pub noise: Option<f64>,
+            /// Variance of the changes (in percent) between consecutive
+            /// commits among the newest 30, if there were at least two
+            pub variance: Option<f64>,
+            /// Whether the threshold is above the default of 1%
+            pub noisy: bool,
+            /// How the threshold was arrived at, e.g. "3 times the noise
+            /// score of 0.412%; noisy"
+            pub reason: String,
+            pub changes: Vec<Change>,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Change {
+            pub a: Commit,
+            pub b: Commit,
+            pub before: f64,
+            pub after: f64,
+            pub percent: f64,
+            pub verdict: Verdict,
+        }
+
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        pub enum Verdict {
+            Regression,
+            Improvement,
+            /// Smaller than the threshold
+            Insignificant,
+        }
+    }
+
+    /// `/api/v1/channels?target=&metric=&profile=&backend=&std=&flags=&custom_profile=`: the
+    /// results of the current stable, beta and nightly releases side by side,
+    /// optionally only for `metric`.
+    pub mod channels {
+        use super::Commit;
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Response {
+            pub target: String,
+            /// Stable, beta and nightly, in that order
+            pub channels: Vec<Channel>,
+            pub results: Vec<Measurement>,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Channel {
+            /// `stable`, `beta` or `nightly`
+            pub name: String,
+            /// e.g. `1.25.0`
+            pub version: String,
+            /// The master commit the release was built or, for stable and
+            /// beta, branched from
+            pub sha: String,
+            /// The commit whose results are reported: `sha` if it has
+            /// results, or else the newest commit with results before it.
+            /// `None` if there is none.
+            pub commit: Option<Commit>,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Measurement {
+            pub benchmark: String,
+            pub scenario: String,
+            pub metric: String,
+            /// Channel name -> value, for the channels with a result
+            pub values: BTreeMap<String, f64>,
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Embeds the files of `static` into the site, see `frontend`. Generates
+//! `static_files.rs`, a list of their paths relative to `static` and their
+//! contents.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("static");
+    let mut files = Vec::new();
+    collect(&root, &root, &mut files);
+    files.sort();
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("static_files.rs");
+    let mut out = File::create(&out).unwrap();
+    writeln!(out, "pub static FILES: &[(&str, &[u8])] = &[").unwrap();
+    for (name, path) in files {
+        writeln!(out, "    ({:?}, include_bytes!({:?})),", name, path).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+}
+
+fn collect(root: &Path, dir: &Path, files: &mut Vec<(String, String)>) {
+    // Also reruns when files are added or removed.
+    println!("cargo:rerun-if-changed={}", dir.display());
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect(root, &path, files);
+            continue;
+        }
+        println!("cargo:rerun-if-changed={}", path.display());
+        let name = path.strip_prefix(root)
+            .unwrap()
+            .to_str()
+            .expect("non-UTF-8 file name in static")
+            .replace('\\', "/");
+        files.push((name, path.to_str().unwrap().to_string()));
+    }
+}
@@ -0,0 +1,459 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Detection of significant changes between two commits.
+
+use std::collections::BTreeMap;
+
+use load::{Backend, Category, CommitData, InputData, Profile};
+use noise::Noise;
+use server::{self, Selector};
+use errors::*;
+use util;
+
+pub use api::days::{Change, Summary, WeightedSummary};
+
+/// Changes (in percent) smaller than this are considered noise, unless the
+/// noise of a benchmark's results says otherwise.
+pub const SIGNIFICANCE_THRESHOLD: f64 = 1.0;
+
+/// Weighted changes (in percent) smaller than this are considered noise. The
+/// noise of many benchmarks partly cancels out in the weighted mean, so this
+/// is lower than `SIGNIFICANCE_THRESHOLD`.
+pub const WEIGHTED_SIGNIFICANCE_THRESHOLD: f64 = 0.2;
+
+/// Overall classification of the significant changes between two commits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Regression,
+    Improvement,
+    Mixed,
+}
+
+impl Direction {
+    /// Returns `None` if `changes` contains no significant change. Primary
+    /// benchmarks decide the direction; secondary ones only count if no
+    /// primary benchmark changed significantly.
+    pub fn of(changes: &[Change], categories: &BTreeMap<String, Category>) -> Option<Direction> {
+        let significant = changes
+            .iter()
+            .filter(|c| c.is_significant())
+            .collect::<Vec<_>>();
+        let primary = significant
+            .iter()
+            .cloned()
+            .filter(|c| category(categories, &c.name) == Category::Primary)
+            .collect::<Vec<_>>();
+        let relevant = if primary.is_empty() {
+            significant
+        } else {
+            primary
+        };
+        let regressions = relevant.iter().filter(|c| c.is_regression()).count();
+        let improvements = relevant.len() - regressions;
+        match (regressions, improvements) {
+            (0, 0) => None,
+            (_, 0) => Some(Direction::Regression),
+            (0, _) => Some(Direction::Improvement),
+            _ => Some(Direction::Mixed),
+        }
+    }
+}
+
+/// All changes of `stat` between `a` and `b`, for patches present in both,
+/// with the significance thresholds of their `noise`. Benchmarks whose
+/// sources differ between the two commits are skipped. Sorted by decreasing
+/// magnitude, with changes of missing (NaN) results last.
+pub fn changes(
+    a: &CommitData,
+    b: &CommitData,
+    stat: &str,
+    selector: Selector,
+    noise: &Noise,
+) -> Vec<Change> {
+    changes_between(a, selector, b, selector, stat, noise)
+}
+
+/// Like `changes`, but with different builds selected for `a` and `b`, e.g.
+/// to compare a flag experiment against the builds without extra flags. No
+/// changes if no machine is selected and `a` and `b` have no results from
+/// the same class of machines.
+pub fn changes_between(
+    a: &CommitData,
+    a_selector: Selector,
+    b: &CommitData,
+    b_selector: Selector,
+    stat: &str,
+    noise: &Noise,
+) -> Vec<Change> {
+    let target = &a.triple;
+    let machine = match a_selector.machine {
+        Some(machine) => Some(machine),
+        None => match common_machine(a, b) {
+            Ok(machine) => machine,
+            Err(_) => return Vec::new(),
+        },
+    };
+    let a_selector = a_selector.on(machine);
+    let b_selector = b_selector.on(b_selector.machine.or(machine));
+    let suite_changes = a.suite_changes(b);
+    let a = server::date_data(a, stat, a_selector);
+    let b = server::date_data(b, stat, b_selector);
+
+    let mut changes = a.data
+        .iter()
+        .filter_map(|(name, &a)| {
+            let benchmark = name.split('@').next().unwrap();
+            if suite_changes.iter().any(|changed| changed == benchmark) {
+                return None;
+            }
+            let b = *b.data.get(name)?;
+            if a == 0.0 {
+                return None;
+            }
+            Some(Change {
+                name: name.clone(),
+                a: a,
+                b: b,
+                percent: 100.0 * (b - a) / a,
+                threshold: noise.threshold(target, a_selector.profile, stat, name),
+            })
+        })
+        .collect::<Vec<_>>();
+    changes.sort_by(|x, y| util::descending(x.percent.abs(), y.percent.abs()));
+    changes
+}
+
+/// Weights `changes` from `a` by the build times of the patches
+/// `selector` selects in `a`: their wall time if measured, or else
+/// their task clock. `None` if none of the changes has a build time.
+pub fn weighted_summary(
+    changes: &[Change],
+    a: &CommitData,
+    selector: Selector,
+) -> Option<WeightedSummary> {
+    let wall_time = server::date_data(a, "wall-time", selector).data;
+    let task_clock = server::date_data(a, "task-clock", selector).data;
+    let build_time = |name: &str| {
+        wall_time
+            .get(name)
+            .cloned()
+            .or_else(|| task_clock.get(name).map(|ms| ms / 1000.0))
+    };
+
+    let (mut total, mut regressions, mut improvements, mut weighted) = (0.0, 0.0, 0.0, 0);
+    for change in changes {
+        let seconds = match build_time(&change.name) {
+            Some(seconds) if seconds > 0.0 => seconds,
+            _ => continue,
+        };
+        total += seconds;
+        weighted += 1;
+        if change.is_regression() {
+            regressions += seconds * change.percent;
+        } else {
+            improvements += seconds * change.percent;
+        }
+    }
+    if weighted == 0 {
+        return None;
+    }
+    let (regressions, improvements) = (regressions / total, improvements / total);
+    let percent = regressions + improvements;
+    let verdict = if percent.abs() < WEIGHTED_SIGNIFICANCE_THRESHOLD {
+        format!("no relevant change weighted by build time ({:+.2}%)", percent)
+    } else if percent > 0.0 {
+        format!("regression of {:+.2}% weighted by build time", percent)
+    } else {
+        format!("improvement of {:+.2}% weighted by build time", percent)
+    };
+    Some(WeightedSummary {
+        percent: percent,
+        regressions: regressions,
+        improvements: improvements,
+        seconds: total,
+        weighted: weighted,
+        verdict: verdict,
+    })
+}
+
+/// The class of the machines to compare `a` and `b` on: of those both have
+/// results from, the one with the most results. `None` if either commit's
+/// results predate identifying collectors; all results are compared then.
+/// An error if they were measured on different classes of machines only,
+/// whose results can't be compared.
+pub fn common_machine<'a>(a: &'a CommitData, b: &CommitData) -> Result<Option<&'a str>> {
+    let a_machines = machine_counts(a);
+    let b_machines = machine_counts(b);
+    if a_machines.is_empty() || b_machines.is_empty() {
+        return Ok(None);
+    }
+    let mut common = a_machines
+        .into_iter()
+        .filter_map(|(machine, count)| b_machines.get(machine).map(|b| (count + b, machine)))
+        .collect::<Vec<_>>();
+    common.sort();
+    match common.pop() {
+        Some((_, machine)) => Ok(Some(machine)),
+        None => bail!(
+            "{} and {} have no results from the same class of machines",
+            a.commit.sha,
+            b.commit.sha
+        ),
+    }
+}
+
+/// The class of the machine with the most results of `day`.
+pub fn main_machine(day: &CommitData) -> Option<&str> {
+    let mut machines = machine_counts(day)
+        .into_iter()
+        .map(|(machine, count)| (count, machine))
+        .collect::<Vec<_>>();
+    machines.sort();
+    machines.pop().map(|(_, machine)| machine)
+}
+
+/// The number of patches of `day` measured on each class of machine.
+fn machine_counts(day: &CommitData) -> BTreeMap<&str, usize> {
+    let mut counts = BTreeMap::new();
+    for patch in day.benchmarks
+        .values()
+        .filter_map(|result| result.as_ref().ok())
+        .flat_map(|patches| patches)
+    {
+        if let Some(machine) = day.machine_class(patch) {
+            *counts.entry(machine).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Like `changes`, but only the significant ones.
+pub fn significant_changes(
+    a: &CommitData,
+    b: &CommitData,
+    stat: &str,
+    selector: Selector,
+    noise: &Noise,
+) -> Vec<Change> {
+    changes(a, b, stat, selector, noise)
+        .into_iter()
+        .filter(|c| c.is_significant())
+        .collect()
+}
+
+/// The category of the benchmark of the patch `name`. Results gathered
+/// before benchmarks declared a category fall back to treating the `issue-*`
+/// stress tests as secondary.
+pub fn category(categories: &BTreeMap<String, Category>, name: &str) -> Category {
+    let benchmark = name.split('@').next().unwrap();
+    match categories.get(benchmark) {
+        Some(&category) => category,
+        None if benchmark.starts_with("issue-") => Category::Secondary,
+        None => Category::Primary,
+    }
+}
+
+/// Which patches a comparison is restricted to, by the category of their
+/// benchmark and by their scenario. An empty list doesn't restrict anything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    pub categories: Vec<Category>,
+    /// Parts of the scenario, the patch name after the `@`: `incr` selects
+    /// `helloworld@020-incr-patched`, but not the plain build `helloworld`
+    pub scenarios: Vec<String>,
+}
+
+impl Filter {
+    /// Whether the patch `name` is selected.
+    pub fn matches(&self, categories: &BTreeMap<String, Category>, name: &str) -> bool {
+        let scenario = name.find('@').map(|idx| &name[idx + 1..]);
+        (self.categories.is_empty() || self.categories.contains(&category(categories, name)))
+            && (self.scenarios.is_empty() || self.scenarios.iter().any(|part| {
+                scenario.map_or(false, |scenario| scenario.contains(&**part))
+            }))
+    }
+
+    /// Whether the benchmark `name` is selected, whatever its scenarios.
+    pub fn matches_benchmark(&self, categories: &BTreeMap<String, Category>, name: &str) -> bool {
+        self.categories.is_empty() || self.categories.contains(&category(categories, name))
+    }
+}
+
+/// The newest commit of each target, if it regressed `instructions:u` of
+/// default builds relative to the commit before it. Commits found on
+/// several targets are only returned once.
+pub fn latest_regressions(data: &InputData) -> Vec<&str> {
+    let no_flags = None;
+    let selector = Selector::new(Profile::default(), Backend::default(), &no_flags);
+    let mut regressions = Vec::new();
+    for commits in data.data.values() {
+        let mut newest = commits.values().rev();
+        let (b, a) = match (newest.next(), newest.next()) {
+            (Some(b), Some(a)) => (b, a),
+            _ => continue,
+        };
+        let changes = significant_changes(a, b, "instructions:u", selector, &data.noise);
+        if Direction::of(&changes, &data.categories) == Some(Direction::Regression)
+            && !regressions.contains(&&*b.commit.sha)
+        {
+            regressions.push(&*b.commit.sha);
+        }
+    }
+    regressions
+}
+
+/// Stable-sorts `changes` so that those to primary benchmarks come first.
+pub fn primary_first(changes: &mut [Change], categories: &BTreeMap<String, Category>) {
+    changes.sort_by_key(|c| category(categories, &c.name) != Category::Primary);
+}
+
+/// Summarizes `changes`, which are expected to be sorted by decreasing
+/// magnitude as returned by `changes`. Insignificant changes are ignored.
+pub fn summarize(changes: &[Change], categories: &BTreeMap<String, Category>) -> Summary {
+    let mut primary_regressions = Vec::new();
+    let mut primary_improvements = Vec::new();
+    let mut secondary = Vec::new();
+    for change in changes.iter().filter(|c| c.is_significant()) {
+        if category(categories, &change.name) != Category::Primary {
+            secondary.push(change.clone());
+        } else if change.is_regression() {
+            primary_regressions.push(change.clone());
+        } else {
+            primary_improvements.push(change.clone());
+        }
+    }
+
+    let verdict = match (primary_regressions.first(), primary_improvements.first()) {
+        (None, None) if secondary.is_empty() => String::from("no relevant changes"),
+        (None, None) => format!(
+            "only secondary benchmarks changed ({})",
+            secondary.len()
+        ),
+        (Some(worst), None) => format!(
+            "regression in {} primary benchmarks (up to {:+.1}%)",
+            primary_regressions.len(),
+            worst.percent
+        ),
+        (None, Some(best)) => format!(
+            "improvement in {} primary benchmarks (up to {:+.1}%)",
+            primary_improvements.len(),
+            best.percent
+        ),
+        (Some(worst), Some(best)) => format!(
+            "mixed: {} primary regressions (up to {:+.1}%) and {} primary improvements \
+             (up to {:+.1}%)",
+            primary_regressions.len(),
+            worst.percent,
+            primary_improvements.len(),
+            best.percent
+        ),
+    };
+
+    Summary {
+        primary_regressions: primary_regressions,
+        primary_improvements: primary_improvements,
+        secondary: secondary,
+        verdict: verdict,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use load::Patch;
+
+    use super::*;
+
+    /// Results of `sha` with an `instructions:u` of `cnt` for each patch,
+    /// measured on the collector of the machine class given with it.
+    fn day(sha: &str, patches: &[(&str, f64, Option<&str>)]) -> CommitData {
+        let mut day: CommitData = serde_json::from_str(&format!(
+            r#"{{
+                "commit": {{ "sha": "{}", "date": "2017-10-01T00:00:00Z" }},
+                "benchmarks": {{}},
+                "triple": "x86_64-unknown-linux-gnu"
+            }}"#,
+            sha
+        )).unwrap();
+        for &(name, cnt, class) in patches {
+            let mut patch: Patch = serde_json::from_str(&format!(
+                r#"{{
+                    "name": "{}",
+                    "runs": [{{ "stats": [{{ "name": "instructions:u", "cnt": {} }}] }}]
+                }}"#,
+                name, cnt
+            )).unwrap();
+            if let Some(class) = class {
+                let machine = serde_json::from_str(&format!(
+                    r#"{{ "class": "{}", "cpu": "", "kernel": "", "mitigations": {{}} }}"#,
+                    class
+                )).unwrap();
+                day.collectors.insert(class.to_lowercase(), machine);
+                patch.collector = Some(class.to_lowercase());
+            }
+            let benchmark = name.split('@').next().unwrap().to_string();
+            day.benchmarks
+                .entry(benchmark)
+                .or_insert_with(|| Ok(Vec::new()))
+                .as_mut()
+                .unwrap()
+                .push(patch);
+        }
+        day
+    }
+
+    #[test]
+    fn common_machine_with_most_results() {
+        let a = day(
+            "aaa",
+            &[("regex@0", 1.0, Some("X")), ("regex@1", 1.0, Some("X")), ("syn@0", 1.0, Some("Y"))],
+        );
+        let b = day("bbb", &[("regex@0", 1.0, Some("Y")), ("syn@0", 1.0, Some("Z"))]);
+        assert_eq!(common_machine(&a, &b).unwrap(), Some("Y"));
+        assert_eq!(common_machine(&a, &a).unwrap(), Some("X"));
+    }
+
+    #[test]
+    fn common_machine_of_unidentified_collectors() {
+        let a = day("aaa", &[("regex@0", 1.0, None)]);
+        let b = day("bbb", &[("regex@0", 1.0, Some("X"))]);
+        assert_eq!(common_machine(&a, &b).unwrap(), None);
+        assert_eq!(common_machine(&b, &a).unwrap(), None);
+    }
+
+    #[test]
+    fn no_changes_across_machine_classes() {
+        let a = day("aaa", &[("regex@0", 100.0, Some("X"))]);
+        let b = day("bbb", &[("regex@0", 110.0, Some("Y"))]);
+        let err = common_machine(&a, &b).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "aaa and bbb have no results from the same class of machines"
+        );
+
+        let flags = None;
+        let selector = Selector::new(Profile::Debug, Backend::Llvm, &flags);
+        let noise = Noise::default();
+        assert!(changes(&a, &b, "instructions:u", selector, &noise).is_empty());
+
+        // Unless a class is chosen for each side
+        let changes = changes_between(
+            &a,
+            selector.on(Some("X")),
+            &b,
+            selector.on(Some("Y")),
+            "instructions:u",
+            &noise,
+        );
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].percent, 10.0);
+    }
+}
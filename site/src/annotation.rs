@@ -0,0 +1,123 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Notes on events that explain step changes in the graphs, such as LLVM
+//! upgrades, changes to the benchmark suite or new hardware.
+//!
+//! Only admins add and remove annotations. They are persisted to the JSON
+//! file named by `SITE_ANNOTATIONS` (`annotations.json` by default).
+
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde_json;
+
+use auth::User;
+use date::Date;
+use errors::*;
+
+pub use api::annotations::Annotation;
+
+/// Longest allowed label, in characters.
+const MAX_LABEL_LEN: usize = 200;
+
+pub struct Annotations {
+    path: PathBuf,
+    annotations: Mutex<Vec<Annotation>>,
+}
+
+impl Annotations {
+    pub fn from_env() -> Result<Annotations> {
+        let path = env::var_os("SITE_ANNOTATIONS")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("annotations.json"));
+        let annotations = if path.exists() {
+            let file =
+                File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+            serde_json::from_reader(file)
+                .chain_err(|| format!("failed to parse {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+        Ok(Annotations {
+            path: path,
+            annotations: Mutex::new(annotations),
+        })
+    }
+
+    /// All annotations, oldest first.
+    pub fn list(&self) -> Vec<Annotation> {
+        self.annotations.lock().unwrap().clone()
+    }
+
+    /// The annotations dated between `start` and `end`, inclusive.
+    pub fn between(&self, start: Date, end: Date) -> Vec<Annotation> {
+        self.annotations
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|annotation| start <= annotation.date && annotation.date <= end)
+            .cloned()
+            .collect()
+    }
+
+    /// Add an annotation on behalf of `user`.
+    pub fn add(
+        &self,
+        date: Date,
+        commit: Option<String>,
+        label: &str,
+        link: Option<String>,
+        user: &User,
+    ) -> Result<Annotation> {
+        if label.trim().is_empty() || label.chars().count() > MAX_LABEL_LEN {
+            bail!("labels must be 1 to {} characters", MAX_LABEL_LEN);
+        }
+        if let Some(ref link) = link {
+            if !link.starts_with("https://") && !link.starts_with("http://") {
+                bail!("links must be http or https URLs");
+            }
+        }
+        let mut annotations = self.annotations.lock().unwrap();
+        let annotation = Annotation {
+            id: annotations.iter().map(|a| a.id + 1).max().unwrap_or(0),
+            date: date,
+            commit: commit,
+            label: label.to_string(),
+            link: link,
+            added_by: user.name.clone(),
+        };
+        annotations.push(annotation.clone());
+        annotations.sort_by_key(|a| a.date);
+        self.save(&annotations)?;
+        Ok(annotation)
+    }
+
+    /// Remove the annotation `id`.
+    pub fn remove(&self, id: u32) -> Result<()> {
+        let mut annotations = self.annotations.lock().unwrap();
+        match annotations.iter().position(|a| a.id == id) {
+            Some(idx) => {
+                annotations.remove(idx);
+            }
+            None => bail!("there is no annotation {}", id),
+        }
+        self.save(&annotations)
+    }
+
+    fn save(&self, annotations: &[Annotation]) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        serde_json::to_writer(&mut File::create(&tmp)?, annotations)?;
+        fs::rename(&tmp, &self.path)
+            .chain_err(|| format!("failed to write annotations {}", self.path.display()))?;
+        Ok(())
+    }
+}
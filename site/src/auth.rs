@@ -0,0 +1,113 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Access control for endpoints that change the site's state.
+//!
+//! Tokens are read from the JSON file named by `SITE_TOKENS`:
+//!
+//! ```json
+//! {
+//!     "<token>": { "name": "rustc-timing webhook", "role": "collector" }
+//! }
+//! ```
+//!
+//! Every authorized request is appended to the audit log (`SITE_AUDIT_LOG`,
+//! `audit.log` by default).
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use serde_json;
+
+use errors::*;
+
+/// Members and collectors may only do what their own role may; admins may
+/// do everything.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// May request benchmarks and manage the queue, e.g. flag experiments
+    Member,
+    /// May announce new data and benchmark queued commits, e.g. the data
+    /// repository's push hook
+    Collector,
+    Admin,
+}
+
+impl Role {
+    /// Whether this role may use the endpoints requiring `role`.
+    pub fn permits(self, role: Role) -> bool {
+        self == Role::Admin || self == role
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    pub role: Role,
+}
+
+pub struct Auth {
+    tokens: HashMap<String, User>,
+    audit_log: PathBuf,
+}
+
+impl Auth {
+    pub fn from_env() -> Result<Auth> {
+        let tokens = match env::var_os("SITE_TOKENS") {
+            Some(path) => {
+                let file = File::open(&path)
+                    .chain_err(|| format!("failed to open {:?}", path))?;
+                serde_json::from_reader(file)
+                    .chain_err(|| format!("failed to parse {:?}", path))?
+            }
+            None => {
+                warn!("SITE_TOKENS not set; all mutating endpoints are disabled");
+                HashMap::new()
+            }
+        };
+        let audit_log = env::var_os("SITE_AUDIT_LOG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("audit.log"));
+        Ok(Auth::new(tokens, audit_log))
+    }
+
+    pub fn new(tokens: HashMap<String, User>, audit_log: PathBuf) -> Auth {
+        Auth {
+            tokens: tokens,
+            audit_log: audit_log,
+        }
+    }
+
+    /// The user owning `token`, if they have at least `role`.
+    pub fn authorize(&self, token: Option<&str>, role: Role) -> Option<&User> {
+        match token.and_then(|token| self.tokens.get(token)) {
+            Some(user) if user.role.permits(role) => Some(user),
+            _ => None,
+        }
+    }
+
+    /// Record that `user` performed `action`.
+    pub fn audit(&self, user: &User, action: &str) {
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.audit_log)
+            .and_then(|mut log| {
+                writeln!(log, "{}\t{}\t{}", Utc::now().to_rfc3339(), user.name, action)
+            });
+        if let Err(err) = result {
+            error!("failed to write audit log {}: {:?}", self.audit_log.display(), err);
+        }
+    }
+}
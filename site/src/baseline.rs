@@ -0,0 +1,123 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Named commits to compare against, e.g. `1.70 release`, so that working
+//! groups can follow their cumulative progress since a fixed reference
+//! instead of only the changes between adjacent commits.
+//!
+//! A baseline belongs to the user or team whose token set it; only they and
+//! admins can move or remove it. Baselines are persisted to the JSON file
+//! named by `SITE_BASELINES` (`baselines.json` by default).
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde_json;
+
+use auth::{Role, User};
+use errors::*;
+
+pub use api::baselines::Baseline;
+
+/// Longest allowed baseline name, in characters.
+const MAX_NAME_LEN: usize = 64;
+
+pub struct Baselines {
+    path: PathBuf,
+    baselines: Mutex<BTreeMap<String, Baseline>>,
+}
+
+impl Baselines {
+    pub fn from_env() -> Result<Baselines> {
+        let path = env::var_os("SITE_BASELINES")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("baselines.json"));
+        Baselines::open(path)
+    }
+
+    /// The baselines persisted to `path`, none if there is no such file yet.
+    pub fn open(path: PathBuf) -> Result<Baselines> {
+        let baselines = if path.exists() {
+            let file =
+                File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+            serde_json::from_reader(file)
+                .chain_err(|| format!("failed to parse {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Baselines {
+            path: path,
+            baselines: Mutex::new(baselines),
+        })
+    }
+
+    pub fn list(&self) -> BTreeMap<String, Baseline> {
+        self.baselines.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Baseline> {
+        self.baselines.lock().unwrap().get(name).cloned()
+    }
+
+    /// Point the baseline `name` at the commit `sha` on behalf of `user`, or
+    /// remove it if `sha` is `None`. Returns the baseline as set.
+    pub fn set(
+        &self,
+        name: &str,
+        sha: Option<String>,
+        user: &User,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Baseline>> {
+        if name.trim().is_empty() || name.chars().count() > MAX_NAME_LEN
+            || name.chars().any(|c| c.is_control())
+        {
+            bail!(
+                "baseline names must be 1 to {} characters, without control characters",
+                MAX_NAME_LEN
+            );
+        }
+        let mut baselines = self.baselines.lock().unwrap();
+        if let Some(existing) = baselines.get(name) {
+            if existing.owner != user.name && user.role != Role::Admin {
+                bail!("baseline `{}` belongs to {}", name, existing.owner);
+            }
+        }
+        let baseline = match sha {
+            Some(sha) => {
+                let baseline = Baseline {
+                    sha: sha,
+                    owner: user.name.clone(),
+                    set: now,
+                };
+                baselines.insert(name.to_string(), baseline.clone());
+                Some(baseline)
+            }
+            None => {
+                if baselines.remove(name).is_none() {
+                    bail!("there is no baseline `{}`", name);
+                }
+                None
+            }
+        };
+        self.save(&baselines)?;
+        Ok(baseline)
+    }
+
+    fn save(&self, baselines: &BTreeMap<String, Baseline>) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        serde_json::to_writer(&mut File::create(&tmp)?, baselines)?;
+        fs::rename(&tmp, &self.path)
+            .chain_err(|| format!("failed to write baselines {}", self.path.display()))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Cache of the responses of endpoints that only depend on the request and
+//! the data, such as the graphs of the front page, which are expensive to
+//! compute and requested over and over with the same settings.
+//!
+//! Responses are kept in memory, serialized, until the data is reloaded or a
+//! baseline changes. Insertions happen while the data is locked for reading
+//! and clearing while it is locked for writing, so no response computed from
+//! old data survives a reload.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// More distinct requests than the front page and compare page make with
+/// all combinations of their settings.
+const MAX_ENTRIES: usize = 1024;
+
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    /// By endpoint and request body
+    entries: Mutex<HashMap<(String, Vec<u8>), Arc<String>>>,
+}
+
+impl ResponseCache {
+    pub fn get(&self, path: &str, request: &[u8]) -> Option<Arc<String>> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(&(path.to_string(), request.to_vec())).cloned()
+    }
+
+    pub fn insert(&self, path: &str, request: Vec<u8>, response: String) -> Arc<String> {
+        let response = Arc::new(response);
+        let mut entries = self.entries.lock().unwrap();
+        // Only unusual requests miss once the cache is full, so there's no
+        // need to keep track of which entries are popular.
+        if entries.len() >= MAX_ENTRIES {
+            entries.clear();
+        }
+        entries.insert((path.to_string(), request), response.clone());
+        response
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
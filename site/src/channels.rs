@@ -0,0 +1,226 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The master commits the current stable, beta and nightly releases were
+//! built from, so that their results can be put side by side.
+//!
+//! The channel manifests on static.rust-lang.org name the commit each
+//! release was built from. Nightlies are built from master, but stable and
+//! beta releases are built from release branches, whose commits are never
+//! benchmarked. They are represented by the master commit their branch was
+//! cut from instead: that of the last nightly with the same version. It is
+//! found by a binary search over the dated nightly manifests, which takes a
+//! few requests once per release.
+//!
+//! The releases are looked up when new results arrive, at most once an
+//! hour, and persisted with the branch points to the JSON file named by
+//! `SITE_CHANNELS` (`channels.json` by default).
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{self, DateTime, NaiveDate, Utc};
+use reqwest::{self, StatusCode};
+use rust_sysroot::git::Commit as GitCommit;
+use serde_json;
+
+use errors::*;
+
+/// The channels, oldest release first.
+pub const CHANNELS: &[&str] = &["stable", "beta", "nightly"];
+
+/// How often to look for new releases, in seconds.
+const CHECK_EVERY: i64 = 60 * 60;
+
+/// How far before a stable or beta release its branch point is searched
+/// for, in days. Releases branch off master at most 12 weeks before they
+/// become stable.
+const SEARCH_DAYS: i64 = 180;
+
+/// The current release of a channel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Release {
+    pub channel: String,
+    /// e.g. `1.25.0` or `1.27.0-nightly`
+    pub version: String,
+    /// The master commit the release was built or branched from, in full if
+    /// it is among the master commits, abbreviated otherwise
+    pub sha: String,
+    /// Date of the master commit, if it is among the master commits
+    pub date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    checked: Option<DateTime<Utc>>,
+    releases: Vec<Release>,
+    /// Minor version -> abbreviated hash of the master commit its release
+    /// branch was cut from
+    branch_points: BTreeMap<String, String>,
+}
+
+pub struct Channels {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl Channels {
+    pub fn from_env() -> Result<Channels> {
+        let path = env::var_os("SITE_CHANNELS")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("channels.json"));
+        let state = if path.exists() {
+            let file =
+                File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+            serde_json::from_reader(file)
+                .chain_err(|| format!("failed to parse {}", path.display()))?
+        } else {
+            State::default()
+        };
+        Ok(Channels {
+            path: path,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// The current releases, in the order of `CHANNELS`; empty until they
+    /// were looked up.
+    pub fn releases(&self) -> Vec<Release> {
+        self.state.lock().unwrap().releases.clone()
+    }
+
+    /// Look up the current releases unless that was done within the last
+    /// hour, resolving their commits among the master `commits`.
+    pub fn refresh(&self, commits: &[GitCommit]) -> Result<()> {
+        let (checked, mut branch_points) = {
+            let state = self.state.lock().unwrap();
+            (state.checked, state.branch_points.clone())
+        };
+        let now = Utc::now();
+        let check_every = chrono::Duration::seconds(CHECK_EVERY);
+        if checked.map_or(false, |checked| now - checked < check_every) {
+            return Ok(());
+        }
+
+        // Not holding the lock while waiting for static.rust-lang.org.
+        let mut releases = Vec::new();
+        for &channel in CHANNELS {
+            let url = format!("https://static.rust-lang.org/dist/channel-rust-{}.toml", channel);
+            let (version, sha) = match dist_rustc(&url)? {
+                Some(rustc) => rustc,
+                None => bail!("no {} release", channel),
+            };
+            let sha = if channel == "nightly" {
+                sha
+            } else {
+                let minor = minor_version(&version)?;
+                let key = minor.to_string();
+                if !branch_points.contains_key(&key) {
+                    let branch_point = branch_point(minor, now.naive_utc().date())?;
+                    info!("1.{} branched off master at {}", minor, branch_point);
+                    branch_points.insert(key.clone(), branch_point);
+                }
+                branch_points[&key].clone()
+            };
+            let commit = commits.iter().find(|c| c.sha.starts_with(&*sha));
+            releases.push(Release {
+                channel: channel.to_string(),
+                version: version.split_whitespace().next().unwrap_or("").to_string(),
+                sha: commit.map_or(sha.clone(), |c| c.sha.clone()),
+                date: commit.map(|c| c.date),
+            });
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.checked = Some(now);
+        state.releases = releases;
+        state.branch_points = branch_points;
+        let tmp = self.path.with_extension("tmp");
+        serde_json::to_writer(&mut File::create(&tmp)?, &*state)?;
+        fs::rename(&tmp, &self.path)
+            .chain_err(|| format!("failed to write channels {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// The version and abbreviated commit hash of the rustc in the channel
+/// manifest at `url`, or `None` if there is no such manifest. The manifest
+/// has them in the `version` of `[pkg.rustc]`, e.g.
+/// `1.25.0-nightly (0b17b4cd1 2018-01-21)`.
+pub fn dist_rustc(url: &str) -> Result<Option<(String, String)>> {
+    let mut response = reqwest::get(url)?;
+    if *response.status() == StatusCode::NotFound {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        bail!("{} returned {}", url, response.status());
+    }
+    let mut manifest = String::new();
+    response.read_to_string(&mut manifest)?;
+    let version = manifest
+        .lines()
+        .skip_while(|line| line.trim() != "[pkg.rustc]")
+        .find(|line| line.starts_with("version = "))
+        .map(|line| line["version = ".len()..].trim_matches('"'));
+    let sha = version
+        .and_then(|version| version.split('(').nth(1))
+        .and_then(|rest| rest.split_whitespace().next());
+    match (version, sha) {
+        (Some(version), Some(sha)) => Ok(Some((version.to_string(), sha.to_string()))),
+        _ => bail!("no rustc version in {}", url),
+    }
+}
+
+/// The minor version of a version like `1.25.0-beta.3 (...)`.
+fn minor_version(version: &str) -> Result<u32> {
+    version
+        .split('.')
+        .nth(1)
+        .and_then(|minor| minor.parse().ok())
+        .ok_or_else(|| format!("unexpected rustc version `{}`", version).into())
+}
+
+/// The minor version and commit of the nightly of `date`, or of the newest
+/// one in the week before if there was none that day.
+fn nightly_before(date: NaiveDate) -> Result<(u32, String)> {
+    for days in 0..7 {
+        let day = date - chrono::Duration::days(days);
+        let url = format!("https://static.rust-lang.org/dist/{}/channel-rust-nightly.toml", day);
+        if let Some((version, sha)) = dist_rustc(&url)? {
+            return Ok((minor_version(&version)?, sha));
+        }
+    }
+    bail!("no nightly in the week before {}", date)
+}
+
+/// The commit of the last nightly of minor version `minor`, which its
+/// release branch was cut from. Nightly versions only ever go up, so this is
+/// a binary search for the first nightly of a newer version.
+fn branch_point(minor: u32, today: NaiveDate) -> Result<String> {
+    let (mut older, mut newer) = (today - chrono::Duration::days(SEARCH_DAYS), today);
+    if nightly_before(older)?.0 > minor {
+        bail!("1.{} branched off more than {} days ago", minor, SEARCH_DAYS);
+    }
+    if nightly_before(newer)?.0 <= minor {
+        bail!("1.{} hasn't branched off yet", minor);
+    }
+    while newer - older > chrono::Duration::days(1) {
+        let mid = older + (newer - older) / 2;
+        if nightly_before(mid)?.0 > minor {
+            newer = mid;
+        } else {
+            older = mid;
+        }
+    }
+    Ok(nightly_before(older)?.1)
+}
@@ -7,4 +7,4 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-pub use collector::{Date, DeltaTime, End, OptionalDate, Start};
+pub use collector::{Bound, Date, DeltaTime, End, OptionalDate, Start};
@@ -0,0 +1,215 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Synthetic results, for working on the site without a copy of the
+//! production data repository.
+//!
+//! `site --dev-seed <dir>` fills `<dir>`, unless it has results already,
+//! with a data repository of generated commits: one a day for the last
+//! `COMMITS` days, each with results of a few made-up primary and secondary
+//! benchmarks. The series are noisy, and now and then a commit regresses or
+//! improves some of the benchmarks for good, so that the graphs, the compare
+//! page, triage reports and the feed have something to show.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::path::Path;
+
+use chrono::{Duration, Utc};
+use serde_json;
+
+use api;
+use collector::{AppliedMigration, MIGRATIONS};
+use errors::*;
+use load::{Backend, BenchmarkInfo, Category, Commit, CommitData, Name, Patch, Profile, Run, Stat,
+           Std};
+use date::Date;
+
+/// How many commits to generate.
+const COMMITS: i64 = 90;
+
+/// Name, category and millions of instructions of the full build.
+const BENCHMARKS: &[(&str, Category, f64)] = &[
+    ("helloworld", Category::Primary, 120.0),
+    ("regex", Category::Primary, 9_500.0),
+    ("syn", Category::Primary, 21_000.0),
+    ("serde", Category::Primary, 15_000.0),
+    ("deep-vector", Category::Secondary, 4_200.0),
+    ("coercions", Category::Secondary, 2_100.0),
+    ("unify-linearly", Category::Secondary, 1_300.0),
+];
+
+/// Suffix of each patch and its instructions relative to the full build.
+const PATCHES: &[(&str, f64)] = &[
+    ("", 1.0),
+    ("@010-incr-unchanged", 0.35),
+    ("@020-incr-patched", 0.55),
+];
+
+/// How many iterations each build has.
+const ITERATIONS: usize = 3;
+
+/// A xorshift generator. The data only has to look plausible, and the same
+/// seed always gives the same repository.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniformly distributed in `[0, 1)`.
+    fn float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// `1.0`, give or take up to `spread`.
+    fn noise(&mut self, spread: f64) -> f64 {
+        1.0 + spread * (2.0 * self.float() - 1.0)
+    }
+}
+
+/// Generate a data repository in `dir`, unless it has results already.
+pub fn generate(dir: &Path) -> Result<()> {
+    let times = dir.join("times");
+    if times.exists() {
+        info!("{} has results already, not generating any", dir.display());
+        return Ok(());
+    }
+    fs::create_dir_all(&times).chain_err(|| format!("failed to create {}", times.display()))?;
+    info!("generating {} commits of synthetic results in {}", COMMITS, dir.display());
+
+    let now = Utc::now();
+    // In the collector's current layout, so that no migrations are pending.
+    let migrations = MIGRATIONS
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| AppliedMigration {
+            version: idx + 1,
+            name: name.to_string(),
+            applied: Date(now),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(File::create(dir.join("migrations.json"))?, &migrations)?;
+
+    let target = api::default_target();
+    let metrics = ["instructions:u", "cycles:u", "wall-time", "max-rss"]
+        .iter()
+        .map(|metric| metric.to_string())
+        .collect::<BTreeSet<_>>();
+    let benchmark_info = BENCHMARKS
+        .iter()
+        .map(|&(name, _, instructions)| {
+            let info = BenchmarkInfo {
+                description: Some(format!("Synthetic benchmark `{}`", name)),
+                crates: Some(1),
+                lines: (instructions / 10.0) as usize,
+                patches: PATCHES[1..].iter().map(|p| p.0[1..].to_string()).collect(),
+            };
+            (name.to_string(), info)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    // Each benchmark's level relative to its first commit
+    let mut levels = vec![1.0; BENCHMARKS.len()];
+    for day in (0..COMMITS).rev() {
+        let commit = Commit {
+            sha: (0..5).map(|_| format!("{:08x}", rng.next() as u32)).collect(),
+            date: Date(now - Duration::days(day)),
+        };
+        // About one commit in ten changes some benchmarks for good, more
+        // often for the worse.
+        if rng.float() < 0.1 {
+            let size = 0.01 + 0.04 * rng.float();
+            let factor = if rng.float() < 0.6 { 1.0 + size } else { 1.0 - size };
+            for level in &mut levels {
+                if rng.float() < 0.5 {
+                    *level *= factor;
+                }
+            }
+        }
+
+        let mut benchmarks = BTreeMap::new();
+        for (&(name, _, instructions), level) in BENCHMARKS.iter().zip(&levels) {
+            let patches = PATCHES
+                .iter()
+                .map(|&(patch, share)| Patch {
+                    name: Name::from(format!("{}{}", name, patch)),
+                    backend: Backend::default(),
+                    flags: None,
+                    custom_profile: None,
+                    profile: Profile::default(),
+                    std: Std::default(),
+                    runs: vec![Run {
+                        stats: stats(&mut rng, instructions * 1e6 * share * level),
+                    }],
+                    queries: Vec::new(),
+                    collector: None,
+                    timings: Vec::new(),
+                    llvm_passes: Vec::new(),
+                    anomalies: Vec::new(),
+                })
+                .collect();
+            benchmarks.insert(name.to_string(), Ok(patches));
+        }
+        let data = CommitData {
+            commit: commit.clone(),
+            benchmarks: benchmarks,
+            triple: target.clone(),
+            manifest: None,
+            metrics: Some(metrics.clone()),
+            categories: BENCHMARKS
+                .iter()
+                .map(|&(name, category, _)| (name.to_string(), category))
+                .collect(),
+            benchmark_info: benchmark_info.clone(),
+            collectors: BTreeMap::new(),
+            failures: BTreeMap::new(),
+        };
+        let path = times.join(format!("{}-{}-{}.json", commit.date, commit.sha, target));
+        serde_json::to_writer(File::create(&path)?, &data)
+            .chain_err(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The statistics of a build executing about `instructions` instructions.
+fn stats(rng: &mut Rng, instructions: f64) -> Vec<Stat> {
+    // Name, value per instruction and noise
+    let metrics = [
+        ("instructions:u", 1.0, 0.002),
+        ("cycles:u", 0.8, 0.01),
+        ("wall-time", 0.8 / 3e9, 0.03),
+        ("max-rss", 0.002, 0.01),
+    ];
+    metrics
+        .iter()
+        .map(|&(name, per_instruction, spread)| {
+            let mut samples = (0..ITERATIONS)
+                .map(|_| instructions * per_instruction * rng.noise(spread))
+                .collect::<Vec<_>>();
+            samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            // Like the collector, the median of noisy statistics.
+            let cnt = if name == "wall-time" {
+                samples[ITERATIONS / 2]
+            } else {
+                samples[0]
+            };
+            Stat {
+                name: Name::from(name),
+                cnt: cnt,
+                samples: samples,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,152 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Results that are known to be bad, e.g. because the machine hiccuped
+//! during the run, and are left out of analysis and graphs.
+//!
+//! Only admins add and remove exclusions. They are persisted to the JSON
+//! file named by `SITE_EXCLUSIONS` (`exclusions.json` by default). The
+//! results stay in the data repository; they are only dropped from the data
+//! the site loaded into memory.
+
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use serde_json;
+
+use auth::User;
+use errors::*;
+use load::InputData;
+
+pub use api::exclusions::Exclusion;
+
+/// Longest allowed reason, in characters.
+const MAX_REASON_LEN: usize = 200;
+
+/// Drop the results `exclusion` excludes from `data`, returning how many
+/// commits' results were affected.
+fn exclude(exclusion: &Exclusion, data: &mut InputData) -> usize {
+    let mut affected = 0;
+    for (triple, commits) in &mut data.data {
+        if exclusion.target.as_ref().map_or(false, |target| target != triple) {
+            continue;
+        }
+        let commit = match commits.keys().find(|commit| commit.sha == exclusion.commit) {
+            Some(commit) => commit.clone(),
+            None => continue,
+        };
+        match exclusion.benchmark {
+            Some(ref benchmark) => {
+                let day = commits.get_mut(&commit).unwrap();
+                if day.benchmarks.remove(benchmark).is_some() {
+                    affected += 1;
+                }
+            }
+            None => {
+                commits.remove(&commit);
+                affected += 1;
+            }
+        }
+    }
+    affected
+}
+
+pub struct Exclusions {
+    path: PathBuf,
+    exclusions: Mutex<Vec<Exclusion>>,
+}
+
+impl Exclusions {
+    pub fn from_env() -> Result<Exclusions> {
+        let path = env::var_os("SITE_EXCLUSIONS")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("exclusions.json"));
+        let exclusions = if path.exists() {
+            let file =
+                File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+            serde_json::from_reader(file)
+                .chain_err(|| format!("failed to parse {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+        Ok(Exclusions {
+            path: path,
+            exclusions: Mutex::new(exclusions),
+        })
+    }
+
+    /// All exclusions, oldest first.
+    pub fn list(&self) -> Vec<Exclusion> {
+        self.exclusions.lock().unwrap().clone()
+    }
+
+    /// Drop the results of all exclusions from `data`.
+    pub fn apply(&self, data: &mut InputData) {
+        for exclusion in self.exclusions.lock().unwrap().iter() {
+            exclude(exclusion, data);
+        }
+    }
+
+    /// Exclude the results of `benchmark`, or all results, of `commit` on
+    /// `target`, or on all targets, on behalf of `user`, and drop them from
+    /// `data`.
+    pub fn add(
+        &self,
+        data: &mut InputData,
+        commit: &str,
+        target: Option<String>,
+        benchmark: Option<String>,
+        reason: &str,
+        user: &User,
+    ) -> Result<Exclusion> {
+        if reason.trim().is_empty() || reason.chars().count() > MAX_REASON_LEN {
+            bail!("reasons must be 1 to {} characters", MAX_REASON_LEN);
+        }
+        let mut exclusions = self.exclusions.lock().unwrap();
+        let exclusion = Exclusion {
+            id: exclusions.iter().map(|e| e.id + 1).max().unwrap_or(0),
+            commit: commit.to_string(),
+            target: target,
+            benchmark: benchmark,
+            reason: reason.to_string(),
+            added_by: user.name.clone(),
+            added: Utc::now(),
+        };
+        if exclude(&exclusion, data) == 0 {
+            bail!("no such results of {} to exclude", commit);
+        }
+        exclusions.push(exclusion.clone());
+        self.save(&exclusions)?;
+        Ok(exclusion)
+    }
+
+    /// Remove the exclusion `id`. Its results are back once the data is
+    /// reloaded.
+    pub fn remove(&self, id: u32) -> Result<()> {
+        let mut exclusions = self.exclusions.lock().unwrap();
+        match exclusions.iter().position(|e| e.id == id) {
+            Some(idx) => {
+                exclusions.remove(idx);
+            }
+            None => bail!("there is no exclusion {}", id),
+        }
+        self.save(&exclusions)
+    }
+
+    fn save(&self, exclusions: &[Exclusion]) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        serde_json::to_writer(&mut File::create(&tmp)?, exclusions)?;
+        fs::rename(&tmp, &self.path)
+            .chain_err(|| format!("failed to write exclusions {}", self.path.display()))?;
+        Ok(())
+    }
+}
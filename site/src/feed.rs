@@ -0,0 +1,115 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An Atom feed of the regressions on master, to subscribe to instead of
+//! watching the graphs or GitHub comments.
+//!
+//! Each commit that regressed the benchmarks compared to the commit before
+//! it, as decided for triage reports, is an entry with the summary of the
+//! changes. Links point to the site at `SITE_URL`.
+
+use std::env;
+use std::fmt::Write;
+
+use analysis::{self, Direction};
+use load::{Backend, InputData, Profile};
+use server::Selector;
+
+/// How many of the newest commits are checked for regressions.
+const CHECKED_COMMITS: usize = 200;
+
+/// The feed of regressions of `stat` on `target`, newest first.
+pub fn atom(data: &InputData, stat: &str, target: &str) -> String {
+    let site_url =
+        env::var("SITE_URL").unwrap_or_else(|_| String::from("https://perf.rust-lang.org"));
+    let site_url = site_url.trim_right_matches('/');
+    let no_flags = None;
+    let selector = Selector::new(Profile::default(), Backend::default(), &no_flags);
+
+    let commits = data.data
+        .get(target)
+        .map(|commits| commits.values().rev().take(CHECKED_COMMITS + 1).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let mut entries = String::new();
+    let mut updated = None;
+    for pair in commits.windows(2) {
+        let (b, a) = (pair[0], pair[1]);
+        let mut changes = analysis::significant_changes(a, b, stat, selector, &data.noise);
+        analysis::primary_first(&mut changes, &data.categories);
+        match Direction::of(&changes, &data.categories) {
+            Some(Direction::Regression) | Some(Direction::Mixed) => {}
+            _ => continue,
+        }
+        let summary = analysis::summarize(&changes, &data.categories);
+        let date = b.commit.date.0.to_rfc3339();
+        updated = updated.or_else(|| Some(date.clone()));
+        let link = format!(
+            "{}/compare.html?commit_a={}&commit_b={}&stat={}",
+            site_url,
+            a.commit.sha,
+            b.commit.sha,
+            stat
+        );
+
+        let mut content = format!("{}\n", summary.verdict);
+        for change in summary
+            .primary_regressions
+            .iter()
+            .chain(&summary.primary_improvements)
+            .chain(&summary.secondary)
+        {
+            writeln!(content, "{}: {:+.1}%", change.name, change.percent).unwrap();
+        }
+        writeln!(entries, "<entry>").unwrap();
+        writeln!(
+            entries,
+            "<title>{}: {}</title>",
+            &b.commit.sha[..b.commit.sha.len().min(8)],
+            escape(&summary.verdict)
+        ).unwrap();
+        writeln!(entries, "<id>{}</id>", escape(&link)).unwrap();
+        writeln!(entries, "<link href=\"{}\"/>", escape(&link)).unwrap();
+        writeln!(entries, "<updated>{}</updated>", date).unwrap();
+        writeln!(entries, "<content type=\"text\">{}</content>", escape(&content)).unwrap();
+        writeln!(entries, "</entry>").unwrap();
+    }
+
+    let mut feed = String::new();
+    writeln!(feed, "<?xml version=\"1.0\" encoding=\"utf-8\"?>").unwrap();
+    writeln!(feed, "<feed xmlns=\"http://www.w3.org/2005/Atom\">").unwrap();
+    writeln!(
+        feed,
+        "<title>rustc performance regressions ({}, {})</title>",
+        escape(stat),
+        escape(target)
+    ).unwrap();
+    writeln!(
+        feed,
+        "<id>{}/perf/feed.xml?stat={}&amp;target={}</id>",
+        escape(site_url),
+        escape(stat),
+        escape(target)
+    ).unwrap();
+    writeln!(feed, "<link href=\"{}/index.html\"/>", escape(site_url)).unwrap();
+    writeln!(
+        feed,
+        "<updated>{}</updated>",
+        updated.unwrap_or_else(|| data.last_date.0.to_rfc3339())
+    ).unwrap();
+    feed.push_str(&entries);
+    writeln!(feed, "</feed>").unwrap();
+    feed
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,166 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The pages, scripts, stylesheets and fonts of the site.
+//!
+//! They are embedded into the binary when it is built (see `build.rs`), so
+//! the site serves them wherever it was started from. Scripts and
+//! stylesheets are also served under names containing a hash of their
+//! contents, e.g. `perf.3f2a9c1e07b5d864.css`, and the pages are rewritten
+//! to refer to those. As such a name changes with the contents, browsers may
+//! cache the file for a year. Everything else is sent with the hash as its
+//! ETag and revalidated on every use.
+//!
+//! For working on the frontend, the site can be started with
+//! `--frontend-dir site/static` to serve the files from that directory
+//! instead, read anew for every request and without hashed names.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use hyper::mime::{self, Mime};
+
+use errors::*;
+
+mod embedded {
+    include!(concat!(env!("OUT_DIR"), "/static_files.rs"));
+}
+
+/// A file of the frontend.
+#[derive(Debug, Clone)]
+pub struct Asset {
+    pub body: Vec<u8>,
+    pub content_type: Mime,
+    /// Hash of `body`, as hex
+    pub hash: String,
+    /// Whether the file is requested under its hashed name, so that it never
+    /// changes
+    pub immutable: bool,
+}
+
+pub struct Frontend {
+    source: Source,
+}
+
+enum Source {
+    /// Path -> file, with the hashed names of scripts and stylesheets
+    Embedded(HashMap<String, Asset>),
+    Dir(PathBuf),
+}
+
+impl Frontend {
+    /// The files embedded at build time.
+    pub fn embedded() -> Frontend {
+        let hashed = embedded::FILES
+            .iter()
+            .filter(|&&(name, _)| has_hashed_name(name))
+            .map(|&(name, body)| (name, hashed_name(name, &hash(body))))
+            .collect::<Vec<_>>();
+        let mut assets = HashMap::new();
+        for &(name, body) in embedded::FILES {
+            let body = if name.ends_with(".html") {
+                let mut page = String::from_utf8(body.to_vec()).expect("non-UTF-8 page");
+                for &(file, ref hashed) in &hashed {
+                    page = page.replace(&format!("\"{}\"", file), &format!("\"{}\"", hashed));
+                }
+                page.into_bytes()
+            } else {
+                body.to_vec()
+            };
+            let asset = Asset {
+                content_type: content_type(name),
+                hash: hash(&body),
+                body: body,
+                immutable: false,
+            };
+            if has_hashed_name(name) {
+                let mut immutable = asset.clone();
+                immutable.immutable = true;
+                assets.insert(hashed_name(name, &asset.hash), immutable);
+            }
+            // Under its plain name too, for pages cached before it changed.
+            assets.insert(name.to_string(), asset);
+        }
+        Frontend {
+            source: Source::Embedded(assets),
+        }
+    }
+
+    /// The files in `dir`, as they are when requested.
+    pub fn from_dir(dir: PathBuf) -> Result<Frontend> {
+        if !dir.join("index.html").is_file() {
+            bail!("{} has no index.html", dir.display());
+        }
+        Ok(Frontend {
+            source: Source::Dir(dir),
+        })
+    }
+
+    /// The file requested with `path`, e.g. `/perf.css`; `/` is the index.
+    pub fn get(&self, path: &str) -> Option<Asset> {
+        let name = match path.trim_left_matches('/') {
+            "" => "index.html",
+            name => name,
+        };
+        match self.source {
+            Source::Embedded(ref assets) => assets.get(name).cloned(),
+            Source::Dir(ref dir) => {
+                if name.split('/').any(|part| part == "." || part == "..") {
+                    return None;
+                }
+                let mut body = Vec::new();
+                File::open(dir.join(name))
+                    .and_then(|mut file| file.read_to_end(&mut body))
+                    .ok()?;
+                Some(Asset {
+                    content_type: content_type(name),
+                    hash: hash(&body),
+                    body: body,
+                    immutable: false,
+                })
+            }
+        }
+    }
+}
+
+/// Whether the file named `name` is served under a hashed name; those the
+/// pages refer to.
+fn has_hashed_name(name: &str) -> bool {
+    name.ends_with(".js") || name.ends_with(".css")
+}
+
+/// `libs/fetch.js` with hash `hash` is `libs/fetch.<hash>.js`.
+fn hashed_name(name: &str, hash: &str) -> String {
+    match name.rfind('.') {
+        Some(dot) => format!("{}.{}{}", &name[..dot], hash, &name[dot..]),
+        None => format!("{}.{}", name, hash),
+    }
+}
+
+fn content_type(name: &str) -> Mime {
+    match name.rsplit('.').next() {
+        Some("html") => mime::TEXT_HTML_UTF_8,
+        Some("css") => mime::TEXT_CSS,
+        Some("js") => mime::TEXT_JAVASCRIPT,
+        Some("woff") => "font/woff".parse().unwrap(),
+        _ => mime::APPLICATION_OCTET_STREAM,
+    }
+}
+
+/// 64-bit FNV-1a hash of `data`, as hex.
+fn hash(data: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in data {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
@@ -0,0 +1,196 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The parts of the GitHub API the site uses, for rust-lang/rust.
+//!
+//! Requests are authenticated with `GITHUB_TOKEN` if it is set; without it,
+//! GitHub allows 60 requests an hour.
+
+use std::env;
+
+use crypto::hmac::Hmac;
+use crypto::mac::{Mac, MacResult};
+use crypto::sha1::Sha1;
+use hex::FromHex;
+use reqwest::Client;
+use reqwest::header::{Authorization, UserAgent};
+use serde::de::DeserializeOwned;
+
+use errors::*;
+
+pub use api::days::RollupPr;
+
+const API: &'static str = "https://api.github.com/repos/rust-lang/rust";
+
+#[derive(Debug, Deserialize)]
+struct CommitResponse {
+    parents: Vec<Parent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Parent {
+    sha: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Comparison {
+    commits: Vec<ComparedCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ComparedCommit {
+    commit: CommitMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitMessage {
+    message: String,
+}
+
+/// The pull requests rolled up into the bors merge `sha`, or an empty list
+/// if it didn't merge a rollup.
+///
+/// A rollup branch merges each of its pull requests with a commit titled
+/// `Rollup merge of #N - user:branch, r=reviewer`, followed by the title of
+/// the pull request. These are the commits between the merge's first
+/// parent, the previous master commit, and the merge itself.
+pub fn rollup_prs(sha: &str) -> Result<Vec<RollupPr>> {
+    let commit: CommitResponse = get(&format!("{}/commits/{}", API, sha))?;
+    let parent = match commit.parents.first() {
+        Some(parent) => parent,
+        None => return Ok(Vec::new()),
+    };
+    let comparison: Comparison = get(&format!("{}/compare/{}...{}", API, parent.sha, sha))?;
+    Ok(comparison
+        .commits
+        .iter()
+        .filter_map(|c| rollup_merge(&c.commit.message))
+        .collect())
+}
+
+fn rollup_merge(message: &str) -> Option<RollupPr> {
+    let mut lines = message.lines();
+    let header = lines.next()?;
+    if !header.starts_with("Rollup merge of #") {
+        return None;
+    }
+    let number = pr_number(&header["Rollup merge of #".len()..])?;
+    Some(RollupPr {
+        number: number,
+        title: lines
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty())
+            .unwrap_or("")
+            .to_string(),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct Comment<'a> {
+    body: &'a str,
+}
+
+/// Comment `body` on issue `number` of `repo`, e.g. `rust-lang/rustc-perf`.
+/// Needs `GITHUB_TOKEN`.
+pub fn post_comment(repo: &str, number: u64, body: &str) -> Result<()> {
+    let token = env::var("GITHUB_TOKEN").chain_err(|| "commenting needs GITHUB_TOKEN")?;
+    let url = format!("https://api.github.com/repos/{}/issues/{}/comments", repo, number);
+    let response = Client::new()?
+        .post(&url)
+        .header(UserAgent(String::from("rustc-perf")))
+        .header(Authorization(format!("token {}", token)))
+        .json(&Comment { body: body })
+        .send()?;
+    if !response.status().is_success() {
+        bail!("{} returned {}", url, response.status());
+    }
+    Ok(())
+}
+
+fn get<T: DeserializeOwned>(url: &str) -> Result<T> {
+    let client = Client::new()?;
+    let mut request = client
+        .get(url)
+        .header(UserAgent(String::from("rustc-perf")));
+    if let Ok(token) = env::var("GITHUB_TOKEN") {
+        request = request.header(Authorization(format!("token {}", token)));
+    }
+    let mut response = request.send()?;
+    if !response.status().is_success() {
+        bail!("{} returned {}", url, response.status());
+    }
+    Ok(response.json()?)
+}
+
+/// The number of the pull request tested by a bors merge with the summary
+/// `summary`, e.g. `Auto merge of #45123 - user:branch, r=reviewer`.
+pub fn bors_pr(summary: &str) -> Option<u64> {
+    if !summary.starts_with("Auto merge of #") {
+        return None;
+    }
+    pr_number(&summary["Auto merge of #".len()..])
+}
+
+/// The author of the pull request merged by bors with the summary
+/// `summary`, and who approved it, e.g. `user` and `reviewer` for
+/// `Auto merge of #45123 - user:branch, r=reviewer`.
+pub fn bors_people(summary: &str) -> Option<(String, Option<String>)> {
+    bors_pr(summary)?;
+    let rest = &summary[summary.find(" - ")? + " - ".len()..];
+    let author = rest.split(':').next()?.trim();
+    if author.is_empty() {
+        return None;
+    }
+    let reviewer = rest.find("r=").map(|idx| {
+        rest[idx + "r=".len()..]
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .next()
+            .unwrap()
+            .to_string()
+    });
+    Some((author.to_string(), reviewer))
+}
+
+/// Whether the bors merge with the summary `summary` merged a rollup, whose
+/// branch is named like `rollup-abc1234`, e.g. `Auto merge of #45123 -
+/// user:rollup-abc1234, r=user`.
+pub fn is_rollup(summary: &str) -> bool {
+    if bors_pr(summary).is_none() {
+        return false;
+    }
+    match summary.find(" - ") {
+        Some(idx) => summary[idx..]
+            .split(',')
+            .next()
+            .and_then(|head| head.split(':').nth(1))
+            .map_or(false, |branch| branch.starts_with("rollup")),
+        None => false,
+    }
+}
+
+/// Whether `signature`, the `X-Hub-Signature` header of a webhook delivery,
+/// is the HMAC of `body` with the webhook's `secret`, as `sha1=<hex>`.
+pub fn valid_signature(secret: &str, signature: &[u8], body: &[u8]) -> bool {
+    let code = match signature.split_at(signature.len().min(5)) {
+        (b"sha1=", code) => match Vec::<u8>::from_hex(code) {
+            Ok(code) => code,
+            Err(_) => return false,
+        },
+        _ => return false,
+    };
+    let mut mac = Hmac::new(Sha1::new(), secret.as_bytes());
+    mac.input(body);
+    // Compared in constant time
+    mac.result() == MacResult::new(&code)
+}
+
+/// The number at the start of `s`.
+fn pr_number(s: &str) -> Option<u64> {
+    s.split(|c: char| !c.is_digit(10)).next()?.parse().ok()
+}
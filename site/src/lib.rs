@@ -14,6 +14,8 @@ extern crate chrono;
 extern crate error_chain;
 extern crate futures;
 extern crate futures_cpupool;
+extern crate crypto;
+extern crate hex;
 extern crate hyper;
 #[macro_use]
 extern crate log;
@@ -22,8 +24,12 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate reqwest;
 extern crate rust_sysroot;
+pub extern crate site_api as api;
 extern crate url;
+#[cfg(test)]
+extern crate tempdir;
 
 mod errors {
     error_chain! {
@@ -31,6 +37,7 @@ mod errors {
             Io(::std::io::Error);
             Json(::serde_json::Error);
             Chrono(::chrono::ParseError);
+            Reqwest(::reqwest::Error);
         }
 
         errors {
@@ -43,9 +50,25 @@ mod errors {
 }
 
 mod git;
+mod github;
 
-pub mod api;
+pub mod analysis;
+pub mod annotation;
+pub mod auth;
+pub mod baseline;
+pub mod cache;
+pub mod channels;
+pub mod dev_seed;
+pub mod exclusion;
+pub mod feed;
+pub mod frontend;
 pub mod load;
+pub mod metrics;
+pub mod nightly;
+pub mod noise;
+pub mod queue;
+pub mod rollup;
+pub mod snapshot;
 pub mod date;
 pub mod util;
 pub mod server;
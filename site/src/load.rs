@@ -7,9 +7,9 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fs::{self, File};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::io::Read;
 use std::env;
 
@@ -19,8 +19,13 @@ use errors::*;
 use util;
 use git;
 use date::Date;
+use noise::Noise;
+use collector::{pending_migrations, AppliedMigration};
 
-pub use collector::{Commit, CommitData, Patch, Run, Stat};
+pub use collector::{Backend, BenchmarkInfo, BootstrapData, CalibrationRun, Category, Commit,
+                    CommitData, CrateTiming, Failure, FailureKind, Interner, LlvmPassTiming,
+                    Machine, Name, Patch, Profile, QueryTiming, Rename, Run, SelfProfile, Stat,
+                    Std};
 
 #[derive(Debug)]
 pub struct InputData {
@@ -30,12 +35,56 @@ pub struct InputData {
     /// All known statistics gathered for crates
     pub stats_list: BTreeSet<String>,
 
+    /// All codegen backends that have been benchmarked
+    pub backend_list: BTreeSet<Backend>,
+
+    /// All ways of building the standard library that have been benchmarked
+    pub std_list: BTreeSet<Std>,
+
+    /// All extra rustc flag sets that have been benchmarked
+    pub flag_set_list: BTreeSet<String>,
+
+    /// All custom Cargo profiles that have been benchmarked
+    pub custom_profile_list: BTreeSet<String>,
+
+    /// All profiles that have been benchmarked
+    pub profile_list: BTreeSet<Profile>,
+
+    /// Classes of all machines that have recorded results
+    pub machine_list: BTreeSet<String>,
+
+    /// The category of each benchmark, as recorded with its newest results
+    pub categories: BTreeMap<String, Category>,
+
+    /// What each benchmark is, as recorded with its newest results
+    pub benchmark_info: BTreeMap<String, BenchmarkInfo>,
+
+    /// The first commit with results for each benchmark, for any target
+    pub first_commits: BTreeMap<String, Commit>,
+
     /// The last date that was seen while loading files. The DateTime variant is
     /// used here since the date may or may not contain a time. Since the
     /// timezone is not important, it isn't stored, hence the Naive variant.
     pub last_date: Date,
 
-    pub data: BTreeMap<Commit, CommitData>,
+    /// All benchmark results, by target triple
+    pub data: BTreeMap<String, BTreeMap<Commit, CommitData>>,
+
+    /// Timings of rustc's own bootstrap, for the commits that have them.
+    pub bootstrap: BTreeMap<Commit, BootstrapData>,
+
+    /// Re-benchmarks of the calibration artifact, oldest first
+    pub calibration: Vec<CalibrationRun>,
+
+    /// Benchmarks continued under a new name, from `renames.json`
+    pub renames: Vec<Rename>,
+
+    /// The patch and statistic names of all results. Dropped with the rest of
+    /// the data when it's reloaded.
+    pub names: Interner,
+
+    /// How noisy the newest results of each benchmark are
+    pub noise: Noise,
 }
 
 impl InputData {
@@ -61,6 +110,8 @@ impl InputData {
             )?;
         }
 
+        check_migrations(&repo_loc)?;
+
         // Read all files from repo_loc/processed
         let mut file_count = 0;
         for entry in fs::read_dir(repo_loc.join("times"))? {
@@ -95,34 +146,96 @@ impl InputData {
                 continue;
             }
 
-            data.insert(contents.commit.clone(), contents);
+            data.entry(contents.triple.clone())
+                .or_insert_with(BTreeMap::new)
+                .insert(contents.commit.clone(), contents);
         }
 
         info!("{} total files", file_count);
         info!("{} skipped files", skipped);
-        info!("{} measured", data.len());
+        for (target, commits) in &data {
+            info!("{} measured for {}", commits.len(), target);
+        }
 
-        InputData::new(data)
+        let mut input = InputData::new(data)?;
+        input.bootstrap = load_bootstrap(&repo_loc)?;
+        input.calibration = load_calibration(&repo_loc)?;
+        for run in &mut input.calibration {
+            input.names.intern_commit(&mut run.data);
+        }
+        input.renames = load_renames(&repo_loc)?;
+        Ok(input)
     }
 
-    pub fn new(data: BTreeMap<Commit, CommitData>) -> Result<InputData> {
+    pub fn new(mut data: BTreeMap<String, BTreeMap<Commit, CommitData>>) -> Result<InputData> {
+        let names = Interner::new();
+        for commit in data.values_mut().flat_map(|commits| commits.values_mut()) {
+            names.intern_commit(commit);
+        }
         let mut last_date = None;
         let mut crate_list = BTreeSet::new();
         let mut stats_list = BTreeSet::new();
+        let mut backend_list = BTreeSet::new();
+        let mut std_list = BTreeSet::new();
+        let mut flag_set_list = BTreeSet::new();
+        let mut custom_profile_list = BTreeSet::new();
+        let mut profile_list = BTreeSet::new();
+        let mut machine_list = BTreeSet::new();
+        let mut categories = BTreeMap::new();
+        let mut category_dates = BTreeMap::new();
+        let mut benchmark_info = BTreeMap::new();
+        let mut info_dates = BTreeMap::new();
+        let mut first_commits = BTreeMap::<String, Commit>::new();
 
-        for run in data.values() {
+        for run in data.values().flat_map(|commits| commits.values()) {
             if last_date.is_none() || last_date.as_ref().unwrap() < &run.commit.date {
                 last_date = Some(run.commit.date);
             }
 
+            for machine in run.collectors.values() {
+                machine_list.insert(machine.class.clone());
+            }
+
+            for (benchmark, &category) in &run.categories {
+                if category_dates.get(benchmark).map_or(true, |date| *date < run.commit.date) {
+                    category_dates.insert(benchmark.clone(), run.commit.date);
+                    categories.insert(benchmark.clone(), category);
+                }
+            }
+
+            for (benchmark, info) in &run.benchmark_info {
+                if info_dates.get(benchmark).map_or(true, |date| *date < run.commit.date) {
+                    info_dates.insert(benchmark.clone(), run.commit.date);
+                    benchmark_info.insert(benchmark.clone(), info.clone());
+                }
+            }
+
+            for benchmark in run.benchmarks.keys() {
+                let first = first_commits
+                    .get(benchmark)
+                    .map_or(true, |commit| run.commit.date < commit.date);
+                if first {
+                    first_commits.insert(benchmark.clone(), run.commit.clone());
+                }
+            }
+
             for patch in run.benchmarks
                 .values()
                 .filter(|v| v.is_ok())
                 .flat_map(|v| v.as_ref().unwrap())
             {
-                crate_list.insert(patch.name.clone());
+                crate_list.insert(patch.name.to_string());
+                backend_list.insert(patch.backend);
+                std_list.insert(patch.std);
+                profile_list.insert(patch.profile);
+                if let Some(ref flags) = patch.flags {
+                    flag_set_list.insert(flags.clone());
+                }
+                if let Some(ref custom_profile) = patch.custom_profile {
+                    custom_profile_list.insert(custom_profile.clone());
+                }
                 for stat in &patch.run().stats {
-                    stats_list.insert(stat.name.clone());
+                    stats_list.insert(stat.name.to_string());
                 }
             }
         }
@@ -132,12 +245,141 @@ impl InputData {
         Ok(InputData {
             crate_list: crate_list,
             stats_list: stats_list,
+            backend_list: backend_list,
+            std_list: std_list,
+            flag_set_list: flag_set_list,
+            custom_profile_list: custom_profile_list,
+            profile_list: profile_list,
+            machine_list: machine_list,
+            categories: categories,
+            benchmark_info: benchmark_info,
+            first_commits: first_commits,
             last_date: last_date,
+            noise: Noise::of(&data),
             data: data,
+            bootstrap: BTreeMap::new(),
+            calibration: Vec::new(),
+            renames: Vec::new(),
+            names: names,
         })
     }
 }
 
+/// Refuse to load a repository migrated beyond what this site understands.
+/// A repository that isn't fully migrated yet is still readable.
+fn check_migrations(repo_loc: &Path) -> Result<()> {
+    let path = repo_loc.join("migrations.json");
+    let applied = if path.exists() {
+        serde_json::from_reader::<_, Vec<AppliedMigration>>(File::open(&path)?)
+            .chain_err(|| format!("failed to parse {}", path.display()))?
+    } else {
+        Vec::new()
+    };
+    let pending = pending_migrations(&applied)
+        .map_err(Error::from)
+        .chain_err(|| format!("can't load {}", repo_loc.display()))?;
+    if !pending.is_empty() {
+        warn!("{} migrations of {} are pending", pending.len(), repo_loc.display());
+    }
+    Ok(())
+}
+
+/// Read all bootstrap timings from repo_loc/bootstrap, which older data
+/// repositories don't have.
+fn load_bootstrap(repo_loc: &Path) -> Result<BTreeMap<Commit, BootstrapData>> {
+    let mut bootstrap = BTreeMap::new();
+    let dir = repo_loc.join("bootstrap");
+    if !dir.exists() {
+        return Ok(bootstrap);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let mut file_contents = String::new();
+        File::open(entry.path())?.read_to_string(&mut file_contents)?;
+        match serde_json::from_str::<BootstrapData>(&file_contents) {
+            Ok(data) => {
+                bootstrap.insert(data.commit.clone(), data);
+            }
+            Err(err) => {
+                error!("Failed to parse JSON for {}: {:?}", entry.path().display(), err);
+            }
+        }
+    }
+    info!("{} bootstrap timings", bootstrap.len());
+    Ok(bootstrap)
+}
+
+/// Read all calibration runs from repo_loc/calibration, which older data
+/// repositories don't have.
+fn load_calibration(repo_loc: &Path) -> Result<Vec<CalibrationRun>> {
+    let mut runs = Vec::new();
+    let dir = repo_loc.join("calibration");
+    if !dir.exists() {
+        return Ok(runs);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let mut file_contents = String::new();
+        File::open(entry.path())?.read_to_string(&mut file_contents)?;
+        match serde_json::from_str::<CalibrationRun>(&file_contents) {
+            Ok(run) => runs.push(run),
+            Err(err) => {
+                error!("Failed to parse JSON for {}: {:?}", entry.path().display(), err);
+            }
+        }
+    }
+    runs.sort_by_key(|run| run.date);
+    info!("{} calibration runs", runs.len());
+    Ok(runs)
+}
+
+/// The commits listed in repo_loc/broken-commits-log, as `<sha>: <reason>`
+/// lines. These can't be benchmarked and aren't backfilled.
+pub fn broken_commits(repo_loc: &str) -> Result<HashSet<String>> {
+    let path = Path::new(repo_loc).join("broken-commits-log");
+    let mut contents = String::new();
+    if path.exists() {
+        File::open(&path)?.read_to_string(&mut contents)?;
+    }
+    Ok(contents
+        .lines()
+        .filter_map(|line| line.split(':').next())
+        .map(|sha| sha.trim().to_string())
+        .filter(|sha| !sha.is_empty())
+        .collect())
+}
+
+fn load_renames(repo_loc: &Path) -> Result<Vec<Rename>> {
+    let path = repo_loc.join("renames.json");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let renames = serde_json::from_reader(File::open(&path)?)
+        .chain_err(|| format!("failed to parse {}", path.display()))?;
+    Ok(renames)
+}
+
+/// Read the query timings of `commit` on `triple`, which are only loaded on
+/// demand. `None` if the commit was benchmarked without query statistics.
+pub fn load_self_profile(
+    repo_loc: &Path,
+    commit: &Commit,
+    triple: &str,
+) -> Result<Option<SelfProfile>> {
+    let path = repo_loc
+        .join("self-profile")
+        .join(format!("{}-{}-{}.json", commit.date, commit.sha, triple));
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+    let profile = serde_json::from_reader(file)
+        .chain_err(|| format!("failed to parse {}", path.display()))?;
+    Ok(Some(profile))
+}
+
 /// One decimal place rounded percent
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Percent(#[serde(with = "util::round_float")] pub f64);
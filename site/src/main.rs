@@ -10,14 +10,26 @@
 extern crate env_logger;
 extern crate site;
 
-use site::{load, server, util};
+use std::env;
+use std::path::{Path, PathBuf};
+
+use site::{dev_seed, load, server, util};
+use site::frontend::Frontend;
 
 fn main() {
     env_logger::init().unwrap();
 
-    let data = load::InputData::from_fs(&util::get_repo_path().unwrap()).unwrap();
+    let repo_path = util::get_repo_path().unwrap();
+    if env::args().any(|arg| arg == "--dev-seed") {
+        dev_seed::generate(Path::new(&repo_path)).unwrap();
+    }
+    let frontend = match env::args().skip_while(|arg| arg != "--frontend-dir").nth(1) {
+        Some(dir) => Frontend::from_dir(PathBuf::from(dir)).unwrap(),
+        None => Frontend::embedded(),
+    };
+    let data = load::InputData::from_fs(&repo_path).unwrap();
 
     println!("Starting server!");
 
-    server::start(data);
+    server::start(data, frontend);
 }
@@ -0,0 +1,239 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Operational metrics in the Prometheus text format, served at `/metrics`,
+//! so that stalled collection can be alerted on.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rust_sysroot::git::Commit as GitCommit;
+
+use load::InputData;
+use queue::{self, Priority};
+
+/// Upper bounds, in seconds, of the request latency histogram buckets.
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket of `LATENCY_BUCKETS`, not cumulative
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        if let Some(idx) = LATENCY_BUCKETS.iter().position(|&bound| secs <= bound) {
+            self.buckets[idx] += 1;
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    /// Request latencies by endpoint
+    latencies: Mutex<BTreeMap<String, Histogram>>,
+    /// Master commits without results by target, as of the last listing of
+    /// master commits
+    missing_commits: Mutex<BTreeMap<String, usize>>,
+}
+
+impl Metrics {
+    pub fn observe_request(&self, endpoint: &str, duration: Duration) {
+        let secs = duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9;
+        self.latencies
+            .lock()
+            .unwrap()
+            .entry(endpoint.to_string())
+            .or_insert_with(Histogram::default)
+            .observe(secs);
+    }
+
+    /// Count the `master` commits without results in `data`, for each
+    /// target, leaving out those in `skip` that can't be benchmarked.
+    pub fn record_missing_commits(
+        &self,
+        master: &[GitCommit],
+        data: &InputData,
+        skip: &HashSet<String>,
+    ) {
+        let missing = data.data
+            .iter()
+            .map(|(target, commits)| {
+                let have = commits.keys().map(|commit| &commit.sha).collect::<HashSet<_>>();
+                let missing = master
+                    .iter()
+                    .filter(|commit| !have.contains(&commit.sha) && !skip.contains(&commit.sha))
+                    .count();
+                (target.clone(), missing)
+            })
+            .collect();
+        *self.missing_commits.lock().unwrap() = missing;
+    }
+
+    pub fn render(
+        &self,
+        data: &InputData,
+        queue: &queue::State,
+        updating: bool,
+        now: DateTime<Utc>,
+    ) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "rustc_perf_request_duration_seconds",
+            "histogram",
+            "Time taken to handle requests, by endpoint.",
+        );
+        for (endpoint, histogram) in self.latencies.lock().unwrap().iter() {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "rustc_perf_request_duration_seconds_bucket{{endpoint=\"{}\",le=\"{}\"}} {}",
+                    endpoint,
+                    bound,
+                    cumulative
+                ).unwrap();
+            }
+            writeln!(
+                out,
+                "rustc_perf_request_duration_seconds_bucket{{endpoint=\"{}\",le=\"+Inf\"}} {}",
+                endpoint,
+                histogram.count
+            ).unwrap();
+            writeln!(
+                out,
+                "rustc_perf_request_duration_seconds_sum{{endpoint=\"{}\"}} {}",
+                endpoint,
+                histogram.sum
+            ).unwrap();
+            writeln!(
+                out,
+                "rustc_perf_request_duration_seconds_count{{endpoint=\"{}\"}} {}",
+                endpoint,
+                histogram.count
+            ).unwrap();
+        }
+
+        header(
+            &mut out,
+            "rustc_perf_queue_pending",
+            "gauge",
+            "Commits waiting to be benchmarked.",
+        );
+        writeln!(out, "rustc_perf_queue_pending {}", queue.pending.len()).unwrap();
+        header(
+            &mut out,
+            "rustc_perf_queue_in_progress",
+            "gauge",
+            "Commits handed out to collectors without results yet.",
+        );
+        writeln!(out, "rustc_perf_queue_in_progress {}", queue.in_progress.len()).unwrap();
+        header(
+            &mut out,
+            "rustc_perf_queue_failed",
+            "gauge",
+            "Commits given up on after failing repeatedly.",
+        );
+        writeln!(out, "rustc_perf_queue_failed {}", queue.failed.len()).unwrap();
+        header(
+            &mut out,
+            "rustc_perf_queue_oldest_pending_age_seconds",
+            "gauge",
+            "How long the longest waiting commit, not counting backfill, has been queued.",
+        );
+        let oldest = queue
+            .pending
+            .iter()
+            .filter(|c| c.priority != Priority::Backfill)
+            .map(|c| c.queued)
+            .min();
+        writeln!(
+            out,
+            "rustc_perf_queue_oldest_pending_age_seconds {}",
+            oldest.map_or(0, |queued| (now - queued).num_seconds())
+        ).unwrap();
+
+        header(
+            &mut out,
+            "rustc_perf_commits",
+            "gauge",
+            "Commits with results, by target.",
+        );
+        for (target, commits) in &data.data {
+            writeln!(out, "rustc_perf_commits{{target=\"{}\"}} {}", target, commits.len()).unwrap();
+        }
+        header(
+            &mut out,
+            "rustc_perf_seconds_since_last_commit",
+            "gauge",
+            "Age of the newest commit with results, by target.",
+        );
+        for (target, commits) in &data.data {
+            if let Some(commit) = commits.keys().next_back() {
+                writeln!(
+                    out,
+                    "rustc_perf_seconds_since_last_commit{{target=\"{}\"}} {}",
+                    target,
+                    (now - commit.date.0).num_seconds()
+                ).unwrap();
+            }
+        }
+        header(
+            &mut out,
+            "rustc_perf_missing_commits",
+            "gauge",
+            "Master commits with published artifacts but no results, by target.",
+        );
+        for (target, missing) in self.missing_commits.lock().unwrap().iter() {
+            writeln!(out, "rustc_perf_missing_commits{{target=\"{}\"}} {}", target, missing)
+                .unwrap();
+        }
+        header(
+            &mut out,
+            "rustc_perf_failed_benchmarks",
+            "gauge",
+            "Benchmarks that failed for the newest commit, by target.",
+        );
+        for (target, commits) in &data.data {
+            if let Some(data) = commits.values().next_back() {
+                let failed = data.benchmarks.values().filter(|b| b.is_err()).count();
+                writeln!(out, "rustc_perf_failed_benchmarks{{target=\"{}\"}} {}", target, failed)
+                    .unwrap();
+            }
+        }
+
+        header(
+            &mut out,
+            "rustc_perf_updating",
+            "gauge",
+            "Whether the site is reloading the data repository.",
+        );
+        writeln!(out, "rustc_perf_updating {}", updating as u8).unwrap();
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
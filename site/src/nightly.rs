@@ -0,0 +1,314 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Daily notifications of regressions between consecutive nightlies.
+//!
+//! Once a day, the commits the newest two nightlies were built from are read
+//! from their channel manifests on static.rust-lang.org. Once both have
+//! results, a summary is posted if the newer one regressed primary
+//! benchmarks: as a comment on the GitHub issue `NIGHTLY_ISSUE`
+//! (`owner/repo#number`), and to the Zulip or Slack incoming webhook
+//! `NIGHTLY_WEBHOOK`. Links point to the site at `SITE_URL`.
+//!
+//! The date of the last nightly checked is kept in the JSON file named by
+//! `SITE_NIGHTLY_STATE` (`nightly.json` by default), so that restarts don't
+//! post twice.
+
+use std::env;
+use std::fmt::Write;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{self, NaiveDate, Utc};
+use reqwest;
+use serde_json;
+
+use analysis;
+use api;
+use channels;
+use errors::*;
+use github;
+use load::{self, Backend, Category, CommitData, InputData, Profile, QueryTiming};
+use server::Selector;
+use util;
+
+/// How often to look for a new nightly and its results.
+const CHECK_EVERY: u64 = 60 * 60;
+
+/// How many of the queries that got slower to name.
+const SLOWER_QUERIES: usize = 3;
+
+pub struct Notifier {
+    issue: Option<(String, u64)>,
+    webhook: Option<String>,
+    site_url: String,
+    path: PathBuf,
+    state: State,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    last_checked: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookMessage<'a> {
+    text: &'a str,
+}
+
+impl Notifier {
+    /// `None` unless `NIGHTLY_ISSUE` or `NIGHTLY_WEBHOOK` is set.
+    pub fn from_env() -> Result<Option<Notifier>> {
+        let issue = match env::var("NIGHTLY_ISSUE") {
+            Ok(issue) => Some(parse_issue(&issue)?),
+            Err(_) => None,
+        };
+        let webhook = env::var("NIGHTLY_WEBHOOK").ok();
+        if issue.is_none() && webhook.is_none() {
+            return Ok(None);
+        }
+        let path = env::var_os("SITE_NIGHTLY_STATE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("nightly.json"));
+        let state = if path.exists() {
+            let file =
+                File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+            serde_json::from_reader(file)
+                .chain_err(|| format!("failed to parse {}", path.display()))?
+        } else {
+            State::default()
+        };
+        Ok(Some(Notifier {
+            issue: issue,
+            webhook: webhook,
+            site_url: env::var("SITE_URL")
+                .unwrap_or_else(|_| String::from("https://perf.rust-lang.org")),
+            path: path,
+            state: state,
+        }))
+    }
+
+    /// Check for regressions every hour, in the background.
+    pub fn spawn(mut self, data: Arc<RwLock<InputData>>) {
+        thread::spawn(move || loop {
+            if let Err(err) = self.check(&data) {
+                warn!("failed to check the newest nightly: {:?}", err);
+            }
+            thread::sleep(Duration::from_secs(CHECK_EVERY));
+        });
+    }
+
+    /// Notify about today's nightly, unless that was done already or its
+    /// results aren't in yet.
+    fn check(&mut self, data: &RwLock<InputData>) -> Result<()> {
+        let today = Utc::today().naive_utc();
+        if self.state.last_checked.map_or(false, |date| date >= today) {
+            return Ok(());
+        }
+        let (newer, older) = match (
+            nightly_commit(today)?,
+            nightly_commit(today - chrono::Duration::days(1))?,
+        ) {
+            (Some(newer), Some(older)) => (newer, older),
+            _ => return Ok(()),
+        };
+        if newer != older {
+            let message = {
+                let data = data.read().unwrap();
+                let commits = match data.data.get(&api::default_target()) {
+                    Some(commits) => commits,
+                    None => return Ok(()),
+                };
+                let find = |short: &str| commits.values().find(|c| c.commit.sha.starts_with(short));
+                match (find(&older[..]), find(&newer[..])) {
+                    (Some(a), Some(b)) => self.message(today, a, b, &data),
+                    _ => return Ok(()),
+                }
+            };
+            if let Some(message) = message {
+                self.post(&message)?;
+            }
+        }
+
+        self.state.last_checked = Some(today);
+        let file = File::create(&self.path)
+            .chain_err(|| format!("failed to create {}", self.path.display()))?;
+        serde_json::to_writer(file, &self.state)?;
+        Ok(())
+    }
+
+    /// The summary of the changes from `a` to `b`, the nightly of `date`,
+    /// or `None` if no primary benchmark regressed.
+    fn message(
+        &self,
+        date: NaiveDate,
+        a: &CommitData,
+        b: &CommitData,
+        data: &InputData,
+    ) -> Option<String> {
+        let no_flags = None;
+        let selector = Selector::new(Profile::default(), Backend::default(), &no_flags);
+        let stat = "instructions:u";
+        let changes = analysis::changes_between(a, selector, b, selector, stat, &data.noise);
+        let summary = analysis::summarize(&changes, &data.categories);
+        if summary.primary_regressions.is_empty() {
+            return None;
+        }
+
+        let mut message = String::new();
+        writeln!(
+            message,
+            "Nightly {} ([{}...{}](https://github.com/rust-lang/rust/compare/{}...{})): {}\n",
+            date,
+            &a.commit.sha[..8],
+            &b.commit.sha[..8],
+            a.commit.sha,
+            b.commit.sha,
+            summary.verdict
+        ).unwrap();
+        for change in changes
+            .iter()
+            .filter(|c| c.is_significant() && c.is_regression())
+            .filter(|c| analysis::category(&data.categories, &c.name) == Category::Primary)
+            .take(5)
+        {
+            writeln!(message, "- `{}`: {:+.1}%", change.name, change.percent).unwrap();
+        }
+        // Changes are sorted primary first, then by magnitude.
+        if let Some(change) = changes.iter().find(|c| c.is_significant() && c.is_regression()) {
+            self.write_slower_queries(&mut message, a, b, &change.name, selector);
+        }
+        writeln!(
+            message,
+            "\n[Comparison]({}/compare.html?commit_a={}&commit_b={}&stat={})",
+            self.site_url.trim_right_matches('/'),
+            a.commit.sha,
+            b.commit.sha,
+            stat
+        ).unwrap();
+        Some(message)
+    }
+
+    /// Names the queries of `patch` whose self time grew the most from `a`
+    /// to `b`, as a lead on what regressed it. Nothing if either commit was
+    /// benchmarked without query statistics.
+    fn write_slower_queries(
+        &self,
+        message: &mut String,
+        a: &CommitData,
+        b: &CommitData,
+        patch: &str,
+        selector: Selector,
+    ) {
+        let repo_path = match util::get_repo_path() {
+            Ok(repo_path) => repo_path,
+            Err(_) => return,
+        };
+        let repo_path = Path::new(&repo_path);
+        let queries = |commit: &CommitData| -> Option<Vec<QueryTiming>> {
+            let profile = match load::load_self_profile(repo_path, &commit.commit, &commit.triple) {
+                Ok(profile) => profile,
+                Err(err) => {
+                    warn!("failed to load self-profile of {}: {:?}", commit.commit.sha, err);
+                    None
+                }
+            };
+            profile?
+                .patches
+                .into_iter()
+                .find(|p| p.name == patch && selector.matches(commit, p))
+                .map(|p| p.queries)
+        };
+        let (a_queries, b_queries) = match (queries(a), queries(b)) {
+            (Some(a_queries), Some(b_queries)) => (a_queries, b_queries),
+            _ => return,
+        };
+
+        // Each query that got slower, with its self time at `a`
+        let mut slower = b_queries
+            .iter()
+            .filter_map(|query| {
+                let before = a_queries
+                    .iter()
+                    .find(|q| q.label == query.label)
+                    .map_or(0.0, |q| q.self_time);
+                if query.self_time > before {
+                    Some((query, before))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if slower.is_empty() {
+            return;
+        }
+        slower.sort_by(|x, y| {
+            (y.0.self_time - y.1)
+                .partial_cmp(&(x.0.self_time - x.1))
+                .unwrap()
+        });
+        writeln!(
+            message,
+            "\nQueries that got slower in [`{}`]\
+             ({}/self-profile.html?commit_a={}&commit_b={}&benchmark={}):",
+            patch,
+            self.site_url.trim_right_matches('/'),
+            a.commit.sha,
+            b.commit.sha,
+            patch
+        ).unwrap();
+        for &(query, before) in slower.iter().take(SLOWER_QUERIES) {
+            writeln!(
+                message,
+                "- `{}`: {:.1}ms to {:.1}ms",
+                query.label,
+                before * 1e3,
+                query.self_time * 1e3
+            ).unwrap();
+        }
+    }
+
+    fn post(&self, message: &str) -> Result<()> {
+        if let Some((ref repo, number)) = self.issue {
+            github::post_comment(repo, number, message)?;
+        }
+        if let Some(ref webhook) = self.webhook {
+            let response = reqwest::Client::new()?
+                .post(webhook)
+                .json(&WebhookMessage { text: message })
+                .send()?;
+            if !response.status().is_success() {
+                bail!("nightly webhook returned {}", response.status());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `owner/repo#number`
+fn parse_issue(issue: &str) -> Result<(String, u64)> {
+    let mut parts = issue.splitn(2, '#');
+    match (parts.next(), parts.next().and_then(|n| n.parse().ok())) {
+        (Some(repo), Some(number)) if repo.contains('/') => Ok((repo.to_string(), number)),
+        _ => bail!("NIGHTLY_ISSUE must look like owner/repo#number, not `{}`", issue),
+    }
+}
+
+/// The abbreviated hash of the commit the nightly of `date` was built from,
+/// or `None` if there is no nightly for that date (yet).
+fn nightly_commit(date: NaiveDate) -> Result<Option<String>> {
+    let url = format!(
+        "https://static.rust-lang.org/dist/{}/channel-rust-nightly.toml",
+        date
+    );
+    Ok(channels::dist_rustc(&url)?.map(|(_, sha)| sha))
+}
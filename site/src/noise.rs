@@ -0,0 +1,152 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! How noisy the results of each benchmark are, so that a change can be
+//! judged against the usual variation of its series rather than one global
+//! threshold.
+//!
+//! The noise score of a series, a statistic of a patch built with some
+//! profile on some target, is the median of the absolute percent changes
+//! between consecutive commits among the newest `WINDOW` commits. The median
+//! disregards the few commits that really changed the series. A change is
+//! significant if it is at least `NOISE_MULTIPLE` times the score, and at
+//! least `MIN_THRESHOLD`. Series whose threshold ends up above the global
+//! `SIGNIFICANCE_THRESHOLD` are flagged as noisy; those with too few results
+//! keep the global threshold.
+//!
+//! Scores are computed from the builds with the default backend and std and
+//! without extra flags whenever the data is loaded, and apply to the other
+//! builds of the same patch and profile as well.
+
+use std::collections::{BTreeMap, HashMap};
+
+use analysis::SIGNIFICANCE_THRESHOLD;
+use load::{Backend, Commit, CommitData, Profile, Std};
+
+pub use api::days::Score;
+
+/// How many of the newest commits the scores are computed from.
+pub const WINDOW: usize = 30;
+
+/// How many changes between consecutive commits a series needs to be scored.
+pub const MIN_CHANGES: usize = 10;
+
+/// How many times its noise score a change has to be to be significant.
+pub const NOISE_MULTIPLE: f64 = 3.0;
+
+/// The smallest change (in percent) considered significant, however quiet
+/// the series.
+pub const MIN_THRESHOLD: f64 = 0.5;
+
+#[derive(Debug, Default)]
+pub struct Noise {
+    /// Target -> profile -> statistic -> patch -> score
+    scores: BTreeMap<String, BTreeMap<Profile, BTreeMap<String, HashMap<String, f64>>>>,
+}
+
+impl Noise {
+    /// Score the series of all targets of `data`.
+    pub fn of(data: &BTreeMap<String, BTreeMap<Commit, CommitData>>) -> Noise {
+        let mut scores = BTreeMap::new();
+        for (target, commits) in data {
+            // Profile, statistic and patch -> values, oldest first
+            let mut series: BTreeMap<(Profile, String, String), Vec<f64>> = BTreeMap::new();
+            let newest = commits.values().rev().take(WINDOW + 1).collect::<Vec<_>>();
+            for day in newest.into_iter().rev() {
+                for patch in day.benchmarks
+                    .values()
+                    .filter_map(|result| result.as_ref().ok())
+                    .flat_map(|patches| patches)
+                {
+                    if patch.backend != Backend::default() || patch.std != Std::default()
+                        || patch.flags.is_some() || patch.custom_profile.is_some()
+                    {
+                        continue;
+                    }
+                    for stat in &patch.run().stats {
+                        series
+                            .entry((patch.profile, stat.name.to_string(), patch.name.to_string()))
+                            .or_insert_with(Vec::new)
+                            .push(stat.cnt);
+                    }
+                }
+            }
+
+            let target_scores = scores.entry(target.clone()).or_insert_with(BTreeMap::new);
+            for ((profile, stat, patch), values) in series {
+                if let Some(score) = score(&values) {
+                    target_scores
+                        .entry(profile)
+                        .or_insert_with(BTreeMap::new)
+                        .entry(stat)
+                        .or_insert_with(HashMap::new)
+                        .insert(patch, score);
+                }
+            }
+        }
+        Noise { scores: scores }
+    }
+
+    /// The noise of `stat` of the patch `patch` built with `profile` on
+    /// `target`, if the series has enough results to tell.
+    pub fn get(&self, target: &str, profile: Profile, stat: &str, patch: &str) -> Option<Score> {
+        let score = *self.scores.get(target)?.get(&profile)?.get(stat)?.get(patch)?;
+        let threshold = (NOISE_MULTIPLE * score).max(MIN_THRESHOLD);
+        Some(Score {
+            score: score,
+            threshold: threshold,
+            noisy: threshold > SIGNIFICANCE_THRESHOLD,
+        })
+    }
+
+    /// The smallest change (in percent) of the series that is significant.
+    pub fn threshold(&self, target: &str, profile: Profile, stat: &str, patch: &str) -> f64 {
+        self.get(target, profile, stat, patch)
+            .map_or(SIGNIFICANCE_THRESHOLD, |score| score.threshold)
+    }
+
+    /// How the threshold of the series was arrived at, in words.
+    pub fn explain(&self, target: &str, profile: Profile, stat: &str, patch: &str) -> String {
+        match self.get(target, profile, stat, patch) {
+            None => format!(
+                "fewer than {} changes between the newest {} commits, so the default threshold \
+                 of {}% applies",
+                MIN_CHANGES,
+                WINDOW + 1,
+                SIGNIFICANCE_THRESHOLD
+            ),
+            Some(score) if NOISE_MULTIPLE * score.score < MIN_THRESHOLD => format!(
+                "{} times the noise score of {:.3}% is below the minimum of {}%, which applies",
+                NOISE_MULTIPLE,
+                score.score,
+                MIN_THRESHOLD
+            ),
+            Some(score) => format!(
+                "{} times the noise score of {:.3}%{}",
+                NOISE_MULTIPLE,
+                score.score,
+                if score.noisy { "; noisy" } else { "" }
+            ),
+        }
+    }
+}
+
+/// The median absolute percent change between consecutive `values`.
+fn score(values: &[f64]) -> Option<f64> {
+    let mut changes = values
+        .windows(2)
+        .filter(|pair| pair[0] != 0.0)
+        .map(|pair| (100.0 * (pair[1] - pair[0]) / pair[0]).abs())
+        .collect::<Vec<_>>();
+    if changes.len() < MIN_CHANGES {
+        return None;
+    }
+    changes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    Some(changes[changes.len() / 2])
+}
@@ -0,0 +1,987 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Queue of commits waiting to be benchmarked.
+//!
+//! Everything collectors benchmark goes through this queue: master commits
+//! as their GitHub push webhooks arrive, bors try builds of the pull
+//! requests of regressed rollups, flag experiments, and older master commits
+//! without results. Each entry has a `Priority`; within a priority, the
+//! newest commit is handed out first so that fresh merges get results
+//! quickly.
+//!
+//! An entry is identified by its `key`, the commit and the experiment if
+//! any, and queued at most once; queueing it again only raises its priority.
+//! A collector claims an entry with a lease, which it renews while it
+//! benchmarks. Entries whose lease expired or whose collector reported a
+//! failure are queued again, until they failed `MAX_ATTEMPTS` times. Entries
+//! leave the queue once their collector reported them done, or once their
+//! results are in the data.
+//!
+//! When the newest master commit regresses and turns out to be a rollup, the
+//! rolled up pull requests are recorded so they can be benchmarked one by
+//! one. bors try builds of these pull requests are queued as they are pushed.
+//!
+//! Collectors can register toolchains built locally, e.g. of a fork, as
+//! pseudo-artifacts named `local-<name>`. As only the collector that
+//! registered one has the toolchain, only it claims the entry.
+//!
+//! If `SITE_CALIBRATION_COMMIT` names a commit, it is queued once a week as a
+//! calibration entry, to be benchmarked again from scratch. Its results are
+//! kept apart from the commit's regular results, as calibration runs; they
+//! show how the collector machines drift over time.
+//!
+//! Collectors say which metrics they gather when they ask for work, so that
+//! a statistic missing from a machine's results can be told apart from one
+//! it never gathers.
+//!
+//! Members can cancel entries, move pending ones to another priority and
+//! queue failed or canceled ones again. A collector benchmarking a canceled
+//! entry learns about it when it next renews its claim, and abandons the
+//! entry between two benchmarks. How long entries are expected to take is
+//! estimated from the most recent runs.
+//!
+//! The queue is persisted to the JSON file named by `SITE_QUEUE`
+//! (`queue.json` by default) so that it survives restarts.
+
+use std::cmp;
+use std::collections::{BTreeSet, HashSet};
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use rust_sysroot::git::Commit as GitCommit;
+use serde_json;
+
+use auth::User;
+use errors::*;
+use github::{self, RollupPr};
+use load::InputData;
+
+pub use api::queue::{Estimate, Experiment, LocalToolchain, Priority, QueuedCommit, Rollup, State,
+                     UnrolledPr};
+
+/// How long a claim lasts unless the collector renews it. Collectors renew
+/// their claims well before, so an expired claim means the collector died.
+pub fn lease_duration() -> Duration {
+    Duration::hours(1)
+}
+
+/// How often the calibration artifact is benchmarked again.
+pub fn calibration_interval() -> Duration {
+    Duration::weeks(1)
+}
+
+/// Entries that failed this often are given up on and moved to `failed`.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// How many of the most recent runs the estimated durations are based on.
+const RECENT_RUNS: usize = 20;
+
+/// Whether the claim on `commit` ran out without being renewed.
+fn lease_expired(commit: &QueuedCommit, now: DateTime<Utc>) -> bool {
+    // Entries queued before leases existed only have `started`.
+    let expires = commit
+        .lease_expires
+        .or_else(|| commit.started.map(|started| started + lease_duration()));
+    expires.map_or(true, |expires| expires < now)
+}
+
+pub struct Queue {
+    path: PathBuf,
+    state: Mutex<State>,
+    /// The calibration artifact, if any
+    calibration: Option<String>,
+}
+
+impl Queue {
+    pub fn from_env() -> Result<Queue> {
+        let path = env::var_os("SITE_QUEUE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("queue.json"));
+        Queue::open(path)
+    }
+
+    /// The queue persisted to `path`, which is empty if there is no such
+    /// file yet.
+    pub fn open(path: PathBuf) -> Result<Queue> {
+        let state = if path.exists() {
+            let file =
+                File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+            serde_json::from_reader(file)
+                .chain_err(|| format!("failed to parse {}", path.display()))?
+        } else {
+            State::default()
+        };
+        Ok(Queue {
+            path: path,
+            state: Mutex::new(state),
+            calibration: env::var("SITE_CALIBRATION_COMMIT").ok(),
+        })
+    }
+
+    pub fn state(&self) -> State {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Enqueue `commit` unless it is already queued or being benchmarked.
+    pub fn push(&self, commit: QueuedCommit) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if enqueue(&mut state, commit) {
+            self.save(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Enqueue the master commits in `commits` that have no results in
+    /// `data`, except those in `broken`, at `Priority::Backfill`.
+    pub fn backfill(
+        &self,
+        commits: &[GitCommit],
+        data: &InputData,
+        broken: &HashSet<String>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let have = data.data
+            .values()
+            .flat_map(|commits| commits.keys())
+            .map(|commit| &commit.sha)
+            .collect::<HashSet<_>>();
+        let mut state = self.state.lock().unwrap();
+        let mut changed = false;
+        for commit in commits {
+            if have.contains(&commit.sha) || broken.contains(&commit.sha) {
+                continue;
+            }
+            let queued = QueuedCommit::new(
+                commit.sha.clone(),
+                commit.date,
+                commit.summary.clone(),
+                Priority::Backfill,
+                now,
+            );
+            changed |= enqueue(&mut state, queued);
+        }
+        if changed {
+            self.save(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Enqueue the calibration artifact, if one is configured, unless it was
+    /// benchmarked or queued in the last `calibration_interval()`.
+    pub fn schedule_calibration(
+        &self,
+        commits: &[GitCommit],
+        data: &InputData,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let sha = match self.calibration {
+            Some(ref sha) => sha,
+            None => return Ok(()),
+        };
+        let commit = match commits.iter().find(|c| c.sha == *sha) {
+            Some(commit) => commit,
+            None => bail!("calibration commit {} has no published artifacts", sha),
+        };
+        let mut state = self.state.lock().unwrap();
+        let last_run = data.calibration
+            .iter()
+            .filter(|run| run.data.commit.sha == *sha)
+            .map(|run| run.date)
+            .max();
+        let last_queued = state
+            .pending
+            .iter()
+            .chain(&state.in_progress)
+            .chain(&state.failed)
+            .filter(|c| c.calibration && c.sha == *sha)
+            .map(|c| c.queued)
+            .max();
+        if ::std::cmp::max(last_run, last_queued)
+            .map_or(false, |last| now < last + calibration_interval())
+        {
+            return Ok(());
+        }
+        let mut queued = QueuedCommit::new(
+            commit.sha.clone(),
+            commit.date,
+            commit.summary.clone(),
+            Priority::Calibration,
+            now,
+        );
+        queued.calibration = true;
+        if enqueue(&mut state, queued) {
+            self.save(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Claim the first pending commit for `collector`, skipping the local
+    /// toolchains of other collectors. Commits whose lease expired are queued
+    /// again first.
+    pub fn next(&self, collector: &str, now: DateTime<Utc>) -> Result<Option<QueuedCommit>> {
+        let mut state = self.state.lock().unwrap();
+        let (abandoned, in_progress): (Vec<_>, Vec<_>) = state
+            .in_progress
+            .drain(..)
+            .partition(|c| lease_expired(c, now));
+        state.in_progress = in_progress;
+        let changed = !abandoned.is_empty();
+        for commit in abandoned {
+            warn!(
+                "claim of {} by {} ended without results",
+                commit.key(),
+                commit.collector.as_ref().map_or("unknown collector", |c| &**c)
+            );
+            requeue_failed(&mut state, commit);
+        }
+        let claimable = state.pending.iter().position(|c| {
+            c.local
+                .as_ref()
+                .map_or(true, |local| local.collector == collector)
+        });
+        let idx = match claimable {
+            Some(idx) => idx,
+            None => {
+                if changed {
+                    self.save(&state)?;
+                }
+                return Ok(None);
+            }
+        };
+        let mut commit = state.pending.remove(idx);
+        commit.started = Some(now);
+        commit.collector = Some(collector.to_string());
+        commit.lease_expires = Some(now + lease_duration());
+        info!("{} claimed {}", collector, commit.key());
+        state.in_progress.push(commit.clone());
+        self.save(&state)?;
+        Ok(Some(commit))
+    }
+
+    /// Record that `collector` gathers `metrics`.
+    pub fn advertise(&self, collector: &str, metrics: BTreeSet<String>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.collector_metrics.get(collector) == Some(&metrics) {
+            return Ok(());
+        }
+        info!("{} gathers {:?}", collector, metrics);
+        state.collector_metrics.insert(collector.to_string(), metrics);
+        self.save(&state)
+    }
+
+    /// Extend the lease of `collector` on the entry `key`. Returns `false`
+    /// if it doesn't hold one anymore.
+    pub fn renew(&self, key: &str, collector: &str, now: DateTime<Utc>) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let held = match claim(&state, key, collector) {
+            Some(idx) => {
+                state.in_progress[idx].lease_expires = Some(now + lease_duration());
+                true
+            }
+            None => false,
+        };
+        if held {
+            self.save(&state)?;
+        }
+        Ok(held)
+    }
+
+    /// Record that `collector` failed to benchmark the entry `key`, ending
+    /// its claim. Returns `false` if it didn't hold one.
+    pub fn fail(&self, key: &str, collector: &str) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let commit = match claim(&state, key, collector) {
+            Some(idx) => state.in_progress.remove(idx),
+            None => return Ok(false),
+        };
+        warn!("{} failed to benchmark {}", collector, key);
+        requeue_failed(&mut state, commit);
+        self.save(&state)?;
+        Ok(true)
+    }
+
+    /// Record that `collector` pushed the results of the entry `key`, ending
+    /// its claim. The entry is done; the results arrive with the next reload
+    /// of the data. Returns `false` if it didn't hold a claim.
+    pub fn done(&self, key: &str, collector: &str, now: DateTime<Utc>) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let commit = match claim(&state, key, collector) {
+            Some(idx) => state.in_progress.remove(idx),
+            None => return Ok(false),
+        };
+        info!("{} benchmarked {}", collector, key);
+        if let Some(started) = commit.started {
+            record_run(&mut state, (now - started).num_seconds());
+        }
+        if commit.experiment.is_some() {
+            state.finished_experiments.push(commit);
+        }
+        self.save(&state)?;
+        Ok(true)
+    }
+
+    /// Drop the commits that `data` has results for, as of `now`. Experiments
+    /// are done once results with their flag set are in, calibrations once
+    /// there is a calibration run newer than the entry.
+    pub fn retire(&self, data: &InputData, now: DateTime<Utc>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let has_data = |c: &QueuedCommit| {
+            if c.calibration {
+                return data.calibration
+                    .iter()
+                    .any(|run| run.data.commit.sha == c.sha && run.date >= c.queued);
+            }
+            data.data.values().any(|commits| {
+                commits
+                    .values()
+                    .filter(|commit| commit.commit.sha == c.sha)
+                    .any(|commit| match c.experiment {
+                        Some(ref experiment) => commit
+                            .benchmarks
+                            .values()
+                            .filter_map(|result| result.as_ref().ok())
+                            .flat_map(|patches| patches)
+                            .any(|patch| patch.flags.as_ref() == Some(&experiment.name)),
+                        None => true,
+                    })
+            })
+        };
+        let (done, pending): (Vec<_>, Vec<_>) = state.pending.drain(..).partition(&has_data);
+        let (more_done, in_progress): (Vec<_>, Vec<_>) =
+            state.in_progress.drain(..).partition(&has_data);
+        let (failed_done, failed): (Vec<_>, Vec<_>) = state.failed.drain(..).partition(&has_data);
+        let (canceled_done, canceled): (Vec<_>, Vec<_>) =
+            state.canceled.drain(..).partition(&has_data);
+        state.pending = pending;
+        state.in_progress = in_progress;
+        state.failed = failed;
+        state.canceled = canceled;
+        for started in more_done.iter().filter_map(|commit| commit.started) {
+            record_run(&mut state, (now - started).num_seconds());
+        }
+        let mut changed = false;
+        for commit in done.into_iter()
+            .chain(more_done)
+            .chain(failed_done)
+            .chain(canceled_done)
+        {
+            changed = true;
+            if commit.experiment.is_some() {
+                state.finished_experiments.push(commit);
+            }
+        }
+        if changed {
+            self.save(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the entry `key` from the queue on behalf of `user`, whether it
+    /// is pending or being benchmarked.
+    pub fn cancel(&self, key: &str, user: &User) -> Result<QueuedCommit> {
+        let mut state = self.state.lock().unwrap();
+        let pending = state.pending.iter().position(|c| c.key() == key);
+        let in_progress = state.in_progress.iter().position(|c| c.key() == key);
+        let commit = match (pending, in_progress) {
+            (Some(idx), _) => state.pending.remove(idx),
+            (None, Some(idx)) => state.in_progress.remove(idx),
+            (None, None) => bail!("{} is neither pending nor being benchmarked", key),
+        };
+        info!("{} canceled {}", user.name, key);
+        state.canceled.push(commit.clone());
+        self.save(&state)?;
+        Ok(commit)
+    }
+
+    /// Queue the failed or canceled entry `key` again on behalf of `user`,
+    /// with its attempts reset.
+    pub fn retry(&self, key: &str, user: &User) -> Result<QueuedCommit> {
+        let mut state = self.state.lock().unwrap();
+        let failed = state.failed.iter().position(|c| c.key() == key);
+        let canceled = state.canceled.iter().position(|c| c.key() == key);
+        let mut commit = match (failed, canceled) {
+            (Some(idx), _) => state.failed.remove(idx),
+            (None, Some(idx)) => state.canceled.remove(idx),
+            (None, None) => bail!("{} has neither failed nor been canceled", key),
+        };
+        info!("{} queued {} again", user.name, key);
+        commit.attempts = 0;
+        commit.started = None;
+        commit.collector = None;
+        commit.lease_expires = None;
+        enqueue(&mut state, commit.clone());
+        self.save(&state)?;
+        Ok(commit)
+    }
+
+    /// Whether the entry `key` was canceled while `collector` benchmarked it.
+    pub fn canceled(&self, key: &str, collector: &str) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .canceled
+            .iter()
+            .any(|c| c.key() == key && c.collector.as_ref().map(|c| &**c) == Some(collector))
+    }
+
+    /// Move the pending entry `key` to `priority`, up or down, on behalf of
+    /// `user`.
+    pub fn set_priority(
+        &self,
+        key: &str,
+        priority: Priority,
+        user: &User,
+    ) -> Result<QueuedCommit> {
+        let mut state = self.state.lock().unwrap();
+        let commit = match state.pending.iter_mut().find(|c| c.key() == key) {
+            Some(commit) => {
+                commit.priority = priority;
+                commit.clone()
+            }
+            None => bail!("{} isn't pending", key),
+        };
+        info!("{} moved {} to {:?}", user.name, key, priority);
+        sort_pending(&mut state);
+        self.save(&state)?;
+        Ok(commit)
+    }
+
+    /// The entries being benchmarked and those pending, in the order they
+    /// are handed out, with estimates based on the median of the recent
+    /// runs. As many collectors as are busy now are assumed to work through
+    /// the pending entries.
+    pub fn estimates(&self, now: DateTime<Utc>) -> Vec<Estimate> {
+        let state = self.state.lock().unwrap();
+        let mut durations = state.run_durations.clone();
+        durations.sort();
+        let duration = durations.get(durations.len() / 2).cloned();
+
+        // When each collector will be free to claim the next entry
+        let mut free = Vec::new();
+        let mut estimates = Vec::new();
+        for commit in &state.in_progress {
+            let start = commit.started;
+            if let (Some(start), Some(duration)) = (start, duration) {
+                free.push(cmp::max(now, start + Duration::seconds(duration)));
+            }
+            estimates.push(Estimate {
+                key: commit.key(),
+                commit: commit.clone(),
+                start: start,
+                duration: duration,
+            });
+        }
+        if free.is_empty() {
+            free.push(now);
+        }
+        for commit in &state.pending {
+            let start = duration.map(|duration| {
+                let slot = (0..free.len()).min_by_key(|&idx| free[idx]).unwrap();
+                let start = free[slot];
+                free[slot] = start + Duration::seconds(duration);
+                start
+            });
+            estimates.push(Estimate {
+                key: commit.key(),
+                commit: commit.clone(),
+                start: start,
+                duration: duration,
+            });
+        }
+        estimates
+    }
+
+    pub fn has_rollup(&self, sha: &str) -> bool {
+        self.state.lock().unwrap().rollups.iter().any(|r| r.sha == sha)
+    }
+
+    /// Record that the regressed commit `sha` merged a rollup of `prs`.
+    pub fn add_rollup(&self, sha: &str, prs: Vec<RollupPr>, now: DateTime<Utc>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.rollups.iter().any(|r| r.sha == sha) {
+            return Ok(());
+        }
+        info!("{} is a rollup of {} pull requests; unrolling", sha, prs.len());
+        state.rollups.push(Rollup {
+            sha: sha.to_string(),
+            detected: now,
+            prs: prs.into_iter()
+                .map(|pr| UnrolledPr {
+                    number: pr.number,
+                    title: pr.title,
+                    try_sha: None,
+                })
+                .collect(),
+        });
+        self.save(&state)
+    }
+
+    /// Enqueue `commit`, a try build of pull request `pr`, if `pr` is part
+    /// of a regressed rollup and has no try build yet. Returns whether it
+    /// was queued.
+    pub fn push_try_build(&self, pr: u64, commit: QueuedCommit) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        {
+            let unrolled = state
+                .rollups
+                .iter_mut()
+                .flat_map(|rollup| rollup.prs.iter_mut())
+                .find(|unrolled| unrolled.number == pr && unrolled.try_sha.is_none());
+            match unrolled {
+                Some(unrolled) => unrolled.try_sha = Some(commit.sha.clone()),
+                None => return Ok(false),
+            }
+        }
+        enqueue(&mut state, commit);
+        self.save(&state)?;
+        Ok(true)
+    }
+
+    fn save(&self, state: &State) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        serde_json::to_writer(&mut File::create(&tmp)?, state)?;
+        fs::rename(&tmp, &self.path)
+            .chain_err(|| format!("failed to write queue {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// Add `commit` to the pending commits unless an entry with the same key is
+/// already queued, being benchmarked, given up on or canceled. A pending entry
+/// gets the higher of both priorities. Returns whether the queue changed.
+fn enqueue(state: &mut State, commit: QueuedCommit) -> bool {
+    let key = commit.key();
+    if state
+        .in_progress
+        .iter()
+        .chain(&state.failed)
+        .chain(&state.canceled)
+        .any(|c| c.key() == key)
+    {
+        return false;
+    }
+    match state.pending.iter().position(|c| c.key() == key) {
+        Some(idx) => {
+            if state.pending[idx].priority >= commit.priority {
+                return false;
+            }
+            state.pending[idx].priority = commit.priority;
+        }
+        None => {
+            info!("queueing {} ({:?}, {})", key, commit.priority, commit.date);
+            state.pending.push(commit);
+        }
+    }
+    sort_pending(state);
+    true
+}
+
+/// Order the pending commits by priority, newest first within each.
+fn sort_pending(state: &mut State) {
+    state
+        .pending
+        .sort_by(|a, b| b.priority.cmp(&a.priority).then_with(|| b.date.cmp(&a.date)));
+}
+
+/// Queue `commit`, whose claim ended without results, again, or give up on
+/// it after `MAX_ATTEMPTS`.
+fn requeue_failed(state: &mut State, mut commit: QueuedCommit) {
+    commit.attempts += 1;
+    commit.started = None;
+    commit.collector = None;
+    commit.lease_expires = None;
+    if commit.attempts >= MAX_ATTEMPTS {
+        warn!("giving up on {} after {} attempts", commit.key(), commit.attempts);
+        state.failed.push(commit);
+    } else {
+        enqueue(state, commit);
+    }
+}
+
+/// Record that a run took `seconds` from the claim to the results, keeping
+/// the `RECENT_RUNS` most recent.
+fn record_run(state: &mut State, seconds: i64) {
+    state.run_durations.push(seconds);
+    let excess = state.run_durations.len().saturating_sub(RECENT_RUNS);
+    state.run_durations.drain(..excess);
+}
+
+/// The index of the entry `key` in `in_progress` if `collector` holds it.
+fn claim(state: &State, key: &str, collector: &str) -> Option<usize> {
+    state
+        .in_progress
+        .iter()
+        .position(|c| c.key() == key && c.collector.as_ref().map(|c| &**c) == Some(collector))
+}
+
+/// The parts of a GitHub `push` event we use.
+#[derive(Debug, Deserialize)]
+pub struct PushEvent {
+    #[serde(rename = "ref")] pub ref_: String,
+    /// `None` when a branch is deleted
+    pub head_commit: Option<PushCommit>,
+    pub repository: PushRepository,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PushRepository {
+    /// e.g. `rust-lang/rust`
+    pub full_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PushCommit {
+    pub id: String,
+    /// ISO 8601 with an offset, e.g. `2017-10-12T05:23:13-07:00`
+    pub timestamp: String,
+    pub message: String,
+}
+
+impl PushEvent {
+    /// The commit to benchmark, if this push merged one into master. bors
+    /// pushes one merge commit at a time; only it has CI artifacts, the
+    /// commits of the merged PR don't.
+    pub fn merged_commit(&self, now: DateTime<Utc>) -> Result<Option<QueuedCommit>> {
+        if self.ref_ != "refs/heads/master" {
+            return Ok(None);
+        }
+        self.head(now)
+    }
+
+    /// The try build pushed by this event and the pull request it tests, if
+    /// this is a bors push to the `try` branch.
+    pub fn try_build(&self, now: DateTime<Utc>) -> Result<Option<(u64, QueuedCommit)>> {
+        if self.ref_ != "refs/heads/try" {
+            return Ok(None);
+        }
+        Ok(self.head(now)?.and_then(|mut commit| {
+            commit.priority = Priority::Try;
+            github::bors_pr(&commit.summary).map(|pr| (pr, commit))
+        }))
+    }
+
+    fn head(&self, now: DateTime<Utc>) -> Result<Option<QueuedCommit>> {
+        let head = match self.head_commit {
+            Some(ref head) => head,
+            None => return Ok(None),
+        };
+        Ok(Some(QueuedCommit::new(
+            head.id.clone(),
+            DateTime::parse_from_rfc3339(&head.timestamp)?.with_timezone(&Utc),
+            head.message.lines().next().unwrap_or("").to_string(),
+            Priority::Master,
+            now,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::TimeZone;
+    use tempdir::TempDir;
+
+    use auth::Role;
+    use load::{CalibrationRun, CommitData};
+    use super::*;
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+    /// An empty queue persisted to a directory of its own, which is removed
+    /// with the returned `TempDir`.
+    fn queue() -> (TempDir, Queue) {
+        let dir = TempDir::new("rustc-perf-queue").unwrap();
+        let queue = Queue::open(dir.path().join("queue.json")).unwrap();
+        (dir, queue)
+    }
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.ymd(2017, 10, day).and_hms(hour, 0, 0)
+    }
+
+    fn commit(sha: &str, day: u32, priority: Priority) -> QueuedCommit {
+        let summary = format!("Merge {}", sha);
+        QueuedCommit::new(sha.to_string(), at(day, 0), summary, priority, at(day, 0))
+    }
+
+    fn keys(commits: &[QueuedCommit]) -> Vec<String> {
+        commits.iter().map(|c| c.key()).collect()
+    }
+
+    /// Results of `sha`, with the flag set `flags` if any.
+    fn results(sha: &str, flags: Option<&str>) -> CommitData {
+        let flags = flags.map_or(String::from("null"), |flags| format!("\"{}\"", flags));
+        serde_json::from_str(&format!(
+            r#"{{
+                "commit": {{ "sha": "{}", "date": "2017-10-01T00:00:00Z" }},
+                "benchmarks": {{
+                    "helloworld": {{ "Ok": [{{
+                        "name": "helloworld",
+                        "flags": {},
+                        "runs": [{{ "stats": [{{ "name": "instructions:u", "cnt": 1.0 }}] }}]
+                    }}] }}
+                }},
+                "triple": "{}"
+            }}"#,
+            sha,
+            flags,
+            TARGET
+        )).unwrap()
+    }
+
+    fn input_data(results: Vec<CommitData>) -> InputData {
+        let mut commits = BTreeMap::new();
+        for data in results {
+            commits.insert(data.commit.clone(), data);
+        }
+        let mut data = BTreeMap::new();
+        data.insert(TARGET.to_string(), commits);
+        InputData::new(data).unwrap()
+    }
+
+    #[test]
+    fn enqueue_dedups_and_raises_priority() {
+        let (_dir, queue) = queue();
+        queue.push(commit("aaa", 1, Priority::Backfill)).unwrap();
+        queue.push(commit("bbb", 2, Priority::Backfill)).unwrap();
+        queue.push(commit("ccc", 3, Priority::Master)).unwrap();
+        assert_eq!(keys(&queue.state().pending), ["ccc", "bbb", "aaa"]);
+
+        queue.push(commit("aaa", 1, Priority::Master)).unwrap();
+        assert_eq!(keys(&queue.state().pending), ["ccc", "aaa", "bbb"]);
+        // Queueing it again at a lower priority doesn't lower it.
+        queue.push(commit("aaa", 1, Priority::Backfill)).unwrap();
+        let state = queue.state();
+        assert_eq!(keys(&state.pending), ["ccc", "aaa", "bbb"]);
+        assert_eq!(state.pending[1].priority, Priority::Master);
+
+        // The state survives a restart.
+        let reopened = Queue::open(queue.path.clone()).unwrap();
+        assert_eq!(reopened.state(), state);
+    }
+
+    #[test]
+    fn next_claims_the_first_pending_entry() {
+        let (_dir, queue) = queue();
+        queue.push(commit("aaa", 1, Priority::Backfill)).unwrap();
+        queue.push(commit("bbb", 2, Priority::Try)).unwrap();
+
+        let claimed = queue.next("mine", at(5, 0)).unwrap().unwrap();
+        assert_eq!(claimed.key(), "bbb");
+        assert_eq!(claimed.collector, Some(String::from("mine")));
+        assert_eq!(claimed.lease_expires, Some(at(5, 1)));
+        // Asking for more work leaves the claims a collector holds alone.
+        assert_eq!(queue.next("mine", at(5, 0)).unwrap().unwrap().key(), "aaa");
+        assert_eq!(queue.next("third", at(5, 0)).unwrap(), None);
+        let state = queue.state();
+        assert_eq!(state.in_progress.len(), 2);
+        assert!(state.in_progress.iter().all(|c| c.attempts == 0));
+    }
+
+    #[test]
+    fn done_ends_the_claim() {
+        let (_dir, queue) = queue();
+        queue.push(commit("aaa", 1, Priority::Master)).unwrap();
+        queue.next("a", at(5, 0)).unwrap().unwrap();
+
+        assert!(!queue.done("aaa", "b", at(5, 0)).unwrap());
+        assert!(queue.done("aaa", "a", at(5, 0) + Duration::minutes(40)).unwrap());
+        assert!(!queue.done("aaa", "a", at(5, 1)).unwrap());
+        let state = queue.state();
+        assert!(state.in_progress.is_empty());
+        assert!(state.pending.is_empty());
+        assert!(state.failed.is_empty());
+        assert_eq!(state.run_durations, vec![40 * 60]);
+        // Nothing is left to be abandoned once the lease would have expired.
+        assert_eq!(queue.next("a", at(6, 0)).unwrap(), None);
+    }
+
+    #[test]
+    fn advertise_records_metrics_of_collectors() {
+        let (_dir, queue) = queue();
+        let metrics = |names: &[&str]| names.iter().map(|m| m.to_string()).collect::<BTreeSet<_>>();
+        queue.advertise("arm", metrics(&["max-rss", "wall-time"])).unwrap();
+        queue.advertise("x86", metrics(&["instructions:u", "max-rss"])).unwrap();
+        queue.advertise("arm", metrics(&["wall-time"])).unwrap();
+
+        let reopened = Queue::open(queue.path.clone()).unwrap();
+        let state = reopened.state();
+        assert_eq!(state.collector_metrics.len(), 2);
+        assert_eq!(state.collector_metrics["arm"], metrics(&["wall-time"]));
+        assert_eq!(state.collector_metrics["x86"], metrics(&["instructions:u", "max-rss"]));
+    }
+
+    #[test]
+    fn next_skips_local_toolchains_of_others() {
+        let (_dir, queue) = queue();
+        let mut local = commit("local-fork", 2, Priority::Local);
+        local.local = Some(LocalToolchain {
+            rustc: String::from("/home/me/rust/build/stage2/bin/rustc"),
+            collector: String::from("other"),
+            base: None,
+        });
+        queue.push(local).unwrap();
+        queue.push(commit("aaa", 1, Priority::Backfill)).unwrap();
+
+        assert_eq!(queue.next("mine", at(5, 0)).unwrap().unwrap().key(), "aaa");
+        assert_eq!(queue.next("third", at(5, 0)).unwrap(), None);
+        let claimed = queue.next("other", at(5, 0)).unwrap().unwrap();
+        assert_eq!(claimed.key(), "local-fork");
+        assert_eq!(claimed.local.unwrap().rustc, "/home/me/rust/build/stage2/bin/rustc");
+
+        // Only the collector learns the path.
+        let state = queue.state().redacted();
+        let local = state.in_progress[1].local.as_ref().unwrap();
+        assert_eq!(local.rustc, "");
+        assert_eq!(local.collector, "other");
+    }
+
+    #[test]
+    fn renew_extends_lease() {
+        let (_dir, queue) = queue();
+        queue.push(commit("aaa", 1, Priority::Master)).unwrap();
+        queue.next("a", at(5, 0)).unwrap().unwrap();
+
+        assert!(!queue.renew("aaa", "b", at(5, 0)).unwrap());
+        assert!(!queue.renew("bbb", "a", at(5, 0)).unwrap());
+        assert!(queue.renew("aaa", "a", at(5, 1)).unwrap());
+        assert_eq!(queue.state().in_progress[0].lease_expires, Some(at(5, 2)));
+
+        // Still held by `a`.
+        assert_eq!(queue.next("b", at(5, 1)).unwrap(), None);
+        // The lease expired, so the entry is queued again.
+        let claimed = queue.next("b", at(5, 3)).unwrap().unwrap();
+        assert_eq!(claimed.key(), "aaa");
+        assert_eq!(claimed.collector, Some(String::from("b")));
+        assert_eq!(claimed.attempts, 1);
+        assert!(!queue.renew("aaa", "a", at(5, 3)).unwrap());
+    }
+
+    #[test]
+    fn fail_gives_up_after_max_attempts() {
+        let (_dir, queue) = queue();
+        queue.push(commit("aaa", 1, Priority::Master)).unwrap();
+        assert!(!queue.fail("aaa", "a").unwrap());
+        for attempt in 0..MAX_ATTEMPTS {
+            let claimed = queue.next("a", at(5, attempt)).unwrap().unwrap();
+            assert_eq!(claimed.attempts, attempt);
+            assert!(!queue.fail("aaa", "b").unwrap());
+            assert!(queue.fail("aaa", "a").unwrap());
+        }
+        let state = queue.state();
+        assert!(state.pending.is_empty() && state.in_progress.is_empty());
+        assert_eq!(keys(&state.failed), ["aaa"]);
+
+        // Entries given up on aren't queued again.
+        queue.push(commit("aaa", 1, Priority::Master)).unwrap();
+        assert!(queue.state().pending.is_empty());
+    }
+
+    #[test]
+    fn retire_drops_entries_with_results() {
+        let (_dir, queue) = queue();
+        let mut experiment = commit("aaa", 1, Priority::Experiment);
+        experiment.experiment = Some(Experiment {
+            name: String::from("no-debug-assertions"),
+            flags: String::from("-Cdebug-assertions=no"),
+        });
+        let mut calibration = commit("ccc", 1, Priority::Calibration);
+        calibration.calibration = true;
+        calibration.queued = at(4, 0);
+        queue.push(commit("aaa", 1, Priority::Master)).unwrap();
+        queue.push(commit("bbb", 2, Priority::Master)).unwrap();
+        queue.push(experiment).unwrap();
+        queue.push(calibration).unwrap();
+        assert_eq!(queue.next("a", at(5, 0)).unwrap().unwrap().key(), "bbb");
+
+        let mut data = input_data(vec![results("aaa", None), results("bbb", None)]);
+        data.calibration.push(CalibrationRun {
+            date: at(3, 0),
+            data: results("ccc", None),
+        });
+        queue.retire(&data, at(5, 2)).unwrap();
+        let state = queue.state();
+        assert_eq!(
+            keys(&state.pending),
+            ["aaa:no-debug-assertions", "ccc:calibration-2017-10-04"]
+        );
+        assert!(state.in_progress.is_empty());
+        assert_eq!(state.run_durations, [2 * 60 * 60]);
+
+        // Experiments are done once results with their flag set are in, and
+        // calibrations once there is a run newer than the entry.
+        let mut data = input_data(vec![results("aaa", Some("no-debug-assertions"))]);
+        data.calibration.push(CalibrationRun {
+            date: at(5, 0),
+            data: results("ccc", None),
+        });
+        queue.retire(&data, at(6, 0)).unwrap();
+        let state = queue.state();
+        assert!(state.pending.is_empty());
+        assert_eq!(keys(&state.finished_experiments), ["aaa:no-debug-assertions"]);
+    }
+
+    #[test]
+    fn cancel_pending_and_in_progress() {
+        let (_dir, queue) = queue();
+        let member = User {
+            name: String::from("member"),
+            role: Role::Member,
+        };
+        queue.push(commit("aaa", 1, Priority::Master)).unwrap();
+        queue.push(commit("bbb", 2, Priority::Master)).unwrap();
+        assert_eq!(queue.next("a", at(5, 0)).unwrap().unwrap().key(), "bbb");
+
+        queue.cancel("aaa", &member).unwrap();
+        queue.cancel("bbb", &member).unwrap();
+        assert!(queue.cancel("ccc", &member).is_err());
+        assert!(queue.canceled("bbb", "a"));
+        assert!(!queue.canceled("aaa", "a"));
+        assert!(!queue.renew("bbb", "a", at(5, 1)).unwrap());
+
+        // Canceled entries aren't queued again.
+        queue.push(commit("aaa", 1, Priority::Master)).unwrap();
+        let state = queue.state();
+        assert!(state.pending.is_empty() && state.in_progress.is_empty());
+        assert_eq!(keys(&state.canceled), ["aaa", "bbb"]);
+    }
+
+    #[test]
+    fn retry_failed_and_canceled() {
+        let (_dir, queue) = queue();
+        let member = User {
+            name: String::from("member"),
+            role: Role::Member,
+        };
+        queue.push(commit("aaa", 1, Priority::Master)).unwrap();
+        queue.push(commit("bbb", 2, Priority::Master)).unwrap();
+        assert!(queue.retry("aaa", &member).is_err());
+        for attempt in 0..MAX_ATTEMPTS {
+            assert_eq!(queue.next("a", at(5, attempt)).unwrap().unwrap().key(), "bbb");
+            assert!(queue.fail("bbb", "a").unwrap());
+        }
+        assert_eq!(queue.next("a", at(6, 0)).unwrap().unwrap().key(), "aaa");
+        queue.cancel("aaa", &member).unwrap();
+
+        let retried = queue.retry("bbb", &member).unwrap();
+        assert_eq!(retried.attempts, 0);
+        let retried = queue.retry("aaa", &member).unwrap();
+        assert_eq!(retried.collector, None);
+        let state = queue.state();
+        assert_eq!(keys(&state.pending), ["bbb", "aaa"]);
+        assert!(state.failed.is_empty() && state.canceled.is_empty());
+        assert!(!queue.canceled("aaa", "a"));
+        assert_eq!(queue.next("a", at(6, 1)).unwrap().unwrap().attempts, 0);
+    }
+}
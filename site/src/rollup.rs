@@ -0,0 +1,108 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The pull requests rolled up into master commits, so that a change at a
+//! rollup can be narrowed down to the pull requests that might have caused
+//! it.
+//!
+//! A rollup is compared against the master commit before it like any other
+//! commit, but the comparison lists its pull requests. Asking GitHub for
+//! them takes two requests per rollup, so they are fetched when the data is
+//! updated rather than when a comparison is requested, and persisted to the
+//! JSON file named by `SITE_ROLLUPS` (`rollups.json` by default). Merged
+//! commits don't change, so entries never expire.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use rust_sysroot::git::Commit as GitCommit;
+use serde_json;
+
+use errors::*;
+use github::{self, RollupPr};
+
+/// Most rollups to look up per update, to stay well within GitHub's rate
+/// limit. The cache catches up on older rollups over the following updates.
+const MAX_FETCHES: usize = 10;
+
+pub struct RollupCache {
+    path: PathBuf,
+    /// The pull requests of each rollup, by full hash
+    prs: Mutex<BTreeMap<String, Vec<RollupPr>>>,
+}
+
+impl RollupCache {
+    pub fn from_env() -> Result<RollupCache> {
+        let path = env::var_os("SITE_ROLLUPS")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("rollups.json"));
+        RollupCache::open(path)
+    }
+
+    /// The rollups persisted to `path`, none if there is no such file yet.
+    pub fn open(path: PathBuf) -> Result<RollupCache> {
+        let prs = if path.exists() {
+            let file =
+                File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+            serde_json::from_reader(file)
+                .chain_err(|| format!("failed to parse {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(RollupCache {
+            path: path,
+            prs: Mutex::new(prs),
+        })
+    }
+
+    /// The pull requests rolled up into `sha`, if it is a rollup whose pull
+    /// requests were fetched already.
+    pub fn get(&self, sha: &str) -> Option<Vec<RollupPr>> {
+        self.prs.lock().unwrap().get(sha).cloned()
+    }
+
+    /// Fetch the pull requests of the rollups among the master `commits`
+    /// with results that aren't cached yet, newest first.
+    pub fn fetch(&self, commits: &[GitCommit]) -> Result<()> {
+        let mut rollups = commits
+            .iter()
+            .filter(|commit| github::is_rollup(&commit.summary))
+            .collect::<Vec<_>>();
+        rollups.sort_by(|a, b| b.date.cmp(&a.date));
+
+        let mut fetched = 0;
+        for commit in rollups {
+            if fetched == MAX_FETCHES {
+                break;
+            }
+            if self.prs.lock().unwrap().contains_key(&commit.sha) {
+                continue;
+            }
+            // Not holding the lock while waiting for GitHub.
+            let prs = github::rollup_prs(&commit.sha)?;
+            info!("{} is a rollup of {} pull requests", commit.sha, prs.len());
+            let mut cached = self.prs.lock().unwrap();
+            cached.insert(commit.sha.clone(), prs);
+            self.save(&cached)?;
+            fetched += 1;
+        }
+        Ok(())
+    }
+
+    fn save(&self, prs: &BTreeMap<String, Vec<RollupPr>>) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        serde_json::to_writer(&mut File::create(&tmp)?, prs)?;
+        fs::rename(&tmp, &self.path)
+            .chain_err(|| format!("failed to write rollups {}", self.path.display()))?;
+        Ok(())
+    }
+}
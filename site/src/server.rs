@@ -7,15 +7,16 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::cmp;
 use std::str;
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::fmt::Write;
 use std::sync::{Arc, RwLock};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 use serde::Serialize;
 use serde::de::DeserializeOwned;
@@ -23,66 +24,174 @@ use serde_json;
 use futures::{self, Future, Stream};
 use futures_cpupool::CpuPool;
 use hyper::{self, Get, Post, StatusCode};
-use hyper::header::{CacheControl, CacheDirective, ContentLength, ContentType};
+use hyper::header::{Authorization, Bearer, CacheControl, CacheDirective, ContentLength,
+                    ContentType, ETag, EntityTag, IfNoneMatch};
 use hyper::mime;
 use hyper::server::{Http, Request, Response, Service};
 use url::Url;
+use chrono::{DateTime, Utc};
 
 use git;
-use date::Date;
+use github::{self, RollupPr};
+use analysis::{self, Change, Direction};
+use auth::{Auth, Role, User};
+use annotation::Annotations;
+use baseline::Baselines;
+use cache::ResponseCache;
+use channels::Channels;
+use exclusion::Exclusions;
+use feed;
+use frontend::{Asset, Frontend};
+use metrics::Metrics;
+use nightly::Notifier;
+use noise;
+use snapshot::Snapshots;
+use queue::{Experiment, LocalToolchain, Priority, PushEvent, Queue, QueuedCommit, Rollup};
+use rollup::RollupCache;
+use date::{Bound, Date, End, OptionalDate, Start};
 use util::{self, get_repo_path};
-pub use api::{self, data, days, info, stats, CommitResponse};
-use load::{CommitData, InputData};
+pub use api::{self, annotations, baselines, bootstrap, calibration, collectors, data, days,
+              exclusions, experiment, info, llvm_passes, local_toolchain, queue, self_profile,
+              snapshot, stats, timings, triage, v1, CommitResponse};
+use load::{self, Backend, Category, CommitData, CrateTiming, InputData, LlvmPassTiming, Patch,
+           Profile, QueryTiming, Std};
 
 use errors::*;
 
-/// Data associated with a specific date
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct DateData {
-    pub date: Date,
-    pub commit: String,
-    pub data: HashMap<String, f64>,
+pub use api::data::DateData;
+pub use api::stats::Stats;
+
+/// Which of the builds recorded for each patch a request is about.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Selector<'a> {
+    pub profile: Profile,
+    pub backend: Backend,
+    pub std: Std,
+    pub flags: Option<&'a str>,
+    /// Custom Cargo profile; `None` for builds with the profile settings of
+    /// the benchmarks' manifests
+    pub custom_profile: Option<&'a str>,
+    /// Class of the machines to take results from; `None` for any
+    pub machine: Option<&'a str>,
 }
 
-impl DateData {
-    pub fn for_day(day: &CommitData, stat: &str) -> DateData {
-        let crates = day.benchmarks
-            .values()
-            .filter(|v| v.is_ok())
-            .flat_map(|patches| patches.as_ref().unwrap())
-            .collect::<Vec<_>>();
+impl<'a> Selector<'a> {
+    pub fn new(profile: Profile, backend: Backend, flags: &'a Option<String>) -> Selector<'a> {
+        Selector {
+            profile: profile,
+            backend: backend,
+            std: Std::Static,
+            flags: flags.as_ref().map(|f| &**f),
+            custom_profile: None,
+            machine: None,
+        }
+    }
 
-        let mut data = HashMap::new();
-        for patch in &crates {
-            if let Some(stat) = patch.run().get_stat(stat) {
-                data.insert(patch.name.clone(), stat);
-            }
+    /// Select the builds with the custom profile `custom_profile`, if any.
+    pub fn with_custom_profile(self, custom_profile: &'a Option<String>) -> Selector<'a> {
+        Selector {
+            custom_profile: custom_profile.as_ref().map(|c| &**c),
+            ..self
         }
+    }
 
-        DateData {
-            date: day.commit.date,
-            commit: day.commit.sha.clone(),
-            data: data,
+    /// Select the builds against the standard library built as `std`.
+    pub fn with_std(self, std: Std) -> Selector<'a> {
+        Selector { std: std, ..self }
+    }
+
+    /// Only select results from machines of class `machine`, if any.
+    pub fn on(self, machine: Option<&'a str>) -> Selector<'a> {
+        Selector {
+            machine: machine,
+            ..self
         }
     }
+
+    /// Whether `patch` of the results `day` is selected.
+    pub fn matches(&self, day: &CommitData, patch: &Patch) -> bool {
+        patch.profile == self.profile && patch.backend == self.backend && patch.std == self.std
+            && patch.flags.as_ref().map(|f| &**f) == self.flags
+            && patch.custom_profile.as_ref().map(|c| &**c) == self.custom_profile
+            && self.machine
+                .map_or(true, |machine| day.machine_class(patch) == Some(machine))
+    }
+}
+
+/// The values of `stat` of the patches `selector` selects in `day`.
+pub fn date_data(day: &CommitData, stat: &str, selector: Selector) -> DateData {
+    let crates = day.benchmarks
+        .values()
+        .filter(|v| v.is_ok())
+        .flat_map(|patches| patches.as_ref().unwrap())
+        .filter(|patch| selector.matches(day, patch))
+        .collect::<Vec<_>>();
+
+    let mut data = HashMap::new();
+    for patch in &crates {
+        if let Some(stat) = patch.run().get_stat(stat) {
+            data.insert(patch.name.to_string(), stat);
+        }
+    }
+
+    DateData {
+        date: day.commit.date,
+        commit: day.commit.sha.clone(),
+        data: data,
+    }
 }
 
 pub fn handle_info(data: &InputData) -> info::Response {
     info::Response {
         crates: data.crate_list.clone(),
         stats: data.stats_list.clone(),
+        backends: data.backend_list.clone(),
+        stds: data.std_list.clone(),
+        targets: data.data.keys().cloned().collect(),
+        flag_sets: data.flag_set_list.clone(),
+        profiles: data.profile_list.clone(),
+        custom_profiles: data.custom_profile_list.clone(),
+        machines: data.machine_list.clone(),
+        benchmarks: data.first_commits
+            .iter()
+            .map(|(name, first_commit)| {
+                let benchmark = info::Benchmark {
+                    category: data.categories.get(name).cloned().unwrap_or_default(),
+                    info: data.benchmark_info.get(name).cloned(),
+                    first_commit: first_commit.clone(),
+                };
+                (name.clone(), benchmark)
+            })
+            .collect(),
         as_of: data.last_date,
     }
 }
 
-pub fn handle_data(body: data::Request, data: &InputData) -> data::Response {
-    let mut result =
-        util::optional_data_range(data, body.start_date.clone(), body.end_date.clone())
-            .map(|(_, day)| day)
-            .map(|day| {
-                DateData::for_day(day, &body.stat)
-            })
-            .collect::<Vec<_>>();
+pub fn handle_data(
+    body: data::Request,
+    data: &InputData,
+    annotations: &Annotations,
+) -> V1Result<data::Response> {
+    let commits = target_commits(data, &body.target)?;
+    let start = body.start_date.as_date(data.last_date);
+    let end = body.end_date.as_date(data.last_date);
+    let days = util::data_range(commits, start, end)
+        .map(|(_, day)| day)
+        .collect::<Vec<_>>();
+    // Results of different classes of machines don't form one series. By
+    // default, graph those of the machines the newest results are from.
+    let machine = body.machine
+        .as_ref()
+        .map(|machine| &**machine)
+        .or_else(|| days.iter().rev().filter_map(|&day| analysis::main_machine(day)).next());
+    let selector = Selector::new(body.profile, body.backend, &body.flags)
+        .with_std(body.std)
+        .with_custom_profile(&body.custom_profile)
+        .on(machine);
+    let mut result = days.into_iter()
+        .map(|day| date_data(day, &body.stat, selector))
+        .collect::<Vec<_>>();
+    let renames = stitch_renames(&mut result, data, commits);
 
     // Return everything from the first non-empty data to the last non-empty data.
     // Data may contain "holes" of empty data.
@@ -94,40 +203,270 @@ pub fn handle_data(body: data::Request, data: &InputData) -> data::Response {
         .iter()
         .rposition(|day| !day.data.is_empty())
         .unwrap_or(0);
-    let result = result.drain(first_idx..(last_idx + 1)).collect();
-    data::Response {
+    let mut result = result.drain(first_idx..(last_idx + 1)).collect::<Vec<_>>();
+    if body.smoothing > 1 {
+        smooth(&mut result, body.smoothing);
+    }
+    if body.relative {
+        make_relative(&mut result);
+    }
+    Ok(data::Response {
         data: result,
-        start: body.start_date.as_date(data.last_date),
-        end: body.end_date.as_date(data.last_date),
+        start: start,
+        end: end,
         crates: body.crates.into_set(&data.crate_list),
+        machine: machine.map(String::from),
+        renames: renames,
+        annotations: annotations.between(start, end),
+    })
+}
+
+/// Move the results of renamed benchmarks from before their renaming to the
+/// series of their new name. Renames are applied in order, so a benchmark
+/// renamed twice ends up in one series.
+fn stitch_renames(
+    days: &mut [DateData],
+    data: &InputData,
+    commits: &BTreeMap<load::Commit, CommitData>,
+) -> Vec<data::Rename> {
+    let mut renames = data.renames
+        .iter()
+        .filter_map(|rename| {
+            let cutover = commits.values().find(|c| c.commit.sha == rename.commit)?;
+            Some((cutover.commit.date, rename))
+        })
+        .collect::<Vec<_>>();
+    renames.sort_by_key(|&(date, _)| date);
+
+    let mut stitched = Vec::new();
+    for (cutover, rename) in renames {
+        let mut renamed = false;
+        for day in days.iter_mut().filter(|day| day.date < cutover) {
+            let names = day.data
+                .keys()
+                .filter(|name| name.split('@').next() == Some(&*rename.old))
+                .cloned()
+                .collect::<Vec<_>>();
+            for name in names {
+                let value = day.data.remove(&name).unwrap();
+                day.data.insert(format!("{}{}", rename.new, &name[rename.old.len()..]), value);
+                renamed = true;
+            }
+        }
+        if renamed {
+            stitched.push(data::Rename {
+                old: rename.old.clone(),
+                new: rename.new.clone(),
+                date: cutover,
+            });
+        }
     }
+    stitched
 }
 
-pub fn handle_days(body: days::Request, data: &InputData) -> days::Response {
-    days::Response {
-        a: DateData::for_day(
-            util::get_commit_data(data, body.commit_a),
-            &body.stat,
-        ),
-        b: DateData::for_day(
-            util::get_commit_data(data, body.commit_b),
-            &body.stat,
-        ),
+/// Replace each crate's values by the mean of the last `window` values
+/// (fewer at the start of the series).
+fn smooth(days: &mut [DateData], window: usize) {
+    let mut recent: HashMap<String, Vec<f64>> = HashMap::new();
+    for day in days {
+        for (name, value) in day.data.iter_mut() {
+            let recent = recent.entry(name.clone()).or_insert_with(Vec::new);
+            recent.push(*value);
+            if recent.len() > window {
+                recent.remove(0);
+            }
+            *value = recent.iter().sum::<f64>() / recent.len() as f64;
+        }
+    }
+}
+
+/// Replace each crate's values by the percent change from its first value.
+fn make_relative(days: &mut [DateData]) {
+    let mut firsts: HashMap<String, f64> = HashMap::new();
+    for day in days {
+        for (name, value) in day.data.iter_mut() {
+            let first = *firsts.entry(name.clone()).or_insert(*value);
+            *value = if first == 0.0 {
+                0.0
+            } else {
+                100.0 * (*value - first) / first
+            };
+        }
+    }
+}
+
+/// Compare like `handle_days` and keep the result as a snapshot.
+pub fn handle_take_snapshot(
+    body: snapshot::Request,
+    data: &InputData,
+    baselines: &Baselines,
+    rollups: &RollupCache,
+    snapshots: &Snapshots,
+) -> snapshot::CreateResponse {
+    let response = match handle_days(body.clone(), data, baselines, rollups) {
+        Ok(response) => response,
+        Err((_, error)) => {
+            return snapshot::CreateResponse {
+                id: None,
+                permalink: None,
+                error: Some(error.error),
+            }
+        }
+    };
+    match snapshots.take(body, response, Utc::now()) {
+        Ok(snapshot) => snapshot::CreateResponse {
+            permalink: Some(format!("/compare.html?snapshot={}", snapshot.id)),
+            id: Some(snapshot.id),
+            error: None,
+        },
+        Err(err) => {
+            error!("failed to take snapshot: {:?}", err);
+            snapshot::CreateResponse {
+                id: None,
+                permalink: None,
+                error: Some(err.to_string()),
+            }
+        }
     }
 }
 
-pub fn handle_stats(body: stats::Request, data: &InputData) -> stats::Response {
+pub fn handle_get_snapshot(id: &str, snapshots: &Snapshots) -> snapshot::Response {
+    match snapshots.get(id) {
+        Ok(Some(snapshot)) => snapshot::Response {
+            snapshot: Some(snapshot),
+            error: None,
+        },
+        Ok(None) => snapshot::Response {
+            snapshot: None,
+            error: Some(format!("there is no snapshot `{}`", id)),
+        },
+        Err(err) => snapshot::Response {
+            snapshot: None,
+            error: Some(err.to_string()),
+        },
+    }
+}
+
+pub fn handle_days(
+    body: days::Request,
+    data: &InputData,
+    baselines: &Baselines,
+    rollups: &RollupCache,
+) -> V1Result<days::Response> {
+    let commits = target_commits(data, &body.target)?;
+    let a = match body.baseline {
+        Some(ref name) => {
+            let sha = match baselines.get(name) {
+                Some(baseline) => baseline.sha,
+                None => return Err(not_found(format!("no baseline named {}", name))),
+            };
+            match commits.values().find(|day| day.commit.sha == sha) {
+                Some(day) => day,
+                None => {
+                    return Err(not_found(format!(
+                        "no results for baseline {} on target {}",
+                        name, body.target
+                    )))
+                }
+            }
+        }
+        None => commit_data(commits, &body.commit_a)?,
+    };
+    let b = commit_data(commits, &body.commit_b)?;
+    let machine = match body.machine {
+        Some(ref machine) => Some(&**machine),
+        None => analysis::common_machine(a, b).map_err(|err| not_found(err.to_string()))?,
+    };
+    let a_selector = Selector::new(body.profile, body.backend, &body.flags)
+        .with_std(body.std)
+        .with_custom_profile(&body.custom_profile)
+        .on(machine);
+    let b_selector = match body.flags_b {
+        Some(_) => Selector::new(body.profile, body.backend, &body.flags_b)
+            .with_std(body.std)
+            .with_custom_profile(&body.custom_profile)
+            .on(machine),
+        None => a_selector,
+    };
+    // Filtered here rather than in the browser, as large comparisons are
+    // megabytes of data.
+    let filter = analysis::Filter {
+        categories: body.categories.clone(),
+        scenarios: body.scenarios.clone(),
+    };
+    let for_day = |day: &CommitData, stat: &str, selector: Selector| {
+        let mut day_data = date_data(day, stat, selector);
+        day_data
+            .data
+            .retain(|name, _| filter.matches(&data.categories, name));
+        day_data
+    };
+    let extra_stats = body.extra_stats
+        .iter()
+        .filter(|stat| **stat != body.stat)
+        .map(|stat| {
+            let stat_data = days::StatData {
+                a: for_day(a, stat, a_selector).data,
+                b: for_day(b, stat, b_selector).data,
+            };
+            (stat.clone(), stat_data)
+        })
+        .collect();
+    let changes = analysis::changes_between(a, a_selector, b, b_selector, &body.stat, &data.noise)
+        .into_iter()
+        .filter(|change| filter.matches(&data.categories, &change.name))
+        .collect::<Vec<_>>();
+    let noise = changes
+        .iter()
+        .filter_map(|change| {
+            let score = data.noise.get(&a.triple, a_selector.profile, &body.stat, &change.name)?;
+            Some((change.name.clone(), score))
+        })
+        .collect();
+    let newly_failing = b.benchmarks
+        .keys()
+        .filter(|name| a.benchmarks.get(*name).map_or(false, |result| result.is_ok()))
+        .filter(|name| filter.matches_benchmark(&data.categories, name))
+        .filter_map(|name| {
+            b.failure(name).map(|failure| days::NewFailure {
+                benchmark: name.clone(),
+                failure: failure,
+            })
+        })
+        .collect();
+    Ok(days::Response {
+        a: for_day(a, &body.stat, a_selector),
+        b: for_day(b, &body.stat, b_selector),
+        suite_changes: a.suite_changes(b),
+        summary: analysis::summarize(&changes, &data.categories),
+        noise: noise,
+        weighted: if body.weighted {
+            analysis::weighted_summary(&changes, a, a_selector)
+        } else {
+            None
+        },
+        categories: data.categories.clone(),
+        extra_stats: extra_stats,
+        machine: machine.map(String::from),
+        newly_failing: newly_failing,
+        rollup: rollups.get(&b.commit.sha),
+    })
+}
+
+pub fn handle_stats(body: stats::Request, data: &InputData) -> V1Result<stats::Response> {
     let mut counted: HashMap<String, Vec<f64>> = HashMap::new();
     let mut start_date = body.start_date.as_date(data.last_date);
     let mut end_date = body.end_date.as_date(data.last_date);
-    for (_, commit_data) in util::data_range(&data.data, start_date, end_date) {
+    let commits = target_commits(data, &body.target)?;
+    for (_, commit_data) in util::data_range(commits, start_date, end_date) {
         if counted.is_empty() {
             start_date = commit_data.commit.date;
         }
         end_date = commit_data.commit.date;
-        let data = DateData::for_day(
+        let data = date_data(
             commit_data,
             &body.stat,
+            Selector::new(body.profile, body.backend, &body.flags).with_std(body.std),
         );
         for (name, rec) in data.data {
             counted.entry(name).or_insert_with(Vec::new).push(rec);
@@ -136,90 +475,812 @@ pub fn handle_stats(body: stats::Request, data: &InputData) -> stats::Response {
 
     let out = counted
         .into_iter()
-        .map(|(key, values)| (key, Stats::from(&values)))
+        .map(|(key, values)| (key, stats_of(&values)))
         .collect();
 
-    stats::Response {
+    Ok(stats::Response {
         start_date: start_date,
         end_date: end_date,
         data: out,
+    })
+}
+
+pub fn handle_bootstrap(body: bootstrap::Request, data: &InputData) -> bootstrap::Response {
+    let start = body.start_date.as_date(data.last_date);
+    let end = body.end_date.as_date(data.last_date);
+    bootstrap::Response {
+        data: util::data_range(&data.bootstrap, start, end)
+            .map(|(_, timings)| timings.clone())
+            .collect(),
+        start: start,
+        end: end,
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, Default)]
-pub struct Stats {
-    first: f64,
-    last: f64,
-    min: f64,
-    max: f64,
-    mean: f64,
-    variance: f64,
-    #[serde(deserialize_with = "util::null_means_nan")] trend: f64,
-    #[serde(deserialize_with = "util::null_means_nan")] trend_b: f64,
-    n: usize,
+/// How the calibration runs of each class of machine differ from the first
+/// and the previous run of the same artifact.
+pub fn handle_calibration(body: calibration::Request, data: &InputData) -> calibration::Response {
+    let no_flags = None;
+    let selector = Selector::new(body.profile, body.backend, &no_flags).with_std(body.std);
+    let mut machines = BTreeMap::new();
+    for (idx, run) in data.calibration.iter().enumerate() {
+        for machine in run.data.collectors.values() {
+            let class = &*machine.class;
+            let runs = machines.entry(machine.class.clone()).or_insert_with(Vec::new);
+            // A collector may have several machines of the same class.
+            if runs.last().map_or(false, |r: &calibration::Run| r.date == run.date) {
+                continue;
+            }
+            let on_class = |earlier: &&load::CalibrationRun| {
+                earlier.data.commit.sha == run.data.commit.sha
+                    && earlier.data.collectors.values().any(|m| m.class == class)
+            };
+            let first = data.calibration[..idx].iter().find(&on_class).unwrap_or(run);
+            let previous = data.calibration[..idx].iter().rev().find(&on_class);
+            let selector = selector.on(Some(class));
+            let drift =
+                analysis::changes(&first.data, &run.data, &body.stat, selector, &data.noise);
+            let drift = if drift.is_empty() {
+                0.0
+            } else {
+                let log_sum = drift.iter().map(|c| (c.b / c.a).ln()).sum::<f64>();
+                100.0 * ((log_sum / drift.len() as f64).exp() - 1.0)
+            };
+            let changes = match previous {
+                Some(previous) => analysis::significant_changes(
+                    &previous.data,
+                    &run.data,
+                    &body.stat,
+                    selector,
+                    &data.noise,
+                ),
+                None => Vec::new(),
+            };
+            let previous_machine = data.calibration[..idx]
+                .iter()
+                .rev()
+                .filter_map(|earlier| earlier.data.collectors.values().find(|m| m.class == class))
+                .next();
+            runs.push(calibration::Run {
+                date: run.date,
+                commit: run.data.commit.sha.clone(),
+                kernel: machine.kernel.clone(),
+                machine_changes: previous_machine
+                    .map(|previous| machine_changes(previous, machine))
+                    .unwrap_or_default(),
+                drift: drift,
+                changes: changes,
+            });
+        }
+    }
+    calibration::Response { machines: machines }
 }
 
-impl Stats {
-    fn from(sums: &[f64]) -> Stats {
-        if sums.is_empty() {
-            return Stats::default();
+/// What differs between the descriptions of two machines, e.g.
+/// `governor: powersave -> performance`. Parts not recorded for one of them
+/// aren't compared.
+fn machine_changes(a: &load::Machine, b: &load::Machine) -> Vec<String> {
+    let mut changes = Vec::new();
+    {
+        let mut compare = |part: &str, a: Option<&String>, b: Option<&String>| {
+            if let (Some(a), Some(b)) = (a, b) {
+                if a != b {
+                    changes.push(format!("{}: {} -> {}", part, a, b));
+                }
+            }
+        };
+        compare("cpu", Some(&a.cpu), Some(&b.cpu));
+        compare("kernel", Some(&a.kernel), Some(&b.kernel));
+        compare("microcode", a.microcode.as_ref(), b.microcode.as_ref());
+        compare("aslr", a.aslr.as_ref(), b.aslr.as_ref());
+        compare("governor", a.governor.as_ref(), b.governor.as_ref());
+        compare("host", a.host.as_ref(), b.host.as_ref());
+        compare("environment", a.environment.as_ref(), b.environment.as_ref());
+        for (vulnerability, state) in &b.mitigations {
+            compare(vulnerability, a.mitigations.get(vulnerability), Some(state));
         }
+    }
+    changes
+}
 
-        let first = sums[0];
-        let last = *sums.last().unwrap();
+/// The collectors that have recorded results, with the machine and commit of
+/// their newest results.
+pub fn handle_collectors(data: &InputData) -> collectors::Response {
+    let mut newest: BTreeMap<&str, collectors::Collector> = BTreeMap::new();
+    for (target, commits) in &data.data {
+        for day in commits.values() {
+            for (id, machine) in &day.collectors {
+                if newest.get(&**id).map_or(false, |c| c.date >= day.commit.date) {
+                    continue;
+                }
+                let collector = collectors::Collector {
+                    id: id.clone(),
+                    machine: machine.clone(),
+                    target: target.clone(),
+                    commit: day.commit.sha.clone(),
+                    date: day.commit.date,
+                };
+                newest.insert(id, collector);
+            }
+        }
+    }
+    collectors::Response {
+        collectors: newest.into_iter().map(|(_, collector)| collector).collect(),
+    }
+}
+
+pub fn handle_self_profile(
+    body: self_profile::Request,
+    data: &InputData,
+) -> V1Result<self_profile::Response> {
+    let commits = target_commits(data, &body.target)?;
+    let a = commit_data(commits, &body.commit_a)?;
+    let b = commit_data(commits, &body.commit_b)?;
+    let selector = Selector::new(body.profile, body.backend, &body.flags).with_std(body.std);
+    let repo_path = get_repo_path().map_err(internal_error)?;
+    let repo_path = Path::new(&repo_path);
+    let queries = |commit: &CommitData| -> Option<Vec<QueryTiming>> {
+        let profile = match load::load_self_profile(repo_path, &commit.commit, &body.target) {
+            Ok(profile) => profile,
+            Err(err) => {
+                error!("failed to load self-profile of {}: {:?}", commit.commit.sha, err);
+                None
+            }
+        };
+        profile?
+            .patches
+            .into_iter()
+            .find(|patch| {
+                patch.name == body.benchmark && selector.matches(commit, patch)
+            })
+            .map(|patch| patch.queries)
+    };
 
-        let mut min = first;
-        let mut max = first;
-        let q1_idx = sums.len() / 4;
-        let q4_idx = 3 * sums.len() / 4;
-        let mut total = 0.0;
-        let mut q1_total = 0.0;
-        let mut q4_total = 0.0;
-        for (i, &cur) in sums.iter().enumerate() {
-            min = min.min(cur);
-            max = max.max(cur);
+    let mut response = self_profile::Response {
+        commit_a: a.commit.sha.clone(),
+        commit_b: b.commit.sha.clone(),
+        queries: Vec::new(),
+        missing: Vec::new(),
+    };
+    let (a_queries, b_queries) =
+        match both_sides((a, queries(a)), (b, queries(b)), &mut response.missing) {
+            Some(queries) => queries,
+            None => return Ok(response),
+        };
 
-            total += cur;
-            if i < q1_idx {
-                // Within the first quartile
-                q1_total += cur;
+    let mut diffs: BTreeMap<&str, self_profile::QueryDiff> = BTreeMap::new();
+    for (side, queries) in [&a_queries, &b_queries].iter().enumerate() {
+        for query in queries.iter() {
+            let diff = diffs.entry(&query.label).or_insert_with(|| self_profile::QueryDiff {
+                label: query.label.clone(),
+                a_time: 0.0,
+                b_time: 0.0,
+                a_invocations: 0,
+                b_invocations: 0,
+            });
+            if side == 0 {
+                diff.a_time = query.self_time;
+                diff.a_invocations = query.invocations;
+            } else {
+                diff.b_time = query.self_time;
+                diff.b_invocations = query.invocations;
             }
-            if i >= q4_idx {
-                // Within the fourth quartile
-                q4_total += cur;
+        }
+    }
+    response.queries = diffs.into_iter().map(|(_, diff)| diff).collect();
+    // Biggest absolute time change first, as the most likely culprit.
+    response.queries.sort_by(|x, y| {
+        util::descending((x.b_time - x.a_time).abs(), (y.b_time - y.a_time).abs())
+    });
+    Ok(response)
+}
+
+/// The data of both sides of a comparison, or `None` after adding the commits
+/// without it to `missing`.
+fn both_sides<T>(
+    a: (&CommitData, Option<T>),
+    b: (&CommitData, Option<T>),
+    missing: &mut Vec<String>,
+) -> Option<(T, T)> {
+    match (a, b) {
+        ((_, Some(a)), (_, Some(b))) => Some((a, b)),
+        ((a, a_data), (b, b_data)) => {
+            if a_data.is_none() {
+                missing.push(a.commit.sha.clone());
+            }
+            if b_data.is_none() {
+                missing.push(b.commit.sha.clone());
             }
+            None
+        }
+    }
+}
+
+pub fn handle_timings(body: timings::Request, data: &InputData) -> V1Result<timings::Response> {
+    let commits = target_commits(data, &body.target)?;
+    let a = commit_data(commits, &body.commit_a)?;
+    let b = commit_data(commits, &body.commit_b)?;
+    let selector = Selector::new(body.profile, body.backend, &body.flags).with_std(body.std);
+
+    let mut response = timings::Response {
+        commit_a: a.commit.sha.clone(),
+        commit_b: b.commit.sha.clone(),
+        crates: Vec::new(),
+        a: None,
+        b: None,
+        missing: Vec::new(),
+    };
+    let (a_crates, b_crates) = match both_sides(
+        (a, crate_timings(a, &body.benchmark, selector)),
+        (b, crate_timings(b, &body.benchmark, selector)),
+        &mut response.missing,
+    ) {
+        Some(crates) => crates,
+        None => return Ok(response),
+    };
+
+    for timing in b_crates {
+        let before = a_crates
+            .iter()
+            .find(|t| t.name == timing.name && t.leaf == timing.leaf);
+        response.crates.push(timings::CrateDiff {
+            name: timing.name.clone(),
+            leaf: timing.leaf,
+            a_start: before.map(|t| t.start),
+            a_duration: before.map(|t| t.duration),
+            b_start: Some(timing.start),
+            b_duration: Some(timing.duration),
+        });
+    }
+    for timing in a_crates {
+        if !b_crates.iter().any(|t| t.name == timing.name && t.leaf == timing.leaf) {
+            response.crates.push(timings::CrateDiff {
+                name: timing.name.clone(),
+                leaf: timing.leaf,
+                a_start: Some(timing.start),
+                a_duration: Some(timing.duration),
+                b_start: None,
+                b_duration: None,
+            });
         }
+    }
+    response.a = Some(build_summary(a_crates));
+    response.b = Some(build_summary(b_crates));
+    Ok(response)
+}
+
+/// The crate timings recorded for the patch `benchmark` of `commit`, if any.
+fn crate_timings<'a>(
+    commit: &'a CommitData,
+    benchmark: &str,
+    selector: Selector,
+) -> Option<&'a [CrateTiming]> {
+    commit
+        .benchmarks
+        .values()
+        .filter_map(|result| result.as_ref().ok())
+        .flat_map(|patches| patches.iter())
+        .find(|patch| patch.name == benchmark && selector.matches(commit, patch))
+        .map(|patch| &patch.timings[..])
+        .and_then(|crates| if crates.is_empty() { None } else { Some(crates) })
+}
+
+pub fn handle_llvm_passes(
+    body: llvm_passes::Request,
+    data: &InputData,
+) -> V1Result<llvm_passes::Response> {
+    let commits = target_commits(data, &body.target)?;
+    let a = commit_data(commits, &body.commit_a)?;
+    let b = commit_data(commits, &body.commit_b)?;
+    let selector = Selector::new(body.profile, body.backend, &body.flags).with_std(body.std);
+
+    let mut response = llvm_passes::Response {
+        commit_a: a.commit.sha.clone(),
+        commit_b: b.commit.sha.clone(),
+        passes: Vec::new(),
+        missing: Vec::new(),
+    };
+    let (a_passes, b_passes) = match both_sides(
+        (a, llvm_pass_timings(a, &body.benchmark, selector)),
+        (b, llvm_pass_timings(b, &body.benchmark, selector)),
+        &mut response.missing,
+    ) {
+        Some(passes) => passes,
+        None => return Ok(response),
+    };
 
-        // Calculate the variance
-        let mean = total / (sums.len() as f64);
-        let mut var_total = 0.0;
-        for sum in sums {
-            let diff = sum - mean;
-            var_total += diff * diff;
+    for pass in b_passes {
+        response.passes.push(llvm_passes::PassDiff {
+            name: pass.name.clone(),
+            a: a_passes.iter().find(|p| p.name == pass.name).map(|p| p.seconds),
+            b: Some(pass.seconds),
+        });
+    }
+    for pass in a_passes {
+        if !b_passes.iter().any(|p| p.name == pass.name) {
+            response.passes.push(llvm_passes::PassDiff {
+                name: pass.name.clone(),
+                a: Some(pass.seconds),
+                b: None,
+            });
         }
-        let variance = var_total / ((sums.len() - 1) as f64);
+    }
+    Ok(response)
+}
 
-        let trend = if sums.len() >= 10 {
-            let q1_mean = q1_total / (q1_idx as f64);
-            let q4_mean = q4_total / ((sums.len() - q4_idx) as f64);
-            100.0 * ((q4_mean - q1_mean) / first)
-        } else {
-            0.0
+/// The LLVM pass timings recorded for the patch `benchmark` of `commit`, if
+/// any.
+fn llvm_pass_timings<'a>(
+    commit: &'a CommitData,
+    benchmark: &str,
+    selector: Selector,
+) -> Option<&'a [LlvmPassTiming]> {
+    commit
+        .benchmarks
+        .values()
+        .filter_map(|result| result.as_ref().ok())
+        .flat_map(|patches| patches.iter())
+        .find(|patch| patch.name == benchmark && selector.matches(commit, patch))
+        .map(|patch| &patch.llvm_passes[..])
+        .and_then(|passes| if passes.is_empty() { None } else { Some(passes) })
+}
+
+/// How long a build took and how many crates it compiled at once on average.
+fn build_summary(crates: &[CrateTiming]) -> timings::Build {
+    let start = crates.iter().map(|t| t.start).fold(::std::f64::INFINITY, f64::min);
+    let end = crates
+        .iter()
+        .map(|t| t.start + t.duration)
+        .fold(0.0, f64::max);
+    let busy = crates.iter().map(|t| t.duration).sum::<f64>();
+    let duration = end - start;
+    timings::Build {
+        duration: duration,
+        parallelism: if duration > 0.0 { busy / duration } else { 1.0 },
+    }
+}
+
+pub fn handle_triage(
+    body: triage::Request,
+    data: &InputData,
+    unrolled: &[Rollup],
+    rollups: &RollupCache,
+) -> V1Result<String> {
+    let start_date = body.start_date.as_date(data.last_date);
+    let end_date = body.end_date.as_date(data.last_date);
+
+    let mut regressions = Vec::new();
+    let mut improvements = Vec::new();
+    let mut mixed = Vec::new();
+    let mut anomalies = Vec::new();
+    let mut prev: Option<&CommitData> = None;
+    let commits = target_commits(data, &body.target)?;
+    for (_, commit_data) in util::data_range(commits, start_date, end_date) {
+        anomalies.extend(duration_anomalies(commit_data));
+        if let Some(prev) = prev {
+            let mut changes = analysis::significant_changes(
+                prev,
+                commit_data,
+                &body.stat,
+                Selector::new(body.profile, body.backend, &body.flags).with_std(body.std),
+                &data.noise,
+            );
+            analysis::primary_first(&mut changes, &data.categories);
+            let entry = (prev, commit_data, changes);
+            match Direction::of(&entry.2, &data.categories) {
+                Some(Direction::Regression) => regressions.push(entry),
+                Some(Direction::Improvement) => improvements.push(entry),
+                Some(Direction::Mixed) => mixed.push(entry),
+                None => {}
+            }
+        }
+        prev = Some(commit_data);
+    }
+
+    let mut report = String::new();
+    writeln!(
+        report,
+        "# Triage report {} to {}\n",
+        start_date.0.format("%Y-%m-%d"),
+        end_date.0.format("%Y-%m-%d")
+    ).unwrap();
+    writeln!(
+        report,
+        "Based on `{}` of {} builds on {} with the {} backend, {} std and {}; changes are \
+         considered significant if they are at least {} times the usual noise of the benchmark \
+         and at least {}%, or at least {}% for benchmarks with too few recent results.",
+        body.stat,
+        body.profile,
+        body.target,
+        body.backend,
+        body.std,
+        match body.flags {
+            Some(ref flags) => format!("flag set `{}`", flags),
+            None => String::from("no extra flags"),
+        },
+        noise::NOISE_MULTIPLE,
+        noise::MIN_THRESHOLD,
+        analysis::SIGNIFICANCE_THRESHOLD
+    ).unwrap();
+    for &(title, ref entries) in &[
+        ("Regressions", &regressions),
+        ("Improvements", &improvements),
+        ("Mixed", &mixed),
+    ] {
+        writeln!(report, "\n## {}\n", title).unwrap();
+        if entries.is_empty() {
+            writeln!(report, "None.").unwrap();
+        }
+        for &(a, b, ref changes) in entries.iter() {
+            write_triage_entry(&mut report, a, b, changes, &data.categories);
+            if let Some(rollup) = unrolled.iter().find(|r| r.sha == b.commit.sha) {
+                write_rollup(&mut report, rollup);
+            } else if let Some(prs) = rollups.get(&b.commit.sha) {
+                write_rollup_suspects(&mut report, &prs);
+            }
+        }
+    }
+    if !anomalies.is_empty() {
+        // Often the first sign of a toolchain that hangs.
+        writeln!(report, "\n## Anomalous durations\n").unwrap();
+        for anomaly in anomalies {
+            writeln!(report, "- {}", anomaly).unwrap();
+        }
+    }
+    Ok(report)
+}
+
+/// The builds of `commit` that took far longer than typical on their
+/// collector, and the benchmarks killed for it, as triage report lines.
+fn duration_anomalies(commit: &CommitData) -> Vec<String> {
+    let sha = short_sha(&commit.commit.sha);
+    let mut lines = Vec::new();
+    for (name, result) in &commit.benchmarks {
+        match *result {
+            Ok(ref patches) => for patch in patches {
+                for anomaly in &patch.anomalies {
+                    lines.push(format!(
+                        "{}: `{}` ({}) took {:.0}s, typically {:.0}s",
+                        sha,
+                        patch.name,
+                        patch.profile,
+                        anomaly.seconds,
+                        anomaly.typical
+                    ));
+                }
+            },
+            Err(ref err) if err.contains("anomalous duration") => {
+                lines.push(format!("{}: `{}` was killed for taking too long", sha, name));
+            }
+            Err(_) => {}
+        }
+    }
+    lines
+}
+
+fn write_triage_entry(
+    report: &mut String,
+    a: &CommitData,
+    b: &CommitData,
+    changes: &[Change],
+    categories: &BTreeMap<String, Category>,
+) {
+    writeln!(
+        report,
+        "- [{}...{}](https://github.com/rust-lang/rust/compare/{}...{}): {}",
+        short_sha(&a.commit.sha),
+        short_sha(&b.commit.sha),
+        a.commit.sha,
+        b.commit.sha,
+        analysis::summarize(changes, categories).verdict
+    ).unwrap();
+    // Changes are sorted primary first, then by magnitude, so the first few
+    // are the most interesting.
+    for change in changes.iter().take(3) {
+        let label = match analysis::category(categories, &change.name) {
+            Category::Primary => "",
+            Category::Secondary => " (secondary)",
+            Category::Stable => " (stable)",
         };
-        let trend_b = 100.0 * ((last - first) / first);
+        writeln!(
+            report,
+            "  - `{}`{}: {:+.1}%",
+            change.name,
+            label,
+            change.percent
+        ).unwrap();
+    }
+}
+
+/// Lists the pull requests of a regressed rollup and their try builds, so
+/// the regression can be attributed once those are benchmarked.
+fn write_rollup(report: &mut String, rollup: &Rollup) {
+    writeln!(report, "  - Rollup of:").unwrap();
+    for pr in &rollup.prs {
+        let try_build = match pr.try_sha {
+            Some(ref sha) => format!(
+                "try build [{}](https://github.com/rust-lang/rust/commit/{}) queued",
+                short_sha(sha),
+                sha
+            ),
+            None => String::from("no try build yet"),
+        };
+        writeln!(
+            report,
+            "    - [#{}](https://github.com/rust-lang/rust/pull/{}) {}: {}",
+            pr.number,
+            pr.number,
+            pr.title,
+            try_build
+        ).unwrap();
+    }
+}
+
+/// Names the pull requests of a rollup that isn't being unrolled, one of
+/// which probably caused the change.
+fn write_rollup_suspects(report: &mut String, prs: &[RollupPr]) {
+    let links = prs.iter()
+        .map(|pr| {
+            format!(
+                "[#{}](https://github.com/rust-lang/rust/pull/{})",
+                pr.number,
+                pr.number
+            )
+        })
+        .collect::<Vec<_>>();
+    writeln!(report, "  - Rollup; probably one of {}", links.join(", ")).unwrap();
+}
+
+fn short_sha(sha: &str) -> &str {
+    &sha[..sha.len().min(8)]
+}
 
-        Stats {
-            first: first,
-            last: last,
-            min: min,
-            max: max,
-            mean: mean,
-            variance: variance,
-            trend: if trend.is_nan() { trend } else { 0.0 },
-            trend_b: if trend_b.is_nan() { trend_b } else { 0.0 },
-            n: sums.len(),
+/// Summary statistics of the series `sums`, oldest first.
+fn stats_of(sums: &[f64]) -> Stats {
+    if sums.is_empty() {
+        return Stats::default();
+    }
+
+    let first = sums[0];
+    let last = *sums.last().unwrap();
+
+    let mut min = first;
+    let mut max = first;
+    let q1_idx = sums.len() / 4;
+    let q4_idx = 3 * sums.len() / 4;
+    let mut total = 0.0;
+    let mut q1_total = 0.0;
+    let mut q4_total = 0.0;
+    for (i, &cur) in sums.iter().enumerate() {
+        min = min.min(cur);
+        max = max.max(cur);
+
+        total += cur;
+        if i < q1_idx {
+            // Within the first quartile
+            q1_total += cur;
+        }
+        if i >= q4_idx {
+            // Within the fourth quartile
+            q4_total += cur;
         }
     }
+
+    // Calculate the variance
+    let mean = total / (sums.len() as f64);
+    let mut var_total = 0.0;
+    for sum in sums {
+        let diff = sum - mean;
+        var_total += diff * diff;
+    }
+    let variance = var_total / ((sums.len() - 1) as f64);
+
+    let trend = if sums.len() >= 10 {
+        let q1_mean = q1_total / (q1_idx as f64);
+        let q4_mean = q4_total / ((sums.len() - q4_idx) as f64);
+        100.0 * ((q4_mean - q1_mean) / first)
+    } else {
+        0.0
+    };
+    let trend_b = 100.0 * ((last - first) / first);
+
+    Stats {
+        first: first,
+        last: last,
+        min: min,
+        max: max,
+        mean: mean,
+        variance: variance,
+        trend: if trend.is_nan() { trend } else { 0.0 },
+        trend_b: if trend_b.is_nan() { trend_b } else { 0.0 },
+        n: sums.len(),
+    }
+}
+
+/// Queue the newest master commit with results for benchmarking with the
+/// flags of the experiment `body`.
+pub fn handle_experiment(
+    body: experiment::Request,
+    data: &InputData,
+    queue: &Queue,
+) -> experiment::Response {
+    match queue_experiment(body, data, queue) {
+        Ok(queued) => experiment::Response {
+            queued: Some(queued),
+            error: None,
+        },
+        Err(err) => experiment::Response {
+            queued: None,
+            error: Some(err.to_string()),
+        },
+    }
+}
+
+fn queue_experiment(
+    body: experiment::Request,
+    data: &InputData,
+    queue: &Queue,
+) -> Result<QueuedCommit> {
+    let valid_name = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    if body.name.is_empty() || !body.name.chars().all(valid_name) {
+        bail!("experiment names may only contain letters, digits, `-` and `_`");
+    }
+    if body.flags.trim().is_empty() {
+        bail!("no flags given");
+    }
+    if data.flag_set_list.contains(&body.name) {
+        bail!("there already are results for a flag set named `{}`", body.name);
+    }
+    let master = ::rust_sysroot::get_commits()
+        .map_err(|err| format!("failed to list master commits: {:?}", err))?;
+    let target = api::default_target();
+    let newest = match util::target_data(data, &target) {
+        Some(commits) => commits
+            .values()
+            .rev()
+            .find(|commit| master.iter().any(|c| c.sha == commit.commit.sha)),
+        None => bail!("no results for target {}", target),
+    };
+    let newest = match newest {
+        Some(newest) => newest,
+        None => bail!("no master commit has results yet"),
+    };
+    let queued = QueuedCommit {
+        experiment: Some(Experiment {
+            name: body.name.clone(),
+            flags: body.flags.clone(),
+        }),
+        ..QueuedCommit::new(
+            newest.commit.sha.clone(),
+            newest.commit.date.0,
+            format!("experiment {}: {}", body.name, body.flags),
+            Priority::Experiment,
+            Utc::now(),
+        )
+    };
+    queue.push(queued.clone())?;
+    Ok(queued)
+}
+
+/// Queue the toolchain `body`, built locally on the collector `user`, for
+/// benchmarking by that collector as the artifact `local-<name>`.
+pub fn handle_local_toolchain(
+    body: local_toolchain::Request,
+    queue: &Queue,
+    user: &User,
+) -> local_toolchain::Response {
+    match queue_local_toolchain(body, queue, user) {
+        Ok(queued) => local_toolchain::Response {
+            queued: Some(queued),
+            error: None,
+        },
+        Err(err) => local_toolchain::Response {
+            queued: None,
+            error: Some(err.to_string()),
+        },
+    }
+}
+
+fn queue_local_toolchain(
+    body: local_toolchain::Request,
+    queue: &Queue,
+    user: &User,
+) -> Result<QueuedCommit> {
+    let valid_name = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    if body.name.is_empty() || !body.name.chars().all(valid_name) {
+        bail!("toolchain names may only contain letters, digits, `-` and `_`");
+    }
+    if body.rustc.trim().is_empty() {
+        bail!("no path to rustc given");
+    }
+    let summary = match body.base {
+        Some(ref base) => format!("local toolchain {} (based on {})", body.name, base),
+        None => format!("local toolchain {}", body.name),
+    };
+    let queued = QueuedCommit {
+        local: Some(LocalToolchain {
+            rustc: body.rustc.clone(),
+            collector: user.name.clone(),
+            base: body.base.clone(),
+        }),
+        ..QueuedCommit::new(
+            format!("local-{}", body.name),
+            body.date,
+            summary,
+            Priority::Local,
+            Utc::now(),
+        )
+    };
+    queue.push(queued.clone())?;
+    Ok(queued)
+}
+
+/// A Markdown summary of the changes from the commit `a` to `b` in the query
+/// of `url`, e.g. a local toolchain and the commit it was built from, to
+/// paste where no comment is posted automatically.
+pub fn handle_summary(url: &Url, data: &InputData) -> V1Result<String> {
+    let stat = query_param(url, "stat").unwrap_or_else(|| String::from("instructions:u"));
+    let (_, commits) = v1_target_data(url, data)?;
+    let a = v1_find_commit(url, "a", commits)?;
+    let b = v1_find_commit(url, "b", commits)?;
+    let no_flags = None;
+    let selector = Selector::new(Profile::default(), Backend::default(), &no_flags);
+    let changes = analysis::changes_between(a, selector, b, selector, &stat, &data.noise);
+    let summary = analysis::summarize(&changes, &data.categories);
+
+    let mut message = String::new();
+    writeln!(
+        message,
+        "`{}` compared to `{}` ({}): {}\n",
+        b.commit.sha,
+        a.commit.sha,
+        stat,
+        summary.verdict
+    ).unwrap();
+    let significant = summary
+        .primary_regressions
+        .iter()
+        .chain(&summary.primary_improvements)
+        .chain(&summary.secondary);
+    for change in significant.take(10) {
+        writeln!(message, "- `{}`: {:+.1}%", change.name, change.percent).unwrap();
+    }
+    let site_url =
+        env::var("SITE_URL").unwrap_or_else(|_| String::from("https://perf.rust-lang.org"));
+    writeln!(
+        message,
+        "\n[Comparison]({}/compare.html?commit_a={}&commit_b={}&stat={})",
+        site_url.trim_right_matches('/'),
+        a.commit.sha,
+        b.commit.sha,
+        stat
+    ).unwrap();
+    Ok(message)
+}
+
+/// Cancel the queue entry named by `body` or move it to another priority on
+/// behalf of `user`.
+pub fn handle_update_queue(
+    body: queue::Update,
+    queue: &Queue,
+    user: &User,
+) -> queue::UpdateResponse {
+    let result = match (body.cancel, body.retry, body.priority) {
+        (true, false, None) => queue.cancel(&body.key, user),
+        (false, true, None) => queue.retry(&body.key, user),
+        (false, false, Some(priority)) => queue.set_priority(&body.key, priority, user),
+        _ => Err("either cancel or retry the entry, or give it a priority".into()),
+    };
+    match result {
+        Ok(commit) => queue::UpdateResponse {
+            commit: Some(commit),
+            error: None,
+        },
+        Err(err) => queue::UpdateResponse {
+            commit: None,
+            error: Some(err.to_string()),
+        },
+    }
 }
 
 pub fn handle_date_commit(date: Date) -> CommitResponse {
@@ -243,13 +1304,604 @@ pub fn handle_pr_commit(pr: u64) -> CommitResponse {
     }
 }
 
+/// The result of a `/api/v1/` handler; errors come with the status to send.
+type V1Result<T> = ::std::result::Result<T, (StatusCode, v1::Error)>;
+
+fn bad_request<S: Into<String>>(error: S) -> (StatusCode, v1::Error) {
+    (StatusCode::BadRequest, v1::Error { error: error.into() })
+}
+
+fn not_found<S: Into<String>>(error: S) -> (StatusCode, v1::Error) {
+    (StatusCode::NotFound, v1::Error { error: error.into() })
+}
+
+fn internal_error(error: Error) -> (StatusCode, v1::Error) {
+    error!("{:?}", error);
+    (StatusCode::InternalServerError, v1::Error { error: error.to_string() })
+}
+
+pub fn handle_v1_info(data: &InputData) -> v1::info::Response {
+    v1::info::Response {
+        version: v1::VERSION,
+        targets: data.data.keys().cloned().collect(),
+        benchmarks: data.crate_list.clone(),
+        metrics: data.stats_list.clone(),
+        profiles: data.profile_list.clone(),
+        backends: data.backend_list.clone(),
+        stds: data.std_list.clone(),
+        flag_sets: data.flag_set_list.clone(),
+        custom_profiles: data.custom_profile_list.clone(),
+        last_date: data.last_date,
+    }
+}
+
+pub fn handle_v1_commits(url: &Url, data: &InputData) -> V1Result<v1::commits::Response> {
+    let (_, commits) = v1_target_data(url, data)?;
+    let start = v1_date::<Start>(url, "start")?.as_date(data.last_date);
+    let end = v1_date::<End>(url, "end")?.as_date(data.last_date);
+    Ok(v1::commits::Response {
+        commits: util::data_range(commits, start, end)
+            .map(|(_, day)| v1_commit(day))
+            .collect(),
+    })
+}
+
+pub fn handle_v1_neighbors(url: &Url, data: &InputData) -> V1Result<v1::neighbors::Response> {
+    let (_, commits) = v1_target_data(url, data)?;
+    let day = v1_find_commit(url, "commit", commits)?;
+    let master = match ::rust_sysroot::get_commits() {
+        Ok(master) => master,
+        Err(err) => {
+            // Still better than no navigation at all.
+            warn!("failed to list master commits, using all commits: {:?}", err);
+            Vec::new()
+        }
+    };
+    let on_master = master.iter().map(|c| &*c.sha).collect::<HashSet<_>>();
+    let days = commits
+        .values()
+        .filter(|d| {
+            d.commit.sha == day.commit.sha || on_master.is_empty()
+                || on_master.contains(&*d.commit.sha)
+        })
+        .collect::<Vec<_>>();
+    let idx = days.iter()
+        .position(|d| d.commit.sha == day.commit.sha)
+        .unwrap();
+    let neighbor = |day: &CommitData| {
+        let summary = master
+            .iter()
+            .find(|c| c.sha == day.commit.sha)
+            .map(|c| c.summary.clone());
+        let people = summary.as_ref().and_then(|s| github::bors_people(s));
+        v1::neighbors::Neighbor {
+            commit: v1_commit(day),
+            pr: summary.as_ref().and_then(|s| github::bors_pr(s)),
+            author: people.as_ref().map(|p| p.0.clone()),
+            reviewer: people.and_then(|p| p.1),
+            summary: summary,
+        }
+    };
+    Ok(v1::neighbors::Response {
+        commit: neighbor(day),
+        prev: idx.checked_sub(1).map(|idx| neighbor(days[idx])),
+        next: days.get(idx + 1).map(|day| neighbor(day)),
+    })
+}
+
+pub fn handle_v1_results(url: &Url, data: &InputData) -> V1Result<v1::results::Response> {
+    let (target, commits) = v1_target_data(url, data)?;
+    let day = v1_find_commit(url, "commit", commits)?;
+    let metric = query_param(url, "metric");
+
+    let mut response = v1::results::Response {
+        commit: v1_commit(day),
+        target: target,
+        results: Vec::new(),
+        errors: Vec::new(),
+    };
+    for (benchmark, result) in &day.benchmarks {
+        let patches = match *result {
+            Ok(ref patches) => patches,
+            Err(ref error) => {
+                response.errors.push(v1::results::BenchmarkError {
+                    benchmark: benchmark.clone(),
+                    error: error.clone(),
+                });
+                continue;
+            }
+        };
+        for patch in patches {
+            let metrics = patch
+                .runs
+                .iter()
+                .flat_map(|run| run.stats.iter())
+                .filter(|stat| metric.as_ref().map_or(true, |metric| *metric == stat.name))
+                .map(|stat| (stat.name.to_string(), stat.cnt))
+                .collect::<BTreeMap<_, _>>();
+            if metrics.is_empty() {
+                continue;
+            }
+            response.results.push(v1::results::Measurement {
+                benchmark: benchmark.clone(),
+                scenario: scenario(&patch.name).to_string(),
+                profile: patch.profile,
+                backend: patch.backend,
+                std: patch.std,
+                flags: patch.flags.clone(),
+                custom_profile: patch.custom_profile.clone(),
+                machine: day.machine_class(patch).map(String::from),
+                metrics: metrics,
+            });
+        }
+    }
+    Ok(response)
+}
+
+pub fn handle_v1_compare(
+    url: &Url,
+    data: &InputData,
+    baselines: &Baselines,
+    rollups: &RollupCache,
+) -> V1Result<v1::compare::Response> {
+    let (target, commits) = v1_target_data(url, data)?;
+    let baseline = query_param(url, "baseline");
+    let a = match baseline {
+        Some(ref name) => {
+            let sha = match baselines.get(name) {
+                Some(baseline) => baseline.sha,
+                None => return Err(not_found(format!("no baseline named {}", name))),
+            };
+            match commits.values().find(|day| day.commit.sha == sha) {
+                Some(day) => day,
+                None => {
+                    return Err(not_found(format!(
+                        "no results for baseline {} on target {}",
+                        name, target
+                    )))
+                }
+            }
+        }
+        None => v1_find_commit(url, "a", commits)?,
+    };
+    let b = v1_find_commit(url, "b", commits)?;
+    let metric = query_param(url, "metric").unwrap_or_else(|| String::from("instructions:u"));
+    let profile = v1_parse::<Profile>(url, "profile")?.unwrap_or_default();
+    let backend = v1_parse::<Backend>(url, "backend")?.unwrap_or_default();
+    let std = v1_parse::<Std>(url, "std")?.unwrap_or_default();
+    let flags = query_param(url, "flags");
+    let custom_profile = query_param(url, "custom_profile");
+    let filter = analysis::Filter {
+        categories: v1_list(url, "category")?,
+        scenarios: query_param(url, "scenario")
+            .map(|scenarios| {
+                scenarios
+                    .split(',')
+                    .filter(|scenario| !scenario.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    let machine = analysis::common_machine(a, b).map_err(|err| not_found(err.to_string()))?;
+    let selector = Selector::new(profile, backend, &flags)
+        .with_std(std)
+        .with_custom_profile(&custom_profile)
+        .on(machine);
+
+    let changes = analysis::changes_between(a, selector, b, selector, &metric, &data.noise)
+        .into_iter()
+        .filter(|change| filter.matches(&data.categories, &change.name))
+        .collect::<Vec<_>>();
+    Ok(v1::compare::Response {
+        a: v1_commit(a),
+        b: v1_commit(b),
+        baseline: baseline,
+        target: target,
+        machine: machine.map(String::from),
+        verdict: analysis::summarize(&changes, &data.categories).verdict,
+        weighted: if query_param(url, "weighted").map_or(false, |value| value == "true") {
+            analysis::weighted_summary(&changes, a, selector)
+        } else {
+            None
+        },
+        changes: changes
+            .iter()
+            .map(|change| v1::compare::Change {
+                benchmark: change.name.split('@').next().unwrap().to_string(),
+                scenario: scenario(&change.name).to_string(),
+                category: analysis::category(&data.categories, &change.name),
+                a: change.a,
+                b: change.b,
+                percent: change.percent,
+                threshold: change.threshold,
+                noisy: data.noise
+                    .get(&a.triple, profile, &metric, &change.name)
+                    .map_or(false, |score| score.noisy),
+                significant: change.is_significant(),
+            })
+            .collect(),
+        metric: metric,
+        rollup: rollups.get(&b.commit.sha),
+    })
+}
+
+pub fn handle_v1_threshold(url: &Url, data: &InputData) -> V1Result<v1::threshold::Response> {
+    let (target, commits) = v1_target_data(url, data)?;
+    let benchmark = match query_param(url, "benchmark") {
+        Some(benchmark) => benchmark,
+        None => return Err(bad_request("missing `benchmark`")),
+    };
+    let scenario = query_param(url, "scenario").unwrap_or_default();
+    let name = benchmark.clone() + &scenario;
+    let metric = query_param(url, "metric").unwrap_or_else(|| String::from("instructions:u"));
+    let profile = v1_parse::<Profile>(url, "profile")?.unwrap_or_default();
+    let backend = v1_parse::<Backend>(url, "backend")?.unwrap_or_default();
+    let std = v1_parse::<Std>(url, "std")?.unwrap_or_default();
+    let flags = query_param(url, "flags");
+    let custom_profile = query_param(url, "custom_profile");
+    let count = match query_param(url, "count") {
+        Some(count) => count
+            .parse::<usize>()
+            .map_err(|_| bad_request(format!("invalid `count` {}", count)))?,
+        None => 10,
+    };
+    let selector = Selector::new(profile, backend, &flags)
+        .with_std(std)
+        .with_custom_profile(&custom_profile);
+
+    // Newest first, over as many commits as the noise score is taken from
+    // or as were asked for.
+    let newest = commits
+        .values()
+        .rev()
+        .take(cmp::max(count, noise::WINDOW) + 1)
+        .collect::<Vec<_>>();
+    let mut changes = Vec::new();
+    for pair in newest.windows(2) {
+        let (b, a) = (pair[0], pair[1]);
+        let change = analysis::changes_between(a, selector, b, selector, &metric, &data.noise)
+            .into_iter()
+            .find(|change| change.name == name);
+        if let Some(change) = change {
+            changes.push((a, b, change));
+        }
+    }
+    if changes.is_empty() {
+        return Err(not_found(format!(
+            "no changes of {} in {} on target {}",
+            name, metric, target
+        )));
+    }
+
+    let recent = changes
+        .iter()
+        .take(noise::WINDOW)
+        .map(|&(_, _, ref change)| change.percent)
+        .collect::<Vec<_>>();
+    let variance = if recent.len() < 2 {
+        None
+    } else {
+        let mean = recent.iter().sum::<f64>() / recent.len() as f64;
+        let squares = recent.iter().map(|percent| (percent - mean).powi(2));
+        Some(squares.sum::<f64>() / (recent.len() - 1) as f64)
+    };
+    let score = data.noise.get(&target, profile, &metric, &name);
+    Ok(v1::threshold::Response {
+        threshold: data.noise.threshold(&target, profile, &metric, &name),
+        noise: score.map(|score| score.score),
+        variance: variance,
+        noisy: score.map_or(false, |score| score.noisy),
+        reason: data.noise.explain(&target, profile, &metric, &name),
+        changes: changes
+            .into_iter()
+            .take(count)
+            .map(|(a, b, change)| v1::threshold::Change {
+                a: v1_commit(a),
+                b: v1_commit(b),
+                before: change.a,
+                after: change.b,
+                percent: change.percent,
+                verdict: if !change.is_significant() {
+                    v1::threshold::Verdict::Insignificant
+                } else if change.is_regression() {
+                    v1::threshold::Verdict::Regression
+                } else {
+                    v1::threshold::Verdict::Improvement
+                },
+            })
+            .collect(),
+        benchmark: benchmark,
+        scenario: scenario,
+        target: target,
+        metric: metric,
+        profile: profile,
+    })
+}
+
+pub fn handle_v1_channels(
+    url: &Url,
+    data: &InputData,
+    channels: &Channels,
+) -> V1Result<v1::channels::Response> {
+    let (target, commits) = v1_target_data(url, data)?;
+    let metric = query_param(url, "metric");
+    let profile = v1_parse::<Profile>(url, "profile")?.unwrap_or_default();
+    let backend = v1_parse::<Backend>(url, "backend")?.unwrap_or_default();
+    let std = v1_parse::<Std>(url, "std")?.unwrap_or_default();
+    let flags = query_param(url, "flags");
+    let custom_profile = query_param(url, "custom_profile");
+    let selector = Selector::new(profile, backend, &flags)
+        .with_std(std)
+        .with_custom_profile(&custom_profile);
+    let releases = channels.releases();
+    if releases.is_empty() {
+        return Err(not_found("the current releases haven't been looked up yet"));
+    }
+
+    let mut response = v1::channels::Response {
+        target: target,
+        channels: Vec::new(),
+        results: Vec::new(),
+    };
+    // (Patch name, metric) -> channel -> value
+    let mut values = BTreeMap::new();
+    for release in &releases {
+        // Newest first, so the release's own commit is found before any
+        // older one.
+        let day = commits.values().rev().find(|day| {
+            day.commit.sha.starts_with(&*release.sha)
+                || release.date.map_or(false, |date| day.commit.date.0 < date)
+        });
+        if let Some(day) = day {
+            let patches = day.benchmarks
+                .values()
+                .filter_map(|result| result.as_ref().ok())
+                .flat_map(|patches| patches)
+                .filter(|patch| selector.matches(day, patch));
+            for patch in patches {
+                let stats = patch
+                    .run()
+                    .stats
+                    .iter()
+                    .filter(|stat| metric.as_ref().map_or(true, |metric| *metric == stat.name));
+                for stat in stats {
+                    values
+                        .entry((patch.name.to_string(), stat.name.to_string()))
+                        .or_insert_with(BTreeMap::new)
+                        .insert(release.channel.clone(), stat.cnt);
+                }
+            }
+        }
+        response.channels.push(v1::channels::Channel {
+            name: release.channel.clone(),
+            version: release.version.clone(),
+            sha: release.sha.clone(),
+            commit: day.map(v1_commit),
+        });
+    }
+    response.results = values
+        .into_iter()
+        .map(|((name, metric), values)| v1::channels::Measurement {
+            benchmark: name.split('@').next().unwrap().to_string(),
+            scenario: scenario(&name).to_string(),
+            metric: metric,
+            values: values,
+        })
+        .collect();
+    Ok(response)
+}
+
+fn v1_commit(day: &CommitData) -> v1::Commit {
+    v1::Commit {
+        sha: day.commit.sha.clone(),
+        date: day.commit.date,
+    }
+}
+
+/// The `target` parameter, defaulting like the other endpoints, and its
+/// results.
+fn v1_target_data<'a>(
+    url: &Url,
+    data: &'a InputData,
+) -> V1Result<(String, &'a BTreeMap<load::Commit, CommitData>)> {
+    let target = query_param(url, "target").unwrap_or_else(api::default_target);
+    let commits = target_commits(data, &target)?;
+    Ok((target, commits))
+}
+
+/// The results for `target`, or a 404 if it hasn't been benchmarked.
+fn target_commits<'a>(
+    data: &'a InputData,
+    target: &str,
+) -> V1Result<&'a BTreeMap<load::Commit, CommitData>> {
+    util::target_data(data, target)
+        .ok_or_else(|| not_found(format!("no results for target {}", target)))
+}
+
+/// The results of the commit whose hash or hash prefix is the parameter
+/// `key`.
+fn v1_find_commit<'a>(
+    url: &Url,
+    key: &str,
+    commits: &'a BTreeMap<load::Commit, CommitData>,
+) -> V1Result<&'a CommitData> {
+    let sha = match query_param(url, key) {
+        Some(ref sha) if sha.is_empty() => return Err(bad_request(format!("empty `{}`", key))),
+        Some(sha) => sha,
+        None => return Err(bad_request(format!("missing `{}`", key))),
+    };
+    commit_data(commits, &sha)
+}
+
+/// The results of the commit whose hash or hash prefix is `sha`, or a 404.
+fn commit_data<'a>(
+    commits: &'a BTreeMap<load::Commit, CommitData>,
+    sha: &str,
+) -> V1Result<&'a CommitData> {
+    util::get_commit_data(commits, sha)
+        .ok_or_else(|| not_found(format!("no results for commit {}", sha)))
+}
+
+/// Like `query_date`, but only a missing date falls back to the default.
+fn v1_date<B: Bound>(url: &Url, key: &str) -> V1Result<OptionalDate<B>> {
+    match query_date(url, key) {
+        OptionalDate::CouldNotParse(ref value) if !value.is_empty() => {
+            Err(bad_request(format!("`{}` is not a date: {}", key, value)))
+        }
+        date => Ok(date),
+    }
+}
+
+/// The request for a triage report in the query of `url`. Only missing
+/// parameters fall back to their defaults.
+fn triage_request(url: &Url) -> V1Result<triage::Request> {
+    Ok(triage::Request {
+        start_date: v1_date(url, "start")?,
+        end_date: v1_date(url, "end")?,
+        stat: query_param(url, "stat").unwrap_or_else(|| String::from("instructions:u")),
+        profile: v1_parse(url, "profile")?.unwrap_or_default(),
+        backend: v1_parse(url, "backend")?.unwrap_or_default(),
+        std: v1_parse(url, "std")?.unwrap_or_default(),
+        flags: query_param(url, "flags"),
+        target: query_param(url, "target").unwrap_or_else(api::default_target),
+    })
+}
+
+fn v1_parse<T: str::FromStr<Err = String>>(url: &Url, key: &str) -> V1Result<Option<T>> {
+    match query_param(url, key) {
+        Some(value) => value.parse().map(Some).map_err(bad_request),
+        None => Ok(None),
+    }
+}
+
+/// The comma-separated values of the parameter `key`; empty if it is missing.
+fn v1_list<T: str::FromStr<Err = String>>(url: &Url, key: &str) -> V1Result<Vec<T>> {
+    match query_param(url, key) {
+        Some(values) => values
+            .split(',')
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse().map_err(bad_request))
+            .collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The patch of a full patch name, e.g. `@010-baseline` of
+/// `helloworld@010-baseline`, or the empty string for a plain build.
+fn scenario(name: &str) -> &str {
+    name.find('@').map_or("", |idx| &name[idx..])
+}
+
+/// If the regressed commit `sha` merged a rollup, record its pull requests
+/// for benchmarking one by one.
+/// Queue the master commits without results for backfilling, and refresh
+/// what else depends on the list of master commits: the calibration entry,
+/// the pull requests of rollups, the current releases and the count of
+/// missing commits.
+fn refresh_master_commits(
+    repo_path: &str,
+    data: &RwLock<InputData>,
+    queue: &Queue,
+    exclusions: &Exclusions,
+    rollups: &RollupCache,
+    channels: &Channels,
+    metrics: &Metrics,
+) -> Result<()> {
+    let commits = ::rust_sysroot::get_commits().chain_err(|| "failed to list master commits")?;
+    // Commits whose results were excluded as a whole aren't collected again.
+    let mut skip = load::broken_commits(repo_path)?;
+    skip.extend(
+        exclusions
+            .list()
+            .into_iter()
+            .filter(|exclusion| exclusion.benchmark.is_none())
+            .map(|exclusion| exclusion.commit),
+    );
+    let benchmarked = {
+        let data = data.read().unwrap();
+        queue.backfill(&commits, &data, &skip, Utc::now())?;
+        metrics.record_missing_commits(&commits, &data, &skip);
+        if let Err(err) = queue.schedule_calibration(&commits, &data, Utc::now()) {
+            warn!("failed to schedule calibration: {:?}", err);
+        }
+        let have = data.data
+            .values()
+            .flat_map(|commits| commits.keys())
+            .map(|commit| &*commit.sha)
+            .collect::<HashSet<_>>();
+        commits
+            .iter()
+            .filter(|commit| have.contains(&*commit.sha))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    // Not holding the lock while waiting for GitHub.
+    if let Err(err) = rollups.fetch(&benchmarked) {
+        warn!("failed to fetch the pull requests of rollups: {:?}", err);
+    }
+    if let Err(err) = channels.refresh(&commits) {
+        warn!("failed to look up the current releases: {:?}", err);
+    }
+    Ok(())
+}
+
+fn unroll(queue: &Queue, sha: &str) -> Result<()> {
+    if queue.has_rollup(sha) {
+        return Ok(());
+    }
+    let prs = github::rollup_prs(sha)?;
+    if !prs.is_empty() {
+        queue.add_rollup(sha, prs, Utc::now())?;
+    }
+    Ok(())
+}
+
 struct Server {
     data: Arc<RwLock<InputData>>,
     pool: CpuPool,
     updating: Arc<AtomicBool>,
+    auth: Auth,
+    queue: Arc<Queue>,
+    baselines: Arc<Baselines>,
+    annotations: Arc<Annotations>,
+    exclusions: Arc<Exclusions>,
+    rollups: Arc<RollupCache>,
+    channels: Arc<Channels>,
+    snapshots: Arc<Snapshots>,
+    metrics: Arc<Metrics>,
+    cache: Arc<ResponseCache>,
+    frontend: Frontend,
+    /// The secret of the GitHub webhook, `GITHUB_WEBHOOK_SECRET`
+    webhook_secret: Option<String>,
 }
 
 impl Server {
+    /// Runs `handler` only if the request carries a token with at least
+    /// `role`, either as a bearer token or, for webhooks which can't set
+    /// headers, as the `token` query parameter.
+    fn authorized<F>(&self, req: Request, role: Role, handler: F) -> <Server as Service>::Future
+    where
+        F: FnOnce(Request, &User) -> <Server as Service>::Future,
+    {
+        let token = request_token(&req);
+        match self.auth.authorize(token.as_ref().map(|t| &**t), role) {
+            Some(user) => {
+                self.auth.audit(user, &format!("{} {}", req.method(), req.path()));
+                handler(req, user)
+            }
+            None => {
+                warn!("unauthorized request to {}", req.path());
+                Box::new(futures::future::ok(
+                    Response::new()
+                        .with_body("Unauthorized")
+                        .with_status(StatusCode::Unauthorized)
+                        .with_header(ContentType(mime::TEXT_PLAIN_UTF_8)),
+                ))
+            }
+        }
+    }
+
     fn handle_get<F, S>(&self, req: &Request, handler: F) -> <Server as Service>::Future
     where
         F: FnOnce(&InputData) -> S,
@@ -280,13 +1932,95 @@ impl Server {
         Box::new(futures::future::ok(response))
     }
 
-    fn handle_post<'de, F, D, S>(&self, req: Request, handler: F) -> <Server as Service>::Future
+    /// Serve a GET request with the handler's text, or its error with the
+    /// error's status.
+    fn handle_get_text<F>(&self, req: &Request, handler: F) -> <Server as Service>::Future
+    where
+        F: FnOnce(&Request, &InputData) -> V1Result<String>,
+    {
+        assert_eq!(*req.method(), Get);
+        let data = self.data.clone();
+        let data = data.read().unwrap();
+        let (status, body) = match handler(req, &data) {
+            Ok(text) => (StatusCode::Ok, text),
+            Err((status, error)) => (status, error.error + "\n"),
+        };
+        let response = Response::new()
+            .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
+            .with_status(status)
+            .with_body(body);
+        Box::new(futures::future::ok(response))
+    }
+
+    /// Serve a GET request to the stable API, answering with the handler's
+    /// response or error as JSON.
+    fn handle_v1<F, S>(&self, req: &Request, handler: F) -> <Server as Service>::Future
+    where
+        F: FnOnce(&Url, &InputData) -> V1Result<S>,
+        S: Serialize,
+    {
+        let result = if *req.method() != Get {
+            Err((
+                StatusCode::MethodNotAllowed,
+                v1::Error {
+                    error: String::from("only GET requests are supported"),
+                },
+            ))
+        } else {
+            // The request URI lacks the scheme and host.
+            let url = Url::parse("http://localhost")
+                .and_then(|base| base.join(req.uri().as_ref()))
+                .map_err(|err| bad_request(format!("invalid URL: {}", err)));
+            let data = self.data.read().unwrap();
+            url.and_then(|url| handler(&url, &data))
+        };
+        let response = match result {
+            Ok(response) => json_response(serde_json::to_string(&response).unwrap()),
+            Err((status, error)) => {
+                json_response(serde_json::to_string(&error).unwrap()).with_status(status)
+            }
+        };
+        Box::new(futures::future::ok(response))
+    }
+
+    fn handle_post<'de, F, D, S>(&self, req: Request, handler: F) -> <Server as Service>::Future
+    where
+        F: FnOnce(D, &InputData) -> V1Result<S> + Send + 'static,
+        D: DeserializeOwned,
+        S: Serialize,
+    {
+        self.handle_post_inner(req, handler, None)
+    }
+
+    /// Like `handle_post`, but for handlers whose response only depends on
+    /// the request and the data, which is cached until the data changes.
+    fn handle_post_cached<'de, F, D, S>(
+        &self,
+        req: Request,
+        handler: F,
+    ) -> <Server as Service>::Future
+    where
+        F: FnOnce(D, &InputData) -> V1Result<S> + Send + 'static,
+        D: DeserializeOwned,
+        S: Serialize,
+    {
+        let cache = self.cache.clone();
+        self.handle_post_inner(req, handler, Some(cache))
+    }
+
+    fn handle_post_inner<'de, F, D, S>(
+        &self,
+        req: Request,
+        handler: F,
+        cache: Option<Arc<ResponseCache>>,
+    ) -> <Server as Service>::Future
     where
-        F: FnOnce(D, &InputData) -> S + Send + 'static,
+        F: FnOnce(D, &InputData) -> V1Result<S> + Send + 'static,
         D: DeserializeOwned,
         S: Serialize,
     {
         assert_eq!(*req.method(), Post);
+        let path = req.path().to_string();
         let length = req.headers()
             .get::<ContentLength>()
             .expect("content-length to exist")
@@ -304,7 +2038,11 @@ impl Server {
                 })
                 .map(move |body| {
                     let data = data.read().unwrap();
-                    let body: D = match serde_json::from_slice(&body) {
+                    let cached = cache.as_ref().and_then(|cache| cache.get(&path, &body));
+                    if let Some(response) = cached {
+                        return json_response((*response).clone());
+                    }
+                    let request: D = match serde_json::from_slice(&body) {
                         Ok(d) => d,
                         Err(err) => {
                             error!(
@@ -317,13 +2055,17 @@ impl Server {
                                 .with_body(format!("Failed to deserialize request; {:?}", err));
                         }
                     };
-                    let result = handler(body, &data);
-                    Response::new()
-                        .with_header(ContentType::json())
-                        .with_header(CacheControl(
-                            vec![CacheDirective::NoCache, CacheDirective::NoStore],
-                        ))
-                        .with_body(serde_json::to_string(&result).unwrap())
+                    let result = match handler(request, &data) {
+                        Ok(response) => serde_json::to_string(&response).unwrap(),
+                        Err((status, error)) => {
+                            return json_response(serde_json::to_string(&error).unwrap())
+                                .with_status(status)
+                        }
+                    };
+                    match cache {
+                        Some(cache) => json_response((*cache.insert(&path, body, result)).clone()),
+                        None => json_response(result),
+                    }
                 })
         }))
     }
@@ -350,22 +2092,58 @@ impl Server {
 
         let rwlock = self.data.clone();
         let updating = self.updating.clone();
+        let queue = self.queue.clone();
+        let exclusions = self.exclusions.clone();
+        let rollups = self.rollups.clone();
+        let channels = self.channels.clone();
+        let cache = self.cache.clone();
+        let metrics = self.metrics.clone();
+        let pool = self.pool.clone();
         let response = self.pool.spawn_fn(move || -> Result<serde_json::Value> {
             let repo_path = get_repo_path()?;
 
             git::update_repo(&repo_path)?;
 
             info!("updating from filesystem...");
-            let new_data = InputData::from_fs(&repo_path)?;
+            let mut new_data = InputData::from_fs(&repo_path)?;
+            queue.retire(&new_data, Utc::now())?;
+            for sha in analysis::latest_regressions(&new_data) {
+                if let Err(err) = unroll(&queue, sha) {
+                    warn!("failed to check whether {} is a rollup: {:?}", sha, err);
+                }
+            }
+            // Only after queueing, as excluded results mustn't be collected
+            // over and over again.
+            exclusions.apply(&mut new_data);
 
             // Retrieve the stored InputData from the request.
             let mut data = rwlock.write().unwrap();
 
             // Write the new data back into the request
             *data = new_data;
+            cache.clear();
+            drop(data);
 
             updating.store(false, Ordering::Release);
 
+            // Listing the master commits can take a while; the new data is
+            // served meanwhile.
+            pool.spawn_fn(move || -> Result<()> {
+                let refreshed = refresh_master_commits(
+                    &repo_path,
+                    &rwlock,
+                    &queue,
+                    &exclusions,
+                    &rollups,
+                    &channels,
+                    &metrics,
+                );
+                if let Err(ref err) = refreshed {
+                    warn!("failed to backfill master commits: {:?}", err);
+                }
+                refreshed
+            }).forget();
+
             Ok(serde_json::to_value(
                 "Successfully updated from filesystem",
             )?)
@@ -388,6 +2166,255 @@ impl Server {
                 }),
         )
     }
+
+    /// GitHub push webhook for rust-lang/rust: queue the merged commit, or
+    /// the try build of a pull request of a regressed rollup. With a webhook
+    /// secret, only deliveries signed with it are accepted.
+    /// Push payloads are too large for `handle_post`.
+    fn handle_github_push(&self, req: Request) -> <Self as Service>::Future {
+        let length = req.headers()
+            .get::<ContentLength>()
+            .map(|length| length.0)
+            .unwrap_or(0);
+        if length > MAX_PUSH_EVENT {
+            return Box::new(futures::future::err(hyper::Error::TooLarge));
+        }
+        let queue = self.queue.clone();
+        let secret = self.webhook_secret.clone();
+        let signature = req.headers()
+            .get_raw("X-Hub-Signature")
+            .and_then(|raw| raw.one())
+            .map(|signature| signature.to_vec());
+        Box::new(self.pool.spawn_fn(move || {
+            read_body(req.body(), MAX_PUSH_EVENT as usize)
+                .map(move |body| {
+                    if let Some(secret) = secret {
+                        let signed = signature
+                            .map_or(false, |s| github::valid_signature(&secret, &s, &body));
+                        if !signed {
+                            return Response::new()
+                                .with_status(StatusCode::Unauthorized)
+                                .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
+                                .with_body("Missing or invalid X-Hub-Signature");
+                        }
+                    }
+                    match handle_push_event(&body, &queue, Utc::now()) {
+                        Ok(message) => Response::new()
+                            .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
+                            .with_body(message),
+                        Err(err) => {
+                            error!("failed to handle push event: {:?}", err);
+                            Response::new()
+                                .with_status(StatusCode::BadRequest)
+                                .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
+                                .with_body(format!("Failed to handle push event: {:?}", err))
+                        }
+                    }
+                })
+        }))
+    }
+
+    /// Set or remove a baseline on behalf of `user`. The data is locked for
+    /// writing meanwhile, so that no cached comparison with the baseline's
+    /// old commit survives.
+    fn handle_set_baseline(&self, req: Request, user: &User) -> <Self as Service>::Future {
+        let length = req.headers()
+            .get::<ContentLength>()
+            .map(|length| length.0)
+            .unwrap_or(0);
+        if length > 10_000 {
+            // 10 kB
+            return Box::new(futures::future::err(hyper::Error::TooLarge));
+        }
+        let user = user.clone();
+        let data = self.data.clone();
+        let store = self.baselines.clone();
+        let cache = self.cache.clone();
+        Box::new(self.pool.spawn_fn(move || {
+            req.body()
+                .fold(Vec::new(), |mut acc, chunk| {
+                    acc.extend_from_slice(&*chunk);
+                    futures::future::ok::<_, <Self as Service>::Error>(acc)
+                })
+                .map(move |body| {
+                    let result = serde_json::from_slice::<baselines::Request>(&body)
+                        .map_err(Error::from)
+                        .and_then(|request| {
+                            let data = data.write().unwrap();
+                            let sha = match request.commit {
+                                Some(ref commit) => Some(find_commit(&data, commit)?),
+                                None => None,
+                            };
+                            let baseline = store.set(&request.name, sha, &user, Utc::now())?;
+                            cache.clear();
+                            Ok(baseline)
+                        });
+                    let response = match result {
+                        Ok(baseline) => baselines::SetResponse {
+                            baseline: baseline,
+                            error: None,
+                        },
+                        Err(err) => baselines::SetResponse {
+                            baseline: None,
+                            error: Some(err.to_string()),
+                        },
+                    };
+                    json_response(serde_json::to_string(&response).unwrap())
+                })
+        }))
+    }
+
+    /// Add or remove an annotation on behalf of `user`. Cached graph data
+    /// includes the annotations, so the cache is cleared.
+    fn handle_set_annotation(&self, req: Request, user: &User) -> <Self as Service>::Future {
+        let length = req.headers()
+            .get::<ContentLength>()
+            .map(|length| length.0)
+            .unwrap_or(0);
+        if length > 10_000 {
+            // 10 kB
+            return Box::new(futures::future::err(hyper::Error::TooLarge));
+        }
+        let user = user.clone();
+        let data = self.data.clone();
+        let store = self.annotations.clone();
+        let cache = self.cache.clone();
+        Box::new(self.pool.spawn_fn(move || {
+            req.body()
+                .fold(Vec::new(), |mut acc, chunk| {
+                    acc.extend_from_slice(&*chunk);
+                    futures::future::ok::<_, <Self as Service>::Error>(acc)
+                })
+                .map(move |body| {
+                    let result = serde_json::from_slice::<annotations::Request>(&body)
+                        .map_err(Error::from)
+                        .and_then(|request| {
+                            let annotation = match request.remove {
+                                Some(id) => {
+                                    store.remove(id)?;
+                                    None
+                                }
+                                None => {
+                                    let data = data.read().unwrap();
+                                    let commit = match request.commit {
+                                        Some(ref commit) => Some(find_commit_data(&data, commit)?),
+                                        None => None,
+                                    };
+                                    let date = match (request.date, commit.as_ref()) {
+                                        (Some(date), _) => date,
+                                        (None, Some(commit)) => commit.date,
+                                        (None, None) => bail!("a date or a commit is needed"),
+                                    };
+                                    Some(store.add(
+                                        date,
+                                        commit.map(|commit| commit.sha),
+                                        &request.label,
+                                        request.link,
+                                        &user,
+                                    )?)
+                                }
+                            };
+                            cache.clear();
+                            Ok(annotation)
+                        });
+                    let response = match result {
+                        Ok(annotation) => annotations::SetResponse {
+                            annotation: annotation,
+                            error: None,
+                        },
+                        Err(err) => annotations::SetResponse {
+                            annotation: None,
+                            error: Some(err.to_string()),
+                        },
+                    };
+                    json_response(serde_json::to_string(&response).unwrap())
+                })
+        }))
+    }
+
+    fn handle_set_exclusion(&self, req: Request, user: &User) -> <Self as Service>::Future {
+        let length = req.headers()
+            .get::<ContentLength>()
+            .map(|length| length.0)
+            .unwrap_or(0);
+        if length > 10_000 {
+            // 10 kB
+            return Box::new(futures::future::err(hyper::Error::TooLarge));
+        }
+        let user = user.clone();
+        let data = self.data.clone();
+        let store = self.exclusions.clone();
+        let cache = self.cache.clone();
+        Box::new(self.pool.spawn_fn(move || {
+            req.body()
+                .fold(Vec::new(), |mut acc, chunk| {
+                    acc.extend_from_slice(&*chunk);
+                    futures::future::ok::<_, <Self as Service>::Error>(acc)
+                })
+                .map(move |body| {
+                    let result = serde_json::from_slice::<exclusions::Request>(&body)
+                        .map_err(Error::from)
+                        .and_then(|request| {
+                            let exclusion = match request.remove {
+                                Some(id) => {
+                                    store.remove(id)?;
+                                    // The results are only in the files now.
+                                    let mut new_data = InputData::from_fs(&get_repo_path()?)?;
+                                    store.apply(&mut new_data);
+                                    *data.write().unwrap() = new_data;
+                                    None
+                                }
+                                None => {
+                                    let mut data = data.write().unwrap();
+                                    let commit = find_commit_data(&data, &request.commit)?;
+                                    Some(store.add(
+                                        &mut data,
+                                        &commit.sha,
+                                        request.target,
+                                        request.benchmark,
+                                        &request.reason,
+                                        &user,
+                                    )?)
+                                }
+                            };
+                            cache.clear();
+                            Ok(exclusion)
+                        });
+                    let response = match result {
+                        Ok(exclusion) => exclusions::SetResponse {
+                            exclusion: exclusion,
+                            error: None,
+                        },
+                        Err(err) => exclusions::SetResponse {
+                            exclusion: None,
+                            error: Some(err.to_string()),
+                        },
+                    };
+                    json_response(serde_json::to_string(&response).unwrap())
+                })
+        }))
+    }
+
+    /// Respond with the result of `handler` on the queue, never cached.
+    fn handle_queue<F, S>(&self, handler: F) -> <Self as Service>::Future
+    where
+        F: FnOnce(&Queue) -> Result<S>,
+        S: Serialize,
+    {
+        let response = match handler(&self.queue) {
+            Ok(result) => Response::new()
+                .with_header(ContentType::json())
+                .with_header(CacheControl(
+                    vec![CacheDirective::NoCache, CacheDirective::NoStore],
+                ))
+                .with_body(serde_json::to_string(&result).unwrap()),
+            Err(err) => Response::new()
+                .with_body(format!("Internal Server Error: {:?}", err))
+                .with_status(StatusCode::InternalServerError)
+                .with_header(ContentType(mime::TEXT_PLAIN_UTF_8)),
+        };
+        Box::new(futures::future::ok(response))
+    }
 }
 
 impl Service for Server {
@@ -397,54 +2424,229 @@ impl Service for Server {
     type Future = Box<Future<Item = Self::Response, Error = Self::Error>>;
 
     fn call(&self, req: Request) -> Self::Future {
-        let fs_path = format!(
-            "site/static{}",
-            if req.path() == "" || req.path() == "/" {
-                "/index.html"
-            } else {
-                req.path()
-            }
-        );
-
-        info!(
-            "handling: req.path()={:?}, fs_path={:?}",
-            req.path(),
-            fs_path
-        );
+        // Static files are lumped together to keep the number of labels small.
+        let endpoint = if req.path().starts_with("/perf/") || req.path().starts_with("/api/")
+            || req.path() == "/metrics"
+        {
+            req.path().to_string()
+        } else {
+            String::from("static")
+        };
+        let start = Instant::now();
+        let metrics = self.metrics.clone();
+        Box::new(self.route(req).then(move |response| {
+            let endpoint = match response {
+                Ok(ref response) if response.status() == StatusCode::NotFound => {
+                    String::from("not-found")
+                }
+                _ => endpoint,
+            };
+            metrics.observe_request(&endpoint, start.elapsed());
+            response
+        }))
+    }
+}
 
-        if fs_path.contains("./") | fs_path.contains("../") {
-            return Box::new(futures::future::ok(
-                Response::new()
-                    .with_header(ContentType::html())
-                    .with_status(StatusCode::NotFound),
-            ));
-        }
+impl Server {
+    fn route(&self, req: Request) -> <Server as Service>::Future {
+        info!("handling: req.path()={:?}", req.path());
 
-        if Path::new(&fs_path).is_file() {
-            return Box::new(self.pool.spawn_fn(move || {
-                let mut f = File::open(&fs_path).unwrap();
-                let mut source = Vec::new();
-                f.read_to_end(&mut source).unwrap();
-                futures::future::ok(Response::new().with_body(source))
-            }));
+        if let Some(asset) = self.frontend.get(req.path()) {
+            return Box::new(futures::future::ok(asset_response(&req, asset)));
         }
 
         match req.path() {
             "/perf/info" => self.handle_get(&req, handle_info),
-            "/perf/data" => self.handle_post(req, handle_data),
-            "/perf/get" => self.handle_post(req, handle_days),
+            "/perf/data" => {
+                let annotations = self.annotations.clone();
+                self.handle_post_cached(req, move |body, data| {
+                    handle_data(body, data, &annotations)
+                })
+            }
+            "/perf/get" => {
+                let baselines = self.baselines.clone();
+                let rollups = self.rollups.clone();
+                self.handle_post_cached(req, move |body, data| {
+                    handle_days(body, data, &baselines, &rollups)
+                })
+            }
+            "/perf/snapshot" if *req.method() == Get => self.handle_get_req(&req, |req, _data| {
+                // The request URI lacks the scheme and host.
+                let id = Url::parse("http://localhost")
+                    .and_then(|base| base.join(req.uri().as_ref()))
+                    .ok()
+                    .and_then(|url| query_param(&url, "id"))
+                    .unwrap_or_default();
+                handle_get_snapshot(&id, &self.snapshots)
+            }),
+            "/perf/snapshot" => {
+                let baselines = self.baselines.clone();
+                let rollups = self.rollups.clone();
+                let snapshots = self.snapshots.clone();
+                self.handle_post(req, move |body, data| {
+                    Ok(handle_take_snapshot(body, data, &baselines, &rollups, &snapshots))
+                })
+            }
             "/perf/stats" => self.handle_post(req, handle_stats),
+            "/perf/bootstrap" => {
+                self.handle_post(req, |body, data| Ok(handle_bootstrap(body, data)))
+            }
+            "/perf/calibration" => {
+                self.handle_post(req, |body, data| Ok(handle_calibration(body, data)))
+            }
+            "/perf/collectors" => self.handle_get(&req, handle_collectors),
+            "/perf/self_profile" => self.handle_post(req, handle_self_profile),
+            "/perf/timings" => self.handle_post(req, handle_timings),
+            "/perf/llvm-passes" => self.handle_post(req, handle_llvm_passes),
+            "/api/v1/info" => self.handle_v1(&req, |_, data| Ok(handle_v1_info(data))),
+            "/api/v1/commits" => self.handle_v1(&req, handle_v1_commits),
+            "/api/v1/results" => self.handle_v1(&req, handle_v1_results),
+            "/api/v1/neighbors" => self.handle_v1(&req, handle_v1_neighbors),
+            "/api/v1/compare" => self.handle_v1(&req, |url, data| {
+                handle_v1_compare(url, data, &self.baselines, &self.rollups)
+            }),
+            "/api/v1/threshold" => self.handle_v1(&req, handle_v1_threshold),
+            "/api/v1/channels" => self.handle_v1(&req, |url, data| {
+                handle_v1_channels(url, data, &self.channels)
+            }),
+            _ if req.path().starts_with("/api/") => self.handle_v1(&req, |_, _| -> V1Result<()> {
+                Err(not_found("no such endpoint"))
+            }),
             "/perf/pr_commit" => self.handle_get_req(&req, |req, _data| {
-                let url = Url::parse(req.uri().as_ref()).unwrap();
+                let url = request_url(req);
                 let pr = url.query_pairs().find(|&(ref k, _)| k == "pr");
                 handle_pr_commit(pr.unwrap().1.parse().unwrap())
             }),
             "/perf/date_commit" => self.handle_get_req(&req, |req, _data| {
-                let url = Url::parse(req.uri().as_ref()).unwrap();
+                let url = request_url(req);
                 let date = url.query_pairs().find(|&(ref k, _)| k == "date");
                 handle_date_commit(date.unwrap().1.parse().unwrap())
             }),
-            "/perf/onpush" => self.handle_push(req),
+            "/perf/feed.xml" if *req.method() == Get => {
+                let url = request_url(&req);
+                let stat =
+                    query_param(&url, "stat").unwrap_or_else(|| String::from("instructions:u"));
+                let target = query_param(&url, "target").unwrap_or_else(api::default_target);
+                let feed = feed::atom(&self.data.read().unwrap(), &stat, &target);
+                Box::new(futures::future::ok(
+                    Response::new()
+                        .with_header(ContentType("application/atom+xml; charset=utf-8"
+                            .parse()
+                            .unwrap()))
+                        .with_body(feed),
+                ))
+            }
+            "/perf/triage" => self.handle_get_text(&req, |req, data| {
+                let body = triage_request(&request_url(req))?;
+                let rollups = self.queue.state().rollups;
+                handle_triage(body, data, &rollups, &self.rollups)
+            }),
+            "/perf/summary" => self.handle_get_text(&req, |req, data| {
+                handle_summary(&request_url(req), data)
+            }),
+            "/perf/onpush" => {
+                self.authorized(req, Role::Collector, |req, _| self.handle_push(req))
+            }
+            "/perf/github-push" => {
+                self.authorized(req, Role::Collector, |req, _| self.handle_github_push(req))
+            }
+            "/perf/queue" => self.handle_get(&req, |_data| self.queue.state().redacted()),
+            "/perf/annotations" if *req.method() == Get => {
+                self.handle_get(&req, |_data| self.annotations.list())
+            }
+            "/perf/annotations" => self.authorized(req, Role::Admin, |req, user| {
+                self.handle_set_annotation(req, user)
+            }),
+            "/perf/exclusions" if *req.method() == Get => {
+                self.handle_get(&req, |_data| self.exclusions.list())
+            }
+            "/perf/exclusions" => self.authorized(req, Role::Admin, |req, user| {
+                self.handle_set_exclusion(req, user)
+            }),
+            "/perf/baselines" if *req.method() == Get => {
+                self.handle_get(&req, |_data| self.baselines.list())
+            }
+            "/perf/baselines" => self.authorized(req, Role::Member, |req, user| {
+                self.handle_set_baseline(req, user)
+            }),
+            "/perf/experiments" => self.authorized(req, Role::Member, |req, _| {
+                let queue = self.queue.clone();
+                self.handle_post(req, move |body, data| Ok(handle_experiment(body, data, &queue)))
+            }),
+            "/perf/local-toolchain" => self.authorized(req, Role::Collector, |req, user| {
+                let queue = self.queue.clone();
+                let user = user.clone();
+                self.handle_post(req, move |body, _data| {
+                    Ok(handle_local_toolchain(body, &queue, &user))
+                })
+            }),
+            "/metrics" => {
+                let data = self.data.read().unwrap();
+                let body = self.metrics.render(
+                    &data,
+                    &self.queue.state(),
+                    self.updating.load(Ordering::Acquire),
+                    Utc::now(),
+                );
+                Box::new(futures::future::ok(
+                    Response::new()
+                        .with_header(ContentType(
+                            "text/plain; version=0.0.4".parse().unwrap(),
+                        ))
+                        .with_body(body),
+                ))
+            }
+            "/perf/queue/next" => self.authorized(req, Role::Collector, |req, user| {
+                let metrics = query_param(&request_url(&req), "metrics");
+                self.handle_queue(|queue| {
+                    if let Some(metrics) = metrics {
+                        let metrics = metrics.split(',').filter(|m| !m.is_empty());
+                        queue.advertise(&user.name, metrics.map(String::from).collect())?;
+                    }
+                    let commit = queue.next(&user.name, Utc::now())?;
+                    Ok(queue::Next {
+                        key: commit.as_ref().map(|commit| commit.key()),
+                        commit: commit,
+                    })
+                })
+            }),
+            "/perf/queue/renew" => self.authorized(req, Role::Collector, |req, user| {
+                let key = claim_key(&req);
+                self.handle_queue(|queue| {
+                    Ok(queue::Claim {
+                        held: queue.renew(&key, &user.name, Utc::now())?,
+                        canceled: queue.canceled(&key, &user.name),
+                    })
+                })
+            }),
+            "/perf/queue/done" => self.authorized(req, Role::Collector, |req, user| {
+                let key = claim_key(&req);
+                self.handle_queue(|queue| {
+                    Ok(queue::Claim {
+                        held: queue.done(&key, &user.name, Utc::now())?,
+                        canceled: queue.canceled(&key, &user.name),
+                    })
+                })
+            }),
+            "/perf/queue/fail" => self.authorized(req, Role::Collector, |req, user| {
+                let key = claim_key(&req);
+                self.handle_queue(|queue| {
+                    Ok(queue::Claim {
+                        held: queue.fail(&key, &user.name)?,
+                        canceled: queue.canceled(&key, &user.name),
+                    })
+                })
+            }),
+            "/perf/queue/pending" => self.authorized(req, Role::Member, |_, _| {
+                self.handle_queue(|queue| Ok(queue.estimates(Utc::now())))
+            }),
+            "/perf/queue/update" => self.authorized(req, Role::Member, |req, user| {
+                let queue = self.queue.clone();
+                let user = user.clone();
+                self.handle_post(req, move |body, _data| {
+                    Ok(handle_update_queue(body, &queue, &user))
+                })
+            }),
             _ => Box::new(futures::future::ok(
                 Response::new()
                     .with_header(ContentType::html())
@@ -454,12 +2656,175 @@ impl Service for Server {
     }
 }
 
-pub fn start(data: InputData) {
+/// Queue the commit of a GitHub push event, if it merged one into master
+/// or is a try build of an unrolled pull request. Returns what was done.
+fn handle_push_event(body: &[u8], queue: &Queue, now: DateTime<Utc>) -> Result<String> {
+    let event = serde_json::from_slice::<PushEvent>(body)?;
+    if event.repository.full_name != "rust-lang/rust" {
+        return Ok(format!("push to {}; ignored", event.repository.full_name));
+    }
+    if let Some((pr, commit)) = event.try_build(now)? {
+        let message = format!("queued try build {} of #{}", commit.sha, pr);
+        return if queue.push_try_build(pr, commit)? {
+            Ok(message)
+        } else {
+            Ok(String::from("try build of no unrolled PR; ignored"))
+        };
+    }
+    match event.merged_commit(now)? {
+        Some(commit) => {
+            let message = format!("queued {}", commit.sha);
+            queue.push(commit)?;
+            Ok(message)
+        }
+        None => Ok(String::from("not a push to master; ignored")),
+    }
+}
+
+/// The URL of `req`. Servers only get the path and query of a request, which
+/// `Url` can't parse on their own.
+fn request_url(req: &Request) -> Url {
+    let mut url = Url::parse("http://localhost").unwrap();
+    url.set_path(req.path());
+    url.set_query(req.query());
+    url
+}
+
+/// The token of `req`, either a bearer token or, for webhooks which can't set
+/// headers, the `token` query parameter.
+fn request_token(req: &Request) -> Option<String> {
+    match req.headers().get::<Authorization<Bearer>>() {
+        Some(header) => Some(header.0.token.clone()),
+        None => query_param(&request_url(req), "token"),
+    }
+}
+
+/// The full hash of the commit with results for any target whose hash starts
+/// with `prefix`.
+fn find_commit(data: &InputData, prefix: &str) -> Result<String> {
+    find_commit_data(data, prefix).map(|commit| commit.sha)
+}
+
+/// The commit with results for any target whose hash starts with `prefix`.
+fn find_commit_data(data: &InputData, prefix: &str) -> Result<load::Commit> {
+    if prefix.is_empty() {
+        bail!("no commit given");
+    }
+    data.data
+        .values()
+        .flat_map(|commits| commits.values())
+        .find(|day| day.commit.sha.starts_with(prefix))
+        .map(|day| day.commit.clone())
+        .ok_or_else(|| format!("no results for commit {}", prefix).into())
+}
+
+/// The body of a request, failing with `TooLarge` once it exceeds `limit`
+/// bytes. Chunked bodies have no `Content-Length` to check up front.
+fn read_body(
+    body: hyper::Body,
+    limit: usize,
+) -> Box<Future<Item = Vec<u8>, Error = hyper::Error> + Send> {
+    Box::new(body.fold(Vec::new(), move |mut acc, chunk| {
+        if acc.len() + chunk.len() > limit {
+            return futures::future::err(hyper::Error::TooLarge);
+        }
+        acc.extend_from_slice(&*chunk);
+        futures::future::ok(acc)
+    }))
+}
+
+/// The `key` query parameter naming a collector's claim on a queue entry.
+fn claim_key(req: &Request) -> String {
+    query_param(&request_url(req), "key").unwrap_or_default()
+}
+
+fn query_param(url: &Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|&(ref k, _)| k == key)
+        .map(|(_, v)| v.into_owned())
+}
+
+fn json_response(body: String) -> Response {
+    Response::new()
+        .with_header(ContentType::json())
+        .with_header(CacheControl(vec![CacheDirective::NoCache, CacheDirective::NoStore]))
+        .with_body(body)
+}
+
+/// Accepts both RFC 3339 dates and plain `YYYY-MM-DD` days. Missing or
+/// unparseable dates fall back to the defaults of `OptionalDate`.
+fn query_date<B: Bound>(url: &Url, key: &str) -> OptionalDate<B> {
+    let value = query_param(url, key).unwrap_or_default();
+    let date = value
+        .parse::<Date>()
+        .or_else(|_| Date::from_format(&format!("{} 00:00:00", value), "%Y-%m-%d %H:%M:%S"));
+    match date {
+        Ok(date) => OptionalDate::new(date),
+        Err(_) => OptionalDate::CouldNotParse(value),
+    }
+}
+
+/// The largest GitHub push event accepted, in bytes (5 MB).
+const MAX_PUSH_EVENT: u64 = 5_000_000;
+
+/// How long browsers may cache files requested under their hashed names, in
+/// seconds.
+const IMMUTABLE_MAX_AGE: u32 = 365 * 24 * 60 * 60;
+
+/// `asset`, or that the copy the browser has is still current.
+fn asset_response(req: &Request, asset: Asset) -> Response {
+    let etag = EntityTag::strong(asset.hash);
+    let cache_control = if asset.immutable {
+        CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(IMMUTABLE_MAX_AGE),
+        ])
+    } else {
+        CacheControl(vec![CacheDirective::NoCache])
+    };
+    let current = match req.headers().get::<IfNoneMatch>() {
+        Some(&IfNoneMatch::Any) => true,
+        Some(&IfNoneMatch::Items(ref tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+    let response = Response::new()
+        .with_header(ETag(etag))
+        .with_header(cache_control);
+    if current {
+        response.with_status(StatusCode::NotModified)
+    } else {
+        response
+            .with_header(ContentType(asset.content_type))
+            .with_body(asset.body)
+    }
+}
+
+pub fn start(mut data: InputData, frontend: Frontend) {
+    let exclusions = Exclusions::from_env().expect("failed to load exclusions");
+    exclusions.apply(&mut data);
     let server = Arc::new(Server {
         data: Arc::new(RwLock::new(data)),
         pool: CpuPool::new_num_cpus(),
         updating: Arc::new(AtomicBool::new(false)),
+        auth: Auth::from_env().expect("failed to load site tokens"),
+        queue: Arc::new(Queue::from_env().expect("failed to load queue")),
+        baselines: Arc::new(Baselines::from_env().expect("failed to load baselines")),
+        annotations: Arc::new(Annotations::from_env().expect("failed to load annotations")),
+        exclusions: Arc::new(exclusions),
+        rollups: Arc::new(RollupCache::from_env().expect("failed to load rollups")),
+        channels: Arc::new(Channels::from_env().expect("failed to load channels")),
+        snapshots: Arc::new(Snapshots::from_env().expect("failed to set up snapshots")),
+        metrics: Arc::new(Metrics::default()),
+        cache: Arc::new(ResponseCache::default()),
+        frontend: frontend,
+        webhook_secret: env::var("GITHUB_WEBHOOK_SECRET").ok(),
     });
+    if server.webhook_secret.is_none() {
+        warn!("GITHUB_WEBHOOK_SECRET is not set; push events are only checked for the token");
+    }
+    if let Some(notifier) = Notifier::from_env().expect("failed to configure nightly notifier") {
+        notifier.spawn(server.data.clone());
+    }
     let mut server_address: SocketAddr = "0.0.0.0:2346".parse().unwrap();
     server_address.set_port(
         env::var("PORT")
@@ -470,3 +2835,358 @@ pub fn start(data: InputData) {
     let server = Http::new().bind(&server_address, move || Ok(server.clone()));
     server.unwrap().run().unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use hyper::Uri;
+    use rust_sysroot::git::Commit as GitCommit;
+    use tempdir::TempDir;
+
+    use auth::User;
+
+    use super::*;
+
+    fn request(uri: &str) -> Request {
+        Request::new(Get, uri.parse::<Uri>().unwrap())
+    }
+
+    fn auth() -> Auth {
+        let mut tokens = HashMap::new();
+        tokens.insert(
+            String::from("s3cret+token"),
+            User {
+                name: String::from("rustc-timing webhook"),
+                role: Role::Collector,
+            },
+        );
+        Auth::new(tokens, env::temp_dir().join("rustc-perf-test-audit.log"))
+    }
+
+    /// Results of the commits `shas` for the default target, a day apart.
+    fn input_data(shas: &[&str]) -> InputData {
+        let mut commits = BTreeMap::new();
+        for (day, sha) in shas.iter().enumerate() {
+            let data: CommitData = serde_json::from_str(&format!(
+                r#"{{
+                    "commit": {{ "sha": "{}", "date": "2017-10-{:02}T00:00:00Z" }},
+                    "benchmarks": {{
+                        "helloworld": {{ "Ok": [{{
+                            "name": "helloworld",
+                            "runs": [{{ "stats": [{{ "name": "instructions:u", "cnt": 1.0 }}] }}]
+                        }}] }}
+                    }},
+                    "triple": "{}"
+                }}"#,
+                sha,
+                day + 1,
+                api::default_target()
+            )).unwrap();
+            commits.insert(data.commit.clone(), data);
+        }
+        let mut data = BTreeMap::new();
+        data.insert(api::default_target(), commits);
+        InputData::new(data).unwrap()
+    }
+
+    /// A comparison of `commit_b` against `commit_a`, or against the
+    /// baseline `baseline` if it is set.
+    fn days_request(commit_a: &str, commit_b: &str, baseline: Option<&str>) -> days::Request {
+        let mut body: days::Request = serde_json::from_str(&format!(
+            r#"{{
+                "commit_a": "{}",
+                "commit_b": "{}",
+                "crates": {{ "list": "All" }},
+                "stat": "instructions:u"
+            }}"#,
+            commit_a,
+            commit_b
+        )).unwrap();
+        body.baseline = baseline.map(String::from);
+        body
+    }
+
+    #[test]
+    fn request_url_of_path_and_query() {
+        let url = request_url(&request("/perf/triage?start=2017-10-01&stat=wall-time"));
+        assert_eq!(url.path(), "/perf/triage");
+        assert_eq!(query_param(&url, "stat"), Some(String::from("wall-time")));
+        assert_eq!(query_param(&url, "end"), None);
+
+        let url = request_url(&request("/perf/onpush"));
+        assert_eq!(url.path(), "/perf/onpush");
+        assert_eq!(query_param(&url, "token"), None);
+    }
+
+    #[test]
+    fn query_token_authorizes() {
+        let req = request("/perf/onpush?token=s3cret%2Btoken");
+        let token = request_token(&req);
+        assert_eq!(token, Some(String::from("s3cret+token")));
+        let auth = auth();
+        assert!(auth.authorize(token.as_ref().map(|t| &**t), Role::Collector).is_some());
+        assert!(auth.authorize(token.as_ref().map(|t| &**t), Role::Member).is_none());
+        assert!(auth.authorize(token.as_ref().map(|t| &**t), Role::Admin).is_none());
+
+        let token = request_token(&request("/perf/onpush?token=wrong"));
+        assert!(auth.authorize(token.as_ref().map(|t| &**t), Role::Collector).is_none());
+        assert_eq!(request_token(&request("/perf/onpush")), None);
+    }
+
+    /// The parts of a GitHub push event that matter, and some that don't.
+    const PUSH_EVENT: &str = r#"{
+        "ref": "refs/heads/master",
+        "before": "0000000000000000000000000000000000000000",
+        "after": "b0c52c587fe9ba287053359fff5ed886b7edb27c",
+        "head_commit": {
+            "id": "b0c52c587fe9ba287053359fff5ed886b7edb27c",
+            "timestamp": "2017-10-12T05:23:13-07:00",
+            "message": "Auto merge of #45000 - someone:branch, r=reviewer\n\nDetails",
+            "author": { "name": "bors", "username": "bors" }
+        },
+        "repository": { "full_name": "rust-lang/rust" }
+    }"#;
+
+    #[test]
+    fn github_push_with_query_token() {
+        let mut req = request("/perf/github-push?token=s3cret%2Btoken");
+        req.set_method(Post);
+        let token = request_token(&req);
+        assert!(auth().authorize(token.as_ref().map(|t| &**t), Role::Collector).is_some());
+
+        let dir = TempDir::new("rustc-perf-queue").unwrap();
+        let queue = Queue::open(dir.path().join("queue.json")).unwrap();
+        let now = Utc::now();
+        let message = handle_push_event(PUSH_EVENT.as_bytes(), &queue, now).unwrap();
+        assert_eq!(message, "queued b0c52c587fe9ba287053359fff5ed886b7edb27c");
+        let pending = queue.state().pending;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].summary, "Auto merge of #45000 - someone:branch, r=reviewer");
+
+        let branch = PUSH_EVENT.replace("refs/heads/master", "refs/heads/beta");
+        let message = handle_push_event(branch.as_bytes(), &queue, now).unwrap();
+        assert_eq!(message, "not a push to master; ignored");
+        let fork = PUSH_EVENT.replace("rust-lang/rust", "someone/rust");
+        let message = handle_push_event(fork.as_bytes(), &queue, now).unwrap();
+        assert_eq!(message, "push to someone/rust; ignored");
+        assert_eq!(queue.state().pending.len(), 1);
+        assert!(handle_push_event(b"{}", &queue, now).is_err());
+    }
+
+    #[test]
+    fn read_body_limits_size() {
+        let body = |bytes: &[u8]| hyper::Body::from(bytes.to_vec());
+        assert_eq!(read_body(body(b"0123456789"), 10).wait().unwrap(), b"0123456789");
+        match read_body(body(b"0123456789a"), 10).wait() {
+            Err(hyper::Error::TooLarge) => {}
+            other => panic!("expected TooLarge, got {:?}", other),
+        }
+
+        // Chunks are counted as they arrive, without a `Content-Length`.
+        let (mut sender, chunks) = futures::sync::mpsc::channel(3);
+        for _ in 0..3 {
+            sender.try_send(Ok(hyper::Chunk::from(vec![b'x'; 4]))).unwrap();
+        }
+        drop(sender);
+        match read_body(hyper::Body::from(chunks), 10).wait() {
+            Err(hyper::Error::TooLarge) => {}
+            other => panic!("expected TooLarge, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn github_push_signature() {
+        let body = b"The quick brown fox jumps over the lazy dog";
+        let signature = b"sha1=de7c9b85b8b78aa6bc8a7a36f70a90701c9db4d9";
+        assert!(github::valid_signature("key", signature, body));
+        assert!(!github::valid_signature("other key", signature, body));
+        assert!(!github::valid_signature("key", signature, b"The quick brown fox"));
+        assert!(!github::valid_signature("key", &signature[5..], body));
+        assert!(!github::valid_signature("key", b"sha1=not hex", body));
+        assert!(!github::valid_signature("key", b"", body));
+    }
+
+    #[test]
+    fn claim_key_of_request() {
+        let key = "0123abcd:no-debug-assertions";
+        let req = request(&format!("/perf/queue/renew?token=s3cret%2Btoken&key={}", key));
+        assert_eq!(claim_key(&req), key);
+        assert_eq!(claim_key(&request("/perf/queue/renew?token=s3cret%2Btoken")), "");
+    }
+
+    #[test]
+    fn bearer_token_before_query_token() {
+        let mut req = request("/perf/onpush?token=wrong");
+        req.headers_mut().set(Authorization(Bearer {
+            token: String::from("s3cret+token"),
+        }));
+        assert_eq!(request_token(&req), Some(String::from("s3cret+token")));
+    }
+
+    #[test]
+    fn days_against_baselines() {
+        let dir = TempDir::new("rustc-perf-days").unwrap();
+        let baselines = Baselines::open(dir.path().join("baselines.json")).unwrap();
+        let rollups = RollupCache::open(dir.path().join("rollups.json")).unwrap();
+        let data = input_data(&["aaa", "bbb"]);
+        let admin = User {
+            name: String::from("admin"),
+            role: Role::Admin,
+        };
+        baselines
+            .set("1.20.0", Some(String::from("aaa")), &admin, Utc::now())
+            .unwrap();
+        baselines
+            .set("elsewhere", Some(String::from("ccc")), &admin, Utc::now())
+            .unwrap();
+
+        let body = days_request("", "bbb", Some("1.20.0"));
+        let response = handle_days(body, &data, &baselines, &rollups).unwrap();
+        assert_eq!(response.a.commit, "aaa");
+        assert_eq!(response.b.commit, "bbb");
+
+        for baseline in &["1.19.0", "elsewhere"] {
+            let body = days_request("", "bbb", Some(baseline));
+            let err = handle_days(body, &data, &baselines, &rollups).unwrap_err();
+            assert_eq!(err.0, StatusCode::NotFound);
+        }
+    }
+
+    #[test]
+    fn unknown_commit_is_not_found() {
+        let dir = TempDir::new("rustc-perf-days").unwrap();
+        let baselines = Baselines::open(dir.path().join("baselines.json")).unwrap();
+        let rollups = RollupCache::open(dir.path().join("rollups.json")).unwrap();
+        let data = input_data(&["aaa", "bbb"]);
+
+        for &(commit_a, commit_b) in &[("ccc", "bbb"), ("aaa", "ccc")] {
+            let body = days_request(commit_a, commit_b, None);
+            let err = handle_days(body, &data, &baselines, &rollups).unwrap_err();
+            assert_eq!(err.0, StatusCode::NotFound);
+            assert_eq!(err.1.error, "no results for commit ccc");
+        }
+        let body = days_request("", "ccc", Some("1.20.0"));
+        let err = handle_days(body, &data, &baselines, &rollups).unwrap_err();
+        assert_eq!(err.0, StatusCode::NotFound);
+
+        let body: timings::Request = serde_json::from_str(
+            r#"{ "commit_a": "aaa", "commit_b": "ccc", "benchmark": "helloworld" }"#,
+        ).unwrap();
+        assert_eq!(handle_timings(body, &data).unwrap_err().0, StatusCode::NotFound);
+        let body: timings::Request = serde_json::from_str(
+            r#"{ "commit_a": "aaa", "commit_b": "bbb", "benchmark": "helloworld" }"#,
+        ).unwrap();
+        assert_eq!(handle_timings(body, &data).unwrap().missing, vec!["aaa", "bbb"]);
+
+        let body: llvm_passes::Request = serde_json::from_str(
+            r#"{ "commit_a": "ccc", "commit_b": "bbb", "benchmark": "helloworld" }"#,
+        ).unwrap();
+        assert_eq!(handle_llvm_passes(body, &data).unwrap_err().0, StatusCode::NotFound);
+    }
+
+    #[test]
+    fn both_sides_notes_missing_data() {
+        let data = input_data(&["aaa", "bbb"]);
+        let commits = target_commits(&data, &api::default_target()).unwrap();
+        let (a, b) = (commit_data(commits, "aaa").unwrap(), commit_data(commits, "bbb").unwrap());
+        let mut missing = Vec::new();
+        assert_eq!(both_sides((a, Some(1)), (b, Some(2)), &mut missing), Some((1, 2)));
+        assert_eq!(both_sides((a, Some(1)), (b, None), &mut missing), None);
+        assert_eq!(both_sides((a, None::<u32>), (b, None), &mut missing), None);
+        assert_eq!(missing, vec!["bbb", "aaa", "bbb"]);
+
+        let mut times = vec![1.0, ::std::f64::NAN, -3.0, 2.0];
+        times.sort_by(|x, y| util::descending(x.abs(), y.abs()));
+        assert_eq!(&times[..3], &[-3.0, 2.0, 1.0]);
+        assert!(times[3].is_nan());
+    }
+
+    #[test]
+    fn unknown_target_is_not_found() {
+        let dir = TempDir::new("rustc-perf-days").unwrap();
+        let baselines = Baselines::open(dir.path().join("baselines.json")).unwrap();
+        let rollups = RollupCache::open(dir.path().join("rollups.json")).unwrap();
+        let data = input_data(&["aaa", "bbb"]);
+
+        let mut body = days_request("aaa", "bbb", None);
+        assert!(handle_days(body.clone(), &data, &baselines, &rollups).is_ok());
+        body.target = String::from("wasm32-unknown-unknown");
+        let err = handle_days(body, &data, &baselines, &rollups).unwrap_err();
+        assert_eq!(err.0, StatusCode::NotFound);
+        assert_eq!(err.1.error, "no results for target wasm32-unknown-unknown");
+
+        let url = request_url(&request("/perf/summary?a=aaa&b=bbb"));
+        assert!(handle_summary(&url, &data).is_ok());
+        let url = request_url(&request("/perf/summary?a=aaa&b=bbb&target=wasm32-unknown-unknown"));
+        assert_eq!(handle_summary(&url, &data).unwrap_err().0, StatusCode::NotFound);
+    }
+
+    #[test]
+    fn triage_entry_labels_categories() {
+        let data = input_data(&["aaa", "bbb"]);
+        let commits = target_commits(&data, &api::default_target()).unwrap();
+        let (a, b) = (commit_data(commits, "aaa").unwrap(), commit_data(commits, "bbb").unwrap());
+        let mut categories = BTreeMap::new();
+        categories.insert(String::from("syn"), Category::Secondary);
+        categories.insert(String::from("regex-0.1.80"), Category::Stable);
+        let changes = ["helloworld@0", "syn@0", "regex-0.1.80@0"]
+            .iter()
+            .map(|name| Change {
+                name: name.to_string(),
+                a: 100.0,
+                b: 110.0,
+                percent: 10.0,
+                threshold: 1.0,
+            })
+            .collect::<Vec<_>>();
+        let mut report = String::new();
+        write_triage_entry(&mut report, a, b, &changes, &categories);
+        let lines = report.lines().skip(1).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                "  - `helloworld@0`: +10.0%",
+                "  - `syn@0` (secondary): +10.0%",
+                "  - `regex-0.1.80@0` (stable): +10.0%",
+            ]
+        );
+    }
+
+    #[test]
+    fn metrics_count_missing_commits() {
+        let data = input_data(&["aaa", "bbb"]);
+        let master = ["aaa", "bbb", "ccc", "ddd", "eee"]
+            .iter()
+            .map(|sha| GitCommit {
+                sha: sha.to_string(),
+                date: Utc::now(),
+                summary: String::new(),
+            })
+            .collect::<Vec<_>>();
+        let mut broken = HashSet::new();
+        broken.insert(String::from("eee"));
+        let metrics = Metrics::default();
+        metrics.record_missing_commits(&master, &data, &broken);
+        let rendered = metrics.render(&data, &::queue::State::default(), false, Utc::now());
+        let line = format!("rustc_perf_missing_commits{{target=\"{}\"}} 2", api::default_target());
+        assert!(rendered.lines().any(|l| l == line), "{}", rendered);
+    }
+
+    #[test]
+    fn triage_request_of_query() {
+        let url = request_url(&request(
+            "/perf/triage?start=2017-10-02&end=2017-10-09&stat=wall-time&backend=cranelift",
+        ));
+        let body = triage_request(&url).unwrap();
+        assert_eq!(body.start_date, query_date(&url, "start"));
+        assert_eq!(body.stat, "wall-time");
+        assert_eq!(body.backend, Backend::Cranelift);
+        assert_eq!(body.profile, Profile::default());
+        assert_eq!(body.flags, None);
+        assert_eq!(body.target, api::default_target());
+
+        let url = request_url(&request("/perf/triage?profile=fast"));
+        assert_eq!(triage_request(&url).unwrap_err().0, StatusCode::BadRequest);
+        let url = request_url(&request("/perf/triage?start=last-week"));
+        assert_eq!(triage_request(&url).unwrap_err().0, StatusCode::BadRequest);
+    }
+}
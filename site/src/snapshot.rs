@@ -0,0 +1,92 @@
+// Copyright 2016 The rustc-perf Project Developers. See the COPYRIGHT
+// file at the top-level directory.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Snapshots of comparisons, so that triage issues can link to the numbers
+//! and verdicts as they were when the issue was written, even after the
+//! commits are benchmarked again or their results are pruned.
+//!
+//! Each snapshot is kept as `<id>.json` in the directory named by
+//! `SITE_SNAPSHOTS` (`snapshots` by default) and is never changed once
+//! taken.
+
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde_json;
+
+use api::days;
+use errors::*;
+
+pub use api::snapshot::Snapshot;
+
+pub struct Snapshots {
+    dir: PathBuf,
+}
+
+impl Snapshots {
+    pub fn from_env() -> Result<Snapshots> {
+        let dir = env::var_os("SITE_SNAPSHOTS")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("snapshots"));
+        fs::create_dir_all(&dir).chain_err(|| format!("failed to create {}", dir.display()))?;
+        Ok(Snapshots { dir: dir })
+    }
+
+    /// Store the comparison `response` to `request`, returning the snapshot
+    /// with its new id.
+    pub fn take(
+        &self,
+        request: days::Request,
+        response: days::Response,
+        now: DateTime<Utc>,
+    ) -> Result<Snapshot> {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(&request)?.hash(&mut hasher);
+        now.to_rfc3339().hash(&mut hasher);
+        let snapshot = Snapshot {
+            id: format!("{:016x}", hasher.finish()),
+            taken: now,
+            request: request,
+            response: response,
+        };
+        let path = self.path(&snapshot.id);
+        if path.exists() {
+            bail!("snapshot {} already exists", snapshot.id);
+        }
+        let tmp = path.with_extension("tmp");
+        serde_json::to_writer(&mut File::create(&tmp)?, &snapshot)?;
+        fs::rename(&tmp, &path)
+            .chain_err(|| format!("failed to write snapshot {}", path.display()))?;
+        Ok(snapshot)
+    }
+
+    /// The snapshot `id`, if there is one.
+    pub fn get(&self, id: &str) -> Result<Option<Snapshot>> {
+        // Ids are hexadecimal; anything else could name another file.
+        if id.is_empty() || !id.chars().all(|c| c.is_digit(16)) {
+            return Ok(None);
+        }
+        let path = self.path(id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(&path).chain_err(|| format!("failed to open {}", path.display()))?;
+        let snapshot = serde_json::from_reader(file)
+            .chain_err(|| format!("failed to parse {}", path.display()))?;
+        Ok(Some(snapshot))
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
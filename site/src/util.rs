@@ -7,29 +7,42 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::cmp::Ordering;
 use std::env;
 use std::collections::BTreeMap;
 use std::collections::btree_map::Range;
 use std::collections::Bound::Included;
 
 use load::{Commit, CommitData, InputData};
-use date::{Date, End, OptionalDate, Start};
+use date::Date;
 use errors::*;
 
-pub fn get_commit_data(data: &InputData, idx: String) -> &CommitData {
+/// The results for `target`, if it has been benchmarked.
+pub fn target_data<'a>(
+    data: &'a InputData,
+    target: &str,
+) -> Option<&'a BTreeMap<Commit, CommitData>> {
+    data.data.get(target)
+}
+
+/// The results of the commit whose hash or hash prefix is `idx`, if any.
+pub fn get_commit_data<'a>(
+    data: &'a BTreeMap<Commit, CommitData>,
+    idx: &str,
+) -> Option<&'a CommitData> {
     debug!("getting commit for {}", idx);
-    data.data.values().find(|cd| cd.commit.sha.starts_with(&idx)).unwrap()
+    data.values().find(|cd| cd.commit.sha.starts_with(idx))
 }
 
-pub fn get_commit_data_from_end(data: &InputData, idx: Date) -> &CommitData {
+pub fn get_commit_data_from_end(data: &BTreeMap<Commit, CommitData>, idx: Date) -> &CommitData {
     debug!("getting from end for {}", idx);
     let a = Commit {
         sha: String::new(),
         date: idx,
     };
 
-    let r = data.data.range(a..).next().map(|x| x.1).unwrap_or_else(|| {
-        let r = data.data.range(..).next_back().unwrap().1;
+    let r = data.range(a..).next().map(|x| x.1).unwrap_or_else(|| {
+        let r = data.range(..).next_back().unwrap().1;
         debug!("failed to get, instead using: {}", r.commit.date);
         r
     });
@@ -37,15 +50,18 @@ pub fn get_commit_data_from_end(data: &InputData, idx: Date) -> &CommitData {
     r
 }
 
-pub fn get_commit_data_from_start(data: &InputData, idx: Date) -> &CommitData {
+pub fn get_commit_data_from_start(
+    data: &BTreeMap<Commit, CommitData>,
+    idx: Date,
+) -> &CommitData {
     debug!("getting from start for {}", idx);
     let a = Commit {
         sha: String::new(),
         date: idx,
     };
 
-    let r = data.data.range(a..).next().map(|x| x.1).unwrap_or_else(|| {
-        let r = data.data.range(..).next().unwrap().1;
+    let r = data.range(a..).next().map(|x| x.1).unwrap_or_else(|| {
+        let r = data.range(..).next().unwrap().1;
         debug!("failed to get, instead using: {}", r.commit.date);
         r
     });
@@ -53,23 +69,7 @@ pub fn get_commit_data_from_start(data: &InputData, idx: Date) -> &CommitData {
     r
 }
 
-pub fn optional_data_range(
-    data: &InputData,
-    a: OptionalDate<Start>,
-    b: OptionalDate<End>,
-) -> Range<Commit, CommitData> {
-    data_range(
-        &data.data,
-        a.as_date(data.last_date),
-        b.as_date(data.last_date),
-    )
-}
-
-pub fn data_range(
-    data: &BTreeMap<Commit, CommitData>,
-    a: Date,
-    b: Date,
-) -> Range<Commit, CommitData> {
+pub fn data_range<V>(data: &BTreeMap<Commit, V>, a: Date, b: Date) -> Range<Commit, V> {
     let a = Commit {
         sha: String::new(),
         date: a,
@@ -82,10 +82,28 @@ pub fn data_range(
     data.range((Included(a), Included(b)))
 }
 
-/// Reads the repository path from the arguments passed to main()
+/// Orders `a` and `b` from largest to smallest, NaN last, for sorting by a
+/// value that may not be a number.
+pub fn descending(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => b.partial_cmp(&a).unwrap(),
+    }
+}
+
+/// Reads the repository path from the arguments passed to main(), the
+/// first that isn't a flag like `--dev-seed` or the directory given with
+/// `--frontend-dir`.
 pub fn get_repo_path() -> Result<String> {
-    env::args()
-        .nth(1)
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    args.iter()
+        .enumerate()
+        .find(|&(idx, arg)| {
+            !arg.starts_with("--") && (idx == 0 || args[idx - 1] != "--frontend-dir")
+        })
+        .map(|(_, arg)| arg.clone())
         .ok_or("No argument supplied, needs location of data repo.".into())
 }
 
@@ -35,6 +35,11 @@ function getSelected(name) {
     return e.options[e.selectedIndex].value;
 }
 
+function getSelectedList(name) {
+    let e = document.getElementById(name);
+    return Array.from(e.selectedOptions).map(option => option.value);
+}
+
 function toList(list_object, type) {
     if (list_object.list == "All") {
         let result = [];
@@ -96,6 +101,82 @@ function make_settings(callback, total_label) {
                 list.value = 'instructions:u';
             }
 
+            let extra_stats = document.getElementById("extra-stats");
+            if (extra_stats) {
+                extra_stats.innerHTML = phases_html;
+            }
+
+            let targets = document.getElementById("targets");
+            if (targets) {
+                let targets_html = "";
+                for (let target of data.targets) {
+                    targets_html += `<option value="${target}">${target}</option>`;
+                }
+                targets.innerHTML = targets_html;
+                targets.value = 'x86_64-unknown-linux-gnu';
+            }
+
+            let backends = document.getElementById("backends");
+            if (backends) {
+                let backends_html = "";
+                for (let backend of data.backends) {
+                    backends_html += `<option value="${backend}">${backend}</option>`;
+                }
+                backends.innerHTML = backends_html;
+                backends.value = 'llvm';
+            }
+
+            let stds = document.getElementById("stds");
+            if (stds) {
+                let stds_html = "";
+                for (let std of data.stds) {
+                    stds_html += `<option value="${std}">${std}</option>`;
+                }
+                stds.innerHTML = stds_html;
+                stds.value = 'static';
+            }
+
+            let profiles = document.getElementById("profiles");
+            if (profiles) {
+                let profiles_html = "";
+                for (let profile of data.profiles) {
+                    profiles_html += `<option value="${profile}">${profile}</option>`;
+                }
+                profiles.innerHTML = profiles_html;
+                profiles.value = 'debug';
+            }
+
+            let flag_sets = document.getElementById("flag-sets");
+            if (flag_sets) {
+                let flag_sets_html = `<option value="">no extra flags</option>`;
+                for (let flags of data.flag_sets) {
+                    flag_sets_html += `<option value="${flags}">${flags}</option>`;
+                }
+                flag_sets.innerHTML = flag_sets_html;
+                flag_sets.value = '';
+            }
+
+            let custom_profiles = document.getElementById("custom-profiles");
+            if (custom_profiles) {
+                let custom_profiles_html = `<option value="">manifest profile</option>`;
+                for (let custom_profile of data.custom_profiles || []) {
+                    custom_profiles_html +=
+                        `<option value="${custom_profile}">${custom_profile}</option>`;
+                }
+                custom_profiles.innerHTML = custom_profiles_html;
+                custom_profiles.value = '';
+            }
+
+            let machines = document.getElementById("machines");
+            if (machines) {
+                let machines_html = `<option value="">any machine</option>`;
+                for (let machine of data.machines) {
+                    machines_html += `<option value="${machine}">${machine}</option>`;
+                }
+                machines.innerHTML = machines_html;
+                machines.value = '';
+            }
+
             var groupByCrate = document.getElementById("group-by-crate");
             if (groupByCrate) {
                 groupByCrate.checked = true;